@@ -0,0 +1,8 @@
+-- CreateExtension
+CREATE EXTENSION IF NOT EXISTS "pg_trgm";
+
+-- CreateIndex
+CREATE INDEX "topics_title_trgm_idx" ON "topics" USING GIN ("title" gin_trgm_ops);
+
+-- CreateIndex
+CREATE INDEX "reses_content_trgm_idx" ON "reses" USING GIN ("content" gin_trgm_ops) WHERE "type" = 'normal';
@@ -0,0 +1,20 @@
+-- AlterTable
+ALTER TABLE "topics" ADD COLUMN "res_number_seq" INTEGER NOT NULL DEFAULT 0;
+
+-- AlterTable
+ALTER TABLE "reses" ADD COLUMN "number" INTEGER;
+
+-- 既存のレスに作成順で番号を振る
+UPDATE "reses" SET "number" = "numbered"."number"
+FROM (
+    SELECT "id", ROW_NUMBER() OVER (PARTITION BY "topic_id" ORDER BY "created_at", "id")::INTEGER AS "number"
+    FROM "reses"
+) AS "numbered"
+WHERE "reses"."id" = "numbered"."id";
+
+UPDATE "topics" SET "res_number_seq" = COALESCE((SELECT MAX("number") FROM "reses" WHERE "reses"."topic_id" = "topics"."id"), 0);
+
+ALTER TABLE "reses" ALTER COLUMN "number" SET NOT NULL;
+
+-- CreateIndex
+CREATE UNIQUE INDEX "reses_topic_id_number_key" ON "reses"("topic_id", "number");
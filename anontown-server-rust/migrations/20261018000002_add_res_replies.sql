@@ -0,0 +1,25 @@
+-- CreateTable
+CREATE TABLE "res_replies" (
+    "res_id" VARCHAR(64) NOT NULL,
+    "reply_id" VARCHAR(64) NOT NULL,
+
+    CONSTRAINT "res_replies_pkey" PRIMARY KEY ("res_id","reply_id")
+);
+
+-- CreateIndex
+CREATE INDEX "res_replies_reply_id_idx" ON "res_replies"("reply_id");
+
+-- AddForeignKey
+ALTER TABLE "res_replies" ADD CONSTRAINT "res_replies_res_id_fkey" FOREIGN KEY ("res_id") REFERENCES "reses"("id") ON DELETE NO ACTION ON UPDATE NO ACTION;
+
+-- AddForeignKey
+ALTER TABLE "res_replies" ADD CONSTRAINT "res_replies_reply_id_fkey" FOREIGN KEY ("reply_id") REFERENCES "reses"("id") ON DELETE NO ACTION ON UPDATE NO ACTION;
+
+-- 既存の返信先を返信グラフに移す
+INSERT INTO "res_replies" ("res_id", "reply_id")
+SELECT "id", "reply_id" FROM "reses" WHERE "reply_id" IS NOT NULL;
+
+-- AlterTable
+ALTER TABLE "reses" ADD COLUMN "reply_count" INTEGER NOT NULL DEFAULT 0;
+
+UPDATE "reses" SET "reply_count" = (SELECT COUNT(*) FROM "res_replies" WHERE "res_replies"."reply_id" = "reses"."id");
@@ -0,0 +1,9 @@
+-- AlterTable
+ALTER TABLE "reses" ADD COLUMN "next_topic_id" VARCHAR(64),
+ADD COLUMN "prev_topic_id" VARCHAR(64);
+
+-- AddForeignKey
+ALTER TABLE "reses" ADD CONSTRAINT "reses_next_topic_id_fkey" FOREIGN KEY ("next_topic_id") REFERENCES "topics"("id") ON DELETE NO ACTION ON UPDATE NO ACTION;
+
+-- AddForeignKey
+ALTER TABLE "reses" ADD CONSTRAINT "reses_prev_topic_id_fkey" FOREIGN KEY ("prev_topic_id") REFERENCES "topics"("id") ON DELETE NO ACTION ON UPDATE NO ACTION;
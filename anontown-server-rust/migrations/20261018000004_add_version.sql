@@ -0,0 +1,8 @@
+-- AlterTable
+ALTER TABLE "topics" ADD COLUMN "version" INTEGER NOT NULL DEFAULT 0;
+
+-- AlterTable
+ALTER TABLE "users" ADD COLUMN "version" INTEGER NOT NULL DEFAULT 0;
+
+-- AlterTable
+ALTER TABLE "reses" ADD COLUMN "version" INTEGER NOT NULL DEFAULT 0;
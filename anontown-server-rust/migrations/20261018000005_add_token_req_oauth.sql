@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "tokenReqs" ADD COLUMN "redirectUri" TEXT,
+ADD COLUMN "codeChallenge" TEXT;
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "tokens" ADD COLUMN "scopes" TEXT[] NOT NULL DEFAULT ARRAY[]::TEXT[];
+
+-- 既存の一般トークンは今まで通り全ての操作をできるようにする
+UPDATE "tokens" SET "scopes" = ARRAY['read', 'post_res', 'create_topic', 'vote', 'storage', 'profile'] WHERE "type" = 'general';
@@ -0,0 +1,13 @@
+-- AlterTable
+ALTER TABLE "tokens" ADD COLUMN "refresh_expires_at" TIMESTAMPTZ(3),
+ADD COLUMN "family_id" VARCHAR(64),
+ADD COLUMN "rotated_at" TIMESTAMPTZ(3);
+
+-- 既存のトークンはそれぞれ別のファミリーとし、リフレッシュトークンの期限は今から30日にする
+UPDATE "tokens" SET "family_id" = "id", "refresh_expires_at" = now() + interval '30 days';
+
+ALTER TABLE "tokens" ALTER COLUMN "refresh_expires_at" SET NOT NULL,
+ALTER COLUMN "family_id" SET NOT NULL;
+
+-- CreateIndex
+CREATE INDEX "tokens_family_id_idx" ON "tokens"("family_id");
@@ -0,0 +1,9 @@
+-- AlterTable
+ALTER TABLE "tokens" ADD COLUMN "ip" VARCHAR(64),
+ADD COLUMN "user_agent" TEXT,
+ADD COLUMN "last_used_at" TIMESTAMPTZ(3);
+
+-- 既存のトークンは作成日時を最終使用日時とする
+UPDATE "tokens" SET "last_used_at" = "created_at";
+
+ALTER TABLE "tokens" ALTER COLUMN "last_used_at" SET NOT NULL;
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "tokenReqs" ADD COLUMN "pairing" BOOLEAN NOT NULL DEFAULT false;
+
+-- CreateIndex
+CREATE INDEX "tokenReqs_key_pairing_idx" ON "tokenReqs"("key") WHERE "pairing";
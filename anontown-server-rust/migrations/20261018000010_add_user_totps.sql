@@ -0,0 +1,16 @@
+-- CreateTable
+CREATE TABLE "user_totps" (
+    "user_id" VARCHAR(64) NOT NULL,
+    "secret" BYTEA NOT NULL,
+    "enabled" BOOLEAN NOT NULL,
+    "last_used_step" BIGINT,
+    "recovery_code_hashes" TEXT[] NOT NULL,
+    "created_at" TIMESTAMPTZ(3) NOT NULL,
+    "updated_at" TIMESTAMPTZ(3) NOT NULL,
+    "version" INTEGER NOT NULL DEFAULT 0,
+
+    CONSTRAINT "user_totps_pkey" PRIMARY KEY ("user_id")
+);
+
+-- AddForeignKey
+ALTER TABLE "user_totps" ADD CONSTRAINT "user_totps_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES "users"("id") ON DELETE CASCADE ON UPDATE NO ACTION;
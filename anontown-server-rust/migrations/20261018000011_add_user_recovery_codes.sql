@@ -0,0 +1,11 @@
+-- CreateTable
+CREATE TABLE "user_recovery_codes" (
+    "user_id" VARCHAR(64) NOT NULL,
+    "code_hash" VARCHAR(64) NOT NULL,
+    "created_at" TIMESTAMPTZ(3) NOT NULL,
+
+    CONSTRAINT "user_recovery_codes_pkey" PRIMARY KEY ("user_id","code_hash")
+);
+
+-- AddForeignKey
+ALTER TABLE "user_recovery_codes" ADD CONSTRAINT "user_recovery_codes_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES "users"("id") ON DELETE CASCADE ON UPDATE NO ACTION;
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "topics" ADD COLUMN "res_count" INTEGER NOT NULL DEFAULT 0;
+
+-- レスの採番と同じ文で数えるので、既存のトピックは今あるレスの数から始める
+UPDATE "topics" SET "res_count" = (SELECT COUNT(*) FROM "reses" WHERE "reses"."topic_id" = "topics"."id");
@@ -0,0 +1,8 @@
+-- AlterTable
+ALTER TABLE "tokenReqs" ADD COLUMN "failedAttempts" INTEGER NOT NULL DEFAULT 0;
+
+-- 引き継ぎコードは前半の4文字で探す
+DROP INDEX "tokenReqs_key_pairing_idx";
+
+-- CreateIndex
+CREATE INDEX "tokenReqs_key_selector_pairing_idx" ON "tokenReqs"(left("key", 4)) WHERE "pairing" AND "active";
@@ -0,0 +1,2 @@
+-- 今まで閲覧の権限で購読とプッシュ通知の登録ができていた一般トークンは、引き続きできるようにする
+UPDATE "tokens" SET "scopes" = array_append("scopes", 'subscribe') WHERE "type" = 'general' AND 'read' = ANY("scopes");
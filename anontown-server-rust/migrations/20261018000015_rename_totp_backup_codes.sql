@@ -0,0 +1,2 @@
+-- アカウントの回復コード(user_recovery_codes)と区別するため、二要素認証用のものはバックアップコードと呼ぶ
+ALTER TABLE "user_totps" RENAME COLUMN "recovery_code_hashes" TO "backup_code_hashes";
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "profiles" ADD COLUMN "version" INTEGER NOT NULL DEFAULT 0;
+
+-- AlterTable
+ALTER TABLE "storages" ADD COLUMN "version" INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- AlterTable
+-- 認可コードはトークンを作らずに、交換した時に作るトークンのクライアントと権限を持つ
+ALTER TABLE "tokenReqs" ADD COLUMN "clientId" VARCHAR(64),
+ADD COLUMN "scopes" TEXT[] NOT NULL DEFAULT ARRAY[]::TEXT[];
@@ -1,14 +1,35 @@
 use crate::{AuthToken, AuthTokenMaster, AtError, AtResult};
 use crate::ports::AuthContainer;
+use crate::auth::TokenScope;
 use std::option::Option;
 
 pub struct AuthContainerImpl {
     token: Option<AuthToken>,
+    admin: bool,
 }
 
 impl AuthContainerImpl {
     pub fn new() -> Self {
-        Self { token: None }
+        Self {
+            token: None,
+            admin: false,
+        }
+    }
+
+    pub fn with_token(token: Option<AuthToken>) -> Self {
+        Self {
+            token,
+            admin: false,
+        }
+    }
+
+    /// マスタートークンのユーザーが`admin_user_ids`に含まれていれば管理者として扱う
+    pub fn with_admins(mut self, admin_user_ids: &[String]) -> Self {
+        self.admin = match &self.token {
+            Some(AuthToken::Master(token)) => admin_user_ids.contains(&token.base.user),
+            _ => false,
+        };
+        self
     }
 }
 
@@ -35,4 +56,27 @@ impl AuthContainer for AuthContainerImpl {
             None => None,
         }
     }
-} 
\ No newline at end of file
+
+    fn require_scope(&self, scope: TokenScope) -> AtResult<()> {
+        if self.get_token()?.has_scope(scope) {
+            Ok(())
+        } else {
+            Err(AtError::Right(format!(
+                "トークンに{}の権限がありません",
+                scope.as_str()
+            )))
+        }
+    }
+
+    fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    fn require_admin(&self) -> AtResult<()> {
+        if self.admin {
+            Ok(())
+        } else {
+            Err(AtError::Right("管理者の権限がありません".to_string()))
+        }
+    }
+}
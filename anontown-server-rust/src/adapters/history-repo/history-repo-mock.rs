@@ -3,23 +3,37 @@ use chrono::Utc;
 use std::collections::HashMap;
 use crate::entities::History;
 use crate::ports::history::{HistoryPort, HistoryQuery, DateQuery};
+use crate::adapters::mock_store::{MockSnapshot, MockStore};
 
+#[derive(Clone)]
 pub struct HistoryRepoMock {
-    histories: HashMap<String, History>,
+    histories: MockStore<HashMap<String, History>>,
 }
 
 impl HistoryRepoMock {
     pub fn new() -> Self {
         Self {
-            histories: HashMap::new(),
+            histories: MockStore::new(HashMap::new()),
         }
     }
 }
 
+impl MockSnapshot for HistoryRepoMock {
+    type Snapshot = HashMap<String, History>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        self.histories.snapshot()
+    }
+
+    fn restore(&self, snapshot: Self::Snapshot) {
+        self.histories.restore(snapshot);
+    }
+}
+
 #[async_trait]
 impl HistoryPort for HistoryRepoMock {
     async fn find_by_id(&self, id: &str) -> Result<Option<History>, Box<dyn std::error::Error>> {
-        Ok(self.histories.get(id).cloned())
+        Ok(self.histories.lock().unwrap().get(id).cloned())
     }
 
     async fn find_by_topic_id(
@@ -30,6 +44,8 @@ impl HistoryPort for HistoryRepoMock {
     ) -> Result<Vec<History>, Box<dyn std::error::Error>> {
         let mut histories: Vec<History> = self
             .histories
+            .lock()
+            .unwrap()
             .values()
             .filter(|history| history.topic_id == topic_id)
             .cloned()
@@ -49,6 +65,8 @@ impl HistoryPort for HistoryRepoMock {
     ) -> Result<Vec<History>, Box<dyn std::error::Error>> {
         let mut histories: Vec<History> = self
             .histories
+            .lock()
+            .unwrap()
             .values()
             .filter(|history| history.user_id == user_id)
             .cloned()
@@ -61,13 +79,13 @@ impl HistoryPort for HistoryRepoMock {
     }
 
     async fn create(&self, history: &History) -> Result<(), Box<dyn std::error::Error>> {
-        self.histories.insert(history.id.clone(), history.clone());
+        self.histories.lock().unwrap().insert(history.id.clone(), history.clone());
         Ok(())
     }
 
     async fn update(&self, history: &History) -> Result<(), Box<dyn std::error::Error>> {
-        if self.histories.contains_key(&history.id) {
-            self.histories.insert(history.id.clone(), history.clone());
+        if self.histories.lock().unwrap().contains_key(&history.id) {
+            self.histories.lock().unwrap().insert(history.id.clone(), history.clone());
             Ok(())
         } else {
             Err("History not found".into())
@@ -75,14 +93,14 @@ impl HistoryPort for HistoryRepoMock {
     }
 
     async fn find_one(&mut self, id: &str) -> Result<History, Box<dyn std::error::Error>> {
-        self.histories
+        self.histories.lock().unwrap()
             .get(id)
             .cloned()
             .ok_or_else(|| "History not found".into())
     }
 
     async fn find(&mut self, query: &HistoryQuery, limit: i32) -> Result<Vec<History>, Box<dyn std::error::Error>> {
-        let mut histories = self.histories.values().cloned().collect::<Vec<_>>();
+        let mut histories = self.histories.lock().unwrap().values().cloned().collect::<Vec<_>>();
 
         // IDでフィルタリング
         if let Some(ids) = &query.id {
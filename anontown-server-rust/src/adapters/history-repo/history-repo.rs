@@ -1,16 +1,21 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use sqlx::PgPool;
+use crate::adapters::pg_db::PgDb;
 use crate::entities::History;
 use crate::ports::history::{HistoryPort, HistoryQuery, DateQuery};
 
 pub struct HistoryRepo {
-    pool: PgPool,
+    db: PgDb,
 }
 
 impl HistoryRepo {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_db(PgDb::new(pool))
+    }
+
+    pub fn with_db(db: PgDb) -> Self {
+        Self { db }
     }
 }
 
@@ -30,7 +35,7 @@ impl HistoryPort for HistoryRepo {
             history.hash,
             history.user_id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         // タグの挿入
@@ -44,7 +49,7 @@ impl HistoryPort for HistoryRepo {
                 i as i32,
                 tag
             )
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
         }
 
@@ -60,7 +65,7 @@ impl HistoryPort for HistoryRepo {
             "#,
             history.id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         // 履歴を更新
@@ -78,7 +83,7 @@ impl HistoryPort for HistoryRepo {
             history.user_id,
             history.id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         // 新しいタグを挿入
@@ -92,7 +97,7 @@ impl HistoryPort for HistoryRepo {
                 i as i32,
                 tag
             )
-            .execute(&self.pool)
+            .execute(&mut *self.db.acquire().await?)
             .await?;
         }
 
@@ -112,7 +117,7 @@ impl HistoryPort for HistoryRepo {
             "#,
             id
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(history)
@@ -190,7 +195,7 @@ impl HistoryPort for HistoryRepo {
             params[2] as DateTime<Utc>,
             limit
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(histories)
@@ -206,7 +211,7 @@ impl HistoryPort for HistoryRepo {
             "#,
             id
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(history)
@@ -231,7 +236,7 @@ impl HistoryPort for HistoryRepo {
             limit,
             offset
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(histories)
@@ -256,7 +261,7 @@ impl HistoryPort for HistoryRepo {
             limit,
             offset
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(histories)
@@ -276,7 +281,7 @@ impl HistoryPort for HistoryRepo {
             history.topic_id,
             history.res_id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -293,7 +298,7 @@ impl HistoryPort for HistoryRepo {
             history.updated_at,
             history.id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use crate::ports::ip::IpPort;
 
+#[derive(Clone)]
 pub struct IpContainer {
     ip: Option<String>,
 }
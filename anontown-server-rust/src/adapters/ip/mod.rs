@@ -0,0 +1,3 @@
+pub mod ip_container;
+
+pub use ip_container::IpContainer;
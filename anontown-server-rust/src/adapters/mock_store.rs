@@ -0,0 +1,43 @@
+use std::sync::{Arc, LockResult, Mutex, MutexGuard};
+
+/// モックのリポジトリのデータ
+///
+/// 複製しても同じデータを指すので、ポートに渡したモックと`UnitOfWorkMockImpl`に渡したモックで
+/// 書き込みが共有される。
+pub struct MockStore<T>(Arc<Mutex<T>>);
+
+impl<T> MockStore<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(Mutex::new(value)))
+    }
+
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        self.0.lock()
+    }
+}
+
+impl<T> Clone for MockStore<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// `UnitOfWorkMockImpl`がロールバックするためにモックのデータを保存して戻す
+pub trait MockSnapshot {
+    type Snapshot: Send;
+
+    fn snapshot(&self) -> Self::Snapshot;
+    fn restore(&self, snapshot: Self::Snapshot);
+}
+
+impl<T: Clone + Send> MockSnapshot for MockStore<T> {
+    type Snapshot = T;
+
+    fn snapshot(&self) -> T {
+        self.lock().unwrap().clone()
+    }
+
+    fn restore(&self, snapshot: T) {
+        *self.lock().unwrap() = snapshot;
+    }
+}
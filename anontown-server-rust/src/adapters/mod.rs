@@ -9,12 +9,35 @@ pub mod topic_repo;
 pub use topic_repo::topic_repo::TopicRepo;
 pub use topic_repo::topic_repo_mock::TopicRepoMock;
 
+pub mod res_repo;
+pub use res_repo::res_repo::ResRepo;
+pub use res_repo::res_repo_mock::ResRepoMock;
+
 pub mod history_repo;
 pub mod profile_repo;
 pub mod client_repo;
 pub mod token_repo;
 pub mod storage_repo;
 pub mod auth_container_impl;
+pub mod clock;
+pub mod object_id_generator;
+#[cfg(test)]
+pub mod seq_id_generator;
+pub mod rate_limiter_impl;
+pub mod rate_limiter_mock_impl;
+pub mod pg_db;
+pub mod mock_store;
+pub mod unit_of_work_impl;
+pub mod unit_of_work_mock_impl;
+pub mod topic_event_bus_impl;
+pub mod topic_event_bus_mock_impl;
+pub mod token_req_repo_impl;
+pub mod token_req_repo_mock_impl;
+pub mod user_totp_repo_impl;
+pub mod user_totp_repo_mock_impl;
+pub mod recovery_code_repo_impl;
+pub mod recovery_code_repo_mock_impl;
+pub mod ip;
 
 pub use history_repo::history_repo::HistoryRepo;
 pub use history_repo::history_repo_mock::HistoryRepoMock;
@@ -27,6 +50,19 @@ pub use token_repo::token_repo_mock::TokenRepoMock;
 pub use storage_repo::storage_repo::StorageRepo;
 pub use storage_repo::storage_repo_mock::StorageRepoMock;
 pub use auth_container_impl::AuthContainerImpl;
+pub use rate_limiter_impl::RateLimiterImpl;
+pub use rate_limiter_mock_impl::RateLimiterMockImpl;
+pub use unit_of_work_impl::UnitOfWorkImpl;
+pub use unit_of_work_mock_impl::UnitOfWorkMockImpl;
+pub use topic_event_bus_impl::TopicEventBusImpl;
+pub use topic_event_bus_mock_impl::TopicEventBusMockImpl;
+pub use token_req_repo_impl::TokenReqRepoImpl;
+pub use token_req_repo_mock_impl::TokenReqRepoMockImpl;
+pub use user_totp_repo_impl::UserTotpRepoImpl;
+pub use user_totp_repo_mock_impl::UserTotpRepoMockImpl;
+pub use recovery_code_repo_impl::RecoveryCodeRepoImpl;
+pub use recovery_code_repo_mock_impl::RecoveryCodeRepoMockImpl;
+pub use ip::IpContainer;
 
 mod token_repo_impl;
 mod token_repo_mock_impl;
@@ -0,0 +1,118 @@
+use sqlx::pool::PoolConnection;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+use crate::entities::topic_event::TopicEvent;
+use crate::ports::topic_event_bus::TopicEventBus;
+
+/// Postgresのリポジトリが共有する接続先
+///
+/// トランザクション外ではプールから都度接続を借り、`UnitOfWorkImpl`の中では
+/// 同じトランザクションを全てのリポジトリで使い回す。
+#[derive(Clone)]
+pub struct PgDb {
+    pool: PgPool,
+    tx: Option<Arc<tokio::sync::Mutex<Transaction<'static, Postgres>>>>,
+    // コミットされるまで配信しないイベント
+    pending_events: Arc<Mutex<Vec<TopicEvent>>>,
+}
+
+pub enum PgConn<'a> {
+    Pool(PoolConnection<Postgres>),
+    Tx(tokio::sync::MutexGuard<'a, Transaction<'static, Postgres>>),
+}
+
+impl Deref for PgConn<'_> {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &PgConnection {
+        match self {
+            PgConn::Pool(conn) => conn,
+            PgConn::Tx(tx) => tx,
+        }
+    }
+}
+
+impl DerefMut for PgConn<'_> {
+    fn deref_mut(&mut self) -> &mut PgConnection {
+        match self {
+            PgConn::Pool(conn) => conn,
+            PgConn::Tx(tx) => tx,
+        }
+    }
+}
+
+impl PgDb {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            tx: None,
+            pending_events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub async fn begin(pool: PgPool) -> Result<Self, sqlx::Error> {
+        let tx = pool.begin().await?;
+        Ok(Self {
+            pool,
+            tx: Some(Arc::new(tokio::sync::Mutex::new(tx))),
+            pending_events: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    pub async fn acquire(&self) -> Result<PgConn<'_>, sqlx::Error> {
+        match &self.tx {
+            Some(tx) => Ok(PgConn::Tx(tx.lock().await)),
+            None => Ok(PgConn::Pool(self.pool.acquire().await?)),
+        }
+    }
+
+    /// トランザクション中ならコミットまで遅らせ、そうでなければすぐに配信する
+    ///
+    /// ロールバックされた書き込みを購読者に通知しないため
+    pub async fn publish(
+        &self,
+        topic_event_bus: &impl TopicEventBus,
+        event: TopicEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.tx.is_some() {
+            self.pending_events.lock().unwrap().push(event);
+            return Ok(());
+        }
+
+        topic_event_bus.publish(&event).await?;
+        Ok(())
+    }
+
+    /// トランザクションをコミットし、溜めていたイベントを配信する
+    ///
+    /// このDBを共有しているリポジトリは全て破棄されている必要がある
+    pub async fn commit(
+        self,
+        topic_event_bus: &impl TopicEventBus,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(tx) = self.tx {
+            let tx = Arc::try_unwrap(tx)
+                .map_err(|_| "トランザクションがまだ使われています")?
+                .into_inner();
+            tx.commit().await?;
+        }
+
+        let events = std::mem::take(&mut *self.pending_events.lock().unwrap());
+        for event in events {
+            topic_event_bus.publish(&event).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn rollback(self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(tx) = self.tx {
+            let tx = Arc::try_unwrap(tx)
+                .map_err(|_| "トランザクションがまだ使われています")?
+                .into_inner();
+            tx.rollback().await?;
+        }
+        Ok(())
+    }
+}
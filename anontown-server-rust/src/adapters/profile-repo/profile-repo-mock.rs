@@ -1,46 +1,66 @@
 use async_trait::async_trait;
 use std::collections::HashMap;
+use crate::at_error::AtError;
 use crate::entities::Profile;
 use crate::ports::profile::{ProfilePort, ProfileQuery};
+use crate::adapters::mock_store::{MockSnapshot, MockStore};
 
+#[derive(Clone)]
 pub struct ProfileRepoMock {
-    profiles: HashMap<String, Profile>,
+    profiles: MockStore<HashMap<String, Profile>>,
 }
 
 impl ProfileRepoMock {
     pub fn new() -> Self {
         Self {
-            profiles: HashMap::new(),
+            profiles: MockStore::new(HashMap::new()),
         }
     }
 }
 
+impl MockSnapshot for ProfileRepoMock {
+    type Snapshot = HashMap<String, Profile>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        self.profiles.snapshot()
+    }
+
+    fn restore(&self, snapshot: Self::Snapshot) {
+        self.profiles.restore(snapshot);
+    }
+}
+
 #[async_trait]
 impl ProfilePort for ProfileRepoMock {
     async fn find_by_id(&self, id: &str) -> Result<Option<Profile>, Box<dyn std::error::Error>> {
-        Ok(self.profiles.get(id).cloned())
+        Ok(self.profiles.lock().unwrap().get(id).cloned())
     }
 
     async fn find_by_user_id(&self, user_id: &str) -> Result<Option<Profile>, Box<dyn std::error::Error>> {
-        Ok(self.profiles.values().find(|p| p.user_id == user_id).cloned())
+        Ok(self.profiles.lock().unwrap().values().find(|p| p.user_id == user_id).cloned())
     }
 
     async fn create(&self, profile: &Profile) -> Result<(), Box<dyn std::error::Error>> {
-        self.profiles.insert(profile.id.clone(), profile.clone());
+        self.profiles.lock().unwrap().insert(profile.id.clone(), profile.clone());
         Ok(())
     }
 
     async fn update(&self, profile: &Profile) -> Result<(), Box<dyn std::error::Error>> {
-        if self.profiles.contains_key(&profile.id) {
-            self.profiles.insert(profile.id.clone(), profile.clone());
-            Ok(())
-        } else {
-            Err("Profile not found".into())
+        let mut profiles = self.profiles.lock().unwrap();
+        match profiles.get(&profile.id) {
+            Some(current) if current.version == profile.version => {
+                let mut profile = profile.clone();
+                profile.version += 1;
+                profiles.insert(profile.id.clone(), profile);
+                Ok(())
+            }
+            Some(_) => Err(Box::new(AtError::Conflict("プロフィールが他の操作で更新されました".to_string()))),
+            None => Err("Profile not found".into()),
         }
     }
 
     async fn find(&mut self, query: &ProfileQuery) -> Result<Vec<Profile>, Box<dyn std::error::Error>> {
-        let mut profiles = self.profiles.values().cloned().collect::<Vec<_>>();
+        let mut profiles = self.profiles.lock().unwrap().values().cloned().collect::<Vec<_>>();
 
         // IDでフィルタリング
         if let Some(ids) = &query.id {
@@ -3,6 +3,7 @@ use tokio;
 
 use crate::adapters::profile_repo::profile_repo::ProfileRepo;
 use crate::adapters::profile_repo::profile_repo_mock::ProfileRepoMock;
+use crate::at_error::AtError;
 use crate::entities::Profile;
 
 #[tokio::test]
@@ -15,6 +16,7 @@ async fn test_profile_repo_mock() {
         description: "Test Description".to_string(),
         created_at: Utc::now(),
         updated_at: Utc::now(),
+        version: 0,
     };
 
     // Test create
@@ -47,6 +49,7 @@ async fn test_profile_repo() {
         description: "Test Description".to_string(),
         created_at: Utc::now(),
         updated_at: Utc::now(),
+        version: 0,
     };
 
     // Test create
@@ -67,4 +70,32 @@ async fn test_profile_repo() {
 
     let found = repo.find_by_id(&profile.id).await.unwrap().unwrap();
     assert_eq!(found.name, updated_profile.name);
-} 
\ No newline at end of file
+} 
+
+#[tokio::test]
+async fn test_profile_repo_mock_version() {
+    let repo = ProfileRepoMock::new();
+    let profile = Profile {
+        id: "profile1".to_string(),
+        user_id: "user1".to_string(),
+        name: "Test Profile".to_string(),
+        description: "Test Description".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        version: 0,
+    };
+    repo.create(&profile).await.unwrap();
+
+    repo.update(&profile).await.unwrap();
+    let updated = repo.find_by_id(&profile.id).await.unwrap().unwrap();
+    assert_eq!(updated.version, profile.version + 1);
+
+    // 古いバージョンのまま保存しようとすると競合する
+    let result = repo.update(&profile).await;
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<AtError>(),
+        Some(AtError::Conflict(_))
+    ));
+
+    repo.update(&updated).await.unwrap();
+}
@@ -0,0 +1,122 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+use crate::at_error::{AtError, AtResult};
+use crate::entities::rate_limit_rule::{RateLimitAction, RateLimitRule};
+use crate::ports::rate_limiter::RateLimiter;
+
+// 書き込み日時をスコアにしたsorted setで数える
+// KEYS[1]: sorted set, KEYS[2]: メンバーを一意にするための連番
+// ARGV[1]: 現在時刻(ms), ARGV[2..]: ウィンドウ(ms)と上限のペア
+// 上限に達したルールがあれば{-1, ルールの番号}を返す
+const ACQUIRE_SCRIPT: &str = r#"
+local now = tonumber(ARGV[1])
+local longest = 0
+local counts = {}
+for i = 2, #ARGV, 2 do
+    local window = tonumber(ARGV[i])
+    local max = tonumber(ARGV[i + 1])
+    local count = redis.call('ZCOUNT', KEYS[1], '(' .. (now - window), '+inf')
+    if count >= max then
+        return {-1, (i - 2) / 2}
+    end
+    counts[#counts + 1] = count + 1
+    if window > longest then
+        longest = window
+    end
+end
+local seq = redis.call('INCR', KEYS[2])
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - longest)
+redis.call('ZADD', KEYS[1], now, now .. ':' .. seq)
+redis.call('PEXPIRE', KEYS[1], longest)
+redis.call('PEXPIRE', KEYS[2], longest)
+return counts
+"#;
+
+// 同じ時刻に記録したメンバーのうち1つを消す。どれを消しても数は同じ
+// KEYS[1]: sorted set, ARGV[1]: 記録した時刻(ms)
+const RELEASE_SCRIPT: &str = r#"
+local members = redis.call('ZRANGEBYSCORE', KEYS[1], ARGV[1], ARGV[1], 'LIMIT', 0, 1)
+if #members > 0 then
+    redis.call('ZREM', KEYS[1], members[1])
+end
+return #members
+"#;
+
+pub struct RateLimiterImpl {
+    redis: Arc<redis::Client>,
+}
+
+impl RateLimiterImpl {
+    pub fn new(redis: Arc<redis::Client>) -> Self {
+        Self { redis }
+    }
+
+    fn key(user_id: &str, action: RateLimitAction) -> String {
+        format!("rate_limit:{}:{}", action.name(), user_id)
+    }
+}
+
+#[async_trait]
+impl RateLimiter for RateLimiterImpl {
+    async fn acquire(
+        &self,
+        user_id: &str,
+        action: RateLimitAction,
+        rules: &[RateLimitRule],
+        now: DateTime<Utc>,
+    ) -> AtResult<Vec<i32>> {
+        if rules.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let key = Self::key(user_id, action);
+        let mut script = redis::Script::new(ACQUIRE_SCRIPT).prepare_invoke();
+        script
+            .key(&key)
+            .key(format!("{}:seq", key))
+            .arg(now.timestamp_millis());
+        for rule in rules {
+            script
+                .arg(rule.window.duration().num_milliseconds())
+                .arg(rule.max);
+        }
+
+        let mut conn = self
+            .redis
+            .get_async_connection()
+            .await
+            .map_err(|e| AtError::Internal(e.into()))?;
+        let result: Vec<i64> = script
+            .invoke_async(&mut conn)
+            .await
+            .map_err(|e| AtError::Internal(e.into()))?;
+
+        match result[..] {
+            [-1, index] => Err(AtError::Prerequisite(rules[index as usize].exceeded_message())),
+            _ => Ok(result.into_iter().map(|count| count as i32).collect()),
+        }
+    }
+
+    async fn release(
+        &self,
+        user_id: &str,
+        action: RateLimitAction,
+        acquired_at: DateTime<Utc>,
+    ) -> AtResult<()> {
+        let mut conn = self
+            .redis
+            .get_async_connection()
+            .await
+            .map_err(|e| AtError::Internal(e.into()))?;
+        let _: i64 = redis::Script::new(RELEASE_SCRIPT)
+            .key(Self::key(user_id, action))
+            .arg(acquired_at.timestamp_millis())
+            .invoke_async(&mut conn)
+            .await
+            .map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+}
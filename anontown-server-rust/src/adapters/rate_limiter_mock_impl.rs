@@ -0,0 +1,78 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::at_error::{AtError, AtResult};
+use crate::entities::rate_limit_rule::{RateLimitAction, RateLimitRule};
+use crate::ports::rate_limiter::RateLimiter;
+
+pub struct RateLimiterMockImpl {
+    // (ユーザー, 書き込みの種類)ごとの書き込み日時
+    history: Mutex<HashMap<(String, RateLimitAction), Vec<DateTime<Utc>>>>,
+}
+
+impl RateLimiterMockImpl {
+    pub fn new() -> Self {
+        Self {
+            history: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimiter for RateLimiterMockImpl {
+    async fn acquire(
+        &self,
+        user_id: &str,
+        action: RateLimitAction,
+        rules: &[RateLimitRule],
+        now: DateTime<Utc>,
+    ) -> AtResult<Vec<i32>> {
+        let mut history = self.history.lock().unwrap();
+        let dates = history.entry((user_id.to_string(), action)).or_default();
+
+        let mut counts = Vec::new();
+        for rule in rules {
+            let from = now - rule.window.duration();
+            let count = dates.iter().filter(|date| **date > from).count() as i32;
+            if count >= rule.max {
+                return Err(AtError::Prerequisite(rule.exceeded_message()));
+            }
+            counts.push(count + 1);
+        }
+
+        if let Some(longest) = rules.iter().map(|rule| rule.window.duration()).max() {
+            dates.retain(|date| *date > now - longest);
+        }
+        dates.push(now);
+
+        Ok(counts)
+    }
+
+    async fn release(
+        &self,
+        user_id: &str,
+        action: RateLimitAction,
+        acquired_at: DateTime<Utc>,
+    ) -> AtResult<()> {
+        let mut history = self.history.lock().unwrap();
+        if let Some(dates) = history.get_mut(&(user_id.to_string(), action)) {
+            if let Some(i) = dates.iter().position(|date| *date == acquired_at) {
+                dates.remove(i);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::rate_limiter::run_rate_limiter_laws;
+
+    #[tokio::test]
+    async fn test_rate_limiter_mock_impl() {
+        run_rate_limiter_laws(&RateLimiterMockImpl::new()).await;
+    }
+}
@@ -0,0 +1,100 @@
+use async_trait::async_trait;
+use sqlx::{Connection, PgPool};
+
+use crate::adapters::pg_db::{PgConn, PgDb};
+use crate::at_error::{AtError, AtResult};
+use crate::entities::recovery_code::RecoveryCode;
+use crate::ports::RecoveryCodeRepo;
+
+pub struct RecoveryCodeRepoImpl {
+    db: PgDb,
+}
+
+impl RecoveryCodeRepoImpl {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_db(PgDb::new(pool))
+    }
+
+    pub fn with_db(db: PgDb) -> Self {
+        Self { db }
+    }
+
+    async fn acquire(&self) -> AtResult<PgConn<'_>> {
+        self.db.acquire().await.map_err(|e| AtError::Internal(e.into()))
+    }
+}
+
+#[async_trait]
+impl RecoveryCodeRepo for RecoveryCodeRepoImpl {
+    async fn count(&self, user_id: &str) -> AtResult<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM user_recovery_codes
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_one(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(count)
+    }
+
+    async fn replace_all(&self, user_id: &str, codes: &[RecoveryCode]) -> AtResult<()> {
+        let mut conn = self.acquire().await?;
+        // UnitOfWorkの中ではセーブポイントになる
+        let mut tx = conn.begin().await.map_err(|e| AtError::Internal(e.into()))?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM user_recovery_codes
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        for code in codes {
+            sqlx::query!(
+                r#"
+                INSERT INTO user_recovery_codes (user_id, code_hash, created_at)
+                VALUES ($1, $2, $3)
+                "#,
+                code.user_id,
+                code.code_hash,
+                code.created_at
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| AtError::Internal(e.into()))?;
+        }
+
+        tx.commit().await.map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+
+    async fn consume(&self, user_id: &str, code_hash: &str) -> AtResult<()> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM user_recovery_codes
+            WHERE user_id = $1 AND code_hash = $2
+            "#,
+            user_id,
+            code_hash
+        )
+        .execute(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(AtError::NotFound("回復コードが存在しません".to_string()));
+        }
+
+        Ok(())
+    }
+}
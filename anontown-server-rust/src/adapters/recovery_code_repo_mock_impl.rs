@@ -0,0 +1,76 @@
+use async_trait::async_trait;
+
+use crate::adapters::mock_store::{MockSnapshot, MockStore};
+use crate::at_error::{AtError, AtResult};
+use crate::entities::recovery_code::RecoveryCode;
+use crate::ports::RecoveryCodeRepo;
+
+#[derive(Clone)]
+pub struct RecoveryCodeRepoMockImpl {
+    codes: MockStore<Vec<RecoveryCode>>,
+}
+
+impl RecoveryCodeRepoMockImpl {
+    pub fn new() -> Self {
+        Self {
+            codes: MockStore::new(Vec::new()),
+        }
+    }
+}
+
+impl MockSnapshot for RecoveryCodeRepoMockImpl {
+    type Snapshot = Vec<RecoveryCode>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        self.codes.snapshot()
+    }
+
+    fn restore(&self, snapshot: Self::Snapshot) {
+        self.codes.restore(snapshot);
+    }
+}
+
+#[async_trait]
+impl RecoveryCodeRepo for RecoveryCodeRepoMockImpl {
+    async fn count(&self, user_id: &str) -> AtResult<i64> {
+        Ok(self
+            .codes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|code| code.user_id == user_id)
+            .count() as i64)
+    }
+
+    async fn replace_all(&self, user_id: &str, codes: &[RecoveryCode]) -> AtResult<()> {
+        let mut stored = self.codes.lock().unwrap();
+        stored.retain(|code| code.user_id != user_id);
+        stored.extend_from_slice(codes);
+        Ok(())
+    }
+
+    async fn consume(&self, user_id: &str, code_hash: &str) -> AtResult<()> {
+        let mut codes = self.codes.lock().unwrap();
+        match codes
+            .iter()
+            .position(|code| code.user_id == user_id && code.code_hash == code_hash)
+        {
+            Some(index) => {
+                codes.remove(index);
+                Ok(())
+            }
+            None => Err(AtError::NotFound("回復コードが存在しません".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::recovery_code_repo::run_recovery_code_repo_laws;
+
+    #[tokio::test]
+    async fn test_recovery_code_repo_mock_impl() {
+        run_recovery_code_repo_laws(&RecoveryCodeRepoMockImpl::new()).await;
+    }
+}
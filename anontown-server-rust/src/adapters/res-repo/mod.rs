@@ -0,0 +1,2 @@
+pub mod res_repo;
+pub mod res_repo_mock;
@@ -1,26 +1,49 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use futures::{Stream, StreamExt};
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use crate::at_error::AtError;
 use crate::entities::{Res, ResType, ResDeleteFlag, ResNormal, ResHistory, ResTopic, ResFork};
-use crate::ports::res::ResPort;
+use crate::entities::search_query::{SearchHit, SearchQuery};
+use crate::ports::res::{ResPort, ResSearchQuery};
+use crate::adapters::mock_store::{MockSnapshot, MockStore};
 
+const FULL_MESSAGE: &str = "トピックが落ちているかレス数が上限に達しています";
+
+#[derive(Clone)]
 pub struct ResRepoMock {
-    reses: HashMap<String, Res>,
+    reses: MockStore<HashMap<String, Res>>,
+    // (返信元, 返信先)
+    replies: MockStore<Vec<(String, String)>>,
 }
 
 impl ResRepoMock {
     pub fn new() -> Self {
         Self {
-            reses: HashMap::new(),
+            reses: MockStore::new(HashMap::new()),
+            replies: MockStore::new(Vec::new()),
         }
     }
 }
 
+impl MockSnapshot for ResRepoMock {
+    type Snapshot = (HashMap<String, Res>, Vec<(String, String)>);
+
+    fn snapshot(&self) -> Self::Snapshot {
+        (self.reses.snapshot(), self.replies.snapshot())
+    }
+
+    fn restore(&self, (reses, replies): Self::Snapshot) {
+        self.reses.restore(reses);
+        self.replies.restore(replies);
+    }
+}
+
 #[async_trait]
 impl ResPort for ResRepoMock {
     async fn find_by_id(&self, id: &str) -> Result<Option<Res>, Box<dyn std::error::Error>> {
-        Ok(self.reses.get(id).cloned())
+        Ok(self.reses.lock().unwrap().get(id).cloned())
     }
 
     async fn find_by_topic_id(
@@ -31,6 +54,8 @@ impl ResPort for ResRepoMock {
     ) -> Result<Vec<Res>, Box<dyn std::error::Error>> {
         let mut reses: Vec<Res> = self
             .reses
+            .lock()
+            .unwrap()
             .values()
             .filter(|res| res.topic_id == topic_id)
             .cloned()
@@ -43,17 +68,20 @@ impl ResPort for ResRepoMock {
     }
 
     async fn find_by_reply_id(&self, reply_id: &str) -> Result<Vec<Res>, Box<dyn std::error::Error>> {
-        Ok(self.reses
-            .values()
-            .filter(|res| {
-                if let Res::Normal(normal) = res {
-                    normal.reply.as_ref().map_or(false, |reply| reply.get("res") == Some(reply_id))
-                } else {
-                    false
-                }
-            })
+        let all = self.reses.lock().unwrap();
+        let mut reses: Vec<Res> = self
+            .replies
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, to)| to == reply_id)
+            .filter_map(|(from, _)| all.get(from))
             .cloned()
-            .collect())
+            .collect();
+
+        reses.sort_by_key(|res| res.base().number());
+
+        Ok(reses)
     }
 
     async fn find_by_user_id(
@@ -64,6 +92,8 @@ impl ResPort for ResRepoMock {
     ) -> Result<Vec<Res>, Box<dyn std::error::Error>> {
         let mut reses: Vec<Res> = self
             .reses
+            .lock()
+            .unwrap()
             .values()
             .filter(|res| res.user_id == user_id)
             .cloned()
@@ -76,28 +106,145 @@ impl ResPort for ResRepoMock {
     }
 
     async fn find_by_hash(&self, hash: &str) -> Result<Option<Res>, Box<dyn std::error::Error>> {
-        Ok(self.reses
+        Ok(self.reses.lock().unwrap()
             .values()
             .find(|res| res.base().hash == hash)
             .cloned())
     }
 
-    async fn create(&self, res: &Res) -> Result<(), Box<dyn std::error::Error>> {
-        self.reses.insert(res.id.clone(), res.clone());
-        Ok(())
+    async fn find_by_topic_hash(&self, topic_id: &str, hash: &str) -> Result<Vec<Res>, Box<dyn std::error::Error>> {
+        let mut reses: Vec<Res> = self
+            .reses
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|res| res.base().topic_id() == topic_id && res.base().hash() == hash)
+            .cloned()
+            .collect();
+
+        reses.sort_by(|a, b| a.base().date().cmp(&b.base().date()));
+
+        Ok(reses)
+    }
+
+    async fn find_by_number_range(&self, topic_id: &str, from: i32, to: i32) -> Result<Vec<Res>, Box<dyn std::error::Error>> {
+        let mut reses: Vec<Res> = self
+            .reses
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|res| res.base().topic_id() == topic_id)
+            .filter(|res| from <= res.base().number() && res.base().number() <= to)
+            .cloned()
+            .collect();
+
+        reses.sort_by_key(|res| res.base().number());
+
+        Ok(reses)
+    }
+
+    async fn find_by_numbers(&self, topic_id: &str, numbers: &[i32]) -> Result<Vec<Res>, Box<dyn std::error::Error>> {
+        let mut reses: Vec<Res> = self
+            .reses
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|res| res.base().topic_id() == topic_id && numbers.contains(&res.base().number()))
+            .cloned()
+            .collect();
+
+        reses.sort_by_key(|res| res.base().number());
+
+        Ok(reses)
+    }
+
+    async fn add_replies(&self, res_id: &str, reply_ids: &[String]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut added = Vec::new();
+        for reply_id in reply_ids {
+            if self.replies.lock().unwrap().iter().any(|(from, to)| from == res_id && to == reply_id) {
+                continue;
+            }
+            if let Some(reply) = self.reses.lock().unwrap().get_mut(reply_id) {
+                reply.base_mut().add_reply_count();
+                self.replies.lock().unwrap().push((res_id.to_string(), reply_id.clone()));
+                added.push(reply_id.clone());
+            }
+        }
+
+        Ok(added)
+    }
+
+    async fn search(&self, query: &ResSearchQuery, limit: i32) -> Result<Vec<SearchHit<Res>>, Box<dyn std::error::Error>> {
+        let search_query = match SearchQuery::parse(&query.text) {
+            Some(search_query) => search_query,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut hits = self
+            .reses
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|res| query.topic.as_deref().map_or(true, |topic| res.base().topic_id() == topic))
+            .filter_map(|res| match res {
+                Res::Normal(normal) => search_query.hit(res.clone(), &normal.text),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| b.item.base().date().cmp(&a.item.base().date()))
+        });
+
+        Ok(hits.into_iter().take(limit as usize).collect())
+    }
+
+    async fn create(&self, res: &Res) -> Result<Res, Box<dyn std::error::Error>> {
+        let number = self
+            .reses
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|r| r.base().topic_id() == res.base().topic_id())
+            .map(|r| r.base().number())
+            .max()
+            .unwrap_or(0)
+            + 1;
+
+        let mut res = res.clone();
+        res.base_mut().set_number(number);
+        self.reses.lock().unwrap().insert(res.base().id().to_string(), res.clone());
+        Ok(res)
+    }
+
+    // モックはトピックを持たないので、落ちているかは確かめずにレス数だけを数える
+    async fn create_within_limit(&self, res: &Res, res_limit: i32) -> Result<Res, Box<dyn std::error::Error>> {
+        let count = self.count_by_topic_id(res.base().topic_id()).await?;
+        if count >= res_limit as i64 {
+            return Err(Box::new(AtError::Prerequisite(FULL_MESSAGE.to_string())));
+        }
+        self.create(res).await
     }
 
     async fn update(&self, res: &Res) -> Result<(), Box<dyn std::error::Error>> {
-        if self.reses.contains_key(&res.id) {
-            self.reses.insert(res.id.clone(), res.clone());
-            Ok(())
-        } else {
-            Err("Res not found".into())
+        let mut reses = self.reses.lock().unwrap();
+        match reses.get(&res.id) {
+            Some(current) if current.base().version() == res.base().version() => {
+                let mut res = res.clone();
+                res.base_mut().set_version(res.base().version() + 1);
+                reses.insert(res.id.clone(), res);
+                Ok(())
+            }
+            Some(_) => Err(Box::new(AtError::Conflict("レスが他の操作で更新されました".to_string()))),
+            None => Err("Res not found".into()),
         }
     }
 
     async fn update_delete_flag(&self, id: &str, delete_flag: ResDeleteFlag) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(res) = self.reses.get_mut(id) {
+        if let Some(res) = self.reses.lock().unwrap().get_mut(id) {
             if let Res::Normal(normal) = res {
                 normal.delete_flag = Some(delete_flag);
             }
@@ -106,7 +253,7 @@ impl ResPort for ResRepoMock {
     }
 
     async fn update_age(&self, id: &str, age: bool) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(res) = self.reses.get_mut(id) {
+        if let Some(res) = self.reses.lock().unwrap().get_mut(id) {
             if let Res::Normal(normal) = res {
                 normal.age = age;
             }
@@ -115,21 +262,21 @@ impl ResPort for ResRepoMock {
     }
 
     async fn count_by_type(&self, res_type: ResType) -> Result<i64, Box<dyn std::error::Error>> {
-        Ok(self.reses
+        Ok(self.reses.lock().unwrap()
             .values()
             .filter(|res| res.base().res_type == res_type)
             .count() as i64)
     }
 
     async fn count_by_topic_id(&self, topic_id: &str) -> Result<i64, Box<dyn std::error::Error>> {
-        Ok(self.reses
+        Ok(self.reses.lock().unwrap()
             .values()
             .filter(|res| res.base().topic_id == topic_id)
             .count() as i64)
     }
 
     async fn count_by_user_id(&self, user_id: &str) -> Result<i64, Box<dyn std::error::Error>> {
-        Ok(self.reses
+        Ok(self.reses.lock().unwrap()
             .values()
             .filter(|res| res.base().user_id == user_id)
             .count() as i64)
@@ -140,7 +287,7 @@ impl ResPort for ResRepoMock {
         topic_id: &str,
     ) -> Result<Box<dyn Stream<Item = Result<(Res, i64), Box<dyn std::error::Error>>> + Send + Unpin>, Box<dyn std::error::Error>> {
         let topic_id = topic_id.to_string();
-        let reses = self.reses.clone();
+        let reses = self.reses.lock().unwrap().clone();
 
         let stream = async_stream::stream! {
             while false {
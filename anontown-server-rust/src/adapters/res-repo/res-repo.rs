@@ -1,30 +1,115 @@
 use async_trait::async_trait;
 use chrono::Utc;
-use futures::Stream;
-use redis::AsyncCommands;
-use sqlx::PgPool;
+use futures::{Stream, StreamExt};
+use sqlx::{Connection, PgPool};
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use crate::adapters::pg_db::PgDb;
+use crate::adapters::TopicEventBusImpl;
+use crate::at_error::AtError;
 use crate::entities::Res;
-use crate::ports::res::ResPort;
+use crate::entities::search_query::{SearchHit, SearchQuery};
+use crate::entities::topic_event::TopicEvent;
+use crate::ports::res::{ResPort, ResSearchQuery};
+use crate::ports::topic_event_bus::TopicEventBus;
 
-const RES_PUBSUB_CHANNEL: &str = "res/add";
-
-#[derive(serde::Serialize)]
-struct ResPubSub {
-    id: String,
-    topic: String,
-    count: i64,
-}
+const CONFLICT_MESSAGE: &str = "レスが他の操作で更新されました";
+const FULL_MESSAGE: &str = "トピックが落ちているかレス数が上限に達しています";
 
 pub struct ResRepo {
-    pool: PgPool,
+    db: PgDb,
     redis: Arc<redis::Client>,
 }
 
 impl ResRepo {
     pub fn new(pool: PgPool, redis: Arc<redis::Client>) -> Self {
-        Self { pool, redis }
+        Self::with_db(PgDb::new(pool), redis)
+    }
+
+    pub fn with_db(db: PgDb, redis: Arc<redis::Client>) -> Self {
+        Self { db, redis }
+    }
+
+    // 1回のクエリでまとめて取得する。並び順は呼び出し側で決める
+    async fn find_by_ids(&self, ids: &[String]) -> Result<HashMap<String, Res>, Box<dyn std::error::Error>> {
+        let reses = sqlx::query_as!(
+            Res,
+            r#"
+            SELECT id, number, text, created_at, updated_at, user_id, topic_id, history_id, version
+            FROM reses
+            WHERE id = ANY($1)
+            "#,
+            ids
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(reses.into_iter().map(|res| (res.id.clone(), res)).collect())
+    }
+
+    // res_limitがあれば、トピックが落ちておらずレス数が上限未満の時だけ採番する
+    async fn create_numbered(&self, res: &Res, res_limit: Option<i32>) -> Result<Res, Box<dyn std::error::Error>> {
+        let mut conn = self.db.acquire().await?;
+        // UnitOfWorkの中ではセーブポイントになる
+        let mut tx = conn.begin().await?;
+
+        // トピックの行ロックを取って採番するので、同時に書き込まれても番号は重複せず上限も超えない
+        // レス数も同じ文で数えるので、トピックのres_countはコミットされたレスと常に一致する
+        let topic = sqlx::query!(
+            r#"
+            UPDATE topics
+            SET res_number_seq = res_number_seq + 1, res_count = res_count + 1
+            WHERE id = $1 AND ($2::INTEGER IS NULL OR (active AND res_count < $2))
+            RETURNING res_number_seq, res_count
+            "#,
+            res.topic_id,
+            res_limit
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let topic = match (topic, res_limit) {
+            (Some(topic), _) => topic,
+            (None, Some(_)) => return Err(Box::new(AtError::Prerequisite(FULL_MESSAGE.to_string()))),
+            (None, None) => return Err(Box::new(AtError::NotFound("トピックが存在しません".to_string()))),
+        };
+        let number = topic.res_number_seq;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO reses (id, number, text, created_at, updated_at, user_id, topic_id, history_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            res.id,
+            number,
+            res.text,
+            res.created_at,
+            res.updated_at,
+            res.user_id,
+            res.topic_id,
+            res.history_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        drop(conn);
+
+        let mut res = res.clone();
+        res.base_mut().set_number(number);
+
+        self.db
+            .publish(
+                &TopicEventBusImpl::new(self.redis.clone()),
+                TopicEvent::ResAdded {
+                    res: res.id.clone(),
+                    topic: res.topic_id.clone(),
+                    count: topic.res_count as i64,
+                },
+            )
+            .await?;
+
+        Ok(res)
     }
 }
 
@@ -34,13 +119,13 @@ impl ResPort for ResRepo {
         let res = sqlx::query_as!(
             Res,
             r#"
-            SELECT id, text, created_at, updated_at, user_id, topic_id, history_id
+            SELECT id, number, text, created_at, updated_at, user_id, topic_id, history_id, version
             FROM reses
             WHERE id = $1
             "#,
             id
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(res)
@@ -55,7 +140,7 @@ impl ResPort for ResRepo {
         let reses = sqlx::query_as!(
             Res,
             r#"
-            SELECT id, text, created_at, updated_at, user_id, topic_id, history_id
+            SELECT id, number, text, created_at, updated_at, user_id, topic_id, history_id, version
             FROM reses
             WHERE topic_id = $1
             ORDER BY created_at DESC
@@ -65,7 +150,25 @@ impl ResPort for ResRepo {
             limit,
             offset
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(reses)
+    }
+
+    async fn find_by_reply_id(&self, reply_id: &str) -> Result<Vec<Res>, Box<dyn std::error::Error>> {
+        let reses = sqlx::query_as!(
+            Res,
+            r#"
+            SELECT r.id, r.number, r.text, r.created_at, r.updated_at, r.user_id, r.topic_id, r.history_id, r.version
+            FROM res_replies rr
+            JOIN reses r ON r.id = rr.res_id
+            WHERE rr.reply_id = $1
+            ORDER BY r.number ASC
+            "#,
+            reply_id
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(reses)
@@ -80,7 +183,7 @@ impl ResPort for ResRepo {
         let reses = sqlx::query_as!(
             Res,
             r#"
-            SELECT id, text, created_at, updated_at, user_id, topic_id, history_id
+            SELECT id, number, text, created_at, updated_at, user_id, topic_id, history_id, version
             FROM reses
             WHERE user_id = $1
             ORDER BY created_at DESC
@@ -90,76 +193,164 @@ impl ResPort for ResRepo {
             limit,
             offset
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(reses)
     }
 
-    async fn create(&self, res: &Res) -> Result<(), Box<dyn std::error::Error>> {
-        sqlx::query!(
+    async fn find_by_topic_hash(&self, topic_id: &str, hash: &str) -> Result<Vec<Res>, Box<dyn std::error::Error>> {
+        let reses = sqlx::query_as!(
+            Res,
             r#"
-            INSERT INTO reses (id, text, created_at, updated_at, user_id, topic_id, history_id)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            SELECT id, number, text, created_at, updated_at, user_id, topic_id, history_id, version
+            FROM reses
+            WHERE topic_id = $1 AND hash = $2
+            ORDER BY created_at ASC
             "#,
-            res.id,
-            res.text,
-            res.created_at,
-            res.updated_at,
-            res.user_id,
-            res.topic_id,
-            res.history_id
+            topic_id,
+            hash
         )
-        .execute(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
-        // Get count of responses for the topic
-        let count = sqlx::query!(
+        Ok(reses)
+    }
+
+    async fn find_by_number_range(&self, topic_id: &str, from: i32, to: i32) -> Result<Vec<Res>, Box<dyn std::error::Error>> {
+        let reses = sqlx::query_as!(
+            Res,
             r#"
-            SELECT COUNT(*) as count
+            SELECT id, number, text, created_at, updated_at, user_id, topic_id, history_id, version
             FROM reses
-            WHERE topic_id = $1
+            WHERE topic_id = $1 AND number BETWEEN $2 AND $3
+            ORDER BY number ASC
             "#,
-            res.topic_id
+            topic_id,
+            from,
+            to
         )
-        .fetch_one(&self.pool)
-        .await?
-        .count
-        .unwrap_or(0);
-
-        // Publish event to Redis
-        let pubsub = ResPubSub {
-            id: res.id.clone(),
-            topic: res.topic_id.clone(),
-            count,
-        };
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
 
-        let mut redis = self.redis.get_async_connection().await?;
-        redis
-            .publish(
-                RES_PUBSUB_CHANNEL,
-                serde_json::to_string(&pubsub)?,
+        Ok(reses)
+    }
+
+    async fn find_by_numbers(&self, topic_id: &str, numbers: &[i32]) -> Result<Vec<Res>, Box<dyn std::error::Error>> {
+        let reses = sqlx::query_as!(
+            Res,
+            r#"
+            SELECT id, number, text, created_at, updated_at, user_id, topic_id, history_id, version
+            FROM reses
+            WHERE topic_id = $1 AND number = ANY($2)
+            ORDER BY number ASC
+            "#,
+            topic_id,
+            numbers
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(reses)
+    }
+
+    async fn add_replies(&self, res_id: &str, reply_ids: &[String]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        // 新しく追加できた辺の返信先だけreply_countを増やす
+        let rows = sqlx::query!(
+            r#"
+            WITH inserted AS (
+                INSERT INTO res_replies (res_id, reply_id)
+                SELECT $1, reply_id FROM UNNEST($2::varchar[]) AS reply_id
+                ON CONFLICT DO NOTHING
+                RETURNING reply_id
+            ), updated AS (
+                UPDATE reses
+                SET reply_count = reply_count + 1
+                WHERE id IN (SELECT reply_id FROM inserted)
             )
-            .await?;
+            SELECT reply_id as "reply_id!" FROM inserted
+            "#,
+            res_id,
+            reply_ids
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
 
-        Ok(())
+        Ok(rows.into_iter().map(|row| row.reply_id).collect())
+    }
+
+    async fn search(&self, query: &ResSearchQuery, limit: i32) -> Result<Vec<SearchHit<Res>>, Box<dyn std::error::Error>> {
+        let search_query = match SearchQuery::parse(&query.text) {
+            Some(search_query) => search_query,
+            None => return Ok(Vec::new()),
+        };
+
+        // contentにはpg_trgmのGINインデックスが張られている。
+        // 3文字未満のtermはトライグラムを作れずインデックスを使えないので、
+        // 1〜2文字の検索はtopic_idなどで絞り込んだ行を順に走査する
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, content as "content!", similarity(content, $3)::float8 as "score!"
+            FROM reses
+            WHERE type = 'normal'
+              AND delete_flag = 'active'
+              AND ($1::text IS NULL OR topic_id = $1)
+              AND content ILIKE ALL($2)
+            ORDER BY 3 DESC, created_at DESC
+            LIMIT $4
+            "#,
+            query.topic,
+            &search_query.like_patterns(),
+            search_query.raw(),
+            limit as i64
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        let ids = rows.iter().map(|row| row.id.clone()).collect::<Vec<_>>();
+        let mut reses = self.find_by_ids(&ids).await?;
+
+        // 類似度の順を保つ
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                reses.remove(&row.id).map(|res| SearchHit {
+                    item: res,
+                    score: row.score,
+                    snippet: search_query.snippet(&row.content),
+                })
+            })
+            .collect())
+    }
+
+    async fn create(&self, res: &Res) -> Result<Res, Box<dyn std::error::Error>> {
+        self.create_numbered(res, None).await
+    }
+
+    async fn create_within_limit(&self, res: &Res, res_limit: i32) -> Result<Res, Box<dyn std::error::Error>> {
+        self.create_numbered(res, Some(res_limit)).await
     }
 
     async fn update(&self, res: &Res) -> Result<(), Box<dyn std::error::Error>> {
-        sqlx::query!(
+        let result = sqlx::query!(
             r#"
             UPDATE reses
-            SET text = $1, updated_at = $2, history_id = $3
-            WHERE id = $4
+            SET text = $1, updated_at = $2, history_id = $3, version = version + 1
+            WHERE id = $4 AND version = $5
             "#,
             res.text,
             res.updated_at,
             res.history_id,
-            res.id
+            res.id,
+            res.version
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(Box::new(AtError::Conflict(CONFLICT_MESSAGE.to_string())));
+        }
+
         Ok(())
     }
 
@@ -167,34 +358,52 @@ impl ResPort for ResRepo {
         &self,
         topic_id: &str,
     ) -> Result<Box<dyn Stream<Item = Result<(Res, i64), Box<dyn std::error::Error>>> + Send + Unpin>, Box<dyn std::error::Error>> {
-        let mut redis = self.redis.get_async_connection().await?;
-        let mut pubsub = redis.as_pubsub();
-        pubsub.subscribe(RES_PUBSUB_CHANNEL)?;
+        let mut events = TopicEventBusImpl::new(self.redis.clone())
+            .subscribe(None)
+            .await?
+            .events;
 
         let topic_id = topic_id.to_string();
-        let pool = self.pool.clone();
+        let db = self.db.clone();
 
         let stream = async_stream::stream! {
-            while let Ok(msg) = pubsub.get_message() {
-                if let Ok(payload) = msg.get_payload::<String>() {
-                    if let Ok(pubsub) = serde_json::from_str::<ResPubSub>(&payload) {
-                        if pubsub.topic == topic_id {
-                            if let Ok(Some(res)) = sqlx::query_as!(
-                                Res,
-                                r#"
-                                SELECT id, text, created_at, updated_at, user_id, topic_id, history_id
-                                FROM reses
-                                WHERE id = $1
-                                "#,
-                                pubsub.id
-                            )
-                            .fetch_optional(&pool)
-                            .await
-                            {
-                                yield Ok((res, pubsub.count));
-                            }
-                        }
+            while let Some(record) = events.next().await {
+                // 1件のエラーで購読を終わらせず、呼び出し元に渡して次のイベントを待つ
+                let record = match record {
+                    Ok(record) => record,
+                    Err(e) => {
+                        yield Err(Box::new(e) as Box<dyn std::error::Error>);
+                        continue;
+                    }
+                };
+                let TopicEvent::ResAdded { res, topic, count } = record.event else {
+                    continue;
+                };
+                if topic != topic_id {
+                    continue;
+                }
+
+                let res = match db.acquire().await {
+                    Ok(mut conn) => {
+                        sqlx::query_as!(
+                            Res,
+                            r#"
+                            SELECT id, number, text, created_at, updated_at, user_id, topic_id, history_id, version
+                            FROM reses
+                            WHERE id = $1
+                            "#,
+                            res
+                        )
+                        .fetch_optional(&mut *conn)
+                        .await
                     }
+                    Err(e) => Err(e),
+                };
+                match res {
+                    Ok(Some(res)) => yield Ok((res, count)),
+                    // 通知が届く前に削除されたレスは流さない
+                    Ok(None) => {}
+                    Err(e) => yield Err(Box::new(e) as Box<dyn std::error::Error>),
                 }
             }
         };
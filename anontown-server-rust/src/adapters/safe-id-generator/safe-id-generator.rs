@@ -14,4 +14,8 @@ impl SafeIdGeneratorPort for SafeIdGenerator {
     fn generate(&self) -> String {
         nanoid!()
     }
-} 
\ No newline at end of file
+
+    fn generate_code(&self, len: usize, alphabet: &[char]) -> String {
+        nanoid!(len, alphabet)
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::ports::{
+    object_id_generator::ObjectIdGeneratorPort, safe_id_generator::SafeIdGeneratorPort,
+};
+
+/// テストで使う、呼び出すたびに連番で違うIDを返すジェネレーター
+///
+/// IDは`id0`, `key1`のようになる。短いコードは連番を右詰めにするので、長さに関係なく重複しない
+#[derive(Default)]
+pub struct SeqIdGenerator(AtomicUsize);
+
+impl SeqIdGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn next(&self) -> usize {
+        self.0.fetch_add(1, Ordering::SeqCst)
+    }
+}
+
+impl ObjectIdGeneratorPort for SeqIdGenerator {
+    fn generate(&self) -> String {
+        format!("id{}", self.next())
+    }
+}
+
+impl SafeIdGeneratorPort for SeqIdGenerator {
+    fn generate(&self) -> String {
+        format!("key{}", self.next())
+    }
+
+    fn generate_code(&self, len: usize, alphabet: &[char]) -> String {
+        let mut n = self.next();
+        let mut code = vec![alphabet[0]; len];
+        for c in code.iter_mut().rev() {
+            *c = alphabet[n % alphabet.len()];
+            n /= alphabet.len();
+        }
+        code.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seq_id_generator() {
+        let generator = SeqIdGenerator::new();
+        assert_eq!(ObjectIdGeneratorPort::generate(&generator), "id0");
+        assert_eq!(SafeIdGeneratorPort::generate(&generator), "key1");
+        assert_eq!(generator.generate_code(4, &['0', '1']), "0010");
+        assert_eq!(generator.generate_code(4, &['0', '1']), "0011");
+    }
+}
@@ -2,43 +2,63 @@ use async_trait::async_trait;
 use std::collections::HashMap;
 use tokio::test;
 
+use crate::at_error::AtError;
 use crate::entities::Storage;
 use crate::ports::storage::StoragePort;
+use crate::adapters::mock_store::{MockSnapshot, MockStore};
 use super::laws::run_storage_repo_laws;
 
+#[derive(Clone)]
 pub struct StorageRepoMock {
-    storages: HashMap<String, Storage>,
+    storages: MockStore<HashMap<String, Storage>>,
 }
 
 impl StorageRepoMock {
     pub fn new() -> Self {
         Self {
-            storages: HashMap::new(),
+            storages: MockStore::new(HashMap::new()),
         }
     }
 }
 
+impl MockSnapshot for StorageRepoMock {
+    type Snapshot = HashMap<String, Storage>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        self.storages.snapshot()
+    }
+
+    fn restore(&self, snapshot: Self::Snapshot) {
+        self.storages.restore(snapshot);
+    }
+}
+
 #[async_trait]
 impl StoragePort for StorageRepoMock {
     async fn find_by_id(&self, id: &str) -> Result<Option<Storage>, Box<dyn std::error::Error>> {
-        Ok(self.storages.get(id).cloned())
+        Ok(self.storages.lock().unwrap().get(id).cloned())
     }
 
     async fn find_by_user_id(&self, user_id: &str) -> Result<Option<Storage>, Box<dyn std::error::Error>> {
-        Ok(self.storages.values().find(|s| s.user_id == user_id).cloned())
+        Ok(self.storages.lock().unwrap().values().find(|s| s.user_id == user_id).cloned())
     }
 
     async fn create(&self, storage: &Storage) -> Result<(), Box<dyn std::error::Error>> {
-        self.storages.insert(storage.id.clone(), storage.clone());
+        self.storages.lock().unwrap().insert(storage.id.clone(), storage.clone());
         Ok(())
     }
 
     async fn update(&self, storage: &Storage) -> Result<(), Box<dyn std::error::Error>> {
-        if self.storages.contains_key(&storage.id) {
-            self.storages.insert(storage.id.clone(), storage.clone());
-            Ok(())
-        } else {
-            Err("Storage not found".into())
+        let mut storages = self.storages.lock().unwrap();
+        match storages.get(&storage.id) {
+            Some(current) if current.version == storage.version => {
+                let mut storage = storage.clone();
+                storage.version += 1;
+                storages.insert(storage.id.clone(), storage);
+                Ok(())
+            }
+            Some(_) => Err(Box::new(AtError::Conflict("ストレージが他の操作で更新されました".to_string()))),
+            None => Err("Storage not found".into()),
         }
     }
 }
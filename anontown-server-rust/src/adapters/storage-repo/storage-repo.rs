@@ -1,17 +1,25 @@
 use async_trait::async_trait;
 use sqlx::PgPool;
 
+use crate::adapters::pg_db::PgDb;
+use crate::at_error::AtError;
 use crate::entities::Storage;
 use crate::ports::storage::StoragePort;
 
+const CONFLICT_MESSAGE: &str = "ストレージが他の操作で更新されました";
+
 pub struct StorageRepo {
-    pool: PgPool,
+    db: PgDb,
 }
 
 impl StorageRepo {
     pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
         let pool = PgPool::connect(&std::env::var("DATABASE_URL")?).await?;
-        Ok(Self { pool })
+        Ok(Self::with_db(PgDb::new(pool)))
+    }
+
+    pub fn with_db(db: PgDb) -> Self {
+        Self { db }
     }
 }
 
@@ -21,13 +29,13 @@ impl StoragePort for StorageRepo {
         let storage = sqlx::query_as!(
             Storage,
             r#"
-            SELECT id, user_id, name, description, created_at, updated_at
+            SELECT id, user_id, name, description, created_at, updated_at, version
             FROM storages
             WHERE id = $1
             "#,
             id
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(storage)
@@ -37,13 +45,13 @@ impl StoragePort for StorageRepo {
         let storage = sqlx::query_as!(
             Storage,
             r#"
-            SELECT id, user_id, name, description, created_at, updated_at
+            SELECT id, user_id, name, description, created_at, updated_at, version
             FROM storages
             WHERE user_id = $1
             "#,
             user_id
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(storage)
@@ -62,27 +70,33 @@ impl StoragePort for StorageRepo {
             storage.created_at,
             storage.updated_at
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
     }
 
     async fn update(&self, storage: &Storage) -> Result<(), Box<dyn std::error::Error>> {
-        sqlx::query!(
+        let result = sqlx::query!(
             r#"
             UPDATE storages
-            SET name = $1, description = $2, updated_at = $3
-            WHERE id = $4
+            SET name = $1, description = $2, updated_at = $3,
+                version = version + 1
+            WHERE id = $4 AND version = $5
             "#,
             storage.name,
             storage.description,
             storage.updated_at,
-            storage.id
+            storage.id,
+            storage.version
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(Box::new(AtError::Conflict(CONFLICT_MESSAGE.to_string())));
+        }
+
         Ok(())
     }
 } 
\ No newline at end of file
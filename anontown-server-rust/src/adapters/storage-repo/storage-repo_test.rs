@@ -3,6 +3,7 @@ use tokio;
 
 use crate::adapters::storage_repo::storage_repo::StorageRepo;
 use crate::adapters::storage_repo::storage_repo_mock::StorageRepoMock;
+use crate::at_error::AtError;
 use crate::entities::Storage;
 
 #[tokio::test]
@@ -15,6 +16,7 @@ async fn test_storage_repo_mock() {
         description: "Test Description".to_string(),
         created_at: Utc::now(),
         updated_at: Utc::now(),
+        version: 0,
     };
 
     // Test create
@@ -47,6 +49,7 @@ async fn test_storage_repo() {
         description: "Test Description".to_string(),
         created_at: Utc::now(),
         updated_at: Utc::now(),
+        version: 0,
     };
 
     // Test create
@@ -67,4 +70,32 @@ async fn test_storage_repo() {
 
     let found = repo.find_by_id(&storage.id).await.unwrap().unwrap();
     assert_eq!(found.name, updated_storage.name);
-} 
\ No newline at end of file
+} 
+
+#[tokio::test]
+async fn test_storage_repo_mock_version() {
+    let repo = StorageRepoMock::new();
+    let storage = Storage {
+        id: "storage1".to_string(),
+        user_id: "user1".to_string(),
+        name: "Test Storage".to_string(),
+        description: "Test Description".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        version: 0,
+    };
+    repo.create(&storage).await.unwrap();
+
+    repo.update(&storage).await.unwrap();
+    let updated = repo.find_by_id(&storage.id).await.unwrap().unwrap();
+    assert_eq!(updated.version, storage.version + 1);
+
+    // 古いバージョンのまま保存しようとすると競合する
+    let result = repo.update(&storage).await;
+    assert!(matches!(
+        result.unwrap_err().downcast_ref::<AtError>(),
+        Some(AtError::Conflict(_))
+    ));
+
+    repo.update(&updated).await.unwrap();
+}
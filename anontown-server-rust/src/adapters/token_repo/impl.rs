@@ -1,16 +1,27 @@
 use async_trait::async_trait;
-use sqlx::PgPool;
+use sqlx::{Connection, PgPool};
+use crate::adapters::pg_db::{PgConn, PgDb};
 use crate::{AuthTokenMaster, AuthUser, Token, AtError, AtErrorKind, AtResult};
 use crate::ports::TokenRepo;
 use super::model::TokenRepoModel;
 
+const ROTATED_MESSAGE: &str = "トークンは既に更新されています";
+
 pub struct TokenRepoImpl {
-    pool: PgPool,
+    db: PgDb,
 }
 
 impl TokenRepoImpl {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_db(PgDb::new(pool))
+    }
+
+    pub fn with_db(db: PgDb) -> Self {
+        Self { db }
+    }
+
+    async fn acquire(&self) -> AtResult<PgConn<'_>> {
+        self.db.acquire().await.map_err(|e| AtError::Internal(e.into()))
     }
 }
 
@@ -20,72 +31,42 @@ impl TokenRepo for TokenRepoImpl {
         let model = sqlx::query_as!(
             TokenRepoModel,
             r#"
-            SELECT id, user_id, client_id, access_token, refresh_token, expires_at, date
+            SELECT id, user_id, client_id, access_token, refresh_token, expires_at,
+                refresh_expires_at, family_id, rotated_at, scopes, ip, user_agent, last_used_at, date
             FROM tokens
             WHERE id = $1
             "#,
             id
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.acquire().await?)
         .await?
         .ok_or_else(|| AtError::new(AtErrorKind::NotFound, "トークンが存在しません"))?;
 
-        Ok(Token {
-            id: model.id,
-            user_id: model.user_id,
-            client_id: model.client_id,
-            access_token: model.access_token,
-            refresh_token: model.refresh_token,
-            expires_at: model.expires_at,
-            date: model.date,
-        })
+        Ok(model.into_token())
     }
 
     async fn find_all(&self, auth_token: &AuthTokenMaster) -> AtResult<Vec<Token>> {
         let models = sqlx::query_as!(
             TokenRepoModel,
             r#"
-            SELECT id, user_id, client_id, access_token, refresh_token, expires_at, date
+            SELECT id, user_id, client_id, access_token, refresh_token, expires_at,
+                refresh_expires_at, family_id, rotated_at, scopes, ip, user_agent, last_used_at, date
             FROM tokens
-            WHERE user_id = $1
+            WHERE user_id = $1 AND rotated_at IS NULL
             "#,
             auth_token.user
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.acquire().await?)
         .await?;
 
-        Ok(models
-            .into_iter()
-            .map(|model| Token {
-                id: model.id,
-                user_id: model.user_id,
-                client_id: model.client_id,
-                access_token: model.access_token,
-                refresh_token: model.refresh_token,
-                expires_at: model.expires_at,
-                date: model.date,
-            })
-            .collect())
+        Ok(models.into_iter().map(TokenRepoModel::into_token).collect())
     }
 
     async fn insert(&self, token: &Token) -> AtResult<()> {
-        sqlx::query!(
-            r#"
-            INSERT INTO tokens (id, user_id, client_id, access_token, refresh_token, expires_at, date)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
-            "#,
-            token.id,
-            token.user_id,
-            token.client_id,
-            token.access_token,
-            token.refresh_token,
-            token.expires_at,
-            token.date
-        )
-        .execute(&self.pool)
-        .await?;
-
-        Ok(())
+        let mut conn = self.acquire().await?;
+        insert_token(&mut *conn, &TokenRepoModel::from_token(token))
+            .await
+            .map_err(|e| AtError::Internal(e.into()))
     }
 
     async fn update(&self, token: &Token) -> AtResult<()> {
@@ -102,7 +83,7 @@ impl TokenRepo for TokenRepoImpl {
             token.expires_at,
             token.id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.acquire().await?)
         .await?;
 
         if result.rows_affected() == 0 {
@@ -121,7 +102,7 @@ impl TokenRepo for TokenRepoImpl {
             token.user,
             client_id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.acquire().await?)
         .await?;
 
         Ok(())
@@ -135,9 +116,133 @@ impl TokenRepo for TokenRepoImpl {
             "#,
             user.id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.acquire().await?)
         .await?;
 
         Ok(())
     }
-} 
\ No newline at end of file
+
+    async fn rotate(&self, old: &Token, new: &Token) -> AtResult<()> {
+        let mut conn = self.acquire().await?;
+        // UnitOfWorkの中ではセーブポイントになる
+        let mut tx = conn.begin().await.map_err(|e| AtError::Internal(e.into()))?;
+
+        // 同じリフレッシュトークンで並行して更新された場合は片方だけが成功する
+        let result = sqlx::query!(
+            r#"
+            UPDATE tokens
+            SET rotated_at = $1
+            WHERE id = $2 AND rotated_at IS NULL
+            "#,
+            old.rotated_at,
+            old.id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+        if result.rows_affected() == 0 {
+            return Err(AtError::Conflict(ROTATED_MESSAGE.to_string()));
+        }
+
+        insert_token(&mut *tx, &TokenRepoModel::from_token(new))
+            .await
+            .map_err(|e| AtError::Internal(e.into()))?;
+        tx.commit().await.map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+
+    async fn del_family(&self, family_id: &str) -> AtResult<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM tokens
+            WHERE family_id = $1
+            "#,
+            family_id
+        )
+        .execute(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+
+    async fn update_last_used(&self, token: &Token) -> AtResult<()> {
+        sqlx::query!(
+            r#"
+            UPDATE tokens
+            SET last_used_at = $1
+            WHERE id = $2
+            "#,
+            token.last_used_at,
+            token.id
+        )
+        .execute(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+
+    async fn del_other_families(&self, user_id: &str, family_id: &str) -> AtResult<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM tokens
+            WHERE user_id = $1 AND family_id <> $2
+            "#,
+            user_id,
+            family_id
+        )
+        .execute(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+
+    async fn del_all(&self, user_id: &str) -> AtResult<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM tokens
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *self.acquire().await?)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+}
+
+async fn insert_token<'e>(
+    executor: impl sqlx::PgExecutor<'e>,
+    model: &TokenRepoModel,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO tokens (id, user_id, client_id, access_token, refresh_token, expires_at,
+            refresh_expires_at, family_id, rotated_at, scopes, ip, user_agent, last_used_at, date)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        "#,
+        model.id,
+        model.user_id,
+        model.client_id,
+        model.access_token,
+        model.refresh_token,
+        model.expires_at,
+        model.refresh_expires_at,
+        model.family_id,
+        model.rotated_at,
+        &model.scopes,
+        model.ip,
+        model.user_agent,
+        model.last_used_at,
+        model.date
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
@@ -1,102 +1,141 @@
 use async_trait::async_trait;
 use std::collections::HashMap;
-use crate::{AuthTokenMaster, AuthUser, Token, AtError, AtErrorKind, AtResult};
+use crate::{AuthTokenMaster, AuthUser, Token, AtError, AtResult};
 use crate::ports::TokenRepo;
+use crate::adapters::mock_store::{MockSnapshot, MockStore};
 use super::model::TokenRepoModel;
 
+const NOT_FOUND_MESSAGE: &str = "トークンが存在しません";
+
+#[derive(Clone)]
 pub struct TokenRepoMockImpl {
-    tokens: HashMap<String, TokenRepoModel>,
+    tokens: MockStore<HashMap<String, TokenRepoModel>>,
 }
 
 impl TokenRepoMockImpl {
     pub fn new() -> Self {
         Self {
-            tokens: HashMap::new(),
+            tokens: MockStore::new(HashMap::new()),
         }
     }
 }
 
+impl MockSnapshot for TokenRepoMockImpl {
+    type Snapshot = HashMap<String, TokenRepoModel>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        self.tokens.snapshot()
+    }
+
+    fn restore(&self, snapshot: Self::Snapshot) {
+        self.tokens.restore(snapshot);
+    }
+}
+
 #[async_trait]
 impl TokenRepo for TokenRepoMockImpl {
     async fn find_one(&self, id: &str) -> AtResult<Token> {
-        let model = self
-            .tokens
+        self.tokens
+            .lock()
+            .unwrap()
             .get(id)
             .cloned()
-            .ok_or_else(|| AtError::new(AtErrorKind::NotFound, "トークンが存在しません"))?;
-
-        Ok(Token {
-            id: model.id,
-            user_id: model.user_id,
-            client_id: model.client_id,
-            access_token: model.access_token,
-            refresh_token: model.refresh_token,
-            expires_at: model.expires_at,
-            date: model.date,
-        })
+            .map(TokenRepoModel::into_token)
+            .ok_or_else(|| AtError::NotFound(NOT_FOUND_MESSAGE.to_string()))
     }
 
     async fn find_all(&self, auth_token: &AuthTokenMaster) -> AtResult<Vec<Token>> {
         let tokens = self
             .tokens
+            .lock()
+            .unwrap()
             .values()
-            .filter(|token| token.user_id == auth_token.user)
+            .filter(|token| token.user_id == auth_token.base.user && token.rotated_at.is_none())
             .cloned()
-            .map(|model| Token {
-                id: model.id,
-                user_id: model.user_id,
-                client_id: model.client_id,
-                access_token: model.access_token,
-                refresh_token: model.refresh_token,
-                expires_at: model.expires_at,
-                date: model.date,
-            })
+            .map(TokenRepoModel::into_token)
             .collect();
 
         Ok(tokens)
     }
 
     async fn insert(&self, token: &Token) -> AtResult<()> {
-        let model = TokenRepoModel {
-            id: token.id.clone(),
-            user_id: token.user_id.clone(),
-            client_id: token.client_id.clone(),
-            access_token: token.access_token.clone(),
-            refresh_token: token.refresh_token.clone(),
-            expires_at: token.expires_at,
-            date: token.date,
-        };
-        self.tokens.insert(token.id.clone(), model);
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(token.id.clone(), TokenRepoModel::from_token(token));
         Ok(())
     }
 
     async fn update(&self, token: &Token) -> AtResult<()> {
-        if !self.tokens.contains_key(&token.id) {
-            return Err(AtError::new(AtErrorKind::NotFound, "トークンが存在しません"));
+        let mut tokens = self.tokens.lock().unwrap();
+        if !tokens.contains_key(&token.id) {
+            return Err(AtError::NotFound(NOT_FOUND_MESSAGE.to_string()));
         }
 
-        let model = TokenRepoModel {
-            id: token.id.clone(),
-            user_id: token.user_id.clone(),
-            client_id: token.client_id.clone(),
-            access_token: token.access_token.clone(),
-            refresh_token: token.refresh_token.clone(),
-            expires_at: token.expires_at,
-            date: token.date,
-        };
-        self.tokens.insert(token.id.clone(), model);
+        tokens.insert(token.id.clone(), TokenRepoModel::from_token(token));
         Ok(())
     }
 
     async fn del_client_token(&self, token: &AuthTokenMaster, client_id: &str) -> AtResult<()> {
-        self.tokens.retain(|_, t| {
-            !(t.user_id == token.user && t.client_id == client_id)
+        self.tokens.lock().unwrap().retain(|_, t| {
+            !(t.user_id == token.base.user && t.client_id == client_id)
         });
         Ok(())
     }
 
     async fn del_master_token(&self, user: &AuthUser) -> AtResult<()> {
-        self.tokens.retain(|_, t| t.user_id != user.id);
+        self.tokens.lock().unwrap().retain(|_, t| t.user_id != user.id);
+        Ok(())
+    }
+
+    async fn rotate(&self, old: &Token, new: &Token) -> AtResult<()> {
+        let mut tokens = self.tokens.lock().unwrap();
+        match tokens.get(&old.id) {
+            Some(stored) if stored.rotated_at.is_none() => {}
+            Some(_) => {
+                return Err(AtError::Conflict(
+                    "トークンは既に更新されています".to_string(),
+                ))
+            }
+            None => return Err(AtError::NotFound(NOT_FOUND_MESSAGE.to_string())),
+        }
+
+        tokens.insert(old.id.clone(), TokenRepoModel::from_token(old));
+        tokens.insert(new.id.clone(), TokenRepoModel::from_token(new));
         Ok(())
     }
-} 
\ No newline at end of file
+
+    async fn del_family(&self, family_id: &str) -> AtResult<()> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .retain(|_, t| t.family_id != family_id);
+        Ok(())
+    }
+
+    async fn update_last_used(&self, token: &Token) -> AtResult<()> {
+        match self.tokens.lock().unwrap().get_mut(&token.id) {
+            Some(stored) => {
+                stored.last_used_at = token.last_used_at;
+                Ok(())
+            }
+            None => Err(AtError::NotFound(NOT_FOUND_MESSAGE.to_string())),
+        }
+    }
+
+    async fn del_other_families(&self, user_id: &str, family_id: &str) -> AtResult<()> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .retain(|_, t| t.user_id != user_id || t.family_id == family_id);
+        Ok(())
+    }
+
+    async fn del_all(&self, user_id: &str) -> AtResult<()> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .retain(|_, t| t.user_id != user_id);
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::auth::TokenScope;
+use crate::entities::token::{Token, TokenDevice};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenRepoModel {
     pub id: String,
@@ -9,5 +12,68 @@ pub struct TokenRepoModel {
     pub access_token: String,
     pub refresh_token: String,
     pub expires_at: DateTime<Utc>,
+    pub refresh_expires_at: DateTime<Utc>,
+    pub family_id: String,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub scopes: Vec<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub last_used_at: DateTime<Utc>,
     pub date: DateTime<Utc>,
-} 
\ No newline at end of file
+}
+
+impl TokenRepoModel {
+    pub fn from_token(token: &Token) -> Self {
+        Self {
+            id: token.id.clone(),
+            user_id: token.user_id.clone(),
+            client_id: token.client_id.clone(),
+            access_token: token.access_token.clone(),
+            refresh_token: token.refresh_token.clone(),
+            expires_at: token.expires_at,
+            refresh_expires_at: token.refresh_expires_at,
+            family_id: token.family_id.clone(),
+            rotated_at: token.rotated_at,
+            scopes: scopes_to_strings(&token.scopes),
+            ip: token.device.ip.clone(),
+            user_agent: token.device.user_agent.clone(),
+            last_used_at: token.last_used_at,
+            date: token.created_at,
+        }
+    }
+
+    pub fn into_token(self) -> Token {
+        let scopes = self.parse_scopes();
+        Token {
+            id: self.id,
+            user_id: self.user_id,
+            client_id: self.client_id,
+            access_token: self.access_token,
+            refresh_token: self.refresh_token,
+            expires_at: self.expires_at,
+            refresh_expires_at: self.refresh_expires_at,
+            family_id: self.family_id,
+            rotated_at: self.rotated_at,
+            scopes,
+            device: TokenDevice {
+                ip: self.ip,
+                user_agent: self.user_agent,
+            },
+            last_used_at: self.last_used_at,
+            created_at: self.date,
+            updated_at: self.rotated_at.unwrap_or(self.date),
+        }
+    }
+
+    // 知らない名前の権限は無視する
+    pub fn parse_scopes(&self) -> Vec<TokenScope> {
+        self.scopes
+            .iter()
+            .filter_map(|scope| TokenScope::parse(scope))
+            .collect()
+    }
+}
+
+pub fn scopes_to_strings(scopes: &[TokenScope]) -> Vec<String> {
+    scopes.iter().map(|scope| scope.as_str().to_string()).collect()
+}
@@ -0,0 +1,164 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use chrono::{DateTime, Utc};
+
+use crate::at_error::{AtError, AtResult};
+use crate::auth::TokenScope;
+use crate::entities::token_req::TokenReq;
+use crate::ports::TokenReqRepo;
+
+// 権限はDBに名前で保存されているので、読んでからTokenScopeにする
+struct TokenReqRow {
+    token_id: String,
+    key: String,
+    expires: DateTime<Utc>,
+    active: bool,
+    redirect_uri: Option<String>,
+    code_challenge: Option<String>,
+    client_id: Option<String>,
+    scopes: Vec<String>,
+    pairing: bool,
+    failed_attempts: i32,
+}
+
+impl TokenReqRow {
+    // 知らない名前の権限は無視する
+    fn into_token_req(self) -> TokenReq {
+        TokenReq {
+            token_id: self.token_id,
+            key: self.key,
+            expires: self.expires,
+            active: self.active,
+            redirect_uri: self.redirect_uri,
+            code_challenge: self.code_challenge,
+            client_id: self.client_id,
+            scopes: self
+                .scopes
+                .iter()
+                .filter_map(|scope| TokenScope::parse(scope))
+                .collect(),
+            pairing: self.pairing,
+            failed_attempts: self.failed_attempts,
+        }
+    }
+}
+
+pub struct TokenReqRepoImpl {
+    pool: PgPool,
+}
+
+impl TokenReqRepoImpl {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl TokenReqRepo for TokenReqRepoImpl {
+    async fn find_one(&self, token_id: &str, key: &str) -> AtResult<TokenReq> {
+        sqlx::query_as!(
+            TokenReqRow,
+            r#"
+            SELECT "tokenId" AS token_id, key, expires, active,
+                "redirectUri" AS redirect_uri, "codeChallenge" AS code_challenge,
+                "clientId" AS client_id, scopes, pairing, "failedAttempts" AS failed_attempts
+            FROM "tokenReqs"
+            WHERE "tokenId" = $1 AND key = $2
+            "#,
+            token_id,
+            key
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?
+        .map(TokenReqRow::into_token_req)
+        .ok_or_else(|| AtError::NotFound("トークンリクエストが存在しません".to_string()))
+    }
+
+    async fn find_pairing(&self, selector: &str) -> AtResult<Vec<TokenReq>> {
+        sqlx::query_as!(
+            TokenReqRow,
+            r#"
+            SELECT "tokenId" AS token_id, key, expires, active,
+                "redirectUri" AS redirect_uri, "codeChallenge" AS code_challenge,
+                "clientId" AS client_id, scopes, pairing, "failedAttempts" AS failed_attempts
+            FROM "tokenReqs"
+            WHERE left(key, 4) = $1 AND pairing AND active
+            "#,
+            selector
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map(|rows| rows.into_iter().map(TokenReqRow::into_token_req).collect())
+        .map_err(|e| AtError::Internal(e.into()))
+    }
+
+    async fn insert(&self, req: &TokenReq) -> AtResult<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO "tokenReqs" ("tokenId", key, expires, active, "redirectUri", "codeChallenge", "clientId", scopes, pairing, "failedAttempts")
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            req.token_id,
+            req.key,
+            req.expires,
+            req.active,
+            req.redirect_uri,
+            req.code_challenge,
+            req.client_id,
+            &req.scopes.iter().map(|scope| scope.as_str().to_string()).collect::<Vec<_>>(),
+            req.pairing,
+            req.failed_attempts
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+
+    async fn update(&self, req: &TokenReq) -> AtResult<()> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE "tokenReqs"
+            SET expires = $1, active = $2
+            WHERE "tokenId" = $3 AND key = $4 AND active
+            "#,
+            req.expires,
+            req.active,
+            req.token_id,
+            req.key
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(AtError::Conflict(
+                "トークンリクエストは既に使用されています".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn add_failed_attempt(&self, req: &TokenReq, max_failed_attempts: i32) -> AtResult<()> {
+        sqlx::query!(
+            r#"
+            UPDATE "tokenReqs"
+            SET "failedAttempts" = "failedAttempts" + 1,
+                active = active AND "failedAttempts" + 1 < $3
+            WHERE "tokenId" = $1 AND key = $2
+            "#,
+            req.token_id,
+            req.key,
+            max_failed_attempts
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+}
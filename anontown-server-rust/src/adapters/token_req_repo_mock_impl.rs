@@ -0,0 +1,87 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::at_error::{AtError, AtResult};
+use crate::entities::token_req::TokenReq;
+use crate::ports::TokenReqRepo;
+
+pub struct TokenReqRepoMockImpl {
+    reqs: Mutex<HashMap<(String, String), TokenReq>>,
+}
+
+impl TokenReqRepoMockImpl {
+    pub fn new() -> Self {
+        Self {
+            reqs: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenReqRepo for TokenReqRepoMockImpl {
+    async fn find_one(&self, token_id: &str, key: &str) -> AtResult<TokenReq> {
+        self.reqs
+            .lock()
+            .unwrap()
+            .get(&(token_id.to_string(), key.to_string()))
+            .cloned()
+            .ok_or_else(|| AtError::NotFound("トークンリクエストが存在しません".to_string()))
+    }
+
+    async fn find_pairing(&self, selector: &str) -> AtResult<Vec<TokenReq>> {
+        Ok(self
+            .reqs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|req| {
+                req.pairing && req.active && TokenReq::pairing_selector(&req.key) == selector
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn insert(&self, req: &TokenReq) -> AtResult<()> {
+        self.reqs
+            .lock()
+            .unwrap()
+            .insert((req.token_id.clone(), req.key.clone()), req.clone());
+        Ok(())
+    }
+
+    async fn update(&self, req: &TokenReq) -> AtResult<()> {
+        let mut reqs = self.reqs.lock().unwrap();
+        match reqs.get_mut(&(req.token_id.clone(), req.key.clone())) {
+            Some(stored) if stored.active => {
+                *stored = req.clone();
+                Ok(())
+            }
+            _ => Err(AtError::Conflict(
+                "トークンリクエストは既に使用されています".to_string(),
+            )),
+        }
+    }
+
+    async fn add_failed_attempt(&self, req: &TokenReq, max_failed_attempts: i32) -> AtResult<()> {
+        let mut reqs = self.reqs.lock().unwrap();
+        if let Some(stored) = reqs.get_mut(&(req.token_id.clone(), req.key.clone())) {
+            stored.failed_attempts += 1;
+            if stored.failed_attempts >= max_failed_attempts {
+                stored.active = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::token_req_repo::run_token_req_repo_laws;
+
+    #[tokio::test]
+    async fn test_token_req_repo_mock_impl() {
+        run_token_req_repo_laws(&TokenReqRepoMockImpl::new()).await;
+    }
+}
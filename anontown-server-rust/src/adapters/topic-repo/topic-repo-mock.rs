@@ -1,27 +1,46 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
+use crate::at_error::AtError;
 use crate::entities::{Topic, TopicType};
+use crate::entities::topic::TopicLifecycle;
+use crate::entities::search_query::{SearchHit, SearchQuery};
 use crate::ports::topic::{TopicPort, TopicQuery};
+use crate::adapters::mock_store::{MockSnapshot, MockStore};
 
+#[derive(Clone)]
 pub struct TopicRepoMock {
-    topics: HashMap<String, Topic>,
-    subscriptions: HashMap<String, HashSet<String>>,
+    topics: MockStore<HashMap<String, Topic>>,
+    subscriptions: MockStore<HashMap<String, HashSet<String>>>,
 }
 
 impl TopicRepoMock {
     pub fn new() -> Self {
         Self {
-            topics: HashMap::new(),
-            subscriptions: HashMap::new(),
+            topics: MockStore::new(HashMap::new()),
+            subscriptions: MockStore::new(HashMap::new()),
         }
     }
 }
 
+impl MockSnapshot for TopicRepoMock {
+    type Snapshot = (HashMap<String, Topic>, HashMap<String, HashSet<String>>);
+
+    fn snapshot(&self) -> Self::Snapshot {
+        (self.topics.snapshot(), self.subscriptions.snapshot())
+    }
+
+    fn restore(&self, (topics, subscriptions): Self::Snapshot) {
+        self.topics.restore(topics);
+        self.subscriptions.restore(subscriptions);
+    }
+}
+
 #[async_trait]
 impl TopicPort for TopicRepoMock {
     async fn find_by_id(&self, id: &str) -> Result<Option<Topic>, Box<dyn std::error::Error>> {
-        Ok(self.topics.get(id).cloned())
+        Ok(self.topics.lock().unwrap().get(id).cloned())
     }
 
     async fn find_by_user_id(
@@ -32,6 +51,8 @@ impl TopicPort for TopicRepoMock {
     ) -> Result<Vec<Topic>, Box<dyn std::error::Error>> {
         let mut topics: Vec<Topic> = self
             .topics
+            .lock()
+            .unwrap()
             .values()
             .filter(|topic| topic.user_id == user_id)
             .cloned()
@@ -44,25 +65,30 @@ impl TopicPort for TopicRepoMock {
     }
 
     async fn find_by_hash(&self, hash: &str) -> Result<Option<Topic>, Box<dyn std::error::Error>> {
-        Ok(self.topics.values().find(|topic| topic.hash == hash).cloned())
+        Ok(self.topics.lock().unwrap().values().find(|topic| topic.hash == hash).cloned())
     }
 
     async fn create(&self, topic: &Topic) -> Result<(), Box<dyn std::error::Error>> {
-        self.topics.insert(topic.id.clone(), topic.clone());
+        self.topics.lock().unwrap().insert(topic.id.clone(), topic.clone());
         Ok(())
     }
 
     async fn update(&self, topic: &Topic) -> Result<(), Box<dyn std::error::Error>> {
-        if self.topics.contains_key(&topic.id) {
-            self.topics.insert(topic.id.clone(), topic.clone());
-            Ok(())
-        } else {
-            Err("Topic not found".into())
+        let mut topics = self.topics.lock().unwrap();
+        match topics.get(&topic.id) {
+            Some(current) if current.base().version == topic.base().version => {
+                let mut topic = topic.clone();
+                topic.base_mut().version += 1;
+                topics.insert(topic.id.clone(), topic);
+                Ok(())
+            }
+            Some(_) => Err(Box::new(AtError::Conflict("トピックが他の操作で更新されました".to_string()))),
+            None => Err("Topic not found".into()),
         }
     }
 
     async fn update_res_count(&self, id: &str, count: i64) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(topic) = self.topics.get_mut(id) {
+        if let Some(topic) = self.topics.lock().unwrap().get_mut(id) {
             topic.res_count = count;
             topic.updated_at = Utc::now();
             Ok(())
@@ -72,7 +98,7 @@ impl TopicPort for TopicRepoMock {
     }
 
     async fn update_age(&self, id: &str, age: bool) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(topic) = self.topics.get_mut(id) {
+        if let Some(topic) = self.topics.lock().unwrap().get_mut(id) {
             topic.age = age;
             topic.updated_at = Utc::now();
             Ok(())
@@ -84,6 +110,8 @@ impl TopicPort for TopicRepoMock {
     async fn count_by_type(&self, topic_type: TopicType) -> Result<i64, Box<dyn std::error::Error>> {
         let count = self
             .topics
+            .lock()
+            .unwrap()
             .values()
             .filter(|topic| topic.topic_type == topic_type)
             .count() as i64;
@@ -93,6 +121,8 @@ impl TopicPort for TopicRepoMock {
     async fn count_by_user_id(&self, user_id: &str) -> Result<i64, Box<dyn std::error::Error>> {
         let count = self
             .topics
+            .lock()
+            .unwrap()
             .values()
             .filter(|topic| topic.user_id == user_id)
             .count() as i64;
@@ -101,7 +131,7 @@ impl TopicPort for TopicRepoMock {
 
     async fn find_tags(&mut self, _limit: i32) -> Result<Vec<(String, i32)>, Box<dyn std::error::Error>> {
         let mut tag_counts: HashMap<String, i32> = HashMap::new();
-        for topic in self.topics.values() {
+        for topic in self.topics.lock().unwrap().values() {
             for tag in &topic.tags {
                 *tag_counts.entry(tag.clone()).or_insert(0) += 1;
             }
@@ -110,21 +140,43 @@ impl TopicPort for TopicRepoMock {
     }
 
     async fn insert(&mut self, topic: &Topic) -> Result<(), Box<dyn std::error::Error>> {
-        self.topics.insert(topic.id.clone(), topic.clone());
+        self.topics.lock().unwrap().insert(topic.id.clone(), topic.clone());
         Ok(())
     }
 
     async fn update_topic(&mut self, topic: &Topic) -> Result<(), Box<dyn std::error::Error>> {
-        self.topics.insert(topic.id.clone(), topic.clone());
+        self.topics.lock().unwrap().insert(topic.id.clone(), topic.clone());
         Ok(())
     }
 
-    async fn cron_topic_check(&mut self, _now: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
-        Ok(())
+    async fn close(&mut self, id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        match self.topics.lock().unwrap().get_mut(id) {
+            Some(topic) if !topic.base().is_closed => {
+                topic.base_mut().close();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
     }
 
-    async fn find(&mut self, query: &TopicQuery, _skip: i32, _limit: i32) -> Result<Vec<Topic>, Box<dyn std::error::Error>> {
-        let mut topics = self.topics.values().cloned().collect::<Vec<_>>();
+    async fn find_expired(&mut self, lifecycle: &TopicLifecycle, now: DateTime<Utc>) -> Result<Vec<Topic>, Box<dyn std::error::Error>> {
+        Ok(self
+            .topics
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|topic| topic.base().should_close(lifecycle, now))
+            .cloned()
+            .collect())
+    }
+
+    async fn find(&mut self, query: &TopicQuery, skip: i32, limit: i32) -> Result<Vec<Topic>, Box<dyn std::error::Error>> {
+        if query.title.is_some() {
+            let hits = self.search(query, skip, limit).await?;
+            return Ok(hits.into_iter().map(|hit| hit.item).collect());
+        }
+
+        let mut topics = self.topics.lock().unwrap().values().cloned().collect::<Vec<_>>();
 
         if let Some(active_only) = query.active_only {
             topics.retain(|t| t.active == active_only);
@@ -142,22 +194,52 @@ impl TopicPort for TopicRepoMock {
             topics.retain(|t| tags.iter().all(|tag| t.tags.contains(tag)));
         }
 
-        if let Some(title) = &query.title {
-            topics.retain(|t| t.title.contains(title));
-        }
-
         Ok(topics)
     }
 
+    async fn search(&mut self, query: &TopicQuery, skip: i32, limit: i32) -> Result<Vec<SearchHit<Topic>>, Box<dyn std::error::Error>> {
+        let search_query = match query.title.as_deref().and_then(SearchQuery::parse) {
+            Some(search_query) => search_query,
+            None => return Ok(Vec::new()),
+        };
+
+        let filter = TopicQuery {
+            title: None,
+            ..query.clone()
+        };
+        let mut hits = self
+            .find(&filter, 0, i32::MAX)
+            .await?
+            .into_iter()
+            .filter_map(|topic| {
+                let title = topic.base().title.clone();
+                search_query.hit(topic, &title)
+            })
+            .collect::<Vec<_>>();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| b.item.base().updated_at.cmp(&a.item.base().updated_at))
+        });
+
+        Ok(hits
+            .into_iter()
+            .skip(skip as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
     async fn subscription_user_ids(&mut self, topic_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        Ok(self.subscriptions
+        Ok(self.subscriptions.lock().unwrap()
             .get(topic_id)
             .map(|s| s.iter().cloned().collect())
             .unwrap_or_default())
     }
 
     async fn enable_subscription(&mut self, topic_id: &str, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        self.subscriptions
+        self.subscriptions.lock().unwrap()
             .entry(topic_id.to_string())
             .or_insert_with(HashSet::new)
             .insert(user_id.to_string());
@@ -165,14 +247,14 @@ impl TopicPort for TopicRepoMock {
     }
 
     async fn disable_subscription(&mut self, topic_id: &str, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(subscribers) = self.subscriptions.get_mut(topic_id) {
+        if let Some(subscribers) = self.subscriptions.lock().unwrap().get_mut(topic_id) {
             subscribers.remove(user_id);
         }
         Ok(())
     }
 
     async fn get_subscription(&mut self, topic_id: &str, user_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
-        Ok(self.subscriptions
+        Ok(self.subscriptions.lock().unwrap()
             .get(topic_id)
             .map(|s| s.contains(user_id))
             .unwrap_or(false))
@@ -1,18 +1,92 @@
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
+use crate::adapters::pg_db::PgDb;
+use crate::at_error::AtError;
 use std::collections::HashMap;
 
 use crate::entities::{Topic, TopicType};
-use crate::ports::topic::TopicPort;
+use crate::entities::topic::TopicLifecycle;
+use crate::entities::search_query::{SearchHit, SearchQuery};
+use crate::ports::topic::{TopicPort, TopicQuery};
 
+const CONFLICT_MESSAGE: &str = "トピックが他の操作で更新されました";
+
+#[derive(Clone)]
 pub struct TopicRepo {
-    pool: PgPool,
+    db: PgDb,
 }
 
 impl TopicRepo {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_db(PgDb::new(pool))
+    }
+
+    pub fn with_db(db: PgDb) -> Self {
+        Self { db }
+    }
+
+    // 条件に合うトピックのidを返す。searchが指定されている場合はpg_trgmの類似度順
+    //
+    // 3文字未満のtermはトライグラムを作れずインデックスを使えないので、
+    // 1〜2文字の検索は他の条件で絞り込んだ行を順に走査する
+    async fn find_ids(
+        &self,
+        query: &TopicQuery,
+        search: Option<&SearchQuery>,
+        skip: i32,
+        limit: i32,
+    ) -> Result<Vec<(String, f64)>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, COALESCE(similarity(title, $6), 0)::float8 as "score!"
+            FROM topics
+            WHERE ($1::text[] IS NULL OR id = ANY($1))
+              AND ($2::bool IS NOT TRUE OR active)
+              AND ($3::text IS NULL OR parent_id = $3)
+              AND ($4::text[] IS NULL OR (
+                SELECT COUNT(DISTINCT tag) FROM topic_tags
+                WHERE topic_tags.topic_id = topics.id AND tag = ANY($4)
+              ) = cardinality($4))
+              AND ($5::text[] IS NULL OR title ILIKE ALL($5))
+            ORDER BY 2 DESC, age_updated_at DESC
+            LIMIT $7 OFFSET $8
+            "#,
+            query.id.as_deref(),
+            query.active_only,
+            query.parent,
+            query.tags.as_deref(),
+            search.map(|search| search.like_patterns()).as_deref(),
+            search.map(|search| search.raw()),
+            limit as i64,
+            skip as i64
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.score)).collect())
+    }
+
+    // 1回のクエリでまとめて取得し、idsの順に並べる。見つからなかったidは飛ばす
+    async fn find_by_ids(&self, ids: &[String]) -> Result<Vec<Topic>, Box<dyn std::error::Error>> {
+        let topics = sqlx::query_as!(
+            Topic,
+            r#"
+            SELECT id, title, text, created_at, updated_at, user_id, topic_type as "topic_type: TopicType",
+                   res_count, hash, one, profile_id, age, history_id, fork_id, version
+            FROM topics
+            WHERE id = ANY($1)
+            "#,
+            ids
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        let mut topics = topics
+            .into_iter()
+            .map(|topic| (topic.id.clone(), topic))
+            .collect::<HashMap<_, _>>();
+        Ok(ids.iter().filter_map(|id| topics.remove(id)).collect())
     }
 }
 
@@ -23,13 +97,13 @@ impl TopicPort for TopicRepo {
             Topic,
             r#"
             SELECT id, title, text, created_at, updated_at, user_id, topic_type as "topic_type: TopicType",
-                   res_count, hash, one, profile_id, age, history_id, fork_id
+                   res_count, hash, one, profile_id, age, history_id, fork_id, version
             FROM topics
             WHERE id = $1
             "#,
             id
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(topic)
@@ -45,7 +119,7 @@ impl TopicPort for TopicRepo {
             Topic,
             r#"
             SELECT id, title, text, created_at, updated_at, user_id, topic_type as "topic_type: TopicType",
-                   res_count, hash, one, profile_id, age, history_id, fork_id
+                   res_count, hash, one, profile_id, age, history_id, fork_id, version
             FROM topics
             WHERE user_id = $1
             ORDER BY created_at DESC
@@ -55,7 +129,7 @@ impl TopicPort for TopicRepo {
             limit,
             offset
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(topics)
@@ -66,18 +140,92 @@ impl TopicPort for TopicRepo {
             Topic,
             r#"
             SELECT id, title, text, created_at, updated_at, user_id, topic_type as "topic_type: TopicType",
-                   res_count, hash, one, profile_id, age, history_id, fork_id
+                   res_count, hash, one, profile_id, age, history_id, fork_id, version
             FROM topics
             WHERE hash = $1
             "#,
             hash
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(topic)
     }
 
+    async fn find(&mut self, query: &TopicQuery, skip: i32, limit: i32) -> Result<Vec<Topic>, Box<dyn std::error::Error>> {
+        if query.title.is_some() {
+            let hits = self.search(query, skip, limit).await?;
+            return Ok(hits.into_iter().map(|hit| hit.item).collect());
+        }
+
+        let ids = self
+            .find_ids(query, None, skip, limit)
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        self.find_by_ids(&ids).await
+    }
+
+    async fn search(&mut self, query: &TopicQuery, skip: i32, limit: i32) -> Result<Vec<SearchHit<Topic>>, Box<dyn std::error::Error>> {
+        let search_query = match query.title.as_deref().and_then(SearchQuery::parse) {
+            Some(search_query) => search_query,
+            None => return Ok(Vec::new()),
+        };
+
+        let scored = self.find_ids(query, Some(&search_query), skip, limit).await?;
+        let ids = scored.iter().map(|(id, _)| id.clone()).collect::<Vec<_>>();
+        let scores = scored.into_iter().collect::<HashMap<_, _>>();
+
+        Ok(self
+            .find_by_ids(&ids)
+            .await?
+            .into_iter()
+            .map(|topic| SearchHit {
+                score: scores.get(&topic.id).copied().unwrap_or(0.0),
+                snippet: search_query.snippet(&topic.base().title),
+                item: topic,
+            })
+            .collect())
+    }
+
+    async fn close(&mut self, id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE topics
+            SET active = false
+            WHERE id = $1 AND active
+            "#,
+            id
+        )
+        .execute(&mut *self.db.acquire().await?)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn find_expired(&mut self, lifecycle: &TopicLifecycle, now: DateTime<Utc>) -> Result<Vec<Topic>, Box<dyn std::error::Error>> {
+        let ids = sqlx::query_scalar!(
+            r#"
+            SELECT id
+            FROM topics
+            WHERE active AND (
+                (type = 'normal' AND $1::timestamptz IS NOT NULL AND age_updated_at <= $1)
+                OR (type = 'one' AND (updated_at <= $2 OR created_at <= $3))
+                OR (type = 'fork' AND updated_at <= $4)
+            )
+            "#,
+            lifecycle.normal_inactive.map(|inactive| now - inactive),
+            now - lifecycle.one_inactive,
+            now - lifecycle.one_lifetime,
+            now - lifecycle.fork_inactive
+        )
+        .fetch_all(&mut *self.db.acquire().await?)
+        .await?;
+
+        self.find_by_ids(&ids).await
+    }
+
     async fn create(&self, topic: &Topic) -> Result<(), Box<dyn std::error::Error>> {
         sqlx::query!(
             r#"
@@ -100,20 +248,21 @@ impl TopicPort for TopicRepo {
             topic.history_id,
             topic.fork_id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
     }
 
     async fn update(&self, topic: &Topic) -> Result<(), Box<dyn std::error::Error>> {
-        sqlx::query!(
+        let result = sqlx::query!(
             r#"
             UPDATE topics
             SET title = $1, text = $2, updated_at = $3, topic_type = $4,
                 res_count = $5, hash = $6, one = $7, profile_id = $8,
-                age = $9, history_id = $10, fork_id = $11
-            WHERE id = $12
+                age = $9, history_id = $10, fork_id = $11,
+                version = version + 1
+            WHERE id = $12 AND version = $13
             "#,
             topic.title,
             topic.text,
@@ -126,11 +275,16 @@ impl TopicPort for TopicRepo {
             topic.age,
             topic.history_id,
             topic.fork_id,
-            topic.id
+            topic.id,
+            topic.version
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(Box::new(AtError::Conflict(CONFLICT_MESSAGE.to_string())));
+        }
+
         Ok(())
     }
 
@@ -145,7 +299,7 @@ impl TopicPort for TopicRepo {
             Utc::now(),
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -162,7 +316,7 @@ impl TopicPort for TopicRepo {
             Utc::now(),
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -177,7 +331,7 @@ impl TopicPort for TopicRepo {
             "#,
             topic_type as TopicType
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?
         .count
         .unwrap_or(0);
@@ -194,7 +348,7 @@ impl TopicPort for TopicRepo {
             "#,
             user_id
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?
         .count
         .unwrap_or(0);
@@ -1,5 +1,9 @@
 use super::*;
-use crate::entities::{Topic, TopicType};
+use crate::adapters::clock::fix_clock::FixClock;
+use crate::adapters::seq_id_generator::SeqIdGenerator;
+use crate::entities::{Topic, TopicNormal, TopicType};
+use crate::entities::search_query::SearchHighlight;
+use crate::ports::topic::TopicQuery;
 use chrono::Utc;
 
 #[tokio::test]
@@ -125,4 +129,50 @@ async fn test_topic_repo() {
     // Test count_by_user_id
     let count = repo.count_by_user_id("user1").await.unwrap();
     assert_eq!(count, 1);
-} 
\ No newline at end of file
+} 
+#[tokio::test]
+async fn test_topic_repo_mock_search() {
+    let mut repo = TopicRepoMock::new();
+    let id_gen = SeqIdGenerator::new();
+    let clock = FixClock::new(Utc::now());
+
+    let mut ids = Vec::new();
+    for title in ["雑談スレ", "なんでも雑談スレッド part2", "質問スレ"] {
+        let topic = Topic::Normal(TopicNormal::create(
+            &id_gen,
+            &clock,
+            title.to_string(),
+            "text".to_string(),
+            "user1".to_string(),
+            vec![],
+        ));
+        ids.push(topic.base().id.clone());
+        repo.insert(&topic).await.unwrap();
+    }
+
+    let query = TopicQuery {
+        active_only: None,
+        id: None,
+        parent: None,
+        tags: None,
+        title: Some("雑談\u{3000}スレ".to_string()),
+    };
+
+    // 全てのtermを含むものだけが関連度順に返る
+    let hits = repo.search(&query, 0, 10).await.unwrap();
+    let hit_ids = hits.iter().map(|hit| hit.item.base().id.as_str()).collect::<Vec<_>>();
+    assert_eq!(hit_ids, vec![ids[0].as_str(), ids[1].as_str()]);
+    assert!(hits[0].score > hits[1].score);
+    assert_eq!(hits[0].snippet.text, "雑談スレ");
+    assert_eq!(hits[0].snippet.highlights, vec![SearchHighlight { start: 0, end: 4 }]);
+
+    // findもtitleがあれば関連度順
+    let topics = repo.find(&query, 0, 10).await.unwrap();
+    let hit_ids = topics.iter().map(|topic| topic.base().id.as_str()).collect::<Vec<_>>();
+    assert_eq!(hit_ids, vec![ids[0].as_str(), ids[1].as_str()]);
+
+    // skip/limit
+    let hits = repo.search(&query, 1, 10).await.unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].item.base().id, ids[1]);
+}
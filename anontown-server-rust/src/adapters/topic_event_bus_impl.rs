@@ -0,0 +1,144 @@
+use async_trait::async_trait;
+use futures::stream;
+use futures::StreamExt;
+use std::sync::Arc;
+
+use crate::at_error::{AtError, AtResult};
+use crate::entities::topic_event::{TopicEvent, TopicEventRecord};
+use crate::ports::topic_event_bus::{TopicEventBus, TopicEventSubscription};
+
+// ResRepo::subscribe_insert_eventと同じチャンネルに流す
+pub const TOPIC_EVENT_CHANNEL: &str = "res/add";
+const TOPIC_EVENT_SEQ_KEY: &str = "topic_event:seq";
+const TOPIC_EVENT_LOG_KEY: &str = "topic_event:log";
+// 再接続時に遡れるイベントの数
+const TOPIC_EVENT_BACKLOG: i64 = 1000;
+
+// 連番の採番、ログへの追加、publishをアトミックに行う
+// KEYS[1]: 連番, KEYS[2]: 連番をスコアにしたログのsorted set
+// ARGV[1]: イベントのJSON, ARGV[2]: ログに残す数, ARGV[3]: チャンネル
+const PUBLISH_SCRIPT: &str = r#"
+local seq = redis.call('INCR', KEYS[1])
+local message = '{"seq":' .. seq .. ',"event":' .. ARGV[1] .. '}'
+redis.call('ZADD', KEYS[2], seq, message)
+redis.call('ZREMRANGEBYRANK', KEYS[2], 0, -tonumber(ARGV[2]) - 1)
+redis.call('PUBLISH', ARGV[3], message)
+return seq
+"#;
+
+pub struct TopicEventBusImpl {
+    redis: Arc<redis::Client>,
+}
+
+impl TopicEventBusImpl {
+    pub fn new(redis: Arc<redis::Client>) -> Self {
+        Self { redis }
+    }
+}
+
+fn parse_record(message: &str) -> AtResult<TopicEventRecord> {
+    serde_json::from_str(message).map_err(|e| AtError::Internal(e.into()))
+}
+
+#[async_trait]
+impl TopicEventBus for TopicEventBusImpl {
+    async fn publish(&self, event: &TopicEvent) -> AtResult<i64> {
+        let event = serde_json::to_string(event).map_err(|e| AtError::Internal(e.into()))?;
+        let mut conn = self
+            .redis
+            .get_async_connection()
+            .await
+            .map_err(|e| AtError::Internal(e.into()))?;
+        redis::Script::new(PUBLISH_SCRIPT)
+            .key(TOPIC_EVENT_SEQ_KEY)
+            .key(TOPIC_EVENT_LOG_KEY)
+            .arg(event)
+            .arg(TOPIC_EVENT_BACKLOG)
+            .arg(TOPIC_EVENT_CHANNEL)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(|e| AtError::Internal(e.into()))
+    }
+
+    async fn subscribe(&self, after: Option<i64>) -> AtResult<TopicEventSubscription> {
+        // 取りこぼさないように先に購読してからログを読む
+        let mut pubsub = self
+            .redis
+            .get_async_connection()
+            .await
+            .map_err(|e| AtError::Internal(e.into()))?
+            .into_pubsub();
+        pubsub
+            .subscribe(TOPIC_EVENT_CHANNEL)
+            .await
+            .map_err(|e| AtError::Internal(e.into()))?;
+
+        // 取りこぼしを判定できるように、ログと最新の連番を同時に読む
+        let (backlog, seq): (Vec<String>, Option<i64>) = match after {
+            Some(after) => {
+                let mut conn = self
+                    .redis
+                    .get_async_connection()
+                    .await
+                    .map_err(|e| AtError::Internal(e.into()))?;
+                redis::pipe()
+                    .atomic()
+                    .zrangebyscore(TOPIC_EVENT_LOG_KEY, format!("({}", after), "+inf")
+                    .get(TOPIC_EVENT_SEQ_KEY)
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| AtError::Internal(e.into()))?
+            }
+            None => (Vec::new(), None),
+        };
+        // 読めないメッセージが1件あっても購読できるように、ログに残して読み飛ばす
+        let backlog = backlog
+            .iter()
+            .filter_map(|message| match parse_record(message) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    log::warn!("skipped unreadable topic event: {}", e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // 直後の連番がログから消えているか、まだ振られていない連番を指定されたら取りこぼしている
+        let missed = match after {
+            Some(after) => match backlog.first() {
+                Some(record) => record.seq > after + 1,
+                None => seq.unwrap_or(0) != after,
+            },
+            None => false,
+        };
+
+        // ログを読む前に購読したメッセージはログと重複するので読み飛ばす
+        let mut last_seq = backlog.last().map(|record| record.seq).or(after);
+        let live = pubsub.into_on_message().filter_map(move |msg| {
+            let record = msg
+                .get_payload::<String>()
+                .map_err(|e| AtError::Internal(e.into()))
+                .and_then(|payload| parse_record(&payload));
+            let record = match record {
+                Ok(record) if last_seq.map_or(false, |last| record.seq <= last) => None,
+                Ok(record) => {
+                    last_seq = Some(record.seq);
+                    Some(Ok(record))
+                }
+                // 読めないメッセージで購読を終わらせず、読み飛ばして次を待つ
+                Err(e) => {
+                    log::warn!("skipped unreadable topic event: {}", e);
+                    None
+                }
+            };
+            async move { record }
+        });
+
+        Ok(TopicEventSubscription {
+            missed,
+            events: stream::iter(backlog.into_iter().map(Ok))
+                .chain(live)
+                .boxed(),
+        })
+    }
+}
@@ -0,0 +1,136 @@
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+use crate::at_error::{AtError, AtResult};
+use crate::entities::topic_event::{TopicEvent, TopicEventRecord};
+use crate::ports::topic_event_bus::{TopicEventBus, TopicEventSubscription};
+
+const CHANNEL_CAPACITY: usize = 1024;
+// TopicEventBusImplと同じく、再接続時に遡れるイベントの数
+const DEFAULT_BACKLOG: usize = 1000;
+
+pub struct TopicEventBusMockImpl {
+    log: Mutex<Vec<TopicEventRecord>>,
+    backlog: usize,
+    sender: broadcast::Sender<TopicEventRecord>,
+}
+
+impl TopicEventBusMockImpl {
+    pub fn new() -> Self {
+        Self::with_backlog(DEFAULT_BACKLOG)
+    }
+
+    /// 再接続時に遡れるイベントの数を指定して作る
+    pub fn with_backlog(backlog: usize) -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            log: Mutex::new(Vec::new()),
+            backlog,
+            sender,
+        }
+    }
+
+    /// これまでに配信したイベント
+    pub fn events(&self) -> Vec<TopicEvent> {
+        self.log
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|record| record.event.clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl TopicEventBus for TopicEventBusMockImpl {
+    async fn publish(&self, event: &TopicEvent) -> AtResult<i64> {
+        let mut log = self.log.lock().unwrap();
+        let record = TopicEventRecord {
+            seq: log.len() as i64 + 1,
+            event: event.clone(),
+        };
+        log.push(record.clone());
+        // 購読者がいなければ送れないが、ログには残る
+        let _ = self.sender.send(record.clone());
+        Ok(record.seq)
+    }
+
+    async fn subscribe(&self, after: Option<i64>) -> AtResult<TopicEventSubscription> {
+        // ログのロック中に購読するのでpublishと重複も取りこぼしもしない
+        let log = self.log.lock().unwrap();
+        // 遡れるのは直近のイベントだけ
+        let retained = &log[log.len().saturating_sub(self.backlog)..];
+        let backlog = match after {
+            Some(after) => retained
+                .iter()
+                .filter(|record| record.seq > after)
+                .cloned()
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        let missed = match after {
+            Some(after) => match backlog.first() {
+                Some(record) => record.seq > after + 1,
+                None => log.len() as i64 != after,
+            },
+            None => false,
+        };
+        let receiver = self.sender.subscribe();
+        drop(log);
+
+        let live = stream::unfold(receiver, |mut receiver| async move {
+            match receiver.recv().await {
+                Ok(record) => Some((Ok(record), receiver)),
+                Err(broadcast::error::RecvError::Closed) => None,
+                Err(e) => Some((Err(AtError::Internal(e.into())), receiver)),
+            }
+        });
+
+        Ok(TopicEventSubscription {
+            missed,
+            events: stream::iter(backlog.into_iter().map(Ok))
+                .chain(live)
+                .boxed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::topic_event_bus::run_topic_event_bus_laws;
+
+    #[tokio::test]
+    async fn test_topic_event_bus_mock_impl() {
+        run_topic_event_bus_laws(&TopicEventBusMockImpl::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_after_trimmed_backlog() {
+        let bus = TopicEventBusMockImpl::with_backlog(2);
+        for topic in ["topic1", "topic2", "topic3"] {
+            bus.publish(&TopicEvent::TopicClosed {
+                topic: topic.to_string(),
+            })
+            .await
+            .unwrap();
+        }
+
+        // 連番2は残っているので取りこぼしはない
+        assert!(!bus.subscribe(Some(1)).await.unwrap().missed);
+        // 連番1は消えている
+        let subscription = bus.subscribe(Some(0)).await.unwrap();
+        assert!(subscription.missed);
+        // 残っているイベントは流れる
+        let records = subscription
+            .events
+            .take(2)
+            .map(|record| record.unwrap().seq)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(records, vec![2, 3]);
+    }
+}
@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+use crate::adapters::pg_db::PgDb;
+use crate::adapters::{
+    HistoryRepo, ProfileRepo, RecoveryCodeRepoImpl, ResRepo, StorageRepo, TokenRepoImpl,
+    TopicEventBusImpl, TopicRepo, UserRepo,
+};
+use crate::ports::unit_of_work::{Repos, UnitOfWork, UnitOfWorkRepos};
+
+pub struct UnitOfWorkImpl {
+    pool: PgPool,
+    redis: Arc<redis::Client>,
+}
+
+impl UnitOfWorkImpl {
+    pub fn new(pool: PgPool, redis: Arc<redis::Client>) -> Self {
+        Self { pool, redis }
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for UnitOfWorkImpl {
+    type TopicRepo = TopicRepo;
+    type UserRepo = UserRepo;
+    type ResRepo = ResRepo;
+    type HistoryRepo = HistoryRepo;
+    type ProfileRepo = ProfileRepo;
+    type StorageRepo = StorageRepo;
+    type TokenRepo = TokenRepoImpl;
+    type RecoveryCodeRepo = RecoveryCodeRepoImpl;
+
+    async fn run<T, F>(&self, f: F) -> Result<T, Box<dyn std::error::Error>>
+    where
+        T: Send,
+        F: for<'a> FnOnce(
+                &'a mut UnitOfWorkRepos<Self>,
+            ) -> BoxFuture<'a, Result<T, Box<dyn std::error::Error>>>
+            + Send,
+    {
+        let db = PgDb::begin(self.pool.clone()).await?;
+        let mut repos = Repos {
+            topic_repo: TopicRepo::with_db(db.clone()),
+            user_repo: UserRepo::with_db(db.clone()),
+            res_repo: ResRepo::with_db(db.clone(), self.redis.clone()),
+            history_repo: HistoryRepo::with_db(db.clone()),
+            profile_repo: ProfileRepo::with_db(db.clone()),
+            storage_repo: StorageRepo::with_db(db.clone()),
+            token_repo: TokenRepoImpl::with_db(db.clone()),
+            recovery_code_repo: RecoveryCodeRepoImpl::with_db(db.clone()),
+        };
+
+        let result = f(&mut repos).await;
+        // コミットするにはトランザクションを共有している参照を全て手放す必要がある
+        drop(repos);
+
+        match result {
+            Ok(value) => {
+                db.commit(&TopicEventBusImpl::new(self.redis.clone())).await?;
+                Ok(value)
+            }
+            Err(e) => {
+                // ロールバックに失敗しても、呼び出し元には失敗の原因になったエラーを返す
+                if let Err(rollback_error) = db.rollback().await {
+                    log::error!("failed to roll back transaction: {}", rollback_error);
+                }
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+
+use crate::adapters::mock_store::MockSnapshot;
+use crate::adapters::{
+    HistoryRepoMock, ProfileRepoMock, RecoveryCodeRepoMockImpl, ResRepoMock, StorageRepoMock,
+    TokenRepoMockImpl, TopicRepoMock, UserRepoMock,
+};
+use crate::ports::unit_of_work::{Repos, UnitOfWork, UnitOfWorkRepos};
+
+pub type MockRepos = Repos<
+    TopicRepoMock,
+    UserRepoMock,
+    ResRepoMock,
+    HistoryRepoMock,
+    ProfileRepoMock,
+    StorageRepoMock,
+    TokenRepoMockImpl,
+    RecoveryCodeRepoMockImpl,
+>;
+
+/// ポートに渡したモックのリポジトリにそのまま書き込み、失敗した時は実行前の状態に戻す
+///
+/// モックはデータを共有しているので、`repos`にはポートに渡したモックの複製を渡す。
+/// 実行中は他の`run`を待たせるので、並行して実行しても書き込みは失われない
+pub struct UnitOfWorkMockImpl {
+    pub repos: MockRepos,
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl UnitOfWorkMockImpl {
+    pub fn new(repos: MockRepos) -> Self {
+        Self {
+            repos,
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn snapshot(&self) -> impl FnOnce() + '_ {
+        let topic = self.repos.topic_repo.snapshot();
+        let user = self.repos.user_repo.snapshot();
+        let res = self.repos.res_repo.snapshot();
+        let history = self.repos.history_repo.snapshot();
+        let profile = self.repos.profile_repo.snapshot();
+        let storage = self.repos.storage_repo.snapshot();
+        let token = self.repos.token_repo.snapshot();
+        let recovery_code = self.repos.recovery_code_repo.snapshot();
+        move || {
+            self.repos.topic_repo.restore(topic);
+            self.repos.user_repo.restore(user);
+            self.repos.res_repo.restore(res);
+            self.repos.history_repo.restore(history);
+            self.repos.profile_repo.restore(profile);
+            self.repos.storage_repo.restore(storage);
+            self.repos.token_repo.restore(token);
+            self.repos.recovery_code_repo.restore(recovery_code);
+        }
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for UnitOfWorkMockImpl {
+    type TopicRepo = TopicRepoMock;
+    type UserRepo = UserRepoMock;
+    type ResRepo = ResRepoMock;
+    type HistoryRepo = HistoryRepoMock;
+    type ProfileRepo = ProfileRepoMock;
+    type StorageRepo = StorageRepoMock;
+    type TokenRepo = TokenRepoMockImpl;
+    type RecoveryCodeRepo = RecoveryCodeRepoMockImpl;
+
+    async fn run<T, F>(&self, f: F) -> Result<T, Box<dyn std::error::Error>>
+    where
+        T: Send,
+        F: for<'a> FnOnce(
+                &'a mut UnitOfWorkRepos<Self>,
+            ) -> BoxFuture<'a, Result<T, Box<dyn std::error::Error>>>
+            + Send,
+    {
+        let _guard = self.lock.lock().await;
+        let rollback = self.snapshot();
+        let mut repos = self.repos.clone();
+        match f(&mut repos).await {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                rollback();
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::entities::User;
+    use crate::ports::user::UserPort;
+
+    fn user(id_gen: &SeqIdGenerator, sn: &str) -> User {
+        User::create(
+            id_gen,
+            sn.to_string(),
+            "name".to_string(),
+            "".to_string(),
+            "pass".to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_unit_of_work_mock_impl() {
+        let id_gen = SeqIdGenerator::new();
+        let user_repo = UserRepoMock::new();
+        let unit_of_work = UnitOfWorkMockImpl::new(Repos {
+            topic_repo: TopicRepoMock::new(),
+            user_repo: user_repo.clone(),
+            res_repo: ResRepoMock::new(),
+            history_repo: HistoryRepoMock::new(),
+            profile_repo: ProfileRepoMock::new(),
+            storage_repo: StorageRepoMock::new(),
+            token_repo: TokenRepoMockImpl::new(),
+            recovery_code_repo: RecoveryCodeRepoMockImpl::new(),
+        });
+        let a = user(&id_gen, "sn_a");
+        let b = user(&id_gen, "sn_b");
+        let c = user(&id_gen, "sn_c");
+
+        // 失敗したら途中までの書き込みもポートに渡したモックから消える
+        let result: Result<(), _> = unit_of_work
+            .run(|repos| {
+                Box::pin(async {
+                    repos.user_repo.create(&a).await?;
+                    Err("error".into())
+                })
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(user_repo.find_by_id(&a.id).await.unwrap().is_none());
+
+        // 成功したら全ての書き込みがポートに渡したモックから見える
+        unit_of_work
+            .run(|repos| {
+                Box::pin(async {
+                    repos.user_repo.create(&a).await?;
+                    repos.user_repo.create(&b).await?;
+                    Ok(())
+                })
+            })
+            .await
+            .unwrap();
+        assert!(user_repo.find_by_id(&a.id).await.unwrap().is_some());
+        assert!(user_repo.find_by_id(&b.id).await.unwrap().is_some());
+
+        // 後から失敗しても、先に成功した書き込みは戻さない
+        let result: Result<(), _> = unit_of_work
+            .run(|repos| {
+                Box::pin(async {
+                    repos.user_repo.create(&c).await?;
+                    Err("error".into())
+                })
+            })
+            .await;
+        assert!(result.is_err());
+        assert!(user_repo.find_by_id(&a.id).await.unwrap().is_some());
+        assert!(user_repo.find_by_id(&c.id).await.unwrap().is_none());
+    }
+}
@@ -1,64 +1,91 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
+use crate::at_error::AtError;
 use crate::entities::{User, UserType};
 use crate::ports::user::UserPort;
+use crate::adapters::mock_store::{MockSnapshot, MockStore};
 
+#[derive(Clone)]
 pub struct UserRepoMock {
-    users: HashMap<String, User>,
+    users: MockStore<HashMap<String, User>>,
 }
 
 impl UserRepoMock {
     pub fn new() -> Self {
         Self {
-            users: HashMap::new(),
+            users: MockStore::new(HashMap::new()),
         }
     }
 }
 
+impl MockSnapshot for UserRepoMock {
+    type Snapshot = HashMap<String, User>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        self.users.snapshot()
+    }
+
+    fn restore(&self, snapshot: Self::Snapshot) {
+        self.users.restore(snapshot);
+    }
+}
+
 #[async_trait]
 impl UserPort for UserRepoMock {
     async fn find_by_id(&self, id: &str) -> Result<Option<User>, Box<dyn std::error::Error>> {
-        Ok(self.users.get(id).cloned())
+        Ok(self.users.lock().unwrap().get(id).cloned())
     }
 
     async fn find_by_sn(&self, sn: &str) -> Result<Option<User>, Box<dyn std::error::Error>> {
-        Ok(self.users
+        Ok(self.users.lock().unwrap()
             .values()
             .find(|user| user.sn == sn)
             .cloned())
     }
 
+    async fn find_by_screen_name(&self, screen_name: &str) -> Result<Option<User>, Box<dyn std::error::Error>> {
+        Ok(self.users.lock().unwrap()
+            .values()
+            .find(|user| user.screen_name == screen_name)
+            .cloned())
+    }
+
     async fn find_by_name(&self, name: &str) -> Result<Option<User>, Box<dyn std::error::Error>> {
-        Ok(self.users
+        Ok(self.users.lock().unwrap()
             .values()
             .find(|user| user.name == name)
             .cloned())
     }
 
     async fn create(&self, user: &User) -> Result<(), Box<dyn std::error::Error>> {
-        self.users.insert(user.id.clone(), user.clone());
+        self.users.lock().unwrap().insert(user.id.clone(), user.clone());
         Ok(())
     }
 
     async fn update(&self, user: &User) -> Result<(), Box<dyn std::error::Error>> {
-        if self.users.contains_key(&user.id) {
-            self.users.insert(user.id.clone(), user.clone());
-            Ok(())
-        } else {
-            Err("User not found".into())
+        let mut users = self.users.lock().unwrap();
+        match users.get(&user.id) {
+            Some(current) if current.version == user.version => {
+                let mut user = user.clone();
+                user.version += 1;
+                users.insert(user.id.clone(), user);
+                Ok(())
+            }
+            Some(_) => Err(Box::new(AtError::Conflict("ユーザーが他の操作で更新されました".to_string()))),
+            None => Err("User not found".into()),
         }
     }
 
     async fn count_by_type(&self, user_type: UserType) -> Result<i64, Box<dyn std::error::Error>> {
-        Ok(self.users
+        Ok(self.users.lock().unwrap()
             .values()
             .filter(|user| user.user_type == user_type)
             .count() as i64)
     }
 
     async fn update_res_count(&mut self, id: &str, count: i32) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(user) = self.users.get_mut(id) {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
             user.count_created_res_m10 += count;
             user.count_created_res_m30 += count;
             user.count_created_res_h1 += count;
@@ -70,14 +97,14 @@ impl UserPort for UserRepoMock {
     }
 
     async fn update_topic_count(&mut self, id: &str, _count: i32) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(user) = self.users.get_mut(id) {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
             user.topic_last_created_at = Utc::now();
         }
         Ok(())
     }
 
     async fn update_point(&self, id: &str, point: i64) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(user) = self.users.get_mut(id) {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
             user.point = point;
             user.updated_at = Utc::now();
             Ok(())
@@ -87,28 +114,28 @@ impl UserPort for UserRepoMock {
     }
 
     async fn update_res_last_created_at(&mut self, id: &str, created_at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(user) = self.users.get_mut(id) {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
             user.res_last_created_at = created_at;
         }
         Ok(())
     }
 
     async fn update_topic_last_created_at(&mut self, id: &str, created_at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(user) = self.users.get_mut(id) {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
             user.topic_last_created_at = created_at;
         }
         Ok(())
     }
 
     async fn update_one_topic_last_created_at(&mut self, id: &str, created_at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(user) = self.users.get_mut(id) {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
             user.one_topic_last_created_at = created_at;
         }
         Ok(())
     }
 
     async fn update_lv(&self, id: &str, lv: i64) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(user) = self.users.get_mut(id) {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
             user.lv = lv;
             user.updated_at = Utc::now();
             Ok(())
@@ -118,7 +145,7 @@ impl UserPort for UserRepoMock {
     }
 
     async fn update_age(&self, id: &str, age: bool) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(user) = self.users.get_mut(id) {
+        if let Some(user) = self.users.lock().unwrap().get_mut(id) {
             user.age = age;
             user.updated_at = Utc::now();
             Ok(())
@@ -1,17 +1,25 @@
 use async_trait::async_trait;
 use chrono::Utc;
 use sqlx::PgPool;
+use crate::adapters::pg_db::PgDb;
+use crate::at_error::AtError;
 
 use crate::entities::User;
 use crate::ports::user::UserPort;
 
+const CONFLICT_MESSAGE: &str = "ユーザーが他の操作で更新されました";
+
 pub struct UserRepo {
-    pool: PgPool,
+    db: PgDb,
 }
 
 impl UserRepo {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_db(PgDb::new(pool))
+    }
+
+    pub fn with_db(db: PgDb) -> Self {
+        Self { db }
     }
 }
 
@@ -21,13 +29,13 @@ impl UserPort for UserRepo {
         let user = sqlx::query_as!(
             User,
             r#"
-            SELECT id, name, sn, created_at, updated_at, lv, point, one, age, history_id
+            SELECT id, name, sn, created_at, updated_at, lv, point, one, age, history_id, version
             FROM users
             WHERE id = $1
             "#,
             id
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(user)
@@ -37,13 +45,13 @@ impl UserPort for UserRepo {
         let user = sqlx::query_as!(
             User,
             r#"
-            SELECT id, name, sn, created_at, updated_at, lv, point, one, age, history_id
+            SELECT id, name, sn, created_at, updated_at, lv, point, one, age, history_id, version
             FROM users
             WHERE sn = $1
             "#,
             sn
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(user)
@@ -66,19 +74,20 @@ impl UserPort for UserRepo {
             user.age,
             user.history_id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
     }
 
     async fn update(&self, user: &User) -> Result<(), Box<dyn std::error::Error>> {
-        sqlx::query!(
+        let result = sqlx::query!(
             r#"
             UPDATE users
             SET name = $1, sn = $2, updated_at = $3, lv = $4,
-                point = $5, one = $6, age = $7, history_id = $8
-            WHERE id = $9
+                point = $5, one = $6, age = $7, history_id = $8,
+                version = version + 1
+            WHERE id = $9 AND version = $10
             "#,
             user.name,
             user.sn,
@@ -88,11 +97,16 @@ impl UserPort for UserRepo {
             user.one,
             user.age,
             user.history_id,
-            user.id
+            user.id,
+            user.version
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(Box::new(AtError::Conflict(CONFLICT_MESSAGE.to_string())));
+        }
+
         Ok(())
     }
 
@@ -107,7 +121,7 @@ impl UserPort for UserRepo {
             Utc::now(),
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -124,7 +138,7 @@ impl UserPort for UserRepo {
             Utc::now(),
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -141,7 +155,7 @@ impl UserPort for UserRepo {
             Utc::now(),
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -1,16 +1,24 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use sqlx::PgPool;
+use crate::adapters::pg_db::PgDb;
+use crate::at_error::AtError;
 use crate::models::User;
 use crate::ports::user::UserPort;
 
+const CONFLICT_MESSAGE: &str = "ユーザーが他の操作で更新されました";
+
 pub struct UserRepo {
-    pool: PgPool,
+    db: PgDb,
 }
 
 impl UserRepo {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_db(PgDb::new(pool))
+    }
+
+    pub fn with_db(db: PgDb) -> Self {
+        Self { db }
     }
 }
 
@@ -29,7 +37,7 @@ impl UserPort for UserRepo {
             "#,
             id
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(user)
@@ -48,7 +56,7 @@ impl UserPort for UserRepo {
             "#,
             screen_name
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(user)
@@ -83,7 +91,7 @@ impl UserPort for UserRepo {
             user.point,
             user.one_topic_last_created_at
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(user)
@@ -99,8 +107,9 @@ impl UserPort for UserRepo {
                 count_created_res_m30 = $6, count_created_res_h1 = $7,
                 count_created_res_h6 = $8, count_created_res_h12 = $9,
                 count_created_res_d1 = $10, topic_last_created_at = $11,
-                created_at = $12, point = $13, one_topic_last_created_at = $14
-            WHERE id = $15
+                created_at = $12, point = $13, one_topic_last_created_at = $14,
+                version = version + 1
+            WHERE id = $15 AND version = $16
             RETURNING *
             "#,
             user.screen_name,
@@ -117,10 +126,12 @@ impl UserPort for UserRepo {
             user.created_at,
             user.point,
             user.one_topic_last_created_at,
-            user.id
+            user.id,
+            user.version
         )
-        .fetch_one(&self.pool)
-        .await?;
+        .fetch_optional(&mut *self.db.acquire().await?)
+        .await?
+        .ok_or_else(|| AtError::Conflict(CONFLICT_MESSAGE.to_string()))?;
 
         Ok(user)
     }
@@ -140,7 +151,7 @@ impl UserPort for UserRepo {
             count,
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -155,7 +166,7 @@ impl UserPort for UserRepo {
             "#,
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -171,7 +182,7 @@ impl UserPort for UserRepo {
             point,
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -187,7 +198,7 @@ impl UserPort for UserRepo {
             created_at,
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -203,7 +214,7 @@ impl UserPort for UserRepo {
             created_at,
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -219,7 +230,7 @@ impl UserPort for UserRepo {
             created_at,
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.db.acquire().await?)
         .await?;
 
         Ok(())
@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use crate::at_error::{AtError, AtResult};
+use crate::entities::user_totp::UserTotp;
+use crate::ports::UserTotpRepo;
+
+pub struct UserTotpRepoImpl {
+    pool: PgPool,
+}
+
+impl UserTotpRepoImpl {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserTotpRepo for UserTotpRepoImpl {
+    async fn find_one(&self, user_id: &str) -> AtResult<Option<UserTotp>> {
+        sqlx::query_as!(
+            UserTotp,
+            r#"
+            SELECT user_id, secret, enabled, last_used_step, backup_code_hashes,
+                created_at, updated_at, version
+            FROM user_totps
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))
+    }
+
+    async fn save(&self, totp: &UserTotp) -> AtResult<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO user_totps (user_id, secret, enabled, last_used_step, backup_code_hashes,
+                created_at, updated_at, version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (user_id) DO UPDATE SET
+                secret = EXCLUDED.secret,
+                enabled = EXCLUDED.enabled,
+                last_used_step = EXCLUDED.last_used_step,
+                backup_code_hashes = EXCLUDED.backup_code_hashes,
+                created_at = EXCLUDED.created_at,
+                updated_at = EXCLUDED.updated_at,
+                version = EXCLUDED.version
+            "#,
+            totp.user_id,
+            totp.secret,
+            totp.enabled,
+            totp.last_used_step,
+            &totp.backup_code_hashes,
+            totp.created_at,
+            totp.updated_at,
+            totp.version
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+
+    async fn update(&self, totp: &UserTotp) -> AtResult<()> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE user_totps
+            SET enabled = $1, last_used_step = $2, backup_code_hashes = $3, updated_at = $4,
+                version = version + 1
+            WHERE user_id = $5 AND version = $6
+            "#,
+            totp.enabled,
+            totp.last_used_step,
+            &totp.backup_code_hashes,
+            totp.updated_at,
+            totp.user_id,
+            totp.version
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(AtError::Conflict(
+                "二要素認証の設定が他で更新されました".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, user_id: &str) -> AtResult<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM user_totps
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| AtError::Internal(e.into()))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::at_error::{AtError, AtResult};
+use crate::entities::user_totp::UserTotp;
+use crate::ports::UserTotpRepo;
+
+pub struct UserTotpRepoMockImpl {
+    totps: Mutex<HashMap<String, UserTotp>>,
+}
+
+impl UserTotpRepoMockImpl {
+    pub fn new() -> Self {
+        Self {
+            totps: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl UserTotpRepo for UserTotpRepoMockImpl {
+    async fn find_one(&self, user_id: &str) -> AtResult<Option<UserTotp>> {
+        Ok(self.totps.lock().unwrap().get(user_id).cloned())
+    }
+
+    async fn save(&self, totp: &UserTotp) -> AtResult<()> {
+        self.totps
+            .lock()
+            .unwrap()
+            .insert(totp.user_id.clone(), totp.clone());
+        Ok(())
+    }
+
+    async fn update(&self, totp: &UserTotp) -> AtResult<()> {
+        let mut totps = self.totps.lock().unwrap();
+        match totps.get_mut(&totp.user_id) {
+            Some(stored) if stored.version == totp.version => {
+                *stored = UserTotp {
+                    version: totp.version + 1,
+                    ..totp.clone()
+                };
+                Ok(())
+            }
+            _ => Err(AtError::Conflict(
+                "二要素認証の設定が他で更新されました".to_string(),
+            )),
+        }
+    }
+
+    async fn delete(&self, user_id: &str) -> AtResult<()> {
+        self.totps.lock().unwrap().remove(user_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::user_totp_repo::run_user_totp_repo_laws;
+
+    #[tokio::test]
+    async fn test_user_totp_repo_mock_impl() {
+        run_user_totp_repo_laws(&UserTotpRepoMockImpl::new()).await;
+    }
+}
@@ -1,3 +1,5 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -28,7 +30,7 @@ impl AtError {
     fn to_message(&self) -> String {
         match self {
             AtError::Captcha => "キャプチャ認証に失敗".to_string(),
-            AtError::Params(msg) => msg.clone(),
+            AtError::Params(_) => "パラメーターが不正です".to_string(),
             AtError::Right(msg) => msg.clone(),
             AtError::Conflict(msg) => msg.clone(),
             AtError::Prerequisite(msg) => msg.clone(),
@@ -62,7 +64,13 @@ impl AtError {
         }
     }
 
+    /// クライアントに返す形にする
+    ///
+    /// 内部エラーの詳細はクライアントに返さず、ここでログに出す
     pub fn to_public(&self) -> AtErrorPublic {
+        if let AtError::Internal(e) = self {
+            log::error!("{:?}", e);
+        }
         AtErrorPublic {
             code: self.to_code(),
             message: self.to_message(),
@@ -82,8 +90,49 @@ impl fmt::Display for AtError {
 
 impl std::error::Error for AtError {}
 
+// GraphQL以外のHTTPのハンドラーやミドルウェアで返す時のステータスコード
+impl ResponseError for AtError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AtError::Captcha | AtError::Params(_) | AtError::Prerequisite(_) => StatusCode::BAD_REQUEST,
+            AtError::TokenAuth | AtError::Auth(_) | AtError::UserAuth => StatusCode::UNAUTHORIZED,
+            AtError::Right(_) => StatusCode::FORBIDDEN,
+            AtError::NotFound(_) => StatusCode::NOT_FOUND,
+            AtError::Conflict(_) => StatusCode::CONFLICT,
+            AtError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.to_public())
+    }
+}
+
+// UserPortなど`Box<dyn Error>`を返すポートのエラー。AtErrorが入っていればそのまま使う
+impl From<Box<dyn std::error::Error>> for AtError {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        match e.downcast::<AtError>() {
+            Ok(e) => *e,
+            Err(e) => AtError::Internal(anyhow::anyhow!(e.to_string())),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParamErrorData {
+    // `tags[2]`のような入力項目のパス
     pub field: String,
+    // `required`や`too_long`のような機械向けのコード
+    pub code: String,
     pub message: String,
 }
+
+impl ParamErrorData {
+    pub fn new(field: impl Into<String>, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
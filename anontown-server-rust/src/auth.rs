@@ -1,3 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+// `{id},{key}`形式でトークンを渡すHTTPヘッダー。WebSocketではconnection_initのpayloadのキーに使う
+pub const TOKEN_HEADER: &str = "X-Token";
 
 #[derive(Debug, Clone)]
 pub struct AuthTokenBase {
@@ -15,6 +19,83 @@ pub struct AuthTokenMaster {
 pub struct AuthTokenGeneral {
     pub base: AuthTokenBase,
     pub client: String,
+    pub scopes: Vec<TokenScope>,
+}
+
+/// 一般トークンでクライアントに許可する操作。マスタートークンは全ての操作ができる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    Read,
+    PostRes,
+    CreateTopic,
+    Vote,
+    Storage,
+    Profile,
+    // トピックの購読とプッシュ通知の登録
+    Subscribe,
+}
+
+impl TokenScope {
+    pub const ALL: [TokenScope; 7] = [
+        TokenScope::Read,
+        TokenScope::PostRes,
+        TokenScope::CreateTopic,
+        TokenScope::Vote,
+        TokenScope::Storage,
+        TokenScope::Profile,
+        TokenScope::Subscribe,
+    ];
+
+    /// DBやOAuthのscopeパラメーターで使う名前
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::PostRes => "post_res",
+            TokenScope::CreateTopic => "create_topic",
+            TokenScope::Vote => "vote",
+            TokenScope::Storage => "storage",
+            TokenScope::Profile => "profile",
+            TokenScope::Subscribe => "subscribe",
+        }
+    }
+
+    /// 認可画面でユーザーに見せる説明
+    pub fn description(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "トピックやレスなどの閲覧",
+            TokenScope::PostRes => "レスの書き込み",
+            TokenScope::CreateTopic => "トピックの作成",
+            TokenScope::Vote => "レスへの投票",
+            TokenScope::Storage => "ストレージの読み書き",
+            TokenScope::Profile => "プロフィールの作成と編集",
+            TokenScope::Subscribe => "トピックの購読とプッシュ通知の登録",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<TokenScope> {
+        TokenScope::ALL.into_iter().find(|scope| scope.as_str() == s)
+    }
+
+    /// OAuthのscopeパラメーターのようなスペース区切りの文字列を読む。知らない名前があれば`None`
+    pub fn parse_list(s: &str) -> Option<Vec<TokenScope>> {
+        let mut scopes = Vec::new();
+        for scope in s.split_whitespace() {
+            let scope = TokenScope::parse(scope)?;
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+        Some(scopes)
+    }
+
+    pub fn join(scopes: &[TokenScope]) -> String {
+        scopes
+            .iter()
+            .map(TokenScope::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -23,8 +104,58 @@ pub enum AuthToken {
     General(AuthTokenGeneral),
 }
 
+impl AuthToken {
+    pub fn has_scope(&self, scope: TokenScope) -> bool {
+        match self {
+            AuthToken::Master(_) => true,
+            AuthToken::General(token) => token.scopes.contains(&scope),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthUser {
     pub id: String,
     pub pass: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_scope() {
+        for scope in TokenScope::ALL {
+            assert_eq!(TokenScope::parse(scope.as_str()), Some(scope));
+        }
+        assert_eq!(
+            TokenScope::parse_list(" read  vote read "),
+            Some(vec![TokenScope::Read, TokenScope::Vote])
+        );
+        assert_eq!(TokenScope::parse_list(""), Some(vec![]));
+        assert_eq!(TokenScope::parse_list("read admin"), None);
+        assert_eq!(
+            TokenScope::join(&[TokenScope::PostRes, TokenScope::CreateTopic]),
+            "post_res create_topic"
+        );
+    }
+
+    #[test]
+    fn test_has_scope() {
+        let base = AuthTokenBase {
+            id: "token".to_string(),
+            key: "key".to_string(),
+            user: "user".to_string(),
+        };
+        let master = AuthToken::Master(AuthTokenMaster { base: base.clone() });
+        let general = AuthToken::General(AuthTokenGeneral {
+            base,
+            client: "client".to_string(),
+            scopes: vec![TokenScope::Read],
+        });
+
+        assert!(master.has_scope(TokenScope::Vote));
+        assert!(general.has_scope(TokenScope::Read));
+        assert!(!general.has_scope(TokenScope::Vote));
+    }
+}
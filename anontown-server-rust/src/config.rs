@@ -0,0 +1,102 @@
+use chrono::FixedOffset;
+use std::env;
+use std::net::IpAddr;
+
+use crate::entities::password::PasswordConfig;
+use crate::entities::topic::{HashConfig, TopicLifecycle};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub database_url: String,
+    pub redis_url: String,
+    pub hash: HashConfig,
+    pub topic_lifecycle: TopicLifecycle,
+    pub password: PasswordConfig,
+    // 非公開の情報を見られる管理者のユーザーID
+    pub admin_user_ids: Vec<String>,
+    // X-Forwarded-Forを信頼するリバースプロキシのIP。空なら接続元のIPをそのまま使う
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let port = env::var("PORT")
+            .unwrap_or_else(|_| "3000".to_string())
+            .parse::<u16>()
+            .expect("PORT must be a number");
+        let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let redis_url = env::var("REDIS_URL").expect("REDIS_URL must be set");
+
+        // 再起動してもIDが変わらないようにソルトは固定値を設定する
+        let salt = env::var("SALT_HASH").expect("SALT_HASH must be set");
+        let mut hash = HashConfig::jst(salt);
+        if let Ok(timezone) = env::var("HASH_TIMEZONE") {
+            hash.timezone = timezone
+                .parse::<FixedOffset>()
+                .expect("HASH_TIMEZONE must be an offset like +09:00");
+        }
+
+        let mut topic_lifecycle = TopicLifecycle::default();
+        if let Ok(res_limit) = env::var("TOPIC_RES_LIMIT") {
+            topic_lifecycle.res_limit = res_limit
+                .parse::<i32>()
+                .expect("TOPIC_RES_LIMIT must be a number");
+        }
+
+        // TypeScript版から移行したユーザーのパスワードを検証するために、同じソルトを設定する
+        let salt_pass = env::var("SALT_PASS").expect("SALT_PASS must be set");
+        let mut password = PasswordConfig::new(salt_pass);
+        if let Ok(memory_kib) = env::var("ARGON2_MEMORY_KIB") {
+            password.memory_kib = memory_kib
+                .parse::<u32>()
+                .expect("ARGON2_MEMORY_KIB must be a number");
+        }
+        if let Ok(iterations) = env::var("ARGON2_ITERATIONS") {
+            password.iterations = iterations
+                .parse::<u32>()
+                .expect("ARGON2_ITERATIONS must be a number");
+        }
+        if let Ok(parallelism) = env::var("ARGON2_PARALLELISM") {
+            password.parallelism = parallelism
+                .parse::<u32>()
+                .expect("ARGON2_PARALLELISM must be a number");
+        }
+
+        let admin_user_ids = env::var("ADMIN_USER_IDS")
+            .map(|ids| {
+                ids.split(',')
+                    .map(|id| id.trim().to_string())
+                    .filter(|id| !id.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let trusted_proxies = env::var("TRUSTED_PROXIES")
+            .map(|ips| {
+                ips.split(',')
+                    .map(str::trim)
+                    .filter(|ip| !ip.is_empty())
+                    .map(|ip| {
+                        ip.parse::<IpAddr>()
+                            .expect("TRUSTED_PROXIES must be comma separated IP addresses")
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            host,
+            port,
+            database_url,
+            redis_url,
+            hash,
+            topic_lifecycle,
+            password,
+            admin_user_ids,
+            trusted_proxies,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 
+use crate::at_error::{AtError, AtResult, ParamErrorData};
 use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort};
 
 #[derive(Debug, Clone)]
@@ -32,4 +33,60 @@ impl Client {
         self.url = description;
         self.date = clock.now();
     }
-} 
\ No newline at end of file
+
+    /// OAuthのリダイレクトURIを登録されたURLと照合する
+    ///
+    /// 部分一致を許すとオープンリダイレクトになるので完全一致のみ認める。
+    /// 省略された場合は登録されたURLを使う
+    ///
+    /// # エラー
+    /// * 一致しない、または登録されたURLがリダイレクト先として使えない場合は`AtError::Params`
+    pub fn redirect_uri(&self, redirect_uri: Option<&str>) -> AtResult<String> {
+        let invalid = |message: &str| {
+            AtError::Params(vec![ParamErrorData::new("redirect_uri", "invalid", message)])
+        };
+
+        let usable = url::Url::parse(&self.url)
+            .map(|url| matches!(url.scheme(), "https" | "http") && url.fragment().is_none())
+            .unwrap_or(false);
+        if !usable {
+            return Err(invalid("クライアントのURLがリダイレクト先として使えません"));
+        }
+        match redirect_uri {
+            Some(redirect_uri) if redirect_uri != self.url => {
+                Err(invalid("リダイレクトURIがクライアントのURLと一致しません"))
+            }
+            _ => Ok(self.url.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(url: &str) -> Client {
+        Client {
+            id: "client".to_string(),
+            name: "name".to_string(),
+            url: url.to_string(),
+            date: Utc::now(),
+            update: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_redirect_uri() {
+        let c = client("https://example.com/callback");
+        assert_eq!(c.redirect_uri(None).unwrap(), "https://example.com/callback");
+        assert_eq!(
+            c.redirect_uri(Some("https://example.com/callback")).unwrap(),
+            "https://example.com/callback"
+        );
+        assert!(c.redirect_uri(Some("https://example.com/callback/evil")).is_err());
+        assert!(c.redirect_uri(Some("https://example.com.evil.com/callback")).is_err());
+
+        assert!(client("javascript:alert(1)").redirect_uri(None).is_err());
+        assert!(client("https://example.com/#fragment").redirect_uri(None).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use crate::ports::object_id::ObjectIdGenerator;
+use crate::ports::object_id_generator::ObjectIdGeneratorPort;
 use crate::entities::user::User;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,7 +17,7 @@ pub struct History {
 
 impl History {
     pub fn create(
-        id_gen: &dyn ObjectIdGenerator,
+        id_gen: &dyn ObjectIdGeneratorPort,
         topic_id: String,
         title: String,
         tags: Vec<String>,
@@ -1,5 +1,16 @@
+pub mod search_query;
+pub mod notification;
+pub mod trip;
+pub mod rate_limit_rule;
+pub mod topic_event;
+pub mod token_req;
+pub mod password;
+pub mod short_code;
+pub mod user_totp;
+pub mod recovery_code;
+
 use serde::{Deserialize, Serialize};
-use crate::ports::object_id::ObjectIdGenerator;
+use crate::ports::object_id_generator::ObjectIdGeneratorPort;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
@@ -9,7 +20,7 @@ pub struct User {
 }
 
 impl User {
-    pub fn create(id_gen: &dyn ObjectIdGenerator, sn: String, pass: String) -> Self {
+    pub fn create(id_gen: &dyn ObjectIdGeneratorPort, sn: String, pass: String) -> Self {
         Self {
             id: id_gen.generate(),
             sn,
@@ -26,7 +37,7 @@ pub struct Token {
 }
 
 impl Token {
-    pub fn create(id_gen: &dyn ObjectIdGenerator, user_id: String, token: String) -> Self {
+    pub fn create(id_gen: &dyn ObjectIdGeneratorPort, user_id: String, token: String) -> Self {
         Self {
             id: id_gen.generate(),
             user_id,
@@ -43,7 +54,7 @@ pub struct Client {
 }
 
 impl Client {
-    pub fn create(id_gen: &dyn ObjectIdGenerator, name: String, url: String) -> Self {
+    pub fn create(id_gen: &dyn ObjectIdGeneratorPort, name: String, url: String) -> Self {
         Self {
             id: id_gen.generate(),
             name,
@@ -61,7 +72,7 @@ pub struct Topic {
 }
 
 impl Topic {
-    pub fn create(id_gen: &dyn ObjectIdGenerator, title: String, text: String, user_id: String) -> Self {
+    pub fn create(id_gen: &dyn ObjectIdGeneratorPort, title: String, text: String, user_id: String) -> Self {
         Self {
             id: id_gen.generate(),
             title,
@@ -80,7 +91,7 @@ pub struct Res {
 }
 
 impl Res {
-    pub fn create(id_gen: &dyn ObjectIdGenerator, text: String, topic_id: String, user_id: String) -> Self {
+    pub fn create(id_gen: &dyn ObjectIdGeneratorPort, text: String, topic_id: String, user_id: String) -> Self {
         Self {
             id: id_gen.generate(),
             text,
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum NotificationPayload {
+    // アンカーやreplyで自分のレスが参照された
+    #[serde(rename_all = "camelCase")]
+    Reply {
+        topic_id: String,
+        res_id: String,
+        reply_id: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    pub user_id: String,
+    pub payload: NotificationPayload,
+}
+
+impl Notification {
+    /// `res_id`のレスが`reply_id`のレスに返信したことを`reply_id`の作者に通知する
+    pub fn reply(user_id: String, topic_id: String, res_id: String, reply_id: String) -> Self {
+        Self {
+            user_id,
+            payload: NotificationPayload::Reply {
+                topic_id,
+                res_id,
+                reply_id,
+            },
+        }
+    }
+}
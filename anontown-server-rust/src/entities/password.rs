@@ -0,0 +1,160 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+use subtle::ConstantTimeEq;
+
+use crate::at_error::{AtError, AtResult};
+
+// Argon2idのハッシュはPHC文字列形式なので先頭で見分けられる。それ以外はTypeScript版のサーバーの形式
+const ARGON2ID_PREFIX: &str = "$argon2id$";
+
+/// パスワードのハッシュの設定
+#[derive(Debug, Clone)]
+pub struct PasswordConfig {
+    // TypeScript版のサーバーでパスワードに付けていたソルト。古い形式のハッシュの検証にだけ使う
+    pub legacy_salt: String,
+    // Argon2idのメモリコスト(KiB)
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl PasswordConfig {
+    /// OWASP Password Storage Cheat Sheetで推奨されているパラメーター
+    pub fn new(legacy_salt: String) -> Self {
+        Self {
+            legacy_salt,
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+
+    fn argon2(&self) -> AtResult<Argon2<'static>> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| AtError::Internal(anyhow::anyhow!(e.to_string())))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// パスワードをArgon2idでハッシュにする
+pub fn hash_password(password: &str, config: &PasswordConfig) -> AtResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    config
+        .argon2()?
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AtError::Internal(anyhow::anyhow!(e.to_string())))
+}
+
+/// パスワードがハッシュと一致するか
+///
+/// Argon2idのハッシュはハッシュに含まれているパラメーターで検証する。どちらの形式も比較は定数時間で行う
+pub fn verify_password(password: &str, hash: &str, config: &PasswordConfig) -> bool {
+    if hash.starts_with(ARGON2ID_PREFIX) {
+        PasswordHash::new(hash)
+            .map(|parsed| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &parsed)
+                    .is_ok()
+            })
+            .unwrap_or(false)
+    } else {
+        legacy_hash(password, &config.legacy_salt)
+            .as_bytes()
+            .ct_eq(hash.as_bytes())
+            .into()
+    }
+}
+
+/// 存在しないユーザーの認証でも、今の設定のハッシュでパスワードを検証する
+///
+/// ユーザーが存在する時だけ検証に時間がかかると、応答時間からユーザーIDが存在するか分かってしまうため
+pub fn verify_dummy_password(password: &str, config: &PasswordConfig) {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let hash = DUMMY_HASH.get_or_init(|| hash_password("", config).unwrap_or_default());
+    verify_password(password, hash, config);
+}
+
+/// 古い形式か、今の設定と違うパラメーターのハッシュで、ハッシュし直すべきか
+pub fn needs_rehash(hash: &str, config: &PasswordConfig) -> bool {
+    if !hash.starts_with(ARGON2ID_PREFIX) {
+        return true;
+    }
+    match PasswordHash::new(hash).and_then(|parsed| Params::try_from(&parsed)) {
+        Ok(params) => {
+            params.m_cost() != config.memory_kib
+                || params.t_cost() != config.iterations
+                || params.p_cost() != config.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+// TypeScript版のサーバーの形式。パスワードにソルトを付けたもののSHA-256
+fn legacy_hash(password: &str, salt: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(password.as_bytes());
+    hasher.update(salt.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // テストが遅くならないように最小のパラメーターにする
+    fn config() -> PasswordConfig {
+        PasswordConfig {
+            memory_kib: 8,
+            iterations: 1,
+            ..PasswordConfig::new("salt".to_string())
+        }
+    }
+
+    #[test]
+    fn test_hash_password() {
+        let config = config();
+        let hash = hash_password("password", &config).unwrap();
+        assert!(hash.starts_with(ARGON2ID_PREFIX));
+        assert!(verify_password("password", &hash, &config));
+        assert!(!verify_password("Password", &hash, &config));
+        assert!(!needs_rehash(&hash, &config));
+
+        // 同じパスワードでもソルトが違う
+        assert_ne!(hash_password("password", &config).unwrap(), hash);
+
+        // パラメーターを変えても古いハッシュで検証でき、ハッシュし直す対象になる
+        let stronger = PasswordConfig {
+            iterations: 2,
+            ..config
+        };
+        assert!(verify_password("password", &hash, &stronger));
+        assert!(needs_rehash(&hash, &stronger));
+    }
+
+    #[test]
+    fn test_legacy_hash() {
+        let config = config();
+        // echo -n "passwordsalt" | sha256sum
+        let legacy = "7a37b85c8918eac19a9089c0fa5a2ab4dce3f90528dcdeec108b23ddf3607b99";
+        assert_eq!(legacy_hash("password", "salt"), legacy);
+        assert!(verify_password("password", legacy, &config));
+        assert!(!verify_password("password1", legacy, &config));
+        assert!(needs_rehash(legacy, &config));
+
+        // ソルトが違えば一致しない
+        let other = PasswordConfig::new("other".to_string());
+        assert!(!verify_password("password", legacy, &other));
+    }
+
+    #[test]
+    fn test_invalid_hash() {
+        let config = config();
+        assert!(!verify_password("password", "", &config));
+        assert!(!verify_password("password", "$argon2id$invalid", &config));
+        assert!(needs_rehash("$argon2id$invalid", &config));
+    }
+}
@@ -1,6 +1,15 @@
 use chrono::{DateTime, Utc};
 
 use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort};
+use crate::validation::{normalize, StrRule};
+
+// 文字数は見た目の1文字で数える
+pub const NAME_MAX_LEN: usize = 50;
+pub const TEXT_MAX_LEN: usize = 3000;
+
+pub const NAME_RULES: &[StrRule] =
+    &[StrRule::Required, StrRule::Visible, StrRule::SingleLine, StrRule::MaxLen(NAME_MAX_LEN)];
+pub const TEXT_RULES: &[StrRule] = &[StrRule::Required, StrRule::MultiLine, StrRule::MaxLen(TEXT_MAX_LEN)];
 
 #[derive(Debug)]
 pub struct Profile {
@@ -9,6 +18,8 @@ pub struct Profile {
     pub name: String,
     pub description: String,
     pub date: DateTime<Utc>,
+    // 楽観的排他制御のためのバージョン。保存する度にリポジトリが1増やす
+    pub version: i32,
 }
 
 impl Profile {
@@ -22,9 +33,10 @@ impl Profile {
         Self {
             id: id_generator.generate(),
             user_id,
-            name,
-            description,
+            name: normalize(&name),
+            description: normalize(&description),
             date: clock.now(),
+            version: 0,
         }
     }
 
@@ -34,8 +46,8 @@ impl Profile {
         description: String,
         clock: &impl ClockPort,
     ) {
-        self.name = name;
-        self.description = description;
+        self.name = normalize(&name);
+        self.description = normalize(&description);
         self.date = clock.now();
     }
 
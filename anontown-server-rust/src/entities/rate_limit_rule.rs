@@ -0,0 +1,112 @@
+use crate::entities::user::TimeRange;
+
+/// 連投制限の対象になる書き込み
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitAction {
+    Res,
+    Topic,
+    // 引き継ぎコードの入力。総当たりを防ぐためにユーザーではなくIPごとに数える
+    Pairing,
+}
+
+// 各期間で書き込める件数の基準値。Lv100ごとに基準値の分だけ増える
+const RES_LIMITS: [(TimeRange, i32); 6] = [
+    (TimeRange::M10, 10),
+    (TimeRange::M30, 15),
+    (TimeRange::H1, 20),
+    (TimeRange::H6, 30),
+    (TimeRange::H12, 40),
+    (TimeRange::D1, 50),
+];
+const TOPIC_LIMITS: [(TimeRange, i32); 2] = [(TimeRange::M30, 1), (TimeRange::D1, 5)];
+const PAIRING_LIMITS: [(TimeRange, i32); 2] = [(TimeRange::M10, 10), (TimeRange::D1, 50)];
+
+impl RateLimitAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RateLimitAction::Res => "res",
+            RateLimitAction::Topic => "topic",
+            RateLimitAction::Pairing => "pairing",
+        }
+    }
+
+    /// ユーザーのLvに応じた制限。引き継ぎコードの入力はLvに関係なく同じ制限になる
+    pub fn rules(&self, lv: i32) -> Vec<RateLimitRule> {
+        let (limits, lv): (&[(TimeRange, i32)], i32) = match self {
+            RateLimitAction::Res => (&RES_LIMITS, lv),
+            RateLimitAction::Topic => (&TOPIC_LIMITS, lv),
+            RateLimitAction::Pairing => (&PAIRING_LIMITS, 0),
+        };
+        limits
+            .iter()
+            .map(|&(window, base)| RateLimitRule {
+                window,
+                max: base + base * lv.max(0) / 100,
+            })
+            .collect()
+    }
+}
+
+/// `window`の期間に書き込めるのは`max`件まで
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitRule {
+    pub window: TimeRange,
+    pub max: i32,
+}
+
+impl RateLimitRule {
+    pub fn exceeded_message(&self) -> String {
+        let window = match self.window {
+            TimeRange::M10 => "10分",
+            TimeRange::M30 => "30分",
+            TimeRange::H1 => "1時間",
+            TimeRange::H6 => "6時間",
+            TimeRange::H12 => "12時間",
+            TimeRange::D1 => "1日",
+        };
+        format!("連続書き込みはできません({}に{}件まで)", window, self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules() {
+        let rules = RateLimitAction::Res.rules(1);
+        assert_eq!(rules.len(), 6);
+        assert_eq!(
+            rules[0],
+            RateLimitRule {
+                window: TimeRange::M10,
+                max: 10
+            }
+        );
+
+        // Lvが上がると緩くなる
+        let rules = RateLimitAction::Res.rules(250);
+        assert_eq!(rules[0].max, 35);
+        assert_eq!(rules[5].max, 175);
+
+        let rules = RateLimitAction::Topic.rules(1);
+        assert_eq!(
+            rules,
+            vec![
+                RateLimitRule {
+                    window: TimeRange::M30,
+                    max: 1
+                },
+                RateLimitRule {
+                    window: TimeRange::D1,
+                    max: 5
+                },
+            ]
+        );
+        // 引き継ぎコードの入力はLvで緩くならない
+        assert_eq!(
+            RateLimitAction::Pairing.rules(250),
+            RateLimitAction::Pairing.rules(0)
+        );
+    }
+}
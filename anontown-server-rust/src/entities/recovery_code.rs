@@ -0,0 +1,74 @@
+use chrono::{DateTime, Utc};
+
+use crate::entities::short_code;
+use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort};
+
+// メールアドレスを持たないアカウントで、パスワードを忘れた時の唯一の手段になる
+pub const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LEN: usize = 16;
+
+/// アカウントを回復するための使い捨てのコード。ハッシュだけを保存する
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryCode {
+    pub user_id: String,
+    pub code_hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl RecoveryCode {
+    /// 回復コードを一式発行する
+    ///
+    /// # 返り値
+    /// 保存する回復コードと、表示用のコード。表示用のコードは保存しないので、この時しか表示できない
+    pub fn create_set(
+        user_id: &str,
+        clock: &impl ClockPort,
+        safe_id_generator: &impl SafeIdGeneratorPort,
+    ) -> (Vec<RecoveryCode>, Vec<String>) {
+        let now = clock.now();
+        (0..RECOVERY_CODE_COUNT)
+            .map(|_| {
+                let code = short_code::generate(RECOVERY_CODE_LEN, safe_id_generator);
+                let recovery_code = RecoveryCode {
+                    user_id: user_id.to_string(),
+                    code_hash: short_code::hash(&code),
+                    created_at: now,
+                };
+                (recovery_code, short_code::format(&code))
+            })
+            .unzip()
+    }
+
+    /// 入力されたコードを保存されている形式のハッシュにする。回復コードの形式でなければ`None`
+    pub fn hash_input(input: &str) -> Option<String> {
+        short_code::normalize(input, RECOVERY_CODE_LEN).map(|code| short_code::hash(&code))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+
+    #[test]
+    fn test_create_set() {
+        let clock = FixClock::new(Utc::now());
+        let (recovery_codes, codes) =
+            RecoveryCode::create_set("user", &clock, &SeqIdGenerator::new());
+        assert_eq!(recovery_codes.len(), RECOVERY_CODE_COUNT);
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+
+        for (recovery_code, code) in recovery_codes.iter().zip(&codes) {
+            assert_eq!(recovery_code.user_id, "user");
+            assert_ne!(&recovery_code.code_hash, code);
+            assert_eq!(
+                RecoveryCode::hash_input(&code.to_lowercase()),
+                Some(recovery_code.code_hash.clone())
+            );
+        }
+        assert_ne!(recovery_codes[0].code_hash, recovery_codes[1].code_hash);
+
+        assert_eq!(RecoveryCode::hash_input("ABCD-EFGH"), None);
+    }
+}
@@ -1,8 +1,20 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use crate::ports::object_id::ObjectIdGenerator;
+use std::collections::BTreeSet;
+use crate::ports::object_id_generator::ObjectIdGeneratorPort;
+use crate::ports::clock::ClockPort;
 use crate::entities::user::User;
-use crate::entities::topic::Topic;
+use crate::entities::topic::{HashConfig, Topic};
+use crate::entities::trip::{apply_trip, trip_name};
+use crate::at_error::{AtError, AtResult};
+use crate::validation::{normalize, StrRule, Validator};
+
+// 文字数は見た目の1文字で数える
+pub const NAME_MAX_LEN: usize = 50;
+pub const TEXT_MAX_LEN: usize = 5000;
+
+pub const NAME_RULES: &[StrRule] = &[StrRule::SingleLine, StrRule::MaxLen(NAME_MAX_LEN)];
+pub const TEXT_RULES: &[StrRule] = &[StrRule::Required, StrRule::MultiLine, StrRule::MaxLen(TEXT_MAX_LEN)];
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ResType {
@@ -28,6 +40,8 @@ pub struct Reply {
 pub struct ResBase {
     pub id: String,
     pub topic_id: String,
+    // トピック内での連番(1始まり)。保存前は0
+    pub number: i32,
     pub date: DateTime<Utc>,
     pub user_id: String,
     pub votes: Vec<Vote>,
@@ -35,6 +49,8 @@ pub struct ResBase {
     pub hash: String,
     pub reply_count: i32,
     pub res_type: ResType,
+    // 楽観的排他制御のためのバージョン。保存する度にリポジトリが1増やす
+    pub version: i32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,6 +68,15 @@ impl ResSearchBase {
         &self.base.topic_id
     }
 
+    pub fn number(&self) -> i32 {
+        self.base.number
+    }
+
+    /// 番号はトピック内で競合しないように保存時にリポジトリが採番する
+    pub fn set_number(&mut self, number: i32) {
+        self.base.number = number;
+    }
+
     pub fn date(&self) -> DateTime<Utc> {
         self.base.date
     }
@@ -76,19 +101,40 @@ impl ResSearchBase {
         self.base.reply_count
     }
 
+    /// 他のレスから返信(アンカー)された
+    pub fn add_reply_count(&mut self) {
+        self.base.reply_count += 1;
+    }
+
     pub fn res_type(&self) -> ResType {
         self.base.res_type
     }
 
-    pub fn vote(&mut self, res_user: &mut User, user: &User, vote_type: &str) -> Result<(), String> {
+    pub fn version(&self) -> i32 {
+        self.base.version
+    }
+
+    /// バージョンは競合を検出するために保存時にリポジトリが増やす
+    pub fn set_version(&mut self, version: i32) {
+        self.base.version = version;
+    }
+
+    /// # エラー
+    /// * 自分のレスへの投票は`AtError::Right`
+    /// * `vote_type`が`uv`でも`dv`でもなければ`AtError::Params`
+    pub fn vote(&mut self, res_user: &mut User, user: &User, vote_type: &str) -> AtResult<()> {
         if self.base.user_id == user.id {
-            return Err("自分に投票できません".to_string());
+            return Err(AtError::Right("自分に投票できません".to_string()));
         }
 
         let vote_value = match vote_type {
             "uv" => 2,
             "dv" => -1,
-            _ => return Err("無効な投票タイプです".to_string()),
+            _ => {
+                return Validator::new()
+                    .error("type", "invalid", "無効な投票タイプです")
+                    .finish()
+            }
         };
 
         if let Some(vote) = self.base.votes.iter_mut().find(|v| v.user == user.id) {
@@ -125,10 +171,20 @@ pub struct ResHistory {
     pub history_id: String,
 }
 
+/// レス数が上限に達したトピックと次スレを繋ぐリンク
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TopicLink {
+    // 次スレのid。上限に達したトピックに書き込まれる
+    Next(String),
+    // 前スレのid。次スレに書き込まれる
+    Prev(String),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResTopic {
     #[serde(flatten)]
     base: ResSearchBase,
+    pub link: Option<TopicLink>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -138,39 +194,122 @@ pub struct ResFork {
     pub fork_id: String,
 }
 
+// 1つのレスから参照できるレスの最大数。>>1-100000のような範囲指定で大量の通知が飛ばないようにする
+pub const MAX_ANCHORS: usize = 100;
+
+/// 本文中の`>>N`、`>>N-M`、`>>N,M`形式のアンカーが指すレス番号を昇順・重複なしで返す
+///
+/// 全角の`＞＞１２`も受け付ける。番号が0のものや範囲外のものは無視する
+pub fn parse_anchors(text: &str) -> Vec<i32> {
+    let chars = text.chars().map(normalize_anchor_char).collect::<Vec<_>>();
+    let mut numbers = BTreeSet::new();
+
+    let mut i = 0;
+    while i + 1 < chars.len() {
+        if chars[i] != '>' || chars[i + 1] != '>' {
+            i += 1;
+            continue;
+        }
+        i += 2;
+        while i < chars.len() && chars[i] == '>' {
+            i += 1;
+        }
+
+        while let Some((from, next)) = read_anchor_number(&chars, i) {
+            i = next;
+            let mut to = from;
+            if chars.get(i) == Some(&'-') {
+                if let Some((n, next)) = read_anchor_number(&chars, i + 1) {
+                    to = n;
+                    i = next;
+                }
+            }
+
+            for n in from.min(to)..=from.max(to) {
+                if numbers.len() >= MAX_ANCHORS {
+                    return numbers.into_iter().collect();
+                }
+                numbers.insert(n);
+            }
+
+            if chars.get(i) == Some(&',') {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    numbers.into_iter().collect()
+}
+
+fn read_anchor_number(chars: &[char], start: usize) -> Option<(i32, usize)> {
+    let mut end = start;
+    let mut n: i32 = 0;
+    while let Some(d) = chars.get(end).and_then(|c| c.to_digit(10)) {
+        n = n.checked_mul(10)?.checked_add(d as i32)?;
+        end += 1;
+    }
+
+    if end == start || n == 0 {
+        None
+    } else {
+        Some((n, end))
+    }
+}
+
+fn normalize_anchor_char(c: char) -> char {
+    match c {
+        '＞' => '>',
+        '－' | '−' => '-',
+        '，' | '、' => ',',
+        '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32).unwrap_or(c),
+        _ => c,
+    }
+}
+
 impl ResNormal {
+    /// `name`が`name#key`形式ならトリップ付きの表示名にして保存する。キーは保存しない
     pub fn create(
-        id_gen: &dyn ObjectIdGenerator,
+        id_gen: &dyn ObjectIdGeneratorPort,
         topic: &Topic,
         user: &User,
+        hash_config: &HashConfig,
         name: Option<String>,
         text: String,
         reply: Option<Reply>,
         profile: Option<String>,
         age: bool,
-    ) -> Self {
+    ) -> AtResult<Self> {
+        Validator::new()
+            .opt_str("name", "名前", name.as_deref().map(trip_name), NAME_RULES)
+            .str("text", "本文", &text, TEXT_RULES)
+            .finish()?;
+        let name = name.map(|name| apply_trip(&normalize(&name))).transpose()?;
         let now = Utc::now();
-        Self {
+        Ok(Self {
             base: ResSearchBase {
                 base: ResBase {
                     id: id_gen.generate(),
                     topic_id: topic.id().to_string(),
+                    number: 0,
                     date: now,
                     user_id: user.id.clone(),
                     votes: Vec::new(),
                     lv: user.lv * 5,
-                    hash: topic.hash(now, user),
+                    hash: topic.base().hash(now, user, hash_config),
                     reply_count: 0,
+                    version: 0,
                     res_type: ResType::Normal,
                 },
             },
             name,
-            text,
+            text: normalize(&text),
             reply,
             delete_flag: "active".to_string(),
             profile,
             age,
-        }
+        })
     }
 
     pub fn base(&self) -> &ResSearchBase {
@@ -180,13 +319,25 @@ impl ResNormal {
     pub fn base_mut(&mut self) -> &mut ResSearchBase {
         &mut self.base
     }
+
+    /// 本文中のアンカーが指すレス番号
+    ///
+    /// 自分より後のレスや自分自身は指せない
+    pub fn anchors(&self) -> Vec<i32> {
+        let number = self.base.number();
+        parse_anchors(&self.text)
+            .into_iter()
+            .filter(|&n| n < number)
+            .collect()
+    }
 }
 
 impl ResHistory {
     pub fn create(
-        id_gen: &dyn ObjectIdGenerator,
+        id_gen: &dyn ObjectIdGeneratorPort,
         topic: &Topic,
         user: &User,
+        hash_config: &HashConfig,
         history_id: String,
     ) -> Self {
         let now = Utc::now();
@@ -195,12 +346,14 @@ impl ResHistory {
                 base: ResBase {
                     id: id_gen.generate(),
                     topic_id: topic.id().to_string(),
+                    number: 0,
                     date: now,
                     user_id: user.id.clone(),
                     votes: Vec::new(),
                     lv: user.lv * 5,
-                    hash: topic.hash(now, user),
+                    hash: topic.base().hash(now, user, hash_config),
                     reply_count: 0,
+                    version: 0,
                     res_type: ResType::History,
                 },
             },
@@ -219,9 +372,10 @@ impl ResHistory {
 
 impl ResTopic {
     pub fn create(
-        id_gen: &dyn ObjectIdGenerator,
+        id_gen: &dyn ObjectIdGeneratorPort,
         topic: &Topic,
         user: &User,
+        hash_config: &HashConfig,
     ) -> Self {
         let now = Utc::now();
         Self {
@@ -229,15 +383,59 @@ impl ResTopic {
                 base: ResBase {
                     id: id_gen.generate(),
                     topic_id: topic.id().to_string(),
+                    number: 0,
                     date: now,
                     user_id: user.id.clone(),
                     votes: Vec::new(),
                     lv: user.lv * 5,
-                    hash: topic.hash(now, user),
+                    hash: topic.base().hash(now, user, hash_config),
                     reply_count: 0,
+                    version: 0,
                     res_type: ResType::Topic,
                 },
             },
+            link: None,
+        }
+    }
+
+    /// トピックが落ちた時などにシステムが書き込むレス
+    ///
+    /// 書き込んだユーザーが存在しないのでuser_idはトピック作成者、hashは空にする
+    pub fn create_system(
+        id_gen: &dyn ObjectIdGeneratorPort,
+        clock: &dyn ClockPort,
+        topic: &Topic,
+    ) -> Self {
+        Self {
+            base: ResSearchBase {
+                base: ResBase {
+                    id: id_gen.generate(),
+                    topic_id: topic.base().id.clone(),
+                    number: 0,
+                    date: clock.now(),
+                    user_id: topic.base().user_id.clone(),
+                    votes: Vec::new(),
+                    lv: 0,
+                    hash: String::new(),
+                    reply_count: 0,
+                    version: 0,
+                    res_type: ResType::Topic,
+                },
+            },
+            link: None,
+        }
+    }
+
+    /// 次スレ・前スレへのリンクを示すシステムのレス
+    pub fn create_link(
+        id_gen: &dyn ObjectIdGeneratorPort,
+        clock: &dyn ClockPort,
+        topic: &Topic,
+        link: TopicLink,
+    ) -> Self {
+        Self {
+            link: Some(link),
+            ..Self::create_system(id_gen, clock, topic)
         }
     }
 
@@ -252,9 +450,10 @@ impl ResTopic {
 
 impl ResFork {
     pub fn create(
-        id_gen: &dyn ObjectIdGenerator,
+        id_gen: &dyn ObjectIdGeneratorPort,
         topic: &Topic,
         user: &User,
+        hash_config: &HashConfig,
         fork_id: String,
     ) -> Self {
         let now = Utc::now();
@@ -263,12 +462,14 @@ impl ResFork {
                 base: ResBase {
                     id: id_gen.generate(),
                     topic_id: topic.id().to_string(),
+                    number: 0,
                     date: now,
                     user_id: user.id.clone(),
                     votes: Vec::new(),
                     lv: user.lv * 5,
-                    hash: topic.hash(now, user),
+                    hash: topic.base().hash(now, user, hash_config),
                     reply_count: 0,
+                    version: 0,
                     res_type: ResType::Fork,
                 },
             },
@@ -311,4 +512,123 @@ impl Res {
             Res::Fork(res) => res.base_mut(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::entities::topic::TopicNormal;
+
+    #[test]
+    fn test_res_normal_create_trip() {
+        let id_gen = SeqIdGenerator::new();
+        let topic = Topic::Normal(TopicNormal::create(
+            &id_gen,
+            &FixClock::new(Utc::now()),
+            "title".to_string(),
+            "text".to_string(),
+            "user".to_string(),
+            vec![],
+        ));
+        let user = User::create(
+            &id_gen,
+            "sn".to_string(),
+            "name".to_string(),
+            "".to_string(),
+            "pass".to_string(),
+        );
+        let create = |name: &str| {
+            ResNormal::create(
+                &id_gen,
+                &topic,
+                &user,
+                &HashConfig::jst("salt".to_string()),
+                Some(name.to_string()),
+                "text".to_string(),
+                None,
+                None,
+                true,
+            )
+        };
+
+        let res = create("名無し#istrip").unwrap();
+        assert_eq!(res.name.as_deref(), Some("名無し ◆/WG5qp963c"));
+        assert!(matches!(create("名無し#"), Err(AtError::Params(_))));
+        // 名前の長さはキーを除いて数える
+        let res = create(&format!("{}#{}", "あ".repeat(50), "k".repeat(100))).unwrap();
+        assert!(res.name.unwrap().starts_with(&"あ".repeat(50)));
+        assert!(matches!(create(&format!("{}#key", "あ".repeat(51))), Err(AtError::Params(_))));
+    }
+
+    #[test]
+    fn test_res_normal_create_validation() {
+        let id_gen = SeqIdGenerator::new();
+        let topic = Topic::Normal(TopicNormal::create(
+            &id_gen,
+            &FixClock::new(Utc::now()),
+            "title".to_string(),
+            "text".to_string(),
+            "user".to_string(),
+            vec![],
+        ));
+        let user = User::create(
+            &id_gen,
+            "sn".to_string(),
+            "name".to_string(),
+            "".to_string(),
+            "pass".to_string(),
+        );
+        let create = |name: &str, text: &str| {
+            ResNormal::create(
+                &id_gen,
+                &topic,
+                &user,
+                &HashConfig::jst("salt".to_string()),
+                Some(name.to_string()),
+                text.to_string(),
+                None,
+                None,
+                true,
+            )
+        };
+
+        assert!(create(&"あ".repeat(50), &"あ".repeat(5000)).is_ok());
+        // 本文は正規化して保存する
+        let res = create("名無し", "か\u{3099}\u{200B}\r\n").unwrap();
+        assert_eq!(res.text, "が\n");
+        match create(&"a".repeat(51), "") {
+            Err(AtError::Params(errors)) => {
+                let fields = errors.iter().map(|e| (e.field.as_str(), e.code.as_str())).collect::<Vec<_>>();
+                assert_eq!(fields, vec![("name", "too_long"), ("text", "required")]);
+            }
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_parse_anchors() {
+        assert_eq!(parse_anchors(">>1 >>3-5 >>7,9"), vec![1, 3, 4, 5, 7, 9]);
+        assert_eq!(parse_anchors(">>2,4-5,1"), vec![1, 2, 4, 5]);
+        assert_eq!(parse_anchors("＞＞１２"), vec![12]);
+        assert_eq!(parse_anchors(">>>3"), vec![3]);
+        assert_eq!(parse_anchors(">>5-3"), vec![3, 4, 5]);
+        assert_eq!(parse_anchors(">>1 >>1-2"), vec![1, 2]);
+        assert_eq!(parse_anchors("abc>>10def"), vec![10]);
+
+        assert!(parse_anchors(">> 1").is_empty());
+        assert!(parse_anchors(">>0").is_empty());
+        assert!(parse_anchors(">>abc").is_empty());
+        assert!(parse_anchors(">>99999999999").is_empty());
+        assert!(parse_anchors(">1").is_empty());
+    }
+
+    #[test]
+    fn test_parse_anchors_limit() {
+        let anchors = parse_anchors(">>1-100000");
+        assert_eq!(anchors.len(), MAX_ANCHORS);
+        assert_eq!(anchors[0], 1);
+        assert_eq!(anchors[MAX_ANCHORS - 1], MAX_ANCHORS as i32);
+    }
+}
@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// スニペットとしてヒット箇所の前後に含める文字数
+const SNIPPET_CONTEXT: usize = 30;
+
+/// 検索文字列をパースしたもの
+///
+/// 日本語は空白で単語が区切られないため、単語単位ではなくbi-gramで類似度を計算する。
+/// 各termは部分一致(AND)で絞り込み、DB側のpg_trgmインデックスを使ったILIKE検索と同じ意味になる。
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    raw: String,
+    terms: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SearchHighlight {
+    // 文字単位のオフセット
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchSnippet {
+    pub text: String,
+    pub highlights: Vec<SearchHighlight>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit<T> {
+    pub item: T,
+    pub score: f64,
+    pub snippet: SearchSnippet,
+}
+
+impl<T> SearchHit<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> SearchHit<U> {
+        SearchHit {
+            item: f(self.item),
+            score: self.score,
+            snippet: self.snippet,
+        }
+    }
+}
+
+impl SearchQuery {
+    pub fn parse(raw: &str) -> Option<Self> {
+        let normalized = normalize(raw);
+        let mut seen = HashSet::new();
+        let terms = normalized
+            .split_whitespace()
+            .filter(|term| seen.insert(term.to_string()))
+            .map(|term| term.to_string())
+            .collect::<Vec<_>>();
+
+        if terms.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            raw: terms.join(" "),
+            terms,
+        })
+    }
+
+    /// 正規化済みの検索文字列。pg_trgmのsimilarityに渡す
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn terms(&self) -> &[String] {
+        &self.terms
+    }
+
+    /// ILIKE用のパターン。`%`と`_`はエスケープする
+    pub fn like_patterns(&self) -> Vec<String> {
+        self.terms
+            .iter()
+            .map(|term| {
+                let escaped = term
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                format!("%{}%", escaped)
+            })
+            .collect()
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text = normalize(text);
+        self.terms.iter().all(|term| text.contains(term.as_str()))
+    }
+
+    /// bi-gramのDice係数による類似度(0.0〜1.0)
+    pub fn score(&self, text: &str) -> f64 {
+        let query_grams = ngrams(&self.raw, 2);
+        let text_grams = ngrams(&normalize(text), 2);
+        if query_grams.is_empty() || text_grams.is_empty() {
+            return 0.0;
+        }
+
+        let common = query_grams.intersection(&text_grams).count();
+        (2 * common) as f64 / (query_grams.len() + text_grams.len()) as f64
+    }
+
+    pub fn hit<T>(&self, item: T, text: &str) -> Option<SearchHit<T>> {
+        if !self.is_match(text) {
+            return None;
+        }
+
+        Some(SearchHit {
+            item,
+            score: self.score(text),
+            snippet: self.snippet(text),
+        })
+    }
+
+    /// 最初にヒットした箇所の前後を切り出し、ヒット箇所の位置を返す
+    pub fn snippet(&self, text: &str) -> SearchSnippet {
+        let chars = text.chars().collect::<Vec<_>>();
+        let normalized = chars
+            .iter()
+            .map(|c| normalize_char(*c))
+            .collect::<Vec<_>>();
+
+        let mut matches = self
+            .terms
+            .iter()
+            .flat_map(|term| find_all(&normalized, &term.chars().collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|h| (h.start, h.end));
+
+        let first = matches.first().map(|h| h.start).unwrap_or(0);
+        let start = first.saturating_sub(SNIPPET_CONTEXT);
+        let end = (start + SNIPPET_CONTEXT * 2).min(chars.len());
+
+        let mut highlights: Vec<SearchHighlight> = Vec::new();
+        for h in matches.into_iter().filter(|h| h.start >= start && h.end <= end) {
+            let h = SearchHighlight {
+                start: h.start - start,
+                end: h.end - start,
+            };
+            match highlights.last_mut() {
+                Some(last) if h.start <= last.end => last.end = last.end.max(h.end),
+                _ => highlights.push(h),
+            }
+        }
+
+        SearchSnippet {
+            text: chars[start..end].iter().collect(),
+            highlights,
+        }
+    }
+}
+
+fn find_all(haystack: &[char], needle: &[char]) -> Vec<SearchHighlight> {
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return Vec::new();
+    }
+
+    (0..=haystack.len() - needle.len())
+        .filter(|&i| haystack[i..i + needle.len()] == *needle)
+        .map(|i| SearchHighlight {
+            start: i,
+            end: i + needle.len(),
+        })
+        .collect()
+}
+
+fn ngrams(text: &str, n: usize) -> HashSet<String> {
+    text.split_whitespace()
+        .flat_map(|word| {
+            let chars = word.chars().collect::<Vec<_>>();
+            if chars.len() < n {
+                vec![word.to_string()]
+            } else {
+                chars.windows(n).map(|w| w.iter().collect()).collect()
+            }
+        })
+        .collect()
+}
+
+fn normalize(text: &str) -> String {
+    text.chars().map(normalize_char).collect()
+}
+
+// 全角英数記号・全角スペースを半角にし、大文字小文字を区別しないようにする
+// 文字数が変わらないのでスニペットの位置計算に使える
+fn normalize_char(c: char) -> char {
+    let c = match c {
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    };
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let query = SearchQuery::parse(" 匿名\u{3000}ＡＢＣ  匿名 ").unwrap();
+        assert_eq!(query.terms(), &["匿名".to_string(), "abc".to_string()]);
+        assert_eq!(query.raw(), "匿名 abc");
+
+        assert!(SearchQuery::parse("").is_none());
+        assert!(SearchQuery::parse(" \u{3000} ").is_none());
+    }
+
+    #[test]
+    fn test_like_patterns() {
+        let query = SearchQuery::parse("100% a_b").unwrap();
+        assert_eq!(query.like_patterns(), vec!["%100\\%%", "%a\\_b%"]);
+    }
+
+    #[test]
+    fn test_is_match() {
+        let query = SearchQuery::parse("掲示板 雑談").unwrap();
+        assert!(query.is_match("匿名掲示板で雑談する"));
+        assert!(!query.is_match("匿名掲示板"));
+
+        let query = SearchQuery::parse("anontown").unwrap();
+        assert!(query.is_match("ＡｎｏｎＴｏｗｎ総合"));
+    }
+
+    #[test]
+    fn test_score() {
+        let query = SearchQuery::parse("雑談スレ").unwrap();
+        let exact = query.score("雑談スレ");
+        let partial = query.score("なんでも雑談スレッド part2");
+        let none = query.score("プログラミング");
+
+        assert_eq!(exact, 1.0);
+        assert!(partial < exact);
+        assert!(none < partial);
+        assert_eq!(none, 0.0);
+    }
+
+    #[test]
+    fn test_snippet() {
+        let query = SearchQuery::parse("雑談").unwrap();
+        let snippet = query.snippet("今日も雑談しよう。雑談は楽しい");
+        assert_eq!(snippet.text, "今日も雑談しよう。雑談は楽しい");
+        assert_eq!(
+            snippet.highlights,
+            vec![
+                SearchHighlight { start: 3, end: 5 },
+                SearchHighlight { start: 9, end: 11 },
+            ]
+        );
+
+        let text = format!("{}雑談{}", "あ".repeat(50), "い".repeat(100));
+        let snippet = query.snippet(&text);
+        assert_eq!(snippet.text.chars().count(), SNIPPET_CONTEXT * 2);
+        assert_eq!(
+            snippet.highlights,
+            vec![SearchHighlight {
+                start: SNIPPET_CONTEXT,
+                end: SNIPPET_CONTEXT + 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_hit() {
+        let query = SearchQuery::parse("雑談").unwrap();
+        assert!(query.hit((), "雑談スレ").is_some());
+        assert!(query.hit((), "質問スレ").is_none());
+    }
+}
@@ -0,0 +1,79 @@
+//! 人が読み取って入力する短いコード
+//!
+//! 引き継ぎコード、回復コード、二要素認証のバックアップコードに使う。読み間違えやすい文字を使わないCrockford's Base32で作る
+
+use sha2::{Digest, Sha256};
+
+use crate::ports::safe_id_generator::SafeIdGeneratorPort;
+
+// Crockford's Base32。読み間違えやすいI, L, O, Uは使わない
+pub const ALPHABET: [char; 32] = [
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'J',
+    'K', 'M', 'N', 'P', 'Q', 'R', 'S', 'T', 'V', 'W', 'X', 'Y', 'Z',
+];
+
+/// `len`文字のコードを生成する
+pub fn generate(len: usize, safe_id_generator: &impl SafeIdGeneratorPort) -> String {
+    safe_id_generator.generate_code(len, &ALPHABET)
+}
+
+/// 画面に表示するために、コードを半分に分けてハイフンでつなぐ
+pub fn format(code: &str) -> String {
+    let (head, tail) = code.split_at(code.len() / 2);
+    format!("{}-{}", head, tail)
+}
+
+/// 入力されたコードを保存されている形式にする
+///
+/// 大文字小文字、ハイフンや空白は区別せず、読み間違えやすいO, I, Lはそれぞれ0, 1, 1として扱う。
+/// `len`文字でないか、使わない文字が含まれていれば`None`
+pub fn normalize(input: &str, len: usize) -> Option<String> {
+    let code = input
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        })
+        .collect::<String>();
+    (code.chars().count() == len && code.chars().all(|c| ALPHABET.contains(&c))).then_some(code)
+}
+
+/// 回復コードのように長期間有効なコードを保存する時のハッシュ
+///
+/// 十分長いランダムなコードにだけ使う。総当たりできないので、ソルトやストレッチングはしない
+pub fn hash(code: &str) -> String {
+    format!("{:x}", Sha256::digest(code.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct DummySafeIdGenerator;
+
+    impl SafeIdGeneratorPort for DummySafeIdGenerator {
+        fn generate(&self) -> String {
+            "key".to_string()
+        }
+    }
+
+    #[test]
+    fn test_generate() {
+        let code = generate(10, &DummySafeIdGenerator);
+        assert_eq!(code.len(), 10);
+        assert!(code.chars().all(|c| ALPHABET.contains(&c)));
+        assert_eq!(normalize(&format(&code).to_lowercase(), 10), Some(code));
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(format("ABCD2345"), "ABCD-2345");
+        assert_eq!(normalize(" ab1o-ilz9 ", 8), Some("AB1011Z9".to_string()));
+        assert_eq!(normalize("ABCD-EFG", 8), None);
+        assert_eq!(normalize("ABCD-EFGHJ", 8), None);
+        assert_eq!(normalize("ABCD-EFGU", 8), None);
+        assert_eq!(normalize("ＡＢＣＤ-ＥＦＧＨ", 8), None);
+    }
+}
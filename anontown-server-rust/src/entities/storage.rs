@@ -1,6 +1,14 @@
 use chrono::{DateTime, Utc};
 
 use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort};
+use crate::validation::{normalize, StrRule};
+
+// 文字数は見た目の1文字で数える
+pub const KEY_MAX_LEN: usize = 100;
+
+// 値はクライアントが自由に使うので検査も正規化もしない
+pub const KEY_RULES: &[StrRule] =
+    &[StrRule::Required, StrRule::Visible, StrRule::SingleLine, StrRule::MaxLen(KEY_MAX_LEN)];
 
 #[derive(Debug)]
 pub struct Storage {
@@ -10,6 +18,8 @@ pub struct Storage {
     pub key: String,
     pub value: String,
     pub date: DateTime<Utc>,
+    // 楽観的排他制御のためのバージョン。保存する度にリポジトリが1増やす
+    pub version: i32,
 }
 
 impl Storage {
@@ -25,9 +35,10 @@ impl Storage {
             id: id_generator.generate(),
             client_id,
             user_id,
-            key,
+            key: normalize(&key),
             value,
             date: clock.now(),
+            version: 0,
         }
     }
 
@@ -1,6 +1,27 @@
 use chrono::{DateTime, Duration, Utc};
+use subtle::ConstantTimeEq;
 
-use crate::ports::{clock::ClockPort, object_id_generator::ObjectIdGeneratorPort};
+use crate::at_error::{AtError, AtResult};
+use crate::auth::{AuthToken, AuthTokenBase, AuthTokenGeneral, AuthTokenMaster, TokenScope};
+use crate::ports::{
+    clock::ClockPort, object_id_generator::ObjectIdGeneratorPort,
+    safe_id_generator::SafeIdGeneratorPort,
+};
+
+// クライアントに発行するアクセストークンの有効期間。切れたらリフレッシュトークンで更新する
+pub const ACCESS_TOKEN_LIFETIME_HOURS: i64 = 1;
+pub const REFRESH_TOKEN_LIFETIME_DAYS: i64 = 30;
+// マスタートークンは更新せずに使うので、期限が切れたらログインし直す
+pub const MASTER_TOKEN_LIFETIME_DAYS: i64 = 30;
+// 最終使用日時は認証のたびに書き込まないように、この間隔より古くなった時だけ更新する
+pub const LAST_USED_UPDATE_INTERVAL_MINUTES: i64 = 5;
+
+/// トークンを発行した端末の情報。セッション一覧でユーザーが端末を見分けるのに使う
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenDevice {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
 
 #[derive(Debug)]
 pub struct Token {
@@ -10,6 +31,16 @@ pub struct Token {
     pub access_token: String,
     pub refresh_token: String,
     pub expires_at: DateTime<Utc>,
+    pub refresh_expires_at: DateTime<Utc>,
+    // 同じトークンから更新されたトークンは同じファミリーになる
+    pub family_id: String,
+    // リフレッシュトークンを使って更新された日時。更新されたトークンはもう使えない
+    pub rotated_at: Option<DateTime<Utc>>,
+    // 一般トークンでクライアントに許可した操作。マスタートークンでは使わない
+    pub scopes: Vec<TokenScope>,
+    // 更新されたトークンは更新前のトークンの端末の情報を引き継ぐ
+    pub device: TokenDevice,
+    pub last_used_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -25,22 +56,143 @@ impl Token {
         id_generator: &impl ObjectIdGeneratorPort,
     ) -> Self {
         let now = clock.now();
+        let id = id_generator.generate();
         Self {
-            id: id_generator.generate(),
+            family_id: id.clone(),
+            id,
             user_id,
             client_id,
             access_token,
             refresh_token,
             expires_at,
+            refresh_expires_at: now + Duration::days(REFRESH_TOKEN_LIFETIME_DAYS),
+            rotated_at: None,
+            scopes: Vec::new(),
+            device: TokenDevice::default(),
+            last_used_at: now,
             created_at: now,
             updated_at: now,
         }
     }
 
+    /// `client_id`のクライアントが`scopes`の操作に使うトークンを発行する
+    pub fn create_general(
+        user_id: String,
+        client_id: String,
+        scopes: Vec<TokenScope>,
+        device: TokenDevice,
+        clock: &impl ClockPort,
+        id_generator: &impl ObjectIdGeneratorPort,
+        safe_id_generator: &impl SafeIdGeneratorPort,
+    ) -> Self {
+        let token = Self::new(
+            user_id,
+            client_id,
+            safe_id_generator.generate(),
+            safe_id_generator.generate(),
+            clock.now() + Duration::hours(ACCESS_TOKEN_LIFETIME_HOURS),
+            clock,
+            id_generator,
+        );
+        Self {
+            scopes,
+            device,
+            ..token
+        }
+    }
+
+    /// ログインした端末で全ての操作に使うマスタートークンを発行する
+    pub fn create_master(
+        user_id: String,
+        device: TokenDevice,
+        clock: &impl ClockPort,
+        id_generator: &impl ObjectIdGeneratorPort,
+        safe_id_generator: &impl SafeIdGeneratorPort,
+    ) -> Self {
+        let token = Self::new(
+            user_id,
+            String::new(),
+            safe_id_generator.generate(),
+            safe_id_generator.generate(),
+            clock.now() + Duration::days(MASTER_TOKEN_LIFETIME_DAYS),
+            clock,
+            id_generator,
+        );
+        Self { device, ..token }
+    }
+
+    pub fn is_master(&self) -> bool {
+        self.client_id.is_empty()
+    }
+
     pub fn is_expired(&self, clock: &impl ClockPort) -> bool {
         self.expires_at <= clock.now()
     }
 
+    pub fn is_rotated(&self) -> bool {
+        self.rotated_at.is_some()
+    }
+
+    /// セッションとしてまだ使えるか。アクセストークンの期限が切れていてもリフレッシュトークンで更新できれば使える
+    pub fn is_active(&self, clock: &impl ClockPort) -> bool {
+        let now = clock.now();
+        !self.is_rotated() && (self.expires_at > now || self.refresh_expires_at > now)
+    }
+
+    /// 最終使用日時を更新する
+    ///
+    /// # 返り値
+    /// 前回の更新から`LAST_USED_UPDATE_INTERVAL_MINUTES`以上経っていて、保存が必要な場合は`true`
+    pub fn touch(&mut self, clock: &impl ClockPort) -> bool {
+        let now = clock.now();
+        if now - self.last_used_at < Duration::minutes(LAST_USED_UPDATE_INTERVAL_MINUTES) {
+            return false;
+        }
+        self.last_used_at = now;
+        true
+    }
+
+    /// 一致するまでの時間から推測されないように、比較は一定時間で行う
+    pub fn verify_refresh_token(&self, refresh_token: &str) -> bool {
+        bool::from(self.refresh_token.as_bytes().ct_eq(refresh_token.as_bytes()))
+    }
+
+    /// リフレッシュトークンでアクセストークンとリフレッシュトークンを両方更新する
+    ///
+    /// このトークンは使用済みになり、同じファミリーの新しいトークンを返す
+    ///
+    /// # エラー
+    /// * 既に更新されている、またはリフレッシュトークンの期限が切れている場合は`AtError::TokenAuth`
+    pub fn rotate(
+        &mut self,
+        clock: &impl ClockPort,
+        id_generator: &impl ObjectIdGeneratorPort,
+        safe_id_generator: &impl SafeIdGeneratorPort,
+    ) -> AtResult<Token> {
+        let now = clock.now();
+        if self.is_rotated() || self.refresh_expires_at <= now {
+            return Err(AtError::TokenAuth);
+        }
+        self.rotated_at = Some(now);
+        self.updated_at = now;
+
+        let token = Self::new(
+            self.user_id.clone(),
+            self.client_id.clone(),
+            safe_id_generator.generate(),
+            safe_id_generator.generate(),
+            now + Duration::hours(ACCESS_TOKEN_LIFETIME_HOURS),
+            clock,
+            id_generator,
+        );
+        Ok(Self {
+            family_id: self.family_id.clone(),
+            scopes: self.scopes.clone(),
+            device: self.device.clone(),
+            ..token
+        })
+    }
+
     pub fn update(&mut self, clock: &impl ClockPort) {
         self.updated_at = clock.now();
     }
@@ -52,6 +204,33 @@ impl Token {
     pub fn is_client(&self, client_id: &str) -> bool {
         self.client_id == client_id
     }
+
+    /// APIから渡されたキーで認証する
+    ///
+    /// # エラー
+    /// * キーが一致しない、期限切れ、または更新済みの場合は`AtError::TokenAuth`
+    pub fn auth(&self, key: &str, clock: &impl ClockPort) -> AtResult<AuthToken> {
+        let matched = bool::from(self.access_token.as_bytes().ct_eq(key.as_bytes()));
+        if !matched || self.is_expired(clock) || self.is_rotated() {
+            return Err(AtError::TokenAuth);
+        }
+
+        let base = AuthTokenBase {
+            id: self.id.clone(),
+            key: self.access_token.clone(),
+            user: self.user_id.clone(),
+        };
+        // マスタートークンはクライアントに紐付かない
+        if self.is_master() {
+            Ok(AuthToken::Master(AuthTokenMaster { base }))
+        } else {
+            Ok(AuthToken::General(AuthTokenGeneral {
+                base,
+                client: self.client_id.clone(),
+                scopes: self.scopes.clone(),
+            }))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -59,7 +238,9 @@ mod tests {
     use super::*;
     use crate::adapters::{
         clock::clock::Clock,
+        clock::fix_clock::FixClock,
         object_id_generator::object_id_generator::ObjectIdGenerator,
+        safe_id_generator::safe_id_generator::SafeIdGenerator,
     };
 
     #[tokio::test]
@@ -183,4 +364,127 @@ mod tests {
         assert!(token.is_client("client1"));
         assert!(!token.is_client("client2"));
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_auth() {
+        let clock = Clock::new();
+        let id_generator = ObjectIdGenerator::new();
+        let expires_at = clock.now() + Duration::hours(1);
+
+        let token = Token::new(
+            "user1".to_string(),
+            "client1".to_string(),
+            "access_token1".to_string(),
+            "refresh_token1".to_string(),
+            expires_at,
+            &clock,
+            &id_generator,
+        );
+        let token = Token {
+            scopes: vec![TokenScope::Read],
+            ..token
+        };
+        match token.auth("access_token1", &clock).unwrap() {
+            AuthToken::General(auth) => {
+                assert_eq!(auth.base.user, "user1");
+                assert_eq!(auth.client, "client1");
+                assert_eq!(auth.scopes, vec![TokenScope::Read]);
+            }
+            AuthToken::Master(_) => panic!("general token expected"),
+        }
+        assert!(matches!(token.auth("wrong", &clock), Err(AtError::TokenAuth)));
+
+        let token = Token::new(
+            "user1".to_string(),
+            "".to_string(),
+            "access_token1".to_string(),
+            "refresh_token1".to_string(),
+            expires_at,
+            &clock,
+            &id_generator,
+        );
+        assert!(matches!(token.auth("access_token1", &clock), Ok(AuthToken::Master(_))));
+
+        let expired = FixClock::new(expires_at);
+        assert!(matches!(token.auth("access_token1", &expired), Err(AtError::TokenAuth)));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let now = Utc::now();
+        let clock = FixClock::new(now);
+        let id_generator = ObjectIdGenerator::new();
+        let safe_id_generator = SafeIdGenerator::new();
+
+        let mut token = Token::create_general(
+            "user1".to_string(),
+            "client1".to_string(),
+            vec![TokenScope::Read],
+            TokenDevice {
+                ip: Some("127.0.0.1".to_string()),
+                user_agent: Some("Mozilla/5.0".to_string()),
+            },
+            &clock,
+            &id_generator,
+            &safe_id_generator,
+        );
+        let access_token = token.access_token.clone();
+
+        let later = FixClock::new(now + Duration::hours(ACCESS_TOKEN_LIFETIME_HOURS));
+        let rotated = token.rotate(&later, &id_generator, &safe_id_generator).unwrap();
+        assert_ne!(rotated.id, token.id);
+        assert_eq!(rotated.family_id, token.family_id);
+        assert_eq!(rotated.scopes, vec![TokenScope::Read]);
+        assert_eq!(rotated.device, token.device);
+        assert_ne!(rotated.access_token, access_token);
+        assert_ne!(rotated.refresh_token, token.refresh_token);
+        assert!(rotated.auth(&rotated.access_token, &later).is_ok());
+
+        // 更新前のトークンはもう使えない
+        assert!(token.is_rotated());
+        assert!(matches!(token.auth(&access_token, &clock), Err(AtError::TokenAuth)));
+        assert!(matches!(
+            token.rotate(&later, &id_generator, &safe_id_generator),
+            Err(AtError::TokenAuth)
+        ));
+
+        // リフレッシュトークンの期限が切れたら更新できない
+        let mut rotated = rotated;
+        let expired = FixClock::new(rotated.refresh_expires_at);
+        assert!(matches!(
+            rotated.rotate(&expired, &id_generator, &safe_id_generator),
+            Err(AtError::TokenAuth)
+        ));
+    }
+
+    #[test]
+    fn test_touch_and_is_active() {
+        let now = Utc::now();
+        let clock = FixClock::new(now);
+        let id_generator = ObjectIdGenerator::new();
+        let safe_id_generator = SafeIdGenerator::new();
+
+        let mut token = Token::create_master(
+            "user1".to_string(),
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &safe_id_generator,
+        );
+        assert!(token.is_master());
+        assert!(matches!(token.auth(&token.access_token, &clock), Ok(AuthToken::Master(_))));
+
+        // 間隔が短ければ保存しない
+        let soon = FixClock::new(now + Duration::minutes(LAST_USED_UPDATE_INTERVAL_MINUTES - 1));
+        assert!(!token.touch(&soon));
+        assert_eq!(token.last_used_at, now);
+
+        let later = FixClock::new(now + Duration::minutes(LAST_USED_UPDATE_INTERVAL_MINUTES));
+        assert!(token.touch(&later));
+        assert_eq!(token.last_used_at, later.now());
+
+        assert!(token.is_active(&later));
+        let expired = FixClock::new(now + Duration::days(MASTER_TOKEN_LIFETIME_DAYS));
+        assert!(!token.is_active(&expired));
+    }
+}
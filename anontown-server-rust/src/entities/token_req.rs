@@ -0,0 +1,355 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::at_error::{AtError, AtResult, ParamErrorData};
+use crate::auth::TokenScope;
+use crate::entities::short_code;
+use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort};
+
+// RFC 6749 4.1.2で推奨されている最大の有効期間
+const AUTHORIZATION_CODE_LIFETIME_MINUTES: i64 = 10;
+// 対応しているPKCEのcode_challenge_method。plainは受け付けない
+pub const CODE_CHALLENGE_METHOD_S256: &str = "S256";
+// 端末の引き継ぎコード。手で入力するので短くし、その分有効期間も短くする
+pub const PAIRING_CODE_LIFETIME_MINUTES: i64 = 5;
+const PAIRING_CODE_LEN: usize = 8;
+// 引き継ぎコードの前半。入力されたコードをどのリクエストへの試行として数えるかに使う
+const PAIRING_SELECTOR_LEN: usize = PAIRING_CODE_LEN / 2;
+// 前半が一致して後半が違う入力がこの回数に達したら、総当たりされているとみなして引き継ぎコードを無効にする
+pub const PAIRING_MAX_FAILED_ATTEMPTS: i32 = 5;
+// QRコードを読み取ったクライアントが引き継ぎ画面を開くためのURI
+const PAIRING_URI_PREFIX: &str = "anontown://pair?code=";
+
+/// トークンを受け取るための一時的なリクエスト
+///
+/// `token_id`はリクエストを発行したユーザーのマスタートークンで、トークンは使われた時に初めて作る。
+/// OAuthの認可コードとして発行した場合は、`client_id`のクライアントに`scopes`の権限を持つトークンを渡す。
+/// コードを交換する時には同じクライアント、リダイレクトURI、PKCEのcode_verifierを要求する。
+/// 端末の引き継ぎに使う場合は`key`が引き継ぎコードになる。
+/// `failed_attempts`は引き継ぎコードに対して間違ったコードが入力された回数
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenReq {
+    pub token_id: String,
+    pub key: String,
+    pub expires: DateTime<Utc>,
+    pub active: bool,
+    pub redirect_uri: Option<String>,
+    pub code_challenge: Option<String>,
+    pub client_id: Option<String>,
+    pub scopes: Vec<TokenScope>,
+    pub pairing: bool,
+    pub failed_attempts: i32,
+}
+
+impl TokenReq {
+    /// `token_id`のマスタートークンでログインしているユーザーが、`client_id`のクライアントを認可した認可コードを発行する
+    pub fn create_authorization_code(
+        token_id: String,
+        client_id: String,
+        scopes: Vec<TokenScope>,
+        redirect_uri: String,
+        code_challenge: String,
+        clock: &impl ClockPort,
+        safe_id_generator: &impl SafeIdGeneratorPort,
+    ) -> Self {
+        Self {
+            token_id,
+            key: safe_id_generator.generate(),
+            expires: clock.now() + Duration::minutes(AUTHORIZATION_CODE_LIFETIME_MINUTES),
+            active: true,
+            redirect_uri: Some(redirect_uri),
+            code_challenge: Some(code_challenge),
+            client_id: Some(client_id),
+            scopes,
+            pairing: false,
+            failed_attempts: 0,
+        }
+    }
+
+    /// `token_id`のマスタートークンでログインしている端末から、別の端末にログインを引き継ぐためのコードを発行する
+    pub fn create_pairing(
+        token_id: String,
+        clock: &impl ClockPort,
+        safe_id_generator: &impl SafeIdGeneratorPort,
+    ) -> Self {
+        Self {
+            token_id,
+            key: short_code::generate(PAIRING_CODE_LEN, safe_id_generator),
+            expires: clock.now() + Duration::minutes(PAIRING_CODE_LIFETIME_MINUTES),
+            active: true,
+            redirect_uri: None,
+            code_challenge: None,
+            client_id: None,
+            scopes: Vec::new(),
+            pairing: true,
+            failed_attempts: 0,
+        }
+    }
+
+    /// 画面に表示する`XXXX-XXXX`形式の引き継ぎコード
+    pub fn pairing_code(&self) -> String {
+        short_code::format(&self.key)
+    }
+
+    /// QRコードにする引き継ぎ用のURI
+    pub fn pairing_qr_payload(&self) -> String {
+        format!("{}{}", PAIRING_URI_PREFIX, self.key)
+    }
+
+    /// 入力された引き継ぎコードを保存されている形式にする。QRコードのURIがそのまま渡された場合も受け付ける
+    pub fn normalize_pairing_code(input: &str) -> Option<String> {
+        let input = input.trim();
+        let input = input.strip_prefix(PAIRING_URI_PREFIX).unwrap_or(input);
+        short_code::normalize(input, PAIRING_CODE_LEN)
+    }
+
+    /// 引き継ぎコードの前半。`normalize_pairing_code`した入力から、試行の対象になるリクエストを探すのに使う
+    pub fn pairing_selector(key: &str) -> &str {
+        &key[..PAIRING_SELECTOR_LEN.min(key.len())]
+    }
+
+    /// 入力された引き継ぎコードがこのリクエストのものか
+    pub fn matches_pairing_code(&self, key: &str) -> bool {
+        bool::from(self.key.as_bytes().ct_eq(key.as_bytes()))
+    }
+
+    /// 引き継ぎコードを使用済みにする
+    ///
+    /// # エラー
+    /// * 引き継ぎコードでない、使用済み、期限切れの場合は`AtError::Auth`
+    pub fn redeem_pairing(&mut self, clock: &impl ClockPort) -> AtResult<()> {
+        if !self.pairing || !self.active || self.expires <= clock.now() {
+            return Err(AtError::Auth("引き継ぎコードが無効です".to_string()));
+        }
+
+        self.active = false;
+        Ok(())
+    }
+
+    /// クライアントに渡す`{token_id}.{key}`形式の認可コード
+    pub fn code(&self) -> String {
+        format!("{}.{}", self.token_id, self.key)
+    }
+
+    /// 認可コードを`(token_id, key)`に分ける
+    pub fn parse_code(code: &str) -> Option<(&str, &str)> {
+        code.split_once('.')
+            .filter(|(token_id, key)| !token_id.is_empty() && !key.is_empty())
+    }
+
+    /// 認可コードをトークンと交換し、使用済みにする
+    ///
+    /// # エラー
+    /// * 使用済み、期限切れ、他のクライアントのもの、リダイレクトURIかcode_verifierが一致しない場合は`AtError::Auth`
+    pub fn exchange(
+        &mut self,
+        client_id: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+        clock: &impl ClockPort,
+    ) -> AtResult<()> {
+        let invalid = || AtError::Auth("認可コードが無効です".to_string());
+        if !self.active || self.expires <= clock.now() {
+            return Err(invalid());
+        }
+        if self.client_id.as_deref() != Some(client_id) {
+            return Err(invalid());
+        }
+        if self.redirect_uri.as_deref() != Some(redirect_uri) {
+            return Err(invalid());
+        }
+        match &self.code_challenge {
+            Some(code_challenge) if verify_code_challenge(code_verifier, code_challenge) => {}
+            _ => return Err(invalid()),
+        }
+
+        self.active = false;
+        Ok(())
+    }
+}
+
+// RFC 7636 4.1: 43〜128文字の[A-Z] / [a-z] / [0-9] / "-" / "." / "_" / "~"
+fn is_valid_code_verifier(code_verifier: &str) -> bool {
+    (43..=128).contains(&code_verifier.len())
+        && code_verifier
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'.' | b'_' | b'~'))
+}
+
+/// S256のcode_challengeとして正しい形式か
+///
+/// SHA-256をパディング無しのbase64urlにしたものなので常に43文字になる
+pub fn is_valid_code_challenge(code_challenge: &str) -> bool {
+    code_challenge.len() == 43
+        && code_challenge
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'_'))
+}
+
+/// 認可リクエストのPKCEのパラメーターを確かめ、code_challengeを返す
+///
+/// # エラー
+/// * code_challengeが無い、S256でない、形式が不正な場合は`AtError::Params`
+pub fn check_code_challenge<'a>(
+    code_challenge: Option<&'a str>,
+    code_challenge_method: Option<&str>,
+) -> AtResult<&'a str> {
+    match (code_challenge, code_challenge_method) {
+        (Some(code_challenge), Some(CODE_CHALLENGE_METHOD_S256))
+            if is_valid_code_challenge(code_challenge) =>
+        {
+            Ok(code_challenge)
+        }
+        _ => Err(AtError::Params(vec![ParamErrorData::new(
+            "code_challenge",
+            "invalid",
+            "S256のcode_challengeが必要です",
+        )])),
+    }
+}
+
+/// `BASE64URL(SHA256(code_verifier)) == code_challenge`か
+pub fn verify_code_challenge(code_verifier: &str, code_challenge: &str) -> bool {
+    is_valid_code_verifier(code_verifier)
+        && URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes())) == code_challenge
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+
+    // RFC 7636 Appendix Bの例
+    const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    struct DummySafeIdGenerator;
+
+    impl SafeIdGeneratorPort for DummySafeIdGenerator {
+        fn generate(&self) -> String {
+            "key".to_string()
+        }
+    }
+
+    fn create(clock: &FixClock) -> TokenReq {
+        TokenReq::create_authorization_code(
+            "token".to_string(),
+            "client".to_string(),
+            vec![TokenScope::Read],
+            "https://example.com/callback".to_string(),
+            CODE_CHALLENGE.to_string(),
+            clock,
+            &DummySafeIdGenerator,
+        )
+    }
+
+    #[test]
+    fn test_verify_code_challenge() {
+        assert!(is_valid_code_challenge(CODE_CHALLENGE));
+        assert!(verify_code_challenge(CODE_VERIFIER, CODE_CHALLENGE));
+        assert!(!verify_code_challenge(&CODE_VERIFIER.replace('d', "e"), CODE_CHALLENGE));
+        // 短すぎるcode_verifierは一致しても受け付けない
+        let short = "abc";
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(short.as_bytes()));
+        assert!(!verify_code_challenge(short, &challenge));
+    }
+
+    #[test]
+    fn test_check_code_challenge() {
+        assert_eq!(check_code_challenge(Some(CODE_CHALLENGE), Some("S256")).unwrap(), CODE_CHALLENGE);
+        assert!(matches!(check_code_challenge(Some(CODE_CHALLENGE), Some("plain")), Err(AtError::Params(_))));
+        assert!(matches!(check_code_challenge(Some(CODE_CHALLENGE), None), Err(AtError::Params(_))));
+        assert!(matches!(check_code_challenge(None, Some("S256")), Err(AtError::Params(_))));
+        assert!(matches!(check_code_challenge(Some("short"), Some("S256")), Err(AtError::Params(_))));
+    }
+
+    #[test]
+    fn test_code() {
+        let req = create(&FixClock::new(Utc::now()));
+        assert_eq!(req.code(), "token.key");
+        assert_eq!(TokenReq::parse_code(&req.code()), Some(("token", "key")));
+        assert_eq!(TokenReq::parse_code("token"), None);
+        assert_eq!(TokenReq::parse_code(".key"), None);
+    }
+
+    #[test]
+    fn test_exchange() {
+        let now = Utc::now();
+        let clock = FixClock::new(now);
+        let redirect_uri = "https://example.com/callback";
+
+        let mut req = create(&clock);
+        assert!(matches!(
+            req.exchange("other", redirect_uri, CODE_VERIFIER, &clock),
+            Err(AtError::Auth(_))
+        ));
+        assert!(matches!(
+            req.exchange("client", "https://example.com/other", CODE_VERIFIER, &clock),
+            Err(AtError::Auth(_))
+        ));
+        assert!(matches!(
+            req.exchange("client", redirect_uri, &CODE_VERIFIER.replace('d', "e"), &clock),
+            Err(AtError::Auth(_))
+        ));
+        assert!(req.active);
+
+        req.exchange("client", redirect_uri, CODE_VERIFIER, &clock).unwrap();
+        assert!(!req.active);
+        // 2回は使えない
+        assert!(matches!(
+            req.exchange("client", redirect_uri, CODE_VERIFIER, &clock),
+            Err(AtError::Auth(_))
+        ));
+
+        let mut req = create(&clock);
+        let expired = FixClock::new(now + Duration::minutes(AUTHORIZATION_CODE_LIFETIME_MINUTES));
+        assert!(matches!(
+            req.exchange("client", redirect_uri, CODE_VERIFIER, &expired),
+            Err(AtError::Auth(_))
+        ));
+    }
+
+    #[test]
+    fn test_pairing() {
+        let now = Utc::now();
+        let clock = FixClock::new(now);
+
+        let mut req = TokenReq::create_pairing("token".to_string(), &clock, &DummySafeIdGenerator);
+        assert_eq!(req.key.len(), PAIRING_CODE_LEN);
+        assert_eq!(req.pairing_code().replace('-', ""), req.key);
+        assert_eq!(
+            TokenReq::normalize_pairing_code(&req.pairing_code().to_lowercase()),
+            Some(req.key.clone())
+        );
+        assert_eq!(
+            TokenReq::normalize_pairing_code(&req.pairing_qr_payload()),
+            Some(req.key.clone())
+        );
+
+        // 認可コードとしては使えない
+        assert!(matches!(
+            req.exchange("client", "https://example.com/callback", CODE_VERIFIER, &clock),
+            Err(AtError::Auth(_))
+        ));
+        assert!(matches!(create(&clock).redeem_pairing(&clock), Err(AtError::Auth(_))));
+
+        let expired = FixClock::new(now + Duration::minutes(PAIRING_CODE_LIFETIME_MINUTES));
+        assert!(matches!(req.redeem_pairing(&expired), Err(AtError::Auth(_))));
+        req.redeem_pairing(&clock).unwrap();
+        assert!(!req.active);
+        // 2回は使えない
+        assert!(matches!(req.redeem_pairing(&clock), Err(AtError::Auth(_))));
+    }
+
+    #[test]
+    fn test_pairing_selector() {
+        let clock = FixClock::new(Utc::now());
+        let req = TokenReq::create_pairing("token".to_string(), &clock, &DummySafeIdGenerator);
+        assert_eq!(TokenReq::pairing_selector(&req.key), &req.key[..4]);
+        assert!(req.matches_pairing_code(&req.key));
+        assert!(!req.matches_pairing_code("ZZZZZZZZ"));
+    }
+}
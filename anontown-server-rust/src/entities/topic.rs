@@ -1,10 +1,30 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Utc};
 use serde::{Deserialize, Serialize};
-use crate::ports::object_id::ObjectIdGenerator;
+use crate::ports::object_id_generator::ObjectIdGeneratorPort;
 use crate::ports::clock::ClockPort;
 use crate::entities::user::User;
 use crate::entities::res::Res;
 use crate::adapters::clock::fix_clock::FixClock;
+use crate::at_error::AtResult;
+use crate::validation::{grapheme_len, normalize, ListRule, StrRule, Validator};
+use unicode_segmentation::UnicodeSegmentation;
+
+// 文字数は見た目の1文字で数える
+pub const TITLE_MAX_LEN: usize = 100;
+pub const TAGS_MAX: usize = 15;
+pub const TAG_MAX_LEN: usize = 20;
+pub const TEXT_MAX_LEN: usize = 10000;
+
+pub const TITLE_RULES: &[StrRule] =
+    &[StrRule::Required, StrRule::Visible, StrRule::SingleLine, StrRule::MaxLen(TITLE_MAX_LEN)];
+pub const TAGS_RULES: &[ListRule] = &[ListRule::MaxItems(TAGS_MAX), ListRule::Unique];
+pub const TAG_RULES: &[StrRule] =
+    &[StrRule::Required, StrRule::Visible, StrRule::SingleLine, StrRule::MaxLen(TAG_MAX_LEN)];
+pub const TEXT_RULES: &[StrRule] = &[StrRule::Required, StrRule::MultiLine, StrRule::MaxLen(TEXT_MAX_LEN)];
+
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    tags.iter().map(String::as_str).map(normalize).collect()
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum TopicType {
@@ -24,67 +44,133 @@ pub struct TopicBase {
     pub updated_at: DateTime<Utc>,
     pub res_count: i32,
     pub last_res_at: DateTime<Utc>,
+    // ageされたレスが最後に書き込まれた日時
+    pub age_updated_at: DateTime<Utc>,
     pub is_closed: bool,
     pub tags: Vec<String>,
+    // 楽観的排他制御のためのバージョン。保存する度にリポジトリが1増やす
+    pub version: i32,
+}
+
+/// トピックが落ちるまでの期間
+#[derive(Debug, Clone)]
+pub struct TopicLifecycle {
+    // 通常トピックはageされない期間で判定する。Noneなら落ちない
+    pub normal_inactive: Option<Duration>,
+    pub one_inactive: Duration,
+    pub fork_inactive: Duration,
+    // 単発トピックは更新されていても作成からこの期間が過ぎれば落ちる
+    pub one_lifetime: Duration,
+    // 1つのトピックに書き込めるレスの数。達すると落ち、通常トピックなら次スレが立つ
+    pub res_limit: i32,
+}
+
+impl Default for TopicLifecycle {
+    fn default() -> Self {
+        Self {
+            normal_inactive: None,
+            one_inactive: Duration::days(7),
+            fork_inactive: Duration::days(7),
+            one_lifetime: Duration::days(30),
+            res_limit: 1000,
+        }
+    }
+}
+
+/// レスのID(ハッシュ)の設定
+#[derive(Debug, Clone)]
+pub struct HashConfig {
+    pub salt: String,
+    // 日付の区切りに使うタイムゾーン
+    pub timezone: FixedOffset,
+}
+
+impl HashConfig {
+    pub fn jst(salt: String) -> Self {
+        Self {
+            salt,
+            timezone: FixedOffset::east_opt(9 * 60 * 60).unwrap(),
+        }
+    }
 }
 
 impl TopicBase {
-    pub fn hash(&self, date: DateTime<Utc>, user: &User) -> String {
+    /// 同じトピック・同じユーザー・同じ日なら同じになるID
+    pub fn hash(&self, date: DateTime<Utc>, user: &User, config: &HashConfig) -> String {
         use sha2::{Sha256, Digest};
+        let local = date.with_timezone(&config.timezone);
+        let day = format!("{}-{}-{}", local.year(), local.month(), local.day());
+
         let mut hasher = Sha256::new();
-        hasher.update(self.id.as_bytes());
-        hasher.update(date.to_rfc3339().as_bytes());
+        // ユーザー
         hasher.update(user.id.as_bytes());
+        hasher.update(b" ");
+        // 書き込み年月日
+        hasher.update(day.as_bytes());
+        hasher.update(b" ");
+        // トピック
+        hasher.update(self.id.as_bytes());
+        // ソルト
+        hasher.update(config.salt.as_bytes());
         format!("{:x}", hasher.finalize())
     }
 
-    pub fn check_data(title: &str, tags: &[String], text: &str) -> Result<(), String> {
-        // タイトルのバリデーション
-        if title.is_empty() {
-            return Err("タイトルが空です".to_string());
-        }
-        if title.len() > 100 {
-            return Err("タイトルが長すぎます".to_string());
-        }
-
-        // タグのバリデーション
-        if tags.len() > 15 {
-            return Err("タグが多すぎます".to_string());
-        }
-        let mut unique_tags = std::collections::HashSet::new();
-        for tag in tags {
-            if tag.is_empty() {
-                return Err("タグが空です".to_string());
-            }
-            if tag.len() > 20 {
-                return Err("タグが長すぎます".to_string());
-            }
-            if !unique_tags.insert(tag) {
-                return Err("タグに重複があります".to_string());
-            }
-        }
+    /// 省略された項目は検査しない
+    pub fn check_fields(
+        v: Validator,
+        title: Option<&str>,
+        tags: Option<&[String]>,
+        text: Option<&str>,
+    ) -> Validator {
+        v.opt_str("title", "タイトル", title, TITLE_RULES)
+            .opt_list("tags", "タグ", tags, TAGS_RULES, TAG_RULES)
+            .opt_str("text", "本文", text, TEXT_RULES)
+    }
 
-        // 本文のバリデーション
-        if text.is_empty() {
-            return Err("本文が空です".to_string());
-        }
-        if text.len() > 10000 {
-            return Err("本文が長すぎます".to_string());
-        }
+    pub fn check_data(title: &str, tags: &[String], text: &str) -> AtResult<()> {
+        Self::check_fields(Validator::new(), Some(title), Some(tags), Some(text)).finish()
+    }
 
-        Ok(())
+    pub fn is_full(&self, lifecycle: &TopicLifecycle) -> bool {
+        self.res_count >= lifecycle.res_limit
     }
 
-    pub fn can_create_res(&self) -> bool {
-        !self.is_closed
+    pub fn can_create_res(&self, lifecycle: &TopicLifecycle) -> bool {
+        !self.is_closed && !self.is_full(lifecycle)
     }
 
     pub fn res_update(&mut self, res: &Res, clock: &dyn ClockPort) -> &mut Self {
         self.res_count += 1;
         self.last_res_at = clock.now();
         self.updated_at = clock.now();
+        if let Res::Normal(normal) = res {
+            if normal.age {
+                self.age_updated_at = clock.now();
+            }
+        }
         self
     }
+
+    pub fn should_close(&self, lifecycle: &TopicLifecycle, now: DateTime<Utc>) -> bool {
+        if self.is_closed {
+            return false;
+        }
+
+        match self.topic_type {
+            TopicType::Normal => lifecycle
+                .normal_inactive
+                .map_or(false, |inactive| self.age_updated_at + inactive <= now),
+            TopicType::One => {
+                self.updated_at + lifecycle.one_inactive <= now
+                    || self.created_at + lifecycle.one_lifetime <= now
+            }
+            TopicType::Fork => self.updated_at + lifecycle.fork_inactive <= now,
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.is_closed = true;
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -106,9 +192,42 @@ pub struct TopicFork {
     pub parent_id: String,
 }
 
+/// 次スレのタイトル
+///
+/// 末尾が`Part2`のような番号なら1つ増やし、番号がなければ` Part2`を付ける
+pub fn next_part_title(title: &str) -> String {
+    let title = title.trim_end();
+    let digits_len = title
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    let (prefix, digits) = title.split_at(title.len() - digits_len);
+
+    let (mut prefix, suffix) = match digits.parse::<u32>() {
+        Ok(part)
+            if prefix
+                .trim_end_matches([' ', '.'])
+                .to_lowercase()
+                .ends_with("part") =>
+        {
+            (prefix.to_string(), (part + 1).to_string())
+        }
+        _ => (title.to_string(), " Part2".to_string()),
+    };
+
+    // 長すぎる場合は番号を残して元のタイトルを削る
+    while grapheme_len(&prefix) + grapheme_len(&suffix) > TITLE_MAX_LEN {
+        let last = prefix.grapheme_indices(true).next_back().map_or(0, |(i, _)| i);
+        prefix.truncate(last);
+    }
+
+    prefix + &suffix
+}
+
 impl TopicNormal {
     pub fn create(
-        id_gen: &dyn ObjectIdGenerator,
+        id_gen: &dyn ObjectIdGeneratorPort,
         clock: &dyn ClockPort,
         title: String,
         description: String,
@@ -119,16 +238,18 @@ impl TopicNormal {
         Self {
             base: TopicBase {
                 id: id_gen.generate(),
-                title,
-                description,
+                title: normalize(&title),
+                description: normalize(&description),
                 topic_type: TopicType::Normal,
                 user_id,
                 created_at: now,
                 updated_at: now,
                 res_count: 1,
                 last_res_at: now,
+                age_updated_at: now,
                 is_closed: false,
-                tags,
+                version: 0,
+                tags: normalize_tags(&tags),
             },
         }
     }
@@ -140,16 +261,30 @@ impl TopicNormal {
         description: String,
         tags: Vec<String>,
         user: &mut User,
-    ) -> Result<(), String> {
+    ) -> AtResult<()> {
         TopicBase::check_data(&title, &tags, &description)?;
-        self.base.title = title;
-        self.base.description = description;
-        self.base.tags = tags;
+        self.base.title = normalize(&title);
+        self.base.description = normalize(&description);
+        self.base.tags = normalize_tags(&tags);
         self.base.updated_at = clock.now();
         user.point += 1;
         Ok(())
     }
 
+    /// レス数が上限に達した時に立てる次スレ
+    ///
+    /// 本文・タグ・作成者は引き継ぐ
+    pub fn create_next(&self, id_gen: &dyn ObjectIdGeneratorPort, clock: &dyn ClockPort) -> Self {
+        Self::create(
+            id_gen,
+            clock,
+            next_part_title(&self.base.title),
+            self.base.description.clone(),
+            self.base.user_id.clone(),
+            self.base.tags.clone(),
+        )
+    }
+
     pub fn base(&self) -> &TopicBase {
         &self.base
     }
@@ -161,7 +296,7 @@ impl TopicNormal {
 
 impl TopicOne {
     pub fn create(
-        id_gen: &dyn ObjectIdGenerator,
+        id_gen: &dyn ObjectIdGeneratorPort,
         clock: &dyn ClockPort,
         title: String,
         description: String,
@@ -172,16 +307,18 @@ impl TopicOne {
         Self {
             base: TopicBase {
                 id: id_gen.generate(),
-                title,
-                description,
+                title: normalize(&title),
+                description: normalize(&description),
                 topic_type: TopicType::One,
                 user_id,
                 created_at: now,
                 updated_at: now,
                 res_count: 1,
                 last_res_at: now,
+                age_updated_at: now,
                 is_closed: false,
-                tags,
+                version: 0,
+                tags: normalize_tags(&tags),
             },
         }
     }
@@ -193,11 +330,11 @@ impl TopicOne {
         description: String,
         tags: Vec<String>,
         user: &mut User,
-    ) -> Result<(), String> {
+    ) -> AtResult<()> {
         TopicBase::check_data(&title, &tags, &description)?;
-        self.base.title = title;
-        self.base.description = description;
-        self.base.tags = tags;
+        self.base.title = normalize(&title);
+        self.base.description = normalize(&description);
+        self.base.tags = normalize_tags(&tags);
         self.base.updated_at = clock.now();
         user.point += 1;
         Ok(())
@@ -214,7 +351,7 @@ impl TopicOne {
 
 impl TopicFork {
     pub fn create(
-        id_gen: &dyn ObjectIdGenerator,
+        id_gen: &dyn ObjectIdGeneratorPort,
         clock: &dyn ClockPort,
         title: String,
         description: String,
@@ -226,16 +363,18 @@ impl TopicFork {
         Self {
             base: TopicBase {
                 id: id_gen.generate(),
-                title,
-                description,
+                title: normalize(&title),
+                description: normalize(&description),
                 topic_type: TopicType::Fork,
                 user_id,
                 created_at: now,
                 updated_at: now,
                 res_count: 1,
                 last_res_at: now,
+                age_updated_at: now,
                 is_closed: false,
-                tags,
+                version: 0,
+                tags: normalize_tags(&tags),
             },
             parent_id,
         }
@@ -248,11 +387,11 @@ impl TopicFork {
         description: String,
         tags: Vec<String>,
         user: &mut User,
-    ) -> Result<(), String> {
+    ) -> AtResult<()> {
         TopicBase::check_data(&title, &tags, &description)?;
-        self.base.title = title;
-        self.base.description = description;
-        self.base.tags = tags;
+        self.base.title = normalize(&title);
+        self.base.description = normalize(&description);
+        self.base.tags = normalize_tags(&tags);
         self.base.updated_at = clock.now();
         user.point += 1;
         Ok(())
@@ -326,13 +465,14 @@ impl Topic {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::at_error::AtError;
     use chrono::TimeZone;
 
     struct DummyObjectIdGenerator {
         id: String,
     }
 
-    impl ObjectIdGenerator for DummyObjectIdGenerator {
+    impl ObjectIdGeneratorPort for DummyObjectIdGenerator {
         fn generate(&self) -> String {
             self.id.clone()
         }
@@ -340,33 +480,59 @@ mod tests {
 
     #[test]
     fn test_topic_base_check_data() {
+        let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let fields = |r: AtResult<()>| match r {
+            Err(AtError::Params(errors)) => errors
+                .into_iter()
+                .map(|e| format!("{}:{}", e.field, e.code))
+                .collect::<Vec<_>>(),
+            r => panic!("unexpected result: {:?}", r),
+        };
+
         // 正常なケース
-        assert!(TopicBase::check_data("title", &["a", "b"], "text").is_ok());
+        assert!(TopicBase::check_data("title", &tags(&["a", "b"]), "text").is_ok());
         assert!(TopicBase::check_data("title", &[], "text").is_ok());
+        assert!(TopicBase::check_data(&"あ".repeat(100), &[], "text").is_ok());
 
         // タイトルが空
-        assert!(TopicBase::check_data("", &["a"], "text").is_err());
+        assert_eq!(fields(TopicBase::check_data("", &tags(&["a"]), "text")), vec!["title:required"]);
 
         // タイトルが長すぎる
-        assert!(TopicBase::check_data(&"a".repeat(101), &["a"], "text").is_err());
+        assert_eq!(
+            fields(TopicBase::check_data(&"a".repeat(101), &tags(&["a"]), "text")),
+            vec!["title:too_long"]
+        );
 
         // タグが空
-        assert!(TopicBase::check_data("title", &[""], "text").is_err());
+        assert_eq!(fields(TopicBase::check_data("title", &tags(&["a", ""]), "text")), vec!["tags[1]:required"]);
 
         // タグが長すぎる
-        assert!(TopicBase::check_data("title", &[&"a".repeat(21)], "text").is_err());
+        assert_eq!(
+            fields(TopicBase::check_data("title", &tags(&[&"a".repeat(21)]), "text")),
+            vec!["tags[0]:too_long"]
+        );
 
         // タグが多すぎる
-        assert!(TopicBase::check_data("title", &vec!["a".to_string(); 16], "text").is_err());
+        let many = (0..16).map(|i| i.to_string()).collect::<Vec<_>>();
+        assert_eq!(fields(TopicBase::check_data("title", &many, "text")), vec!["tags:too_many"]);
 
         // タグに重複がある
-        assert!(TopicBase::check_data("title", &["a", "a"], "text").is_err());
+        assert_eq!(fields(TopicBase::check_data("title", &tags(&["a", "a"]), "text")), vec!["tags[1]:duplicate"]);
 
         // 本文が空
-        assert!(TopicBase::check_data("title", &["a"], "").is_err());
+        assert_eq!(fields(TopicBase::check_data("title", &tags(&["a"]), "")), vec!["text:required"]);
 
         // 本文が長すぎる
-        assert!(TopicBase::check_data("title", &["a"], &"a".repeat(10001)).is_err());
+        assert_eq!(
+            fields(TopicBase::check_data("title", &tags(&["a"]), &"a".repeat(10001))),
+            vec!["text:too_long"]
+        );
+
+        // 違反はまとめて返す
+        assert_eq!(
+            fields(TopicBase::check_data("", &tags(&["a", "a"]), "")),
+            vec!["title:required", "tags[1]:duplicate", "text:required"]
+        );
     }
 
     #[test]
@@ -390,20 +556,43 @@ mod tests {
             vec!["tag".to_string()],
         );
 
-        let date1 = Utc.timestamp_opt(1000, 0).unwrap();
-        let date2 = Utc.timestamp_opt(1000, 1000).unwrap();
-        let date3 = Utc.timestamp_opt(1001, 0).unwrap();
+        let config = HashConfig::jst("salt".to_string());
+
+        // JSTで2024-01-01 00:00:00〜23:59:59
+        let date1 = Utc.with_ymd_and_hms(2023, 12, 31, 15, 0, 0).unwrap();
+        let date2 = Utc.with_ymd_and_hms(2024, 1, 1, 14, 59, 59).unwrap();
+        let date3 = Utc.with_ymd_and_hms(2024, 1, 1, 15, 0, 0).unwrap();
 
         // 同じ日付の場合は同じハッシュ
         assert_eq!(
-            topic.base().hash(date1, &user),
-            topic.base().hash(date2, &user)
+            topic.base().hash(date1, &user, &config),
+            topic.base().hash(date2, &user, &config)
         );
 
         // 異なる日付の場合は異なるハッシュ
         assert_ne!(
-            topic.base().hash(date1, &user),
-            topic.base().hash(date3, &user)
+            topic.base().hash(date2, &user, &config),
+            topic.base().hash(date3, &user, &config)
+        );
+
+        // 日付の区切りはタイムゾーンに従う
+        let utc_config = HashConfig {
+            salt: "salt".to_string(),
+            timezone: FixedOffset::east_opt(0).unwrap(),
+        };
+        assert_ne!(
+            topic.base().hash(date1, &user, &utc_config),
+            topic.base().hash(date2, &user, &utc_config)
+        );
+        assert_eq!(
+            topic.base().hash(date2, &user, &utc_config),
+            topic.base().hash(date3, &user, &utc_config)
+        );
+
+        // ソルトが異なる場合は異なるハッシュ
+        assert_ne!(
+            topic.base().hash(date1, &user, &config),
+            topic.base().hash(date1, &user, &HashConfig::jst("salt2".to_string()))
         );
 
         // 異なるユーザーの場合は異なるハッシュ
@@ -415,8 +604,8 @@ mod tests {
             Utc.timestamp_opt(0, 0).unwrap(),
         );
         assert_ne!(
-            topic.base().hash(date1, &user),
-            topic.base().hash(date1, &user2)
+            topic.base().hash(date1, &user, &config),
+            topic.base().hash(date1, &user2, &config)
         );
     }
 
@@ -668,4 +857,144 @@ mod tests {
             &mut user,
         ).is_err());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_topic_base_should_close() {
+        let created = Utc.timestamp_opt(0, 0).unwrap();
+        let clock = FixClock::new(created);
+        let lifecycle = TopicLifecycle {
+            normal_inactive: Some(Duration::days(30)),
+            one_inactive: Duration::days(7),
+            fork_inactive: Duration::days(3),
+            one_lifetime: Duration::days(10),
+            res_limit: 1000,
+        };
+
+        let normal = TopicNormal::create(
+            &DummyObjectIdGenerator { id: "normal".to_string() },
+            &clock,
+            "title".to_string(),
+            "description".to_string(),
+            "user".to_string(),
+            vec![],
+        );
+        let one = TopicOne::create(
+            &DummyObjectIdGenerator { id: "one".to_string() },
+            &clock,
+            "title".to_string(),
+            "description".to_string(),
+            "user".to_string(),
+            vec![],
+        );
+        let fork = TopicFork::create(
+            &DummyObjectIdGenerator { id: "fork".to_string() },
+            &clock,
+            "title".to_string(),
+            "description".to_string(),
+            "user".to_string(),
+            vec![],
+            "parent".to_string(),
+        );
+
+        // 通常トピックはageされない期間で落ちる
+        assert!(!normal.base().should_close(&lifecycle, created + Duration::days(30) - Duration::seconds(1)));
+        assert!(normal.base().should_close(&lifecycle, created + Duration::days(30)));
+        let mut aged = normal.clone();
+        aged.base_mut().age_updated_at = created + Duration::days(20);
+        aged.base_mut().updated_at = created + Duration::days(20);
+        assert!(!aged.base().should_close(&lifecycle, created + Duration::days(30)));
+        let mut sage = normal.clone();
+        sage.base_mut().updated_at = created + Duration::days(20);
+        assert!(sage.base().should_close(&lifecycle, created + Duration::days(30)));
+
+        // normal_inactiveがNoneなら落ちない
+        let default_lifecycle = TopicLifecycle::default();
+        assert!(!normal.base().should_close(&default_lifecycle, created + Duration::days(3650)));
+
+        // 単発トピックは更新がなければ落ちる
+        assert!(!one.base().should_close(&lifecycle, created + Duration::days(7) - Duration::seconds(1)));
+        assert!(one.base().should_close(&lifecycle, created + Duration::days(7)));
+
+        // 単発トピックは更新されていても寿命で落ちる
+        let mut active_one = one.clone();
+        active_one.base_mut().updated_at = created + Duration::days(9);
+        assert!(!active_one.base().should_close(&lifecycle, created + Duration::days(10) - Duration::seconds(1)));
+        assert!(active_one.base().should_close(&lifecycle, created + Duration::days(10)));
+
+        // 派生トピック
+        assert!(!fork.base().should_close(&lifecycle, created + Duration::days(3) - Duration::seconds(1)));
+        assert!(fork.base().should_close(&lifecycle, created + Duration::days(3)));
+
+        // 既に落ちているトピックは対象外
+        let mut closed = fork.clone();
+        closed.base_mut().close();
+        assert!(!closed.base().can_create_res(&lifecycle));
+        assert!(!closed.base().should_close(&lifecycle, created + Duration::days(3)));
+    }
+
+    #[test]
+    fn test_topic_base_is_full() {
+        let lifecycle = TopicLifecycle {
+            res_limit: 3,
+            ..TopicLifecycle::default()
+        };
+        let mut topic = TopicNormal::create(
+            &DummyObjectIdGenerator { id: "topic".to_string() },
+            &FixClock::new(Utc::now()),
+            "title".to_string(),
+            "description".to_string(),
+            "user".to_string(),
+            vec![],
+        );
+
+        topic.base_mut().res_count = 2;
+        assert!(!topic.base().is_full(&lifecycle));
+        assert!(topic.base().can_create_res(&lifecycle));
+
+        topic.base_mut().res_count = 3;
+        assert!(topic.base().is_full(&lifecycle));
+        assert!(!topic.base().can_create_res(&lifecycle));
+    }
+
+    #[test]
+    fn test_next_part_title() {
+        assert_eq!(next_part_title("雑談スレ"), "雑談スレ Part2");
+        assert_eq!(next_part_title("雑談スレ Part2"), "雑談スレ Part3");
+        assert_eq!(next_part_title("雑談スレpart9 "), "雑談スレpart10");
+        assert_eq!(next_part_title("雑談スレ Part.99"), "雑談スレ Part.100");
+        // Partが付いていない数字は番号とみなさない
+        assert_eq!(next_part_title("2024年"), "2024年 Part2");
+
+        // 文字数は見た目の1文字で数える
+        assert_eq!(next_part_title(&"あ".repeat(33)), format!("{} Part2", "あ".repeat(33)));
+        let title = next_part_title(&"👨\u{200D}👩\u{200D}👧".repeat(100));
+        assert_eq!(grapheme_len(&title), 100);
+        assert_eq!(title, format!("{} Part2", "👨\u{200D}👩\u{200D}👧".repeat(94)));
+    }
+
+    #[test]
+    fn test_topic_normal_create_next() {
+        let topic = TopicNormal::create(
+            &DummyObjectIdGenerator { id: "topic1".to_string() },
+            &FixClock::new(Utc.timestamp_opt(0, 0).unwrap()),
+            "雑談スレ Part1".to_string(),
+            "description".to_string(),
+            "user".to_string(),
+            vec!["雑談".to_string()],
+        );
+
+        let now = Utc.timestamp_opt(100, 0).unwrap();
+        let next = topic.create_next(
+            &DummyObjectIdGenerator { id: "topic2".to_string() },
+            &FixClock::new(now),
+        );
+
+        assert_eq!(next.base().id, "topic2");
+        assert_eq!(next.base().title, "雑談スレ Part2");
+        assert_eq!(next.base().description, "description");
+        assert_eq!(next.base().user_id, "user");
+        assert_eq!(next.base().tags, vec!["雑談".to_string()]);
+        assert_eq!(next.base().created_at, now);
+        assert!(!next.base().is_closed);
+    }
+}
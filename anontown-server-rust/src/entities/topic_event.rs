@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+/// トピックで起きた出来事。購読者に配信する
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TopicEvent {
+    ResAdded {
+        res: String,
+        topic: String,
+        // 書き込み後のトピックのレス数
+        count: i64,
+    },
+    ResVoted {
+        res: String,
+        topic: String,
+    },
+    TopicClosed {
+        topic: String,
+    },
+}
+
+impl TopicEvent {
+    pub fn topic_id(&self) -> &str {
+        match self {
+            TopicEvent::ResAdded { topic, .. } => topic,
+            TopicEvent::ResVoted { topic, .. } => topic,
+            TopicEvent::TopicClosed { topic } => topic,
+        }
+    }
+
+    /// SSEのeventフィールドに使う名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            TopicEvent::ResAdded { .. } => "res_added",
+            TopicEvent::ResVoted { .. } => "res_voted",
+            TopicEvent::TopicClosed { .. } => "topic_closed",
+        }
+    }
+}
+
+/// 連番を振られたイベント
+///
+/// 連番は全トピックで共通の単調増加する値で、再接続時にどこまで受け取ったかを表すのに使う
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopicEventRecord {
+    pub seq: i64,
+    pub event: TopicEvent,
+}
+
+/// どのイベントを購読するか
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopicEventFilter {
+    Topic(String),
+    Tag(String),
+}
+
+impl TopicEventFilter {
+    /// `tags`はイベントが起きたトピックのタグ
+    pub fn matches(&self, event: &TopicEvent, tags: &[String]) -> bool {
+        match self {
+            TopicEventFilter::Topic(id) => event.topic_id() == id,
+            TopicEventFilter::Tag(tag) => tags.iter().any(|t| t == tag),
+        }
+    }
+
+    pub fn needs_tags(&self) -> bool {
+        matches!(self, TopicEventFilter::Tag(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_event_serde() {
+        let record = TopicEventRecord {
+            seq: 3,
+            event: TopicEvent::ResAdded {
+                res: "res1".to_string(),
+                topic: "topic1".to_string(),
+                count: 10,
+            },
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            json,
+            r#"{"seq":3,"event":{"type":"res_added","res":"res1","topic":"topic1","count":10}}"#
+        );
+        assert_eq!(serde_json::from_str::<TopicEventRecord>(&json).unwrap(), record);
+    }
+
+    #[test]
+    fn test_topic_event_filter() {
+        let event = TopicEvent::TopicClosed {
+            topic: "topic1".to_string(),
+        };
+        let tags = vec!["雑談".to_string()];
+
+        assert!(TopicEventFilter::Topic("topic1".to_string()).matches(&event, &tags));
+        assert!(!TopicEventFilter::Topic("topic2".to_string()).matches(&event, &tags));
+        assert!(TopicEventFilter::Tag("雑談".to_string()).matches(&event, &tags));
+        assert!(!TopicEventFilter::Tag("質問".to_string()).matches(&event, &tags));
+    }
+}
@@ -0,0 +1,170 @@
+use base64::Engine;
+use pwhash::unix_crypt;
+use sha1::{Digest, Sha1};
+
+use crate::at_error::{AtError, AtResult, ParamErrorData};
+
+// このバイト数(Shift_JIS)以上のキーは新方式(SHA-1)のトリップになる
+const NEW_TRIP_KEY_LEN: usize = 12;
+
+/// `name#key`形式の名前をトリップ付きの表示名`name ◆XXXXXXXXXX`に変換する
+///
+/// `#`を含まない名前はそのまま返す。キーはどこにも保存せず、ここで捨てる。
+/// なりすまし防止のため名前部分の`◆`・`★`は`◇`・`☆`に置き換える
+pub fn apply_trip(input: &str) -> AtResult<String> {
+    let (name, key) = match input.split_once('#') {
+        Some((name, key)) => (name, Some(key)),
+        None => (input, None),
+    };
+    let name = name.replace('◆', "◇").replace('★', "☆");
+
+    match key {
+        None => Ok(name),
+        Some(key) if name.is_empty() => Ok(format!("◆{}", trip(key)?)),
+        Some(key) => Ok(format!("{} ◆{}", name, trip(key)?)),
+    }
+}
+
+/// `name#key`形式の名前からキーを除いた名前部分
+///
+/// 名前の長さなどはキーを含めずにこの部分だけで検査する
+pub fn trip_name(input: &str) -> &str {
+    input.split_once('#').map_or(input, |(name, _)| name)
+}
+
+/// 2ch互換のトリップ
+///
+/// * 12バイト未満: キーの2,3文字目をsaltにしたDES-cryptの末尾10文字
+/// * 12バイト以上: SHA-1のBase64の先頭12文字
+/// * `##`+16進数16桁(+salt2文字): 16進数をそのままキーにしたDES-crypt
+pub fn trip(key: &str) -> AtResult<String> {
+    let (bytes, _, _) = encoding_rs::SHIFT_JIS.encode(key);
+    if bytes.is_empty() {
+        return Err(params_error("required", "トリップキーが空です"));
+    }
+
+    if bytes.len() < NEW_TRIP_KEY_LEN {
+        return des_trip(&bytes, &legacy_salt(&bytes));
+    }
+
+    match bytes[0] {
+        b'#' => raw_key_trip(&bytes[1..]),
+        // 将来の拡張用に予約されている
+        b'$' => Err(params_error("invalid", "$から始まるトリップキーは使えません")),
+        _ => {
+            let digest = Sha1::digest(&bytes);
+            let encoded = base64::engine::general_purpose::STANDARD.encode(digest);
+            Ok(encoded[..12].replace('+', "."))
+        }
+    }
+}
+
+// パスワードには使わないがトリップの互換性のためにDES-cryptが必要
+#[allow(deprecated)]
+fn des_trip(key: &[u8], salt: &str) -> AtResult<String> {
+    let hash = unix_crypt::hash_with(salt, key)
+        .map_err(|e| AtError::Internal(anyhow::anyhow!("トリップの生成に失敗しました: {}", e)))?;
+    // 先頭2文字はsalt、3文字目はキーに関係なくほぼ固定なので除く
+    Ok(hash[3..].to_string())
+}
+
+fn legacy_salt(key: &[u8]) -> String {
+    let mut padded = key.to_vec();
+    padded.extend_from_slice(b"H.");
+    padded[1..3]
+        .iter()
+        .map(|&b| match b {
+            b'.'..=b'z' => b,
+            _ => b'.',
+        })
+        .map(|b| match b {
+            b':'..=b'@' => b + 7,
+            b'['..=b'`' => b + 6,
+            _ => b,
+        })
+        .map(char::from)
+        .collect()
+}
+
+fn raw_key_trip(key: &[u8]) -> AtResult<String> {
+    if !(16..=18).contains(&key.len()) {
+        return Err(params_error("invalid", "生キーは16進数16桁とsalt2文字以内で指定してください"));
+    }
+
+    let (hex, salt) = key.split_at(16);
+    let raw = hex
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| params_error("invalid", "生キーは16進数16桁とsalt2文字以内で指定してください"))?;
+
+    if !salt.iter().all(|b| b.is_ascii_alphanumeric() || *b == b'.' || *b == b'/') {
+        return Err(params_error("invalid", "生キーのsaltに使えない文字が含まれています"));
+    }
+    let mut salt = String::from_utf8_lossy(salt).to_string();
+    while salt.len() < 2 {
+        salt.push('.');
+    }
+
+    des_trip(&raw, &salt)
+}
+
+fn params_error(code: &str, message: &str) -> AtError {
+    AtError::Params(vec![ParamErrorData::new("name", code, message)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trip_des() {
+        assert_eq!(trip("istrip").unwrap(), "/WG5qp963c");
+        assert_eq!(trip("test").unwrap(), ".CzKQna1OU");
+    }
+
+    #[test]
+    fn test_trip_sha1() {
+        assert_eq!(trip("abcdefghijkl").unwrap(), "60YIzr/P1N.B");
+        // Shift_JISで12バイト以上なら新方式
+        assert_eq!(trip("テストトリップ").unwrap(), "AcBQmEK.Al91");
+    }
+
+    #[test]
+    fn test_trip_raw_key() {
+        assert_eq!(trip("#0123456789abcdef").unwrap(), "ClNHFHdYIw");
+        assert!(matches!(trip("#0123456789abcdeg"), Err(AtError::Params(_))));
+        assert!(matches!(trip("#0123456789abcdef!"), Err(AtError::Params(_))));
+    }
+
+    #[test]
+    fn test_trip_error() {
+        assert!(matches!(trip(""), Err(AtError::Params(_))));
+        assert!(matches!(trip("$abcdefghijkl"), Err(AtError::Params(_))));
+    }
+
+    #[test]
+    fn test_trip_name() {
+        assert_eq!(trip_name("名無し"), "名無し");
+        assert_eq!(trip_name("名無し#istrip"), "名無し");
+        assert_eq!(trip_name("#istrip"), "");
+        assert_eq!(trip_name("名無し#key#key"), "名無し");
+    }
+
+    #[test]
+    fn test_apply_trip() {
+        assert_eq!(apply_trip("名無し").unwrap(), "名無し");
+        assert_eq!(apply_trip("名無し#istrip").unwrap(), "名無し ◆/WG5qp963c");
+        assert_eq!(apply_trip("#istrip").unwrap(), "◆/WG5qp963c");
+        // キーは残らない
+        assert!(!apply_trip("名無し#istrip").unwrap().contains("istrip"));
+        // なりすまし防止
+        assert_eq!(apply_trip("名無し ◆/WG5qp963c").unwrap(), "名無し ◇/WG5qp963c");
+        assert_eq!(apply_trip("★管理人").unwrap(), "☆管理人");
+        assert!(matches!(apply_trip("名無し#"), Err(AtError::Params(_))));
+    }
+}
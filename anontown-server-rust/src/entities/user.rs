@@ -1,6 +1,26 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use crate::ports::object_id::ObjectIdGenerator;
+use crate::at_error::{AtError, AtResult};
+use crate::auth::AuthUser;
+use crate::entities::password::{self, PasswordConfig};
+use crate::ports::clock::ClockPort;
+use crate::ports::object_id_generator::ObjectIdGeneratorPort;
+use crate::validation::StrRule;
+
+pub const SN_MIN_LEN: usize = 3;
+pub const SN_MAX_LEN: usize = 20;
+pub const PASS_MIN_LEN: usize = 3;
+pub const PASS_MAX_LEN: usize = 50;
+
+// スクリーンネームはログインに使うので半角英数字と_だけにする
+pub const SN_RULES: &[StrRule] =
+    &[StrRule::Required, StrRule::Word, StrRule::MinLen(SN_MIN_LEN), StrRule::MaxLen(SN_MAX_LEN)];
+pub const PASS_RULES: &[StrRule] = &[
+    StrRule::Required,
+    StrRule::SingleLine,
+    StrRule::MinLen(PASS_MIN_LEN),
+    StrRule::MaxLen(PASS_MAX_LEN),
+];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
@@ -22,11 +42,13 @@ pub struct User {
     pub email: String,
     pub password_hash: String,
     pub updated_at: DateTime<Utc>,
+    // 楽観的排他制御のためのバージョン。保存する度にリポジトリが1増やす
+    pub version: i32,
 }
 
 impl User {
     pub fn create(
-        id_gen: &dyn ObjectIdGenerator,
+        id_gen: &dyn ObjectIdGeneratorPort,
         screen_name: String,
         name: String,
         email: String,
@@ -52,22 +74,68 @@ impl User {
             email,
             password_hash,
             updated_at: now,
+            version: 0,
+        }
+    }
+
+    /// パスワードで認証する
+    ///
+    /// # エラー
+    /// * パスワードが一致しない場合は`AtError::UserAuth`
+    pub fn auth(&self, password: &str, config: &PasswordConfig) -> AtResult<AuthUser> {
+        if !password::verify_password(password, &self.password_hash, config) {
+            return Err(AtError::UserAuth);
         }
+
+        Ok(AuthUser {
+            id: self.id.clone(),
+            pass: self.password_hash.clone(),
+        })
     }
 
-    pub fn increment_res_count(&mut self, time_range: TimeRange) {
-        match time_range {
-            TimeRange::M10 => self.count_created_res_m10 += 1,
-            TimeRange::M30 => self.count_created_res_m30 += 1,
-            TimeRange::H1 => self.count_created_res_h1 += 1,
-            TimeRange::H6 => self.count_created_res_h6 += 1,
-            TimeRange::H12 => self.count_created_res_h12 += 1,
-            TimeRange::D1 => self.count_created_res_d1 += 1,
+    /// 古い形式や古いパラメーターのハッシュなら、認証に成功したパスワードで今の設定のハッシュにし直す
+    ///
+    /// # 返り値
+    /// ハッシュし直して保存が必要な場合は`true`
+    pub fn upgrade_password_hash(
+        &mut self,
+        password: &str,
+        config: &PasswordConfig,
+        clock: &impl ClockPort,
+    ) -> AtResult<bool> {
+        if !password::needs_rehash(&self.password_hash, config) {
+            return Ok(false);
         }
-        self.res_last_created_at = Utc::now();
+
+        self.password_hash = password::hash_password(password, config)?;
+        self.updated_at = clock.now();
+        Ok(true)
+    }
+
+    /// 回復コードでアカウントを回復した時に、パスワードを`password::hash_password`したものにする
+    pub fn reset_password(&mut self, password_hash: String) {
+        self.password_hash = password_hash;
         self.updated_at = Utc::now();
     }
 
+    /// 連投制限で数えた直近のレス数を保存しておく
+    ///
+    /// 制限の判定には使わないキャッシュで、実際の件数はRateLimiterが持つ
+    pub fn update_res_count_snapshot(&mut self, counts: &[(TimeRange, i32)], now: DateTime<Utc>) {
+        for (time_range, count) in counts {
+            match time_range {
+                TimeRange::M10 => self.count_created_res_m10 = *count,
+                TimeRange::M30 => self.count_created_res_m30 = *count,
+                TimeRange::H1 => self.count_created_res_h1 = *count,
+                TimeRange::H6 => self.count_created_res_h6 = *count,
+                TimeRange::H12 => self.count_created_res_h12 = *count,
+                TimeRange::D1 => self.count_created_res_d1 = *count,
+            }
+        }
+        self.res_last_created_at = now;
+        self.updated_at = now;
+    }
+
     pub fn update_topic_last_created_at(&mut self) {
         self.topic_last_created_at = Utc::now();
         self.updated_at = Utc::now();
@@ -82,18 +150,6 @@ impl User {
         self.point += point;
         self.updated_at = Utc::now();
     }
-
-    pub fn can_create_res(&self) -> bool {
-        let now = Utc::now();
-        let one_hour_ago = now - chrono::Duration::hours(1);
-        self.res_last_created_at < one_hour_ago
-    }
-
-    pub fn can_create_topic(&self) -> bool {
-        let now = Utc::now();
-        let one_hour_ago = now - chrono::Duration::hours(1);
-        self.topic_last_created_at < one_hour_ago
-    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -104,4 +160,17 @@ pub enum TimeRange {
     H6,
     H12,
     D1,
-} 
\ No newline at end of file
+}
+
+impl TimeRange {
+    pub fn duration(&self) -> Duration {
+        match self {
+            TimeRange::M10 => Duration::minutes(10),
+            TimeRange::M30 => Duration::minutes(30),
+            TimeRange::H1 => Duration::hours(1),
+            TimeRange::H6 => Duration::hours(6),
+            TimeRange::H12 => Duration::hours(12),
+            TimeRange::D1 => Duration::days(1),
+        }
+    }
+}
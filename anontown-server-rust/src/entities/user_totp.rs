@@ -0,0 +1,362 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+
+use crate::at_error::{AtError, AtResult};
+use crate::entities::short_code;
+use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort};
+
+// 認証アプリの標準的な設定に合わせる(RFC 6238, HMAC-SHA1)
+const TOTP_DIGITS: u32 = 6;
+const TOTP_PERIOD_SECONDS: i64 = 30;
+// 端末の時刻のずれを考慮して前後1ステップまで受け付ける
+const TOTP_SKEW_STEPS: i64 = 1;
+const TOTP_ISSUER: &str = "Anontown";
+const BACKUP_CODE_COUNT: usize = 10;
+const BACKUP_CODE_LEN: usize = 10;
+
+/// ユーザーのTOTPによる二要素認証の設定
+///
+/// 登録した直後は無効で、認証アプリで生成したコードを確認できたら有効になる
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserTotp {
+    pub user_id: String,
+    pub secret: Vec<u8>,
+    pub enabled: bool,
+    // 最後に使われたタイムステップ。同じコードを2回使えないようにする
+    pub last_used_step: Option<i64>,
+    // バックアップコードのSHA-256。使われたものは消す
+    pub backup_code_hashes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    // 楽観的排他制御のためのバージョン。保存する度にリポジトリが1増やす
+    pub version: i32,
+}
+
+impl UserTotp {
+    /// 新しい秘密鍵で、まだ有効になっていない設定を作る
+    pub fn create(
+        user_id: String,
+        clock: &impl ClockPort,
+        safe_id_generator: &impl SafeIdGeneratorPort,
+    ) -> Self {
+        let now = clock.now();
+        // RFC 4226で推奨されている160ビット以上になるように2つつなげる
+        let secret = format!("{}{}", safe_id_generator.generate(), safe_id_generator.generate());
+        Self {
+            user_id,
+            secret: secret.into_bytes(),
+            enabled: false,
+            last_used_step: None,
+            backup_code_hashes: Vec::new(),
+            created_at: now,
+            updated_at: now,
+            version: 0,
+        }
+    }
+
+    /// 認証アプリに入力する、Base32にした秘密鍵
+    pub fn secret_base32(&self) -> String {
+        base32_encode(&self.secret)
+    }
+
+    /// 認証アプリにQRコードで読み込ませる`otpauth://`のURI
+    pub fn provisioning_uri(&self, account_name: &str) -> String {
+        let mut url = url::Url::parse("otpauth://totp/").expect("固定のURIは正しい");
+        url.set_path(&format!("{}:{}", TOTP_ISSUER, account_name));
+        url.query_pairs_mut()
+            .append_pair("secret", &self.secret_base32())
+            .append_pair("issuer", TOTP_ISSUER)
+            .append_pair("algorithm", "SHA1")
+            .append_pair("digits", &TOTP_DIGITS.to_string())
+            .append_pair("period", &TOTP_PERIOD_SECONDS.to_string());
+        url.to_string()
+    }
+
+    /// 認証アプリで生成したコードを確認して有効にし、バックアップコードを発行する
+    ///
+    /// # 返り値
+    /// 表示用のバックアップコード。保存するのはハッシュだけなので、この時しか表示できない
+    ///
+    /// # エラー
+    /// * 既に有効な場合は`AtError::Prerequisite`
+    /// * コードが一致しない場合は`AtError::Auth`
+    pub fn confirm(
+        &mut self,
+        code: &str,
+        clock: &impl ClockPort,
+        safe_id_generator: &impl SafeIdGeneratorPort,
+    ) -> AtResult<Vec<String>> {
+        if self.enabled {
+            return Err(AtError::Prerequisite(
+                "二要素認証は既に有効です".to_string(),
+            ));
+        }
+        if !self.verify_code(code, clock) {
+            return Err(invalid_code());
+        }
+
+        let codes = (0..BACKUP_CODE_COUNT)
+            .map(|_| short_code::generate(BACKUP_CODE_LEN, safe_id_generator))
+            .collect::<Vec<_>>();
+        self.backup_code_hashes = codes.iter().map(|code| short_code::hash(code)).collect();
+        self.enabled = true;
+        self.updated_at = clock.now();
+
+        Ok(codes.iter().map(|code| short_code::format(code)).collect())
+    }
+
+    /// ログインの二要素目を確認する。認証アプリのコードか、未使用のバックアップコードを受け付ける
+    ///
+    /// 使ったコードは記録されるので、成功したら保存する
+    ///
+    /// # エラー
+    /// * コードが一致しない、または使用済みの場合は`AtError::Auth`
+    pub fn verify(&mut self, code: &str, clock: &impl ClockPort) -> AtResult<()> {
+        if self.verify_code(code, clock) || self.use_backup_code(code) {
+            self.updated_at = clock.now();
+            Ok(())
+        } else {
+            Err(invalid_code())
+        }
+    }
+
+    /// 今の時刻で認証アプリが表示するコード
+    #[cfg(test)]
+    pub(crate) fn current_code(&self, clock: &impl ClockPort) -> String {
+        let step = clock.now().timestamp().div_euclid(TOTP_PERIOD_SECONDS);
+        format!(
+            "{:0width$}",
+            hotp(&self.secret, step as u64, TOTP_DIGITS),
+            width = TOTP_DIGITS as usize
+        )
+    }
+
+    // 前後のずれを含めて一致し、まだ使われていないステップのコードか
+    fn verify_code(&mut self, code: &str, clock: &impl ClockPort) -> bool {
+        let code = code.trim();
+        if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+
+        let current = clock.now().timestamp().div_euclid(TOTP_PERIOD_SECONDS);
+        let step = (current - TOTP_SKEW_STEPS..=current + TOTP_SKEW_STEPS)
+            .filter(|step| self.last_used_step.is_none_or(|last| *step > last))
+            .find(|step| {
+                let expected = format!(
+                    "{:0width$}",
+                    hotp(&self.secret, *step as u64, TOTP_DIGITS),
+                    width = TOTP_DIGITS as usize
+                );
+                bool::from(expected.as_bytes().ct_eq(code.as_bytes()))
+            });
+        match step {
+            Some(step) => {
+                self.last_used_step = Some(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn use_backup_code(&mut self, code: &str) -> bool {
+        let code = match short_code::normalize(code, BACKUP_CODE_LEN) {
+            Some(code) => code,
+            None => return false,
+        };
+        let hash = short_code::hash(&code);
+        // 一致するものを探す時も途中で止めずに全て比較する
+        let index = self
+            .backup_code_hashes
+            .iter()
+            .enumerate()
+            .fold(None, |found, (i, stored)| {
+                if bool::from(stored.as_bytes().ct_eq(hash.as_bytes())) {
+                    Some(i)
+                } else {
+                    found
+                }
+            });
+        match index {
+            Some(index) => {
+                self.backup_code_hashes.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn invalid_code() -> AtError {
+    AtError::Auth("二要素認証のコードが正しくありません".to_string())
+}
+
+// RFC 4226 5.3
+fn hotp(secret: &[u8], counter: u64, digits: u32) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMACは任意の長さの鍵を受け付ける");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(digits)
+}
+
+// RFC 4648のBase32。認証アプリはパディング無しを受け付ける
+fn base32_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut result = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            result.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        result.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use chrono::{Duration, TimeZone};
+
+    // RFC 6238 Appendix BのSHA-1の鍵
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn clock(timestamp: i64) -> FixClock {
+        FixClock::new(Utc.timestamp_opt(timestamp, 0).unwrap())
+    }
+
+    fn code_at(totp: &UserTotp, timestamp: i64) -> String {
+        format!(
+            "{:06}",
+            hotp(&totp.secret, (timestamp / TOTP_PERIOD_SECONDS) as u64, TOTP_DIGITS)
+        )
+    }
+
+    fn create(timestamp: i64) -> UserTotp {
+        UserTotp {
+            secret: RFC_SECRET.to_vec(),
+            ..UserTotp::create("user".to_string(), &clock(timestamp), &id_generator())
+        }
+    }
+
+    fn id_generator() -> SeqIdGenerator {
+        SeqIdGenerator::new()
+    }
+
+    #[test]
+    fn test_hotp() {
+        // RFC 6238 Appendix B
+        assert_eq!(hotp(RFC_SECRET, 59 / 30, 8), 94287082);
+        assert_eq!(hotp(RFC_SECRET, 1111111109 / 30, 8), 7081804);
+        assert_eq!(hotp(RFC_SECRET, 1234567890 / 30, 8), 89005924);
+        assert_eq!(hotp(RFC_SECRET, 2000000000 / 30, 8), 69279037);
+        assert_eq!(hotp(RFC_SECRET, 59 / 30, 6), 287082);
+    }
+
+    #[test]
+    fn test_base32_encode() {
+        // RFC 4648 10
+        assert_eq!(base32_encode(b""), "");
+        assert_eq!(base32_encode(b"f"), "MY");
+        assert_eq!(base32_encode(b"foob"), "MZXW6YQ");
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(
+            base32_encode(RFC_SECRET),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+        );
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        let totp = create(0);
+        assert_eq!(
+            totp.provisioning_uri("user"),
+            "otpauth://totp/Anontown:user?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=Anontown&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn test_confirm() {
+        let now = 1111111109;
+        let id_generator = id_generator();
+        let mut totp = create(now);
+        assert!(!totp.enabled);
+        assert!(matches!(
+            totp.confirm("000000", &clock(now), &id_generator),
+            Err(AtError::Auth(_))
+        ));
+
+        let codes = totp
+            .confirm(&code_at(&totp, now), &clock(now), &id_generator)
+            .unwrap();
+        assert!(totp.enabled);
+        assert_eq!(codes.len(), BACKUP_CODE_COUNT);
+        assert_eq!(totp.backup_code_hashes.len(), BACKUP_CODE_COUNT);
+        // バックアップコードそのものは保存しない
+        assert!(!totp.backup_code_hashes.contains(&codes[0]));
+
+        assert!(matches!(
+            totp.confirm(&code_at(&totp, now + 30), &clock(now + 30), &id_generator),
+            Err(AtError::Prerequisite(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify() {
+        let now = 1234567890;
+        let mut totp = create(now);
+        totp.confirm(&code_at(&totp, now), &clock(now), &id_generator())
+            .unwrap();
+
+        // 確認に使ったコードはもう使えない
+        assert!(totp.verify(&code_at(&totp, now), &clock(now)).is_err());
+
+        // 前後1ステップのずれまで受け付ける
+        let later = now + TOTP_PERIOD_SECONDS * 2;
+        assert!(totp.verify(&code_at(&totp, later - TOTP_PERIOD_SECONDS), &clock(later)).is_ok());
+        assert!(totp.verify(&code_at(&totp, later - TOTP_PERIOD_SECONDS), &clock(later)).is_err());
+        let later = later + TOTP_PERIOD_SECONDS * 10;
+        assert!(totp.verify(&code_at(&totp, later - TOTP_PERIOD_SECONDS * 2), &clock(later)).is_err());
+        assert!(totp.verify(&code_at(&totp, later + TOTP_PERIOD_SECONDS), &clock(later)).is_ok());
+
+        let updated_at = clock(later).now();
+        assert_eq!(totp.updated_at, updated_at);
+        assert!(totp.verify("12345", &clock(later + 300)).is_err());
+        assert!(totp.verify("abcdef", &clock(later + 300)).is_err());
+        assert_eq!(totp.updated_at, updated_at);
+    }
+
+    #[test]
+    fn test_verify_backup_code() {
+        let now = Utc::now();
+        let clock = FixClock::new(now);
+        let id_generator = id_generator();
+        let mut totp = UserTotp::create("user".to_string(), &clock, &id_generator);
+        let step = now.timestamp() / TOTP_PERIOD_SECONDS;
+        let code = format!("{:06}", hotp(&totp.secret, step as u64, TOTP_DIGITS));
+        let codes = totp.confirm(&code, &clock, &id_generator).unwrap();
+
+        let later = FixClock::new(now + Duration::minutes(1));
+        totp.verify(&codes[0].to_lowercase(), &later).unwrap();
+        assert_eq!(totp.backup_code_hashes.len(), BACKUP_CODE_COUNT - 1);
+        // バックアップコードは1回しか使えない
+        assert!(matches!(totp.verify(&codes[0], &later), Err(AtError::Auth(_))));
+        assert!(matches!(totp.verify("ZZZZZ-ZZZZZ", &later), Err(AtError::Auth(_))));
+    }
+}
@@ -4,6 +4,8 @@ pub mod ports;
 pub mod schema;
 pub mod at_error;
 pub mod auth;
+pub mod config;
+pub mod validation;
 
 use actix_web::web;
 use juniper::http::GraphQLResponse;
@@ -1,12 +1,22 @@
 use actix_cors::Cors;
-use actix_web::{web, App, HttpServer, HttpResponse, Responder};
+use actix_web::{web, App, HttpRequest, HttpServer, HttpResponse, Responder};
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::http::StatusCode;
+use actix_web::middleware::from_fn;
+use actix_web::web::Bytes;
+use futures::stream::{self, StreamExt};
 use dotenv::dotenv;
-use std::env;
 use juniper::http::graphiql::graphiql_source;
 use juniper::http::GraphQLRequest;
 use juniper::http::playground::playground_source;
 use juniper::http::GraphQLResponse;
+use juniper::Variables;
+use juniper_actix::subscriptions::ws_handler;
+use juniper_graphql_ws::ConnectionConfig;
+use std::sync::Arc;
+use std::time::Duration;
 
+mod config;
 mod context;
 mod error;
 mod handlers;
@@ -14,9 +24,30 @@ mod routes;
 mod schema;
 mod ports;
 mod entities;
+mod adapters;
+mod usecases;
+mod at_error;
+mod auth;
+mod middleware;
+mod oauth;
+mod validation;
 
-use context::Context;
+use config::Config;
+use schema::context::Context;
 use schema::{Query, Mutation, Subscription, Schema};
+use adapters::{TopicEventBusImpl, TopicRepo, UnitOfWorkImpl};
+use entities::topic_event::{TopicEventFilter, TopicEventRecord};
+use adapters::clock::clock::Clock;
+use adapters::object_id_generator::ObjectIdGenerator;
+use at_error::AtError;
+use auth::TOKEN_HEADER;
+
+// トピックが落ちたかをチェックする間隔
+const TOPIC_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+// WebSocketの接続を維持するためにkeep-aliveを送る間隔
+const WS_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+// SSEの接続をプロキシに切られないようにコメントを送る間隔
+const SSE_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
 
 async fn health_check() -> HttpResponse {
     HttpResponse::Ok().json(serde_json::json!({
@@ -37,16 +68,132 @@ async fn graphiql() -> HttpResponse {
         .body(graphiql_source("/graphql", None))
 }
 
+// リゾルバーのエラーはerrorsに入れて200で返す。クエリの構文や検証のエラーだけ400にする
 async fn graphql_handler(
     schema: web::Data<Schema>,
-    context: web::Data<Context>,
+    context: web::ReqData<Context>,
     req: web::Json<juniper::http::GraphQLRequest>,
 ) -> HttpResponse {
-    let res = req.execute(&schema, &context);
-    match res.0 {
-        Ok((value, _)) => HttpResponse::Ok().json(value),
-        Err(_) => HttpResponse::InternalServerError().finish(),
-    }
+    let res = req.execute(&schema, &context).await;
+    let status = if res.is_ok() {
+        StatusCode::OK
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    HttpResponse::build(status).json(res)
+}
+
+// リクエストのJSONが読めない場合もGraphQLのエラーと同じ形で400を返す
+fn graphql_json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let response = HttpResponse::BadRequest().json(serde_json::json!({
+        "errors": [{
+            "message": err.to_string(),
+            "extensions": { "code": "params", "data": null },
+        }],
+    }));
+    InternalError::from_response(err, response).into()
+}
+
+// subscriptions-transport-ws(graphql-ws)とgraphql-transport-wsのどちらで話すかは
+// Sec-WebSocket-Protocolヘッダーで決まる
+async fn graphql_ws_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    schema: web::Data<Schema>,
+    context: web::ReqData<Context>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // ブラウザのWebSocketはヘッダーを付けられないので、connection_initで渡されたトークンを優先する
+    let context = context.into_inner();
+    ws_handler(req, stream, schema.root(), move |params: Variables| async move {
+        // connection_initのpayloadにHTTPと同じX-Tokenを入れて認証する
+        let context = match params.get(TOKEN_HEADER).and_then(|value| value.as_string_value()) {
+            Some(raw) => context.with_auth_token(Some(
+                usecases::authenticate_token(
+                    &context.ports.token_repo,
+                    &context.ports.clock,
+                    raw,
+                )
+                .await
+                // 接続を拒否した理由はクライアントに送られるので、内部エラーの詳細は含めない
+                .map_err(|e| e.to_public().message)?,
+            )),
+            None => context,
+        };
+        Ok::<_, String>(
+            ConnectionConfig::new(context)
+                .with_keep_alive_interval(WS_KEEP_ALIVE_INTERVAL),
+        )
+    })
+    .await
+}
+
+// SSEの1イベント分。idに連番を入れておくと再接続時にLast-Event-IDで送られてくる
+// 誰でも購読できるので、レスの中身は送らずにイベント(レスやトピックのID)だけを送る
+fn sse_message(record: &TopicEventRecord) -> Result<Bytes, AtError> {
+    let data = serde_json::to_string(record).map_err(|e| AtError::Internal(e.into()))?;
+    Ok(Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        record.seq,
+        record.event.name(),
+        data
+    )))
+}
+
+// Last-Event-IDより後のイベントを取りこぼしたことを伝え、クライアントに取得し直させる。
+// 空のidでLast-Event-IDを消し、次の再接続で再び遡らないようにする
+const SSE_RESET_MESSAGE: &[u8] = b"id\nevent: reset\ndata: {}\n\n";
+
+async fn sse_handler(
+    req: HttpRequest,
+    context: web::ReqData<Context>,
+    filter: TopicEventFilter,
+) -> Result<HttpResponse, actix_web::Error> {
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok());
+    let subscription = usecases::subscribe_topic_events(
+        filter,
+        last_event_id,
+        &context.ports.topic_event_bus,
+        context.ports.topic_repo.clone(),
+    )
+    .await?;
+
+    let reset = subscription
+        .missed
+        .then(|| Ok(Bytes::from_static(SSE_RESET_MESSAGE)));
+    let messages = stream::iter(reset)
+        .chain(subscription.events.map(|record| sse_message(&record?)));
+    let keep_alive = stream::unfold(
+        actix_web::rt::time::interval(SSE_KEEP_ALIVE_INTERVAL),
+        |mut interval| async move {
+            interval.tick().await;
+            Some((Ok(Bytes::from_static(b": keep-alive\n\n")), interval))
+        },
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream::select(messages, keep_alive)))
+}
+
+async fn topic_events_handler(
+    req: HttpRequest,
+    context: web::ReqData<Context>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    sse_handler(req, context, TopicEventFilter::Topic(id.into_inner())).await
+}
+
+async fn tag_events_handler(
+    req: HttpRequest,
+    context: web::ReqData<Context>,
+    tag: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    sse_handler(req, context, TopicEventFilter::Tag(tag.into_inner())).await
 }
 
 #[actix_web::main]
@@ -57,30 +204,54 @@ async fn main() -> std::io::Result<()> {
     // Initialize logger
     env_logger::init();
 
-    // Get the port from environment variable or use default
-    let port = env::var("PORT")
-        .unwrap_or_else(|_| "3000".to_string())
-        .parse::<u16>()
-        .expect("PORT must be a number");
-
-    // Get the host from environment variable or use default
-    let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
+    let config = Config::from_env();
 
     // Initialize database connection
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool = sqlx::PgPool::connect(&database_url)
+    let pool = sqlx::PgPool::connect(&config.database_url)
         .await
         .expect("Failed to create pool");
 
     // Initialize Redis connection
-    let redis_url = env::var("REDIS_URL").expect("REDIS_URL must be set");
-    let redis = redis::Client::open(redis_url).expect("Failed to create Redis client");
+    let redis = redis::Client::open(config.redis_url.clone()).expect("Failed to create Redis client");
+
+    // Check topic lifecycle periodically
+    {
+        let pool = pool.clone();
+        let redis = Arc::new(redis.clone());
+        let lifecycle = config.topic_lifecycle.clone();
+        actix_web::rt::spawn(async move {
+            let mut topic_repo = TopicRepo::new(pool.clone());
+            let topic_event_bus = TopicEventBusImpl::new(redis.clone());
+            let unit_of_work = UnitOfWorkImpl::new(pool, redis);
+            let object_id_generator = ObjectIdGenerator::new();
+            let clock = Clock::new();
+            let mut interval = actix_web::rt::time::interval(TOPIC_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                match usecases::cron_topic_check(
+                    &lifecycle,
+                    &mut topic_repo,
+                    &unit_of_work,
+                    &topic_event_bus,
+                    &object_id_generator,
+                    &clock,
+                )
+                .await
+                {
+                    Ok(0) => {}
+                    Ok(count) => log::info!("cron_topic_check: closed {} topics", count),
+                    Err(e) => log::error!("cron_topic_check: {}", e),
+                }
+            }
+        });
+    }
 
     // Create schema
     let schema = Schema::new(Query, Mutation, Subscription);
 
-    log::info!("Starting server at {}:{}", host, port);
+    log::info!("Starting server at {}:{}", config.host, config.port);
 
+    let app_config = config.clone();
     HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
@@ -89,15 +260,30 @@ async fn main() -> std::io::Result<()> {
             .max_age(3600);
 
         App::new()
+            .wrap(from_fn(middleware::authenticate))
             .wrap(cors)
             .app_data(web::Data::new(schema.clone()))
-            .app_data(web::Data::new(Context::new(crate::ports::Ports::new())))
+            .app_data(web::Data::new(app_config.clone()))
+            .app_data(web::Data::new(Context::new(crate::ports::Ports::new(), app_config.clone())))
             .route("/health", web::get().to(health_check))
-            .route("/graphql", web::post().to(graphql_handler))
+            .service(
+                web::resource("/graphql")
+                    .app_data(web::JsonConfig::default().error_handler(graphql_json_error))
+                    .route(web::post().to(graphql_handler))
+                    .route(web::get().to(graphql_ws_handler)),
+            )
+            .service(
+                web::resource("/oauth/authorize")
+                    .route(web::get().to(oauth::authorize))
+                    .route(web::post().to(oauth::authorize_decision)),
+            )
+            .route("/oauth/token", web::post().to(oauth::token))
+            .route("/events/topics/{id}", web::get().to(topic_events_handler))
+            .route("/events/tags/{tag}", web::get().to(tag_events_handler))
             .route("/graphiql", web::get().to(graphiql))
             .route("/playground", web::get().to(graphql_playground))
     })
-    .bind((host, port))?
+    .bind((config.host.clone(), config.port))?
     .run()
     .await
 } 
\ No newline at end of file
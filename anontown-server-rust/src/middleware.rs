@@ -0,0 +1,187 @@
+use std::net::IpAddr;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, AUTHORIZATION, USER_AGENT};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage};
+
+use crate::at_error::AtError;
+use crate::auth::TOKEN_HEADER;
+use crate::schema::context::Context;
+use crate::usecases;
+
+const BEARER_PREFIX: &str = "Bearer ";
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// `X-Token: {id},{key}`か`Authorization: Bearer {id},{key}`からトークンを取り出す
+///
+/// 両方ある場合は`X-Token`を優先する。`X-Token`の形式が不正なら`Err`を返す。
+/// `/oauth/token`のクライアント認証で使う`Basic`などBearer以外の`Authorization`は無視する
+fn raw_token_from_headers(headers: &HeaderMap) -> Result<Option<&str>, AtError> {
+    if let Some(value) = headers.get(TOKEN_HEADER) {
+        return value.to_str().map(Some).map_err(|_| AtError::TokenAuth);
+    }
+    Ok(headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix(BEARER_PREFIX))
+        .map(str::trim))
+}
+
+/// リクエスト元のIP
+///
+/// X-Forwarded-Forは誰でも付けられるので、接続元が信頼するプロキシの場合だけ使う。
+/// その場合も右から順に見て、信頼するプロキシでない最初のIPを使う。左側はクライアントが偽装できるため
+fn client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpAddr],
+) -> Option<String> {
+    let peer = peer?;
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer.to_string());
+    }
+
+    let forwarded = headers
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+    for ip in forwarded.into_iter().rev() {
+        match ip {
+            Some(ip) if trusted_proxies.contains(&ip) => continue,
+            Some(ip) => return Some(ip.to_string()),
+            // 読めない値より左は信用できない
+            None => break,
+        }
+    }
+    Some(peer.to_string())
+}
+
+/// リクエストのトークンを認証し、そのトークンとリクエスト元の情報を持つ`Context`をリクエストごとに作る
+///
+/// ハンドラーでは`web::ReqData<Context>`で受け取る。
+/// トークンが無ければ未認証の`Context`になり、トークンがあって認証に失敗した場合は401を返す
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let context = req
+        .app_data::<web::Data<Context>>()
+        .expect("Contextが登録されていません");
+    let context = context.with_client_info(
+        client_ip(
+            req.peer_addr().map(|addr| addr.ip()),
+            req.headers(),
+            &context.config.trusted_proxies,
+        ),
+        req.headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    );
+
+    let token = match raw_token_from_headers(req.headers()) {
+        Ok(Some(raw)) => {
+            usecases::authenticate_token(&context.ports.token_repo, &context.ports.clock, raw)
+                .await
+                .map(Some)
+        }
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
+    };
+    // AtErrorのResponseErrorで401になる
+    let token = token?;
+
+    req.extensions_mut().insert(context.with_auth_token(token));
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn headers(entries: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in entries {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        headers
+    }
+
+    #[test]
+    fn test_raw_token_from_headers() {
+        assert_eq!(raw_token_from_headers(&headers(&[])).unwrap(), None);
+        assert_eq!(
+            raw_token_from_headers(&headers(&[("X-Token", "id,key")])).unwrap(),
+            Some("id,key")
+        );
+        assert_eq!(
+            raw_token_from_headers(&headers(&[("Authorization", "Bearer id,key")])).unwrap(),
+            Some("id,key")
+        );
+        assert_eq!(
+            raw_token_from_headers(&headers(&[
+                ("X-Token", "id1,key1"),
+                ("Authorization", "Bearer id2,key2"),
+            ]))
+            .unwrap(),
+            Some("id1,key1")
+        );
+        // OAuthのクライアント認証はトークンの認証に使わない
+        assert_eq!(
+            raw_token_from_headers(&headers(&[("Authorization", "Basic dXNlcjpwYXNz")])).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let peer: IpAddr = "192.0.2.1".parse().unwrap();
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let forwarded = headers(&[("X-Forwarded-For", "198.51.100.1, 203.0.113.1")]);
+
+        assert_eq!(client_ip(None, &forwarded, &[proxy]), None);
+        // 信頼するプロキシからの接続でなければX-Forwarded-Forは使わない
+        assert_eq!(
+            client_ip(Some(peer), &forwarded, &[]),
+            Some("192.0.2.1".to_string())
+        );
+        assert_eq!(
+            client_ip(Some(peer), &forwarded, &[proxy]),
+            Some("192.0.2.1".to_string())
+        );
+        // 偽装できる左側ではなく、プロキシが追加した右端を使う
+        assert_eq!(
+            client_ip(Some(proxy), &forwarded, &[proxy]),
+            Some("203.0.113.1".to_string())
+        );
+        // 信頼するプロキシを経由した分は飛ばす
+        assert_eq!(
+            client_ip(
+                Some(proxy),
+                &headers(&[("X-Forwarded-For", "198.51.100.1, 10.0.0.1")]),
+                &[proxy]
+            ),
+            Some("198.51.100.1".to_string())
+        );
+        assert_eq!(
+            client_ip(
+                Some(proxy),
+                &headers(&[("X-Forwarded-For", "unknown, 10.0.0.1")]),
+                &[proxy]
+            ),
+            Some("10.0.0.1".to_string())
+        );
+        assert_eq!(
+            client_ip(Some(proxy), &headers(&[]), &[proxy]),
+            Some("10.0.0.1".to_string())
+        );
+    }
+}
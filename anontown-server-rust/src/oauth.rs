@@ -0,0 +1,622 @@
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::header::{
+    CACHE_CONTROL, CONTENT_SECURITY_POLICY, LOCATION, PRAGMA, X_FRAME_OPTIONS,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::at_error::{AtError, AtResult};
+use crate::auth::{AuthToken, AuthTokenMaster, TokenScope};
+use crate::entities::client::Client;
+use crate::entities::token_req::check_code_challenge;
+use crate::entities::Token;
+use crate::schema::context::Context;
+use crate::usecases;
+
+const OAUTH_PATH: &str = "/oauth";
+const AUTHORIZE_PATH: &str = "/oauth/authorize";
+// ブラウザでログインしたマスタートークンを入れるCookie
+const SESSION_COOKIE: &str = "oauth_session";
+const ACTION_LOGIN: &str = "login";
+const ACTION_APPROVE: &str = "approve";
+const ACTION_DENY: &str = "deny";
+
+const RESPONSE_TYPE_CODE: &str = "code";
+const GRANT_TYPE_AUTHORIZATION_CODE: &str = "authorization_code";
+const GRANT_TYPE_REFRESH_TOKEN: &str = "refresh_token";
+
+// RFC 6749 4.1.2.1, 5.2のエラーレスポンス
+#[derive(Debug, Serialize)]
+struct OAuthError {
+    error: &'static str,
+    error_description: String,
+}
+
+impl OAuthError {
+    fn new(error: &'static str, error_description: impl Into<String>) -> Self {
+        Self {
+            error,
+            error_description: error_description.into(),
+        }
+    }
+
+    fn bad_request(self) -> HttpResponse {
+        HttpResponse::BadRequest()
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .json(self)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeQuery {
+    response_type: Option<String>,
+    client_id: Option<String>,
+    redirect_uri: Option<String>,
+    state: Option<String>,
+    scope: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenForm {
+    grant_type: Option<String>,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    client_id: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: i64,
+    refresh_token: String,
+    scope: String,
+}
+
+impl TokenResponse {
+    fn ok(token: &Token, context: &Context) -> HttpResponse {
+        HttpResponse::Ok()
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .insert_header((PRAGMA, "no-cache"))
+            .json(Self {
+                // X-Tokenと同じ形式なのでAuthorization: Bearerにそのまま使える
+                access_token: format!("{},{}", token.id, token.access_token),
+                token_type: "Bearer",
+                expires_in: (token.expires_at - context.ports.clock.now()).num_seconds(),
+                refresh_token: format!("{},{}", token.id, token.refresh_token),
+                scope: TokenScope::join(&token.scopes),
+            })
+    }
+}
+
+fn server_error(e: AtError) -> HttpResponse {
+    log::error!("oauth token: {}", e);
+    HttpResponse::InternalServerError().json(OAuthError::new("server_error", e.to_public().message))
+}
+
+// リダイレクトURIにクエリを付けてリダイレクトする
+fn redirect(redirect_uri: &str, params: &[(&str, &str)], state: Option<&str>) -> HttpResponse {
+    let mut url = match url::Url::parse(redirect_uri) {
+        Ok(url) => url,
+        Err(_) => {
+            return OAuthError::new("invalid_request", "リダイレクトURIが不正です").bad_request()
+        }
+    };
+    {
+        let mut query = url.query_pairs_mut();
+        query.extend_pairs(params);
+        if let Some(state) = state {
+            query.append_pair("state", state);
+        }
+    }
+    HttpResponse::Found()
+        .insert_header((LOCATION, url.as_str()))
+        .finish()
+}
+
+// 検証した認可リクエスト
+struct AuthorizeRequest {
+    client: Client,
+    redirect_uri: String,
+    scopes: Vec<TokenScope>,
+}
+
+impl AuthorizeQuery {
+    // ログインや許可のフォームで送り直すためのパラメーター
+    fn pairs(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("response_type", &self.response_type),
+            ("client_id", &self.client_id),
+            ("redirect_uri", &self.redirect_uri),
+            ("state", &self.state),
+            ("scope", &self.scope),
+            ("code_challenge", &self.code_challenge),
+            ("code_challenge_method", &self.code_challenge_method),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_deref().map(|value| (name, value)))
+        .collect()
+    }
+
+    /// 認可リクエストを検証する
+    ///
+    /// クライアントかリダイレクトURIが不正な場合は、攻撃者のURLに飛ばさないようにリダイレクトせずにエラーを返す。
+    /// それ以外の不正はリダイレクトURIにエラーを付けてクライアントに返す
+    async fn validate(&self, context: &Context) -> Result<AuthorizeRequest, HttpResponse> {
+        let client = match &self.client_id {
+            Some(client_id) => context
+                .ports
+                .client_repo
+                .find_one(client_id)
+                .await
+                .ok()
+                .flatten(),
+            None => None,
+        };
+        let client = match client {
+            Some(client) => client,
+            None => {
+                return Err(
+                    OAuthError::new("invalid_client", "クライアントが存在しません").bad_request(),
+                )
+            }
+        };
+        let redirect_uri = match client.redirect_uri(self.redirect_uri.as_deref()) {
+            Ok(redirect_uri) => redirect_uri,
+            Err(_) => {
+                return Err(OAuthError::new(
+                    "invalid_request",
+                    "リダイレクトURIがクライアントのURLと一致しません",
+                )
+                .bad_request())
+            }
+        };
+
+        let state = self.state.as_deref();
+        if self.response_type.as_deref() != Some(RESPONSE_TYPE_CODE) {
+            return Err(redirect(
+                &redirect_uri,
+                &[("error", "unsupported_response_type")],
+                state,
+            ));
+        }
+        // 省略された場合は閲覧だけを許可する
+        let scopes = match self.scope.as_deref().map(TokenScope::parse_list) {
+            None => vec![TokenScope::Read],
+            Some(Some(scopes)) if !scopes.is_empty() => scopes,
+            Some(_) => {
+                return Err(redirect(
+                    &redirect_uri,
+                    &[("error", "invalid_scope")],
+                    state,
+                ))
+            }
+        };
+        // ユーザーがログインして許可してから失敗しないように、先に確かめる
+        if check_code_challenge(
+            self.code_challenge.as_deref(),
+            self.code_challenge_method.as_deref(),
+        )
+        .is_err()
+        {
+            return Err(redirect(
+                &redirect_uri,
+                &[
+                    ("error", "invalid_request"),
+                    ("error_description", "S256のcode_challengeが必要です"),
+                ],
+                state,
+            ));
+        }
+
+        Ok(AuthorizeRequest {
+            client,
+            redirect_uri,
+            scopes,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeForm {
+    #[serde(flatten)]
+    query: AuthorizeQuery,
+    action: Option<String>,
+    id: Option<String>,
+    pass: Option<String>,
+    totp: Option<String>,
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// 他のサイトに埋め込まれて、許可のボタンを押させられないようにする
+fn page(title: &str, body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .insert_header((X_FRAME_OPTIONS, "DENY"))
+        .insert_header((
+            CONTENT_SECURITY_POLICY,
+            "default-src 'none'; style-src 'unsafe-inline'; frame-ancestors 'none'",
+        ))
+        .body(format!(
+            "<!DOCTYPE html><html lang=\"ja\"><head><meta charset=\"utf-8\">\
+             <meta name=\"viewport\" content=\"width=device-width\"><title>{}</title></head>\
+             <body><h1>{}</h1>{}</body></html>",
+            escape_html(title),
+            escape_html(title),
+            body
+        ))
+}
+
+// 認可リクエストのパラメーターを引き継ぐフォーム
+fn form(query: &AuthorizeQuery, fields: &str) -> String {
+    let hidden = query
+        .pairs()
+        .into_iter()
+        .map(|(name, value)| {
+            format!(
+                "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+                name,
+                escape_html(value)
+            )
+        })
+        .collect::<String>();
+    format!(
+        "<form method=\"post\" action=\"{}\">{}{}</form>",
+        AUTHORIZE_PATH, hidden, fields
+    )
+}
+
+fn client_html(client: &Client) -> String {
+    format!(
+        "<p><strong>{}</strong> ({})</p>",
+        escape_html(&client.name),
+        escape_html(&client.url)
+    )
+}
+
+fn login_page(
+    query: &AuthorizeQuery,
+    request: &AuthorizeRequest,
+    error: Option<&str>,
+) -> HttpResponse {
+    let error = error
+        .map(|error| format!("<p role=\"alert\">{}</p>", escape_html(error)))
+        .unwrap_or_default();
+    page(
+        "ログイン",
+        &format!(
+            "{}<p>このアプリケーションを認可するにはログインしてください</p>{}{}",
+            client_html(&request.client),
+            error,
+            form(
+                query,
+                &format!(
+                    "<p><label>ID <input name=\"id\" autocomplete=\"username\" required></label></p>\
+                     <p><label>パスワード <input type=\"password\" name=\"pass\" autocomplete=\"current-password\" required></label></p>\
+                     <p><label>二要素認証のコード <input name=\"totp\" autocomplete=\"one-time-code\"></label></p>\
+                     <button name=\"action\" value=\"{}\">ログイン</button>",
+                    ACTION_LOGIN
+                ),
+            )
+        ),
+    )
+}
+
+fn consent_page(query: &AuthorizeQuery, request: &AuthorizeRequest) -> HttpResponse {
+    let scopes = request
+        .scopes
+        .iter()
+        .map(|scope| format!("<li>{}</li>", escape_html(scope.description())))
+        .collect::<String>();
+    page(
+        "アプリケーションの認可",
+        &format!(
+            "{}<p>このアプリケーションに次の操作を許可しますか</p><ul>{}</ul>{}",
+            client_html(&request.client),
+            scopes,
+            form(
+                query,
+                &format!(
+                    "<button name=\"action\" value=\"{}\">許可する</button>\
+                     <button name=\"action\" value=\"{}\">拒否する</button>",
+                    ACTION_APPROVE, ACTION_DENY
+                ),
+            )
+        ),
+    )
+}
+
+fn error_page(e: AtError) -> HttpResponse {
+    log::error!("oauth authorize: {}", e);
+    HttpResponse::InternalServerError()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .body("サーバーでエラーが発生しました")
+}
+
+// ブラウザでのログイン状態。`/oauth`にだけ送られるCookieにマスタートークンを入れておく
+async fn session(req: &HttpRequest, context: &Context) -> Option<AuthTokenMaster> {
+    let cookie = req.cookie(SESSION_COOKIE)?;
+    match usecases::authenticate_token(
+        &context.ports.token_repo,
+        &context.ports.clock,
+        cookie.value(),
+    )
+    .await
+    {
+        Ok(AuthToken::Master(auth)) => Some(auth),
+        _ => None,
+    }
+}
+
+/// 認可エンドポイント
+///
+/// ブラウザでログインしていなければログイン画面を、ログインしていればクライアントと要求された権限を見せて
+/// 認可するか確認する画面を返す。どちらもこのURLへのPOSTで送られ、`authorize_decision`で処理する
+pub async fn authorize(
+    req: HttpRequest,
+    query: web::Query<AuthorizeQuery>,
+    context: web::ReqData<Context>,
+) -> HttpResponse {
+    let query = query.into_inner();
+    let request = match query.validate(&context).await {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
+
+    match session(&req, &context).await {
+        Some(_) => consent_page(&query, &request),
+        None => login_page(&query, &request, None),
+    }
+}
+
+/// 認可エンドポイントのログイン画面と確認画面から送られたフォームを処理する
+///
+/// ログインに成功したらマスタートークンをCookieに入れて確認画面に戻す。
+/// 許可されたら認可コードを、拒否されたらエラーを付けてクライアントにリダイレクトする。
+/// CookieはSameSite=Laxなので、他のサイトから許可のフォームを送らせることはできない
+pub async fn authorize_decision(
+    req: HttpRequest,
+    form: web::Form<AuthorizeForm>,
+    context: web::ReqData<Context>,
+) -> HttpResponse {
+    let form = form.into_inner();
+    let query = form.query;
+    let request = match query.validate(&context).await {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
+    let state = query.state.as_deref();
+
+    match form.action.as_deref() {
+        Some(ACTION_LOGIN) => {
+            let (id, pass) = match (form.id.as_deref(), form.pass.as_deref()) {
+                (Some(id), Some(pass)) => (id, pass),
+                _ => return login_page(&query, &request, Some("IDとパスワードを入力してください")),
+            };
+            let totp = form.totp.as_deref().filter(|totp| !totp.is_empty());
+            match login(&context, id, pass, totp).await {
+                Ok(token) => {
+                    let cookie = Cookie::build(
+                        SESSION_COOKIE,
+                        format!("{},{}", token.id, token.access_token),
+                    )
+                    .path(OAUTH_PATH)
+                    .http_only(true)
+                    .secure(true)
+                    .same_site(SameSite::Lax)
+                    .finish();
+                    let location =
+                        url::form_urlencoded::Serializer::new(format!("{}?", AUTHORIZE_PATH))
+                            .extend_pairs(query.pairs())
+                            .finish();
+                    HttpResponse::SeeOther()
+                        .cookie(cookie)
+                        .insert_header((LOCATION, location))
+                        .finish()
+                }
+                Err(AtError::UserAuth) => {
+                    login_page(&query, &request, Some("IDかパスワードが正しくありません"))
+                }
+                Err(AtError::Auth(message)) => login_page(&query, &request, Some(&message)),
+                Err(e) => error_page(e),
+            }
+        }
+        Some(ACTION_APPROVE) => {
+            let auth = match session(&req, &context).await {
+                Some(auth) => auth,
+                None => return login_page(&query, &request, Some("ログインし直してください")),
+            };
+            let token_req = usecases::authorize_oauth_client(
+                &context.ports.token_req_repo,
+                &auth,
+                &request.client,
+                request.redirect_uri.clone(),
+                request.scopes,
+                query.code_challenge.as_deref(),
+                query.code_challenge_method.as_deref(),
+                &context.ports.clock,
+                &context.ports.safe_id_generator,
+            )
+            .await;
+            match token_req {
+                Ok(token_req) => {
+                    redirect(&request.redirect_uri, &[("code", &token_req.code())], state)
+                }
+                Err(e) => {
+                    log::error!("oauth authorize: {}", e);
+                    redirect(&request.redirect_uri, &[("error", "server_error")], state)
+                }
+            }
+        }
+        Some(ACTION_DENY) => redirect(&request.redirect_uri, &[("error", "access_denied")], state),
+        _ => OAuthError::new("invalid_request", "actionが不正です").bad_request(),
+    }
+}
+
+// GraphQLのcreateTokenMasterと同じように認証し、このブラウザ用のマスタートークンを発行する
+async fn login(context: &Context, id: &str, pass: &str, totp: Option<&str>) -> AtResult<Token> {
+    let auth_user = usecases::authenticate_user(
+        &mut context.ports.user_repo.clone(),
+        id,
+        pass,
+        &context.config.password,
+        &context.ports.clock,
+    )
+    .await?;
+    usecases::verify_second_factor(
+        &context.ports.user_totp_repo,
+        &auth_user.id,
+        totp,
+        &context.ports.clock,
+    )
+    .await?;
+
+    let token = Token::create_master(
+        auth_user.id,
+        context.token_device().await,
+        &context.ports.clock,
+        &context.ports.object_id_generator,
+        &context.ports.safe_id_generator,
+    );
+    context.ports.token_repo.insert(&token).await?;
+
+    Ok(token)
+}
+
+/// トークンエンドポイント
+///
+/// 認可コードをクライアントに紐付いたトークンと交換するか、リフレッシュトークンでトークンを更新する。
+/// クライアントシークレットは無いので、PKCEのcode_verifierで認可リクエストと同じクライアントか確かめる
+pub async fn token(form: web::Form<TokenForm>, context: web::ReqData<Context>) -> HttpResponse {
+    let form = form.into_inner();
+
+    match form.grant_type.as_deref() {
+        Some(GRANT_TYPE_AUTHORIZATION_CODE) => authorization_code_grant(form, &context).await,
+        Some(GRANT_TYPE_REFRESH_TOKEN) => refresh_token_grant(form, &context).await,
+        _ => OAuthError::new(
+            "unsupported_grant_type",
+            "grant_typeはauthorization_codeかrefresh_tokenのみ対応しています",
+        )
+        .bad_request(),
+    }
+}
+
+async fn authorization_code_grant(form: TokenForm, context: &Context) -> HttpResponse {
+    let (code, redirect_uri, client_id, code_verifier) = match (
+        form.code,
+        form.redirect_uri,
+        form.client_id,
+        form.code_verifier,
+    ) {
+        (Some(code), Some(redirect_uri), Some(client_id), Some(code_verifier)) => {
+            (code, redirect_uri, client_id, code_verifier)
+        }
+        _ => {
+            return OAuthError::new(
+                "invalid_request",
+                "code, redirect_uri, client_id, code_verifierが必要です",
+            )
+            .bad_request()
+        }
+    };
+
+    let token = usecases::exchange_oauth_code(
+        &context.ports.token_repo,
+        &context.ports.token_req_repo,
+        &client_id,
+        &code,
+        &redirect_uri,
+        &code_verifier,
+        &context.ports.clock,
+        &context.ports.object_id_generator,
+        &context.ports.safe_id_generator,
+    )
+    .await;
+    match token {
+        Ok(token) => TokenResponse::ok(&token, context),
+        Err(AtError::Auth(message)) => OAuthError::new("invalid_grant", message).bad_request(),
+        Err(e) => server_error(e),
+    }
+}
+
+// 使用済みのリフレッシュトークンが使われた場合は、usecase側で同じファミリーのトークンが全て無効になる
+// クライアントシークレットは無いので、少なくともトークンを発行したクライアントからのリクエストかは確かめる
+async fn refresh_token_grant(form: TokenForm, context: &Context) -> HttpResponse {
+    let (refresh_token, client_id) = match (form.refresh_token, form.client_id) {
+        (Some(refresh_token), Some(client_id)) => (refresh_token, client_id),
+        _ => {
+            return OAuthError::new("invalid_request", "refresh_token, client_idが必要です")
+                .bad_request()
+        }
+    };
+
+    let token = usecases::rotate_token(
+        &context.ports.token_repo,
+        &refresh_token,
+        Some(&client_id),
+        &context.ports.clock,
+        &context.ports.object_id_generator,
+        &context.ports.safe_id_generator,
+    )
+    .await;
+    match token {
+        Ok(token) => TokenResponse::ok(&token, context),
+        Err(AtError::TokenAuth) => {
+            OAuthError::new("invalid_grant", "リフレッシュトークンが無効です").bad_request()
+        }
+        Err(e) => server_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_form() {
+        let query = AuthorizeQuery {
+            response_type: Some("code".to_string()),
+            client_id: Some("client".to_string()),
+            redirect_uri: None,
+            state: Some("\"><script>".to_string()),
+            scope: None,
+            code_challenge: None,
+            code_challenge_method: None,
+        };
+        assert_eq!(
+            query.pairs(),
+            vec![
+                ("response_type", "code"),
+                ("client_id", "client"),
+                ("state", "\"><script>")
+            ]
+        );
+        // 省略されたパラメーターは送り直さず、値はエスケープする
+        let html = form(&query, "");
+        assert!(
+            html.contains(r#"<input type="hidden" name="state" value="&quot;&gt;&lt;script&gt;">"#)
+        );
+        assert!(!html.contains("redirect_uri"));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{AuthToken, AuthTokenMaster, AtResult};
+use crate::auth::TokenScope;
 use std::option::Option;
 
 pub trait AuthContainer {
@@ -6,4 +7,10 @@ pub trait AuthContainer {
     fn get_token_master(&self) -> AtResult<&AuthTokenMaster>;
     fn get_token_or_null(&self) -> Option<&AuthToken>;
     fn get_token_master_or_null(&self) -> Option<&AuthTokenMaster>;
+    // 一般トークンに`scope`の権限が無ければAtError::Rightを返す
+    fn require_scope(&self, scope: TokenScope) -> AtResult<()>;
+    // 管理者のマスタートークンで認証されているか
+    fn is_admin(&self) -> bool;
+    // 管理者でなければAtError::Rightを返す
+    fn require_admin(&self) -> AtResult<()>;
 } 
\ No newline at end of file
@@ -6,14 +6,20 @@ pub mod ip;
 pub mod logger;
 pub mod notification_queue;
 pub mod notification_sender;
-pub mod object_id;
+pub mod object_id_generator;
 pub mod profile;
 pub mod push_subscriptions;
+pub mod rate_limiter;
 pub mod recaptcha;
 pub mod safe_id;
 pub mod storage;
 pub mod token;
+pub mod token_req_repo;
+pub mod user_totp_repo;
+pub mod recovery_code_repo;
+pub mod topic_event_bus;
 pub mod types;
+pub mod unit_of_work;
 pub mod auth_container;
 mod token_repo;
 
@@ -42,4 +48,7 @@ pub struct Ports {
 }
 
 pub use auth_container::AuthContainer;
-pub use token_repo::TokenRepo; 
\ No newline at end of file
+pub use token_repo::TokenRepo;
+pub use token_req_repo::TokenReqRepo; 
+pub use user_totp_repo::UserTotpRepo;
+pub use recovery_code_repo::RecoveryCodeRepo;
\ No newline at end of file
@@ -24,6 +24,7 @@ pub async fn run_profile_repo_laws(repo: &mut impl ProfileRepoPort) {
         name: name.to_string(),
         description: description.to_string(),
         date,
+        version: 0,
     };
 
     // 保存テスト
@@ -0,0 +1,116 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::at_error::AtResult;
+use crate::entities::rate_limit_rule::{RateLimitAction, RateLimitRule};
+
+/// ユーザーごとのスライディングウィンドウによる連投制限
+#[async_trait]
+pub trait RateLimiter {
+    /// 全てのルールで直近の件数が上限未満なら今回の書き込みを記録し、ルールごとの件数(今回の分を含む)を返す
+    ///
+    /// 上限に達しているルールがあれば何も記録せず`AtError::Prerequisite`を返す。
+    /// 判定と記録は同時に呼ばれても上限を超えないようにアトミックに行う
+    async fn acquire(
+        &self,
+        user_id: &str,
+        action: RateLimitAction,
+        rules: &[RateLimitRule],
+        now: DateTime<Utc>,
+    ) -> AtResult<Vec<i32>>;
+
+    /// `acquire`で`acquired_at`に記録した書き込みを1件取り消す
+    ///
+    /// 書き込みが保存できなかった時に、その分を数えないようにするために使う
+    async fn release(
+        &self,
+        user_id: &str,
+        action: RateLimitAction,
+        acquired_at: DateTime<Utc>,
+    ) -> AtResult<()>;
+}
+
+#[cfg(test)]
+pub async fn run_rate_limiter_laws(limiter: &impl RateLimiter) {
+    use crate::at_error::AtError;
+    use crate::entities::user::TimeRange;
+    use chrono::{Duration, TimeZone};
+
+    let now = Utc.timestamp_opt(1_000_000, 0).unwrap();
+    let rules = [
+        RateLimitRule {
+            window: TimeRange::M10,
+            max: 2,
+        },
+        RateLimitRule {
+            window: TimeRange::H1,
+            max: 3,
+        },
+    ];
+
+    // 上限までは書き込める
+    let counts = limiter
+        .acquire("user1", RateLimitAction::Res, &rules, now)
+        .await
+        .unwrap();
+    assert_eq!(counts, vec![1, 1]);
+    let counts = limiter
+        .acquire("user1", RateLimitAction::Res, &rules, now + Duration::minutes(1))
+        .await
+        .unwrap();
+    assert_eq!(counts, vec![2, 2]);
+
+    // 10分に2件を超える
+    let result = limiter
+        .acquire("user1", RateLimitAction::Res, &rules, now + Duration::minutes(2))
+        .await;
+    assert!(matches!(result, Err(AtError::Prerequisite(_))));
+
+    // 拒否された分は数えない
+    let counts = limiter
+        .acquire("user1", RateLimitAction::Res, &rules, now + Duration::minutes(10))
+        .await
+        .unwrap();
+    assert_eq!(counts, vec![2, 3]);
+
+    // 1時間に3件を超える
+    let result = limiter
+        .acquire("user1", RateLimitAction::Res, &rules, now + Duration::minutes(30))
+        .await;
+    assert!(matches!(result, Err(AtError::Prerequisite(_))));
+
+    // 古い書き込みはウィンドウから外れる
+    let counts = limiter
+        .acquire("user1", RateLimitAction::Res, &rules, now + Duration::minutes(60))
+        .await
+        .unwrap();
+    assert_eq!(counts, vec![1, 3]);
+
+    // ユーザーと書き込みの種類ごとに別に数える
+    let counts = limiter
+        .acquire("user2", RateLimitAction::Res, &rules, now)
+        .await
+        .unwrap();
+    assert_eq!(counts, vec![1, 1]);
+    let counts = limiter
+        .acquire("user1", RateLimitAction::Topic, &rules, now + Duration::minutes(60))
+        .await
+        .unwrap();
+    assert_eq!(counts, vec![1, 1]);
+
+    // 取り消した分は数えない
+    let released = now + Duration::minutes(61);
+    limiter
+        .acquire("user3", RateLimitAction::Res, &rules, released)
+        .await
+        .unwrap();
+    limiter
+        .release("user3", RateLimitAction::Res, released)
+        .await
+        .unwrap();
+    let counts = limiter
+        .acquire("user3", RateLimitAction::Res, &rules, released)
+        .await
+        .unwrap();
+    assert_eq!(counts, vec![1, 1]);
+}
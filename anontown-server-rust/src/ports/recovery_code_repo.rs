@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+
+use crate::at_error::AtResult;
+use crate::entities::recovery_code::RecoveryCode;
+
+#[async_trait]
+pub trait RecoveryCodeRepo {
+    // 使われていない回復コードの数
+    async fn count(&self, user_id: &str) -> AtResult<i64>;
+    // ユーザーの回復コードを全て置き換える。古いコードは使えなくなる
+    async fn replace_all(&self, user_id: &str, codes: &[RecoveryCode]) -> AtResult<()>;
+    // 回復コードを削除して使用済みにする。存在しなければAtError::NotFoundを返す
+    // 同じコードが並行して2回使われないように、削除できたかどうかで判定する
+    async fn consume(&self, user_id: &str, code_hash: &str) -> AtResult<()>;
+}
+
+#[cfg(test)]
+pub async fn run_recovery_code_repo_laws(repo: &impl RecoveryCodeRepo) {
+    use crate::at_error::AtError;
+    use chrono::Utc;
+
+    let code = |user_id: &str, code_hash: &str| RecoveryCode {
+        user_id: user_id.to_string(),
+        code_hash: code_hash.to_string(),
+        created_at: Utc::now(),
+    };
+
+    assert_eq!(repo.count("user").await.unwrap(), 0);
+
+    repo.replace_all("user", &[code("user", "a"), code("user", "b")])
+        .await
+        .unwrap();
+    repo.replace_all("other", &[code("other", "c")]).await.unwrap();
+    assert_eq!(repo.count("user").await.unwrap(), 2);
+
+    repo.consume("user", "a").await.unwrap();
+    assert_eq!(repo.count("user").await.unwrap(), 1);
+    // 使用済みのコードや他のユーザーのコードは使えない
+    assert!(matches!(repo.consume("user", "a").await, Err(AtError::NotFound(_))));
+    assert!(matches!(repo.consume("user", "c").await, Err(AtError::NotFound(_))));
+
+    // 発行し直すと古いコードは使えなくなる
+    repo.replace_all("user", &[code("user", "d")]).await.unwrap();
+    assert!(matches!(repo.consume("user", "b").await, Err(AtError::NotFound(_))));
+    repo.consume("user", "d").await.unwrap();
+    assert_eq!(repo.count("user").await.unwrap(), 0);
+    assert_eq!(repo.count("other").await.unwrap(), 1);
+}
@@ -2,15 +2,36 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use crate::entities::{Res, ResType, ResDeleteFlag};
 use futures::Stream;
+use crate::entities::search_query::SearchHit;
+
+#[derive(Debug, Clone)]
+pub struct ResSearchQuery {
+    pub text: String,
+    pub topic: Option<String>,
+}
 
 #[async_trait]
 pub trait ResPort {
     async fn find_by_id(&self, id: &str) -> Result<Option<Res>, Box<dyn std::error::Error>>;
     async fn find_by_topic_id(&self, topic_id: &str, limit: i32, offset: i32) -> Result<Vec<Res>, Box<dyn std::error::Error>>;
+    // reply_idのレスに返信(アンカー)しているレスを返す
     async fn find_by_reply_id(&self, reply_id: &str) -> Result<Vec<Res>, Box<dyn std::error::Error>>;
     async fn find_by_user_id(&self, user_id: &str) -> Result<Vec<Res>, Box<dyn std::error::Error>>;
     async fn find_by_hash(&self, hash: &str) -> Result<Option<Res>, Box<dyn std::error::Error>>;
+    // 同じトピックで同じIDを持つレスを古い順に全て返す
+    async fn find_by_topic_hash(&self, topic_id: &str, hash: &str) -> Result<Vec<Res>, Box<dyn std::error::Error>>;
+    // トピック内の番号がfrom以上to以下のレスを番号順に返す
+    async fn find_by_number_range(&self, topic_id: &str, from: i32, to: i32) -> Result<Vec<Res>, Box<dyn std::error::Error>>;
+    async fn find_by_numbers(&self, topic_id: &str, numbers: &[i32]) -> Result<Vec<Res>, Box<dyn std::error::Error>>;
+    // res_idのレスからreply_idsのレスへの返信を記録し、返信先のreply_countを増やす
+    // 記録済みの返信は無視し、新しく記録した返信先のidを返す
+    async fn add_replies(&self, res_id: &str, reply_ids: &[String]) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+    async fn search(&self, query: &ResSearchQuery, limit: i32) -> Result<Vec<SearchHit<Res>>, Box<dyn std::error::Error>>;
     async fn create(&self, res: &Res) -> Result<Res, Box<dyn std::error::Error>>;
+    // トピックが落ちておらずレス数がres_limit未満の時だけ保存する。そうでなければAtError::Prerequisiteを返す
+    // 採番と同じ文で確かめるので、同時に書き込まれても上限を超えない
+    async fn create_within_limit(&self, res: &Res, res_limit: i32) -> Result<Res, Box<dyn std::error::Error>>;
+    // 取得した時からversionが変わっていれば保存せずにAtError::Conflictを返す
     async fn update(&self, res: &Res) -> Result<Res, Box<dyn std::error::Error>>;
     async fn update_delete_flag(&self, id: &str, delete_flag: ResDeleteFlag) -> Result<(), Box<dyn std::error::Error>>;
     async fn update_age(&self, id: &str, age: bool) -> Result<(), Box<dyn std::error::Error>>;
@@ -1,3 +1,15 @@
 pub trait SafeIdGeneratorPort: Send + Sync {
     fn generate(&self) -> String;
-} 
\ No newline at end of file
+
+    /// `alphabet`の文字だけを使った`len`文字のIDを生成する。人が入力する短いコードに使う
+    ///
+    /// 既定の実装は`generate`の結果を変換するだけで偏りがあるので、実際の実装では上書きする
+    fn generate_code(&self, len: usize, alphabet: &[char]) -> String {
+        self.generate()
+            .bytes()
+            .cycle()
+            .take(len)
+            .map(|b| alphabet[b as usize % alphabet.len()])
+            .collect()
+    }
+}
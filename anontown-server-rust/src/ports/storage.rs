@@ -36,6 +36,7 @@ pub async fn run_storage_repo_laws(repo: &mut impl StorageRepoPort) {
         key: key.to_string(),
         value: value.to_string(),
         date,
+        version: 0,
     };
 
     // 保存テスト
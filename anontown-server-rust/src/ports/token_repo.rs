@@ -11,4 +11,15 @@ pub trait TokenRepo {
     async fn update(&self, token: &Token) -> AtResult<()>;
     async fn del_client_token(&self, token: &AuthTokenMaster, client_id: &str) -> AtResult<()>;
     async fn del_master_token(&self, user: &AuthUser) -> AtResult<()>;
+    // 更新前のトークンを使用済みにして新しいトークンを追加する。両方保存されるかどちらも保存されないか
+    // 更新前のトークンが既に使用済みになっていれば保存せずにAtError::Conflictを返す
+    async fn rotate(&self, old: &Token, new: &Token) -> AtResult<()>;
+    // 同じファミリーのトークンを全て削除する
+    async fn del_family(&self, family_id: &str) -> AtResult<()>;
+    // 最終使用日時だけを保存する
+    async fn update_last_used(&self, token: &Token) -> AtResult<()>;
+    // ユーザーのトークンのうち、family_idのファミリー以外を全て削除する
+    async fn del_other_families(&self, user_id: &str, family_id: &str) -> AtResult<()>;
+    // ユーザーのトークンを全て削除する
+    async fn del_all(&self, user_id: &str) -> AtResult<()>;
 }
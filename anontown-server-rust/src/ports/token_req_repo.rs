@@ -0,0 +1,98 @@
+use async_trait::async_trait;
+
+use crate::at_error::AtResult;
+use crate::entities::token_req::TokenReq;
+
+#[async_trait]
+pub trait TokenReqRepo {
+    async fn find_one(&self, token_id: &str, key: &str) -> AtResult<TokenReq>;
+    // 引き継ぎコードの前半が`selector`と一致する、有効な引き継ぎ用のリクエストを全て返す
+    async fn find_pairing(&self, selector: &str) -> AtResult<Vec<TokenReq>>;
+    async fn insert(&self, req: &TokenReq) -> AtResult<()>;
+    // 保存されているリクエストが既に無効になっていれば保存せずにAtError::Conflictを返す
+    // 同じ認可コードが並行して2回交換されないようにするため
+    async fn update(&self, req: &TokenReq) -> AtResult<()>;
+    // 間違ったコードが入力された回数を1増やし、`max_failed_attempts`に達したら無効にする
+    // 並行して入力されても数え漏れないように、保存されている値に対して加算する
+    async fn add_failed_attempt(&self, req: &TokenReq, max_failed_attempts: i32) -> AtResult<()>;
+}
+
+#[cfg(test)]
+pub async fn run_token_req_repo_laws(repo: &impl TokenReqRepo) {
+    use crate::at_error::AtError;
+    use crate::auth::TokenScope;
+    use chrono::Utc;
+
+    let req = TokenReq {
+        token_id: "token".to_string(),
+        key: "key".to_string(),
+        expires: Utc::now(),
+        active: true,
+        redirect_uri: Some("https://example.com/callback".to_string()),
+        code_challenge: Some("challenge".to_string()),
+        client_id: Some("client".to_string()),
+        scopes: vec![TokenScope::Read, TokenScope::PostRes],
+        pairing: false,
+        failed_attempts: 0,
+    };
+
+    assert!(matches!(
+        repo.find_one("token", "key").await,
+        Err(AtError::NotFound(_))
+    ));
+
+    repo.insert(&req).await.unwrap();
+    assert_eq!(repo.find_one("token", "key").await.unwrap(), req);
+    assert!(matches!(
+        repo.find_one("token", "other").await,
+        Err(AtError::NotFound(_))
+    ));
+
+    let used = TokenReq {
+        active: false,
+        ..req.clone()
+    };
+    repo.update(&used).await.unwrap();
+    assert_eq!(repo.find_one("token", "key").await.unwrap(), used);
+
+    // 無効になったリクエストはもう更新できない
+    assert!(matches!(repo.update(&used).await, Err(AtError::Conflict(_))));
+
+    // 引き継ぎ用でないリクエストはコードだけでは探せない
+    let active = TokenReq {
+        key: "ABCD0000".to_string(),
+        ..req.clone()
+    };
+    repo.insert(&active).await.unwrap();
+    assert!(repo.find_pairing("ABCD").await.unwrap().is_empty());
+
+    let pairing = TokenReq {
+        token_id: "master".to_string(),
+        key: "ABCD2345".to_string(),
+        expires: Utc::now(),
+        active: true,
+        redirect_uri: None,
+        code_challenge: None,
+        client_id: None,
+        scopes: Vec::new(),
+        pairing: true,
+        failed_attempts: 0,
+    };
+    repo.insert(&pairing).await.unwrap();
+    assert_eq!(
+        repo.find_pairing("ABCD").await.unwrap(),
+        vec![pairing.clone()]
+    );
+    assert!(repo.find_pairing("ABCE").await.unwrap().is_empty());
+
+    // 上限に達したら無効になり、もう探せない
+    repo.add_failed_attempt(&pairing, 2).await.unwrap();
+    let stored = repo.find_one("master", "ABCD2345").await.unwrap();
+    assert_eq!(stored.failed_attempts, 1);
+    assert!(stored.active);
+    repo.add_failed_attempt(&pairing, 2).await.unwrap();
+    let stored = repo.find_one("master", "ABCD2345").await.unwrap();
+    assert_eq!(stored.failed_attempts, 2);
+    assert!(!stored.active);
+    assert!(repo.find_pairing("ABCD").await.unwrap().is_empty());
+}
@@ -1,6 +1,8 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use crate::models::Topic;
+use crate::entities::topic::TopicLifecycle;
+use crate::entities::search_query::SearchHit;
 
 #[derive(Debug, Clone)]
 pub struct TopicQuery {
@@ -16,9 +18,15 @@ pub trait TopicPort {
     async fn find_one(&mut self, id: &str) -> Result<Topic, Box<dyn std::error::Error>>;
     async fn find_tags(&mut self, limit: i32) -> Result<Vec<(String, i32)>, Box<dyn std::error::Error>>;
     async fn insert(&mut self, topic: &Topic) -> Result<(), Box<dyn std::error::Error>>;
+    // 取得した時からversionが変わっていれば保存せずにAtError::Conflictを返す
     async fn update(&mut self, topic: &Topic) -> Result<(), Box<dyn std::error::Error>>;
-    async fn cron_topic_check(&mut self, now: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>>;
+    // まだ落ちていなければ落とす。同時に呼ばれても落とせるのは1回だけで、落とせた時にtrueを返す
+    async fn close(&mut self, id: &str) -> Result<bool, Box<dyn std::error::Error>>;
+    // 落ちる条件を満たしたまだ落ちていないトピックを返す。落とすのは呼び出し側で1つずつcloseする
+    async fn find_expired(&mut self, lifecycle: &TopicLifecycle, now: DateTime<Utc>) -> Result<Vec<Topic>, Box<dyn std::error::Error>>;
+    // titleが指定されている場合は関連度順になる
     async fn find(&mut self, query: &TopicQuery, skip: i32, limit: i32) -> Result<Vec<Topic>, Box<dyn std::error::Error>>;
+    async fn search(&mut self, query: &TopicQuery, skip: i32, limit: i32) -> Result<Vec<SearchHit<Topic>>, Box<dyn std::error::Error>>;
     async fn subscription_user_ids(&mut self, topic_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>>;
     async fn enable_subscription(&mut self, topic_id: &str, user_id: &str) -> Result<(), Box<dyn std::error::Error>>;
     async fn disable_subscription(&mut self, topic_id: &str, user_id: &str) -> Result<(), Box<dyn std::error::Error>>;
@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+use crate::at_error::AtResult;
+use crate::entities::topic_event::{TopicEvent, TopicEventRecord};
+
+/// 購読の結果
+pub struct TopicEventSubscription {
+    /// `after`より後のイベントが既にログから消えていて、一部を流せなかったか
+    ///
+    /// 取りこぼした購読者は最新の状態を取得し直す必要がある
+    pub missed: bool,
+    pub events: BoxStream<'static, AtResult<TopicEventRecord>>,
+}
+
+/// トピックのイベントを配信する
+///
+/// 直近のイベントは一定数まで残しておき、再接続した購読者が取りこぼさないようにする
+#[async_trait]
+pub trait TopicEventBus {
+    /// イベントに連番を振って配信し、振った連番を返す
+    async fn publish(&self, event: &TopicEvent) -> AtResult<i64>;
+
+    /// `after`より後の連番のイベントを連番順に流す
+    ///
+    /// `after`が指定された場合は残っている過去のイベントを先に流し、続けて新しいイベントを流す。
+    /// 同じイベントが2回流れることはない。
+    /// `after`の直後のイベントがログから消えている場合や、`after`がまだ振られていない連番の場合は`missed`になる
+    async fn subscribe(&self, after: Option<i64>) -> AtResult<TopicEventSubscription>;
+}
+
+#[cfg(test)]
+pub async fn run_topic_event_bus_laws(bus: &impl TopicEventBus) {
+    use futures::StreamExt;
+
+    let event = |topic: &str| TopicEvent::TopicClosed {
+        topic: topic.to_string(),
+    };
+
+    let seq1 = bus.publish(&event("topic1")).await.unwrap();
+    let seq2 = bus.publish(&event("topic2")).await.unwrap();
+    assert!(seq1 < seq2);
+
+    // 指定した連番より後の過去のイベントから流れる
+    let resumed = bus.subscribe(Some(seq1)).await.unwrap();
+    assert!(!resumed.missed);
+    let mut resumed = resumed.events;
+    // 指定しなければ新しいイベントだけが流れる
+    let latest = bus.subscribe(None).await.unwrap();
+    assert!(!latest.missed);
+    let mut latest = latest.events;
+    // 最新の連番を指定すれば取りこぼしはない
+    assert!(!bus.subscribe(Some(seq2)).await.unwrap().missed);
+    // まだ振られていない連番は取りこぼしとして扱う
+    assert!(bus.subscribe(Some(seq2 + 100)).await.unwrap().missed);
+
+    let seq3 = bus.publish(&event("topic3")).await.unwrap();
+    assert!(seq2 < seq3);
+
+    let record = resumed.next().await.unwrap().unwrap();
+    assert_eq!(record.seq, seq2);
+    assert_eq!(record.event, event("topic2"));
+    let record = resumed.next().await.unwrap().unwrap();
+    assert_eq!(record.seq, seq3);
+    assert_eq!(record.event, event("topic3"));
+
+    let record = latest.next().await.unwrap().unwrap();
+    assert_eq!(record.seq, seq3);
+}
@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+
+use crate::ports::history::HistoryPort;
+use crate::ports::profile::ProfilePort;
+use crate::ports::RecoveryCodeRepo;
+use crate::ports::res::ResPort;
+use crate::ports::storage::StoragePort;
+use crate::ports::TokenRepo;
+use crate::ports::topic::TopicPort;
+use crate::ports::user::UserPort;
+
+/// 1つのトランザクションを共有するリポジトリ
+#[derive(Clone)]
+pub struct Repos<T, U, R, H, P, S, K, C> {
+    pub topic_repo: T,
+    pub user_repo: U,
+    pub res_repo: R,
+    pub history_repo: H,
+    pub profile_repo: P,
+    pub storage_repo: S,
+    pub token_repo: K,
+    pub recovery_code_repo: C,
+}
+
+pub type UnitOfWorkRepos<W> = Repos<
+    <W as UnitOfWork>::TopicRepo,
+    <W as UnitOfWork>::UserRepo,
+    <W as UnitOfWork>::ResRepo,
+    <W as UnitOfWork>::HistoryRepo,
+    <W as UnitOfWork>::ProfileRepo,
+    <W as UnitOfWork>::StorageRepo,
+    <W as UnitOfWork>::TokenRepo,
+    <W as UnitOfWork>::RecoveryCodeRepo,
+>;
+
+/// 複数のリポジトリへの書き込みをまとめてコミットする
+#[async_trait]
+pub trait UnitOfWork {
+    type TopicRepo: TopicPort + Send;
+    type UserRepo: UserPort + Send;
+    type ResRepo: ResPort + Send;
+    type HistoryRepo: HistoryPort + Send;
+    type ProfileRepo: ProfilePort + Send;
+    type StorageRepo: StoragePort + Send;
+    type TokenRepo: TokenRepo + Send;
+    type RecoveryCodeRepo: RecoveryCodeRepo + Send;
+
+    /// `f`の中でreposに対して行った書き込みを1つのトランザクションで実行する
+    ///
+    /// `f`がOkを返せばコミットし、Errを返せば全ての書き込みをロールバックしてそのErrを返す。
+    /// Redisへのpublishなどの副作用もコミットされるまで行わない
+    async fn run<T, F>(&self, f: F) -> Result<T, Box<dyn std::error::Error>>
+    where
+        T: Send,
+        F: for<'a> FnOnce(
+                &'a mut UnitOfWorkRepos<Self>,
+            ) -> BoxFuture<'a, Result<T, Box<dyn std::error::Error>>>
+            + Send;
+}
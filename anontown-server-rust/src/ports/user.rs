@@ -7,6 +7,7 @@ pub trait UserPort {
     async fn find_by_id(&mut self, id: &str) -> Result<Option<User>, Box<dyn std::error::Error>>;
     async fn find_by_screen_name(&mut self, screen_name: &str) -> Result<Option<User>, Box<dyn std::error::Error>>;
     async fn create(&mut self, user: &User) -> Result<User, Box<dyn std::error::Error>>;
+    // 取得した時からversionが変わっていれば保存せずにAtError::Conflictを返す
     async fn update(&mut self, user: &User) -> Result<User, Box<dyn std::error::Error>>;
     async fn update_res_count(&mut self, id: &str, count: i32) -> Result<(), Box<dyn std::error::Error>>;
     async fn update_topic_count(&mut self, id: &str, count: i32) -> Result<(), Box<dyn std::error::Error>>;
@@ -0,0 +1,64 @@
+use async_trait::async_trait;
+
+use crate::at_error::AtResult;
+use crate::entities::user_totp::UserTotp;
+
+#[async_trait]
+pub trait UserTotpRepo {
+    // 二要素認証を登録していなければNone
+    async fn find_one(&self, user_id: &str) -> AtResult<Option<UserTotp>>;
+    // 既に登録されていれば置き換える。有効になる前に登録し直せるようにするため
+    async fn save(&self, totp: &UserTotp) -> AtResult<()>;
+    // 保存されているバージョンが異なればAtError::Conflictを返す
+    // 同じコードが並行して2回使われないようにするため
+    async fn update(&self, totp: &UserTotp) -> AtResult<()>;
+    async fn delete(&self, user_id: &str) -> AtResult<()>;
+}
+
+#[cfg(test)]
+pub async fn run_user_totp_repo_laws(repo: &impl UserTotpRepo) {
+    use crate::at_error::AtError;
+    use chrono::Utc;
+
+    let totp = UserTotp {
+        user_id: "user".to_string(),
+        secret: b"secret".to_vec(),
+        enabled: false,
+        last_used_step: None,
+        backup_code_hashes: vec![],
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        version: 0,
+    };
+
+    assert_eq!(repo.find_one("user").await.unwrap(), None);
+
+    repo.save(&totp).await.unwrap();
+    assert_eq!(repo.find_one("user").await.unwrap(), Some(totp.clone()));
+    assert_eq!(repo.find_one("other").await.unwrap(), None);
+
+    let enabled = UserTotp {
+        enabled: true,
+        last_used_step: Some(1),
+        backup_code_hashes: vec!["hash".to_string()],
+        ..totp.clone()
+    };
+    repo.update(&enabled).await.unwrap();
+    assert_eq!(
+        repo.find_one("user").await.unwrap(),
+        Some(UserTotp {
+            version: 1,
+            ..enabled.clone()
+        })
+    );
+
+    // 古いバージョンでは更新できない
+    assert!(matches!(repo.update(&enabled).await, Err(AtError::Conflict(_))));
+
+    // 登録し直すと置き換わる
+    repo.save(&totp).await.unwrap();
+    assert_eq!(repo.find_one("user").await.unwrap(), Some(totp));
+
+    repo.delete("user").await.unwrap();
+    assert_eq!(repo.find_one("user").await.unwrap(), None);
+}
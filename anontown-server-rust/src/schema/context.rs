@@ -1,6 +1,49 @@
+use crate::adapters::{AuthContainerImpl, IpContainer};
+use crate::auth::AuthToken;
+use crate::config::Config;
+use crate::entities::token::TokenDevice;
+use crate::ports::ip::IpPort;
 use crate::ports::Ports;
 
 #[derive(Clone)]
 pub struct Context {
     pub ports: Ports,
-} 
\ No newline at end of file
+    pub config: Config,
+    pub user_agent: Option<String>,
+}
+
+impl Context {
+    pub fn new(ports: Ports, config: Config) -> Self {
+        Self {
+            ports,
+            config,
+            user_agent: None,
+        }
+    }
+
+    /// 認証したトークンを持つリクエスト(接続)ごとのコンテキストを作る
+    pub fn with_auth_token(&self, token: Option<AuthToken>) -> Self {
+        let mut context = self.clone();
+        context.ports.auth_container =
+            AuthContainerImpl::with_token(token).with_admins(&self.config.admin_user_ids);
+        context
+    }
+
+    /// リクエスト元のIPとUser-Agentを持つリクエストごとのコンテキストを作る
+    pub fn with_client_info(&self, ip: Option<String>, user_agent: Option<String>) -> Self {
+        let mut context = self.clone();
+        context.ports.ip = IpContainer::new(ip);
+        context.user_agent = user_agent;
+        context
+    }
+
+    /// このリクエストで発行するトークンに記録する端末の情報
+    pub async fn token_device(&self) -> TokenDevice {
+        TokenDevice {
+            ip: self.ports.ip.get_ip().await,
+            user_agent: self.user_agent.clone(),
+        }
+    }
+}
+
+impl juniper::Context for Context {}
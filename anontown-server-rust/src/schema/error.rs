@@ -0,0 +1,104 @@
+//! リゾルバーが返した`AtError`をGraphQLのエラーにする
+//!
+//! `extensions.code`でエラーの種類を、`extensions.data`で入力エラーの詳細などを返す
+
+use juniper::{FieldError, IntoFieldError, Object, ScalarValue, Value};
+
+use crate::at_error::AtError;
+
+impl<S: ScalarValue> IntoFieldError<S> for AtError {
+    fn into_field_error(self) -> FieldError<S> {
+        let public = self.to_public();
+        let mut extensions = Object::with_capacity(2);
+        extensions.add_field("code", Value::scalar(public.code.to_string()));
+        extensions.add_field("data", json_to_value(public.data));
+        FieldError::new(public.message, Value::Object(extensions))
+    }
+}
+
+fn json_to_value<S: ScalarValue>(json: serde_json::Value) -> Value<S> {
+    match json {
+        serde_json::Value::Null => Value::null(),
+        serde_json::Value::Bool(b) => Value::scalar(b),
+        serde_json::Value::Number(n) => match n.as_i64().and_then(|n| i32::try_from(n).ok()) {
+            Some(n) => Value::scalar(n),
+            // GraphQLのIntは32ビットなので、収まらない数はFloatにする
+            None => Value::scalar(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::scalar(s),
+        serde_json::Value::Array(values) => {
+            Value::list(values.into_iter().map(json_to_value).collect())
+        }
+        serde_json::Value::Object(fields) => {
+            let mut object = Object::with_capacity(fields.len());
+            for (key, value) in fields {
+                object.add_field(key, json_to_value(value));
+            }
+            Value::Object(object)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::at_error::ParamErrorData;
+    use juniper::{graphql_value, DefaultScalarValue};
+
+    fn field_error(e: AtError) -> FieldError<DefaultScalarValue> {
+        e.into_field_error()
+    }
+
+    #[test]
+    fn test_into_field_error() {
+        let e = field_error(AtError::Captcha);
+        assert_eq!(e.message(), "キャプチャ認証に失敗");
+        assert_eq!(
+            e.extensions(),
+            &graphql_value!({ "code": "captcha", "data": null })
+        );
+
+        let e = field_error(AtError::Params(vec![ParamErrorData::new(
+            "title",
+            "too_long",
+            "タイトルが長すぎます",
+        )]));
+        assert_eq!(e.message(), "パラメーターが不正です");
+        assert_eq!(
+            e.extensions(),
+            &graphql_value!({
+                "code": "params",
+                "data": [{ "field": "title", "code": "too_long", "message": "タイトルが長すぎます" }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_into_field_error_hides_internal() {
+        let e = field_error(AtError::Internal(anyhow::anyhow!("connection refused")));
+        assert_eq!(e.message(), "内部エラーが発生しました");
+        assert_eq!(
+            e.extensions(),
+            &graphql_value!({ "code": "internal", "data": null })
+        );
+    }
+
+    #[test]
+    fn test_json_to_value() {
+        let value: Value<DefaultScalarValue> = json_to_value(serde_json::json!({
+            "int": 1,
+            "big": 10_000_000_000i64,
+            "float": 1.5,
+            "list": [true, "s"],
+        }));
+        assert_eq!(
+            value,
+            graphql_value!({
+                "int": 1,
+                "big": 10_000_000_000.0,
+                "float": 1.5,
+                "list": [true, "s"],
+            })
+        );
+    }
+}
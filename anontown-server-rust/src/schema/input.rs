@@ -1,5 +1,16 @@
 use juniper::GraphQLInputObject;
 use chrono::{DateTime, Utc};
+use crate::entities::{res, user};
+use crate::entities::trip::trip_name;
+use crate::entities::topic::{self, TopicBase};
+use crate::schema::types::{CreateUserInput, RecoverAccountInput, UpdateUserInput};
+use crate::validation::{ListRule, StrRule, Validate, Validator};
+
+// 検索条件で一度に指定できるidの数
+pub const QUERY_IDS_MAX: usize = 100;
+
+const ID_RULES: &[StrRule] = &[StrRule::Required];
+const QUERY_IDS_RULES: &[ListRule] = &[ListRule::MaxItems(QUERY_IDS_MAX)];
 
 #[derive(GraphQLInputObject)]
 pub struct DateQuery {
@@ -56,6 +67,7 @@ pub struct CreateTopicOneInput {
 #[derive(GraphQLInputObject)]
 pub struct CreateTopicForkInput {
     pub title: String,
+    pub text: String,
     pub parent: String,
 }
 
@@ -65,4 +77,181 @@ pub struct UpdateTopicInput {
     pub title: Option<String>,
     pub tags: Option<Vec<String>>,
     pub text: Option<String>,
-} 
\ No newline at end of file
+} 
+
+impl Validate for DateQuery {
+    fn check(&self, v: Validator) -> Validator {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from > to => {
+                v.error("to", "invalid", "期間の終わりが始まりより前です")
+            }
+            _ => v,
+        }
+    }
+}
+
+impl Validate for ResQuery {
+    fn check(&self, v: Validator) -> Validator {
+        let v = v
+            .opt_list("id", "id", self.id.as_deref(), QUERY_IDS_RULES, ID_RULES)
+            .opt_str("text", "本文", self.text.as_deref(), &[StrRule::MaxLen(res::TEXT_MAX_LEN)]);
+        match &self.date {
+            Some(date) => v.nested("date", |v| date.check(v)),
+            None => v,
+        }
+    }
+}
+
+impl Validate for TopicQuery {
+    fn check(&self, v: Validator) -> Validator {
+        v.opt_list("id", "id", self.id.as_deref(), QUERY_IDS_RULES, ID_RULES)
+            .opt_str("title", "タイトル", self.title.as_deref(), &[StrRule::MaxLen(topic::TITLE_MAX_LEN)])
+            .opt_list(
+                "tags",
+                "タグ",
+                self.tags.as_deref(),
+                &[ListRule::MaxItems(topic::TAGS_MAX)],
+                topic::TAG_RULES,
+            )
+    }
+}
+
+impl Validate for CreateResInput {
+    fn check(&self, v: Validator) -> Validator {
+        v.str("topic", "トピック", &self.topic, ID_RULES)
+            .opt_str("name", "名前", self.name.as_deref().map(trip_name), res::NAME_RULES)
+            .str("text", "本文", &self.text, res::TEXT_RULES)
+    }
+}
+
+impl Validate for CreateTopicNormalInput {
+    fn check(&self, v: Validator) -> Validator {
+        TopicBase::check_fields(v, Some(&self.title), Some(&self.tags), Some(&self.text))
+    }
+}
+
+impl Validate for CreateTopicOneInput {
+    fn check(&self, v: Validator) -> Validator {
+        TopicBase::check_fields(v, Some(&self.title), Some(&self.tags), Some(&self.text))
+    }
+}
+
+impl Validate for CreateTopicForkInput {
+    fn check(&self, v: Validator) -> Validator {
+        TopicBase::check_fields(v, Some(&self.title), None, Some(&self.text))
+            .str("parent", "親トピック", &self.parent, ID_RULES)
+    }
+}
+
+impl Validate for CreateUserInput {
+    fn check(&self, v: Validator) -> Validator {
+        v.str("sn", "スクリーンネーム", &self.sn, user::SN_RULES)
+            .str("pass", "パスワード", &self.pass, user::PASS_RULES)
+    }
+}
+
+impl Validate for UpdateUserInput {
+    fn check(&self, v: Validator) -> Validator {
+        v.opt_str("sn", "スクリーンネーム", self.sn.as_deref(), user::SN_RULES)
+            .opt_str("pass", "パスワード", self.pass.as_deref(), user::PASS_RULES)
+    }
+}
+
+// 回復コードの形式は使う時に照合するので、空でないことだけ確かめる
+impl Validate for RecoverAccountInput {
+    fn check(&self, v: Validator) -> Validator {
+        v.str("sn", "スクリーンネーム", &self.sn, &[StrRule::Required])
+            .str("code", "回復コード", &self.code, &[StrRule::Required])
+            .str("pass", "パスワード", &self.pass, user::PASS_RULES)
+    }
+}
+
+impl Validate for UpdateTopicInput {
+    fn check(&self, v: Validator) -> Validator {
+        let v = v.str("id", "id", &self.id, ID_RULES);
+        TopicBase::check_fields(v, self.title.as_deref(), self.tags.as_deref(), self.text.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::at_error::AtError;
+    use chrono::TimeZone;
+
+    fn fields(input: &impl Validate) -> Vec<String> {
+        match input.validate() {
+            Ok(()) => vec![],
+            Err(AtError::Params(errors)) => errors.into_iter().map(|e| format!("{}:{}", e.field, e.code)).collect(),
+            Err(e) => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_res_query_validate() {
+        let query = |date: Option<DateQuery>| ResQuery {
+            id: Some(vec!["a".to_string(), "".to_string()]),
+            topic: None,
+            notice: None,
+            hash: None,
+            reply: None,
+            profile: None,
+            self_: None,
+            text: None,
+            date,
+        };
+
+        assert_eq!(fields(&query(None)), vec!["id[1]:required"]);
+        assert_eq!(
+            fields(&query(Some(DateQuery {
+                from: Some(Utc.timestamp_opt(100, 0).unwrap()),
+                to: Some(Utc.timestamp_opt(0, 0).unwrap()),
+            }))),
+            vec!["id[1]:required", "date.to:invalid"]
+        );
+    }
+
+    #[test]
+    fn test_update_topic_input_validate() {
+        let input = UpdateTopicInput {
+            id: "topic".to_string(),
+            title: None,
+            tags: None,
+            text: None,
+        };
+        assert_eq!(fields(&input), Vec::<String>::new());
+
+        let input = UpdateTopicInput {
+            id: "".to_string(),
+            title: Some("".to_string()),
+            tags: Some(vec!["a".to_string(), "a".to_string()]),
+            text: None,
+        };
+        assert_eq!(fields(&input), vec!["id:required", "title:required", "tags[1]:duplicate"]);
+    }
+
+    #[test]
+    fn test_create_topic_fork_input_validate() {
+        let input = CreateTopicForkInput {
+            title: "title".to_string(),
+            text: "".to_string(),
+            parent: "".to_string(),
+        };
+        assert_eq!(fields(&input), vec!["text:required", "parent:required"]);
+    }
+
+    #[test]
+    fn test_create_user_input_validate() {
+        let input = CreateUserInput {
+            sn: "user_1".to_string(),
+            pass: "password".to_string(),
+        };
+        assert_eq!(fields(&input), Vec::<String>::new());
+
+        let input = CreateUserInput {
+            sn: "ユーザー".to_string(),
+            pass: "".to_string(),
+        };
+        assert_eq!(fields(&input), vec!["sn:invalid_char", "pass:required"]);
+    }
+}
@@ -1,10 +1,14 @@
+use std::sync::Arc;
+
 pub mod context;
+pub mod error;
 pub mod input;
 pub mod mutation;
 pub mod query;
 pub mod scalar;
 pub mod subscription;
 pub mod types;
+pub mod visibility;
 
 #[cfg(test)]
 mod types_test;
@@ -13,19 +17,26 @@ pub use query::Query;
 pub use mutation::Mutation;
 pub use subscription::Subscription;
 
+pub type SchemaRoot = juniper::RootNode<'static, Query, Mutation, Subscription>;
+
 #[derive(Clone)]
-pub struct Schema(juniper::RootNode<'static, Query, Mutation, Subscription>);
+pub struct Schema(Arc<SchemaRoot>);
 
 impl Schema {
     pub fn new(query: Query, mutation: Mutation, subscription: Subscription) -> Self {
-        Self(juniper::RootNode::new(query, mutation, subscription))
+        Self(Arc::new(juniper::RootNode::new(query, mutation, subscription)))
+    }
+
+    /// WebSocketのハンドラーは接続ごとにスキーマを共有するのでArcで渡す
+    pub fn root(&self) -> Arc<SchemaRoot> {
+        self.0.clone()
     }
 }
 
 impl std::ops::Deref for Schema {
-    type Target = juniper::RootNode<'static, Query, Mutation, Subscription>;
+    type Target = SchemaRoot;
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
-} 
\ No newline at end of file
+}
@@ -1,30 +1,75 @@
-use juniper::{graphql_object, graphql_value, FieldError, FieldResult, ID};
+use juniper::{graphql_object, ID};
+use crate::at_error::{AtError, AtResult};
 use crate::ports::Ports;
-use crate::entities::{User, TokenMaster, Client, TokenGeneral, TopicNormal, TopicOne, TopicFork, TopicEdit, Res, Profile, Storage};
+use crate::entities::{User, Token, Client, TopicNormal, TopicOne, TopicFork, TopicEdit, Res, Profile, Storage};
 use crate::schema::types::{
     ClientType, CreateClientInput, CreateTokenInput, CreateUserInput, HistoryType, ProfileType,
     ResType, StorageType, TagType, TokenType, TopicType, UpdateClientInput, UpdateTokenInput,
-    UpdateUserInput, UserType, CreateTokenGeneralResponse, TokenReq, SetStoragesInput, SetStoragesPayload,
+    UpdateUserInput, UserType, TokenReq, SetStoragesInput, SetStoragesPayload,
+    TokenScopeEnum, TokenRefreshResponse, PairingCodeType, TotpEnrollmentType,
+    RecoverAccountInput, StorageInput, ToSchemaType,
 };
 use crate::schema::input::{
     CreateResInput, CreateTopicNormalInput, CreateTopicOneInput,
     CreateTopicForkInput, UpdateTopicInput,
 };
 use crate::ports::{ResPort, TopicPort, UserPort};
+use crate::usecases;
+use crate::usecases::acquire_rate_limit::RateLimitSlot;
+use crate::auth::TokenScope;
+use crate::entities::password;
+use crate::entities::rate_limit_rule::RateLimitAction;
+use crate::entities::topic_event::TopicEvent;
+use crate::ports::topic_event_bus::TopicEventBus;
+use crate::entities::{profile, res, storage};
+use crate::entities::trip::trip_name;
+use crate::entities::topic::TopicBase;
+use crate::validation::{normalize, StrRule, Validate, Validator};
 
 pub struct Mutation;
 
+// クライアントがキャプチャの失敗を見分けられるように、通らなければAtError::Captchaにする
+async fn verify_recaptcha(context: &Context, token: &str) -> AtResult<()> {
+    match context.ports.recaptcha.verify(token).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(AtError::Captcha),
+        Err(e) => Err(AtError::Internal(anyhow::anyhow!(e.to_string()))),
+    }
+}
+
+// 保存できなかった書き込みは連投制限に数えないので、失敗したら記録した枠を解放してからエラーを返す
+async fn release_rate_limit_on_error<T>(
+    context: &Context,
+    slot: &RateLimitSlot,
+    result: Result<T, Box<dyn std::error::Error>>,
+) -> AtResult<T> {
+    if result.is_err() {
+        if let Err(release) = usecases::release_rate_limit(slot, &context.ports.rate_limiter).await {
+            context.ports.logger.error(
+                format!(
+                    "mutation: failed to release rate limit {}",
+                    release
+                )
+            );
+        }
+    }
+    Ok(result?)
+}
+
 #[graphql_object]
 impl Mutation {
-    async fn create_user(&self, context: &Context, input: CreateUserInput) -> FieldResult<UserType> {
+    async fn create_user(&self, context: &Context, input: CreateUserInput) -> AtResult<UserType> {
+        // 入力のバリデーション
+        input.validate()?;
+
         // reCAPTCHAの検証
-        context.ports.recaptcha.verify(&input.recaptcha).await?;
+        verify_recaptcha(context, &input.recaptcha).await?;
 
         // ユーザーの作成
         let user = User::create(
             &context.ports.object_id_generator,
             &input.sn,
-            &input.pass,
+            &password::hash_password(&input.pass, &context.config.password)?,
             context.ports.clock.now(),
         );
 
@@ -32,61 +77,119 @@ impl Mutation {
         context.ports.user_repo.insert(&user).await?;
 
         // マスタートークンの作成
-        let token = TokenMaster::create(
+        let token = Token::create_master(
+            user.id.clone(),
+            context.token_device().await,
+            &context.ports.clock,
             &context.ports.object_id_generator,
-            user.auth(&input.pass),
-            context.ports.clock.now(),
             &context.ports.safe_id_generator,
         );
 
         // トークンの保存
         context.ports.token_repo.insert(&token).await?;
 
-        Ok(UserType::from(user))
+        // 作成したトークンの本人として、本人にだけ見せるフィールドも返す
+        let auth = token.auth(&token.access_token, &context.ports.clock)?;
+        Ok(user.to_schema_type(&context.with_auth_token(Some(auth)).ports.auth_container))
     }
 
-    async fn update_user(&self, context: &Context, input: UpdateUserInput) -> FieldResult<UserType> {
+    async fn update_user(&self, context: &Context, input: UpdateUserInput) -> AtResult<UserType> {
+        // 入力のバリデーション
+        input.validate()?;
+
         // 認証ユーザーの取得
-        let auth_user = context.ports.auth_from_api_param.auth_user_request_to_user(
-            &context.ports.user_repo,
-            &input.auth,
+        let auth_user = usecases::authenticate_user(
+            &mut context.ports.user_repo.clone(),
+            &input.auth.id,
+            &input.auth.pass,
+            &context.config.password,
+            &context.ports.clock,
+        ).await?;
+        usecases::verify_second_factor(
+            &context.ports.user_totp_repo,
+            &auth_user.id,
+            input.auth.totp.as_deref(),
+            &context.ports.clock,
         ).await?;
-
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(&auth_user.id).await?;
 
         // ユーザーの更新
-        let new_user = user.change(
-            auth_user,
-            input.pass.as_deref(),
-            input.sn.as_deref(),
-        );
+        let pass = input
+            .pass
+            .as_deref()
+            .map(|pass| password::hash_password(pass, &context.config.password))
+            .transpose()?;
+        let device = context.token_device().await;
+
+        let (auth_user, pass, sn, device) = (&auth_user, &pass, &input.sn, &device);
+        // 同時にユーザーが更新されて競合したら取得からやり直す
+        let (new_user, token) = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(&auth_user.id).await?;
+            let new_user = user.change(
+                auth_user.clone(),
+                pass.as_deref(),
+                sn.as_deref(),
+            );
 
-        // ユーザーの保存
-        context.ports.user_repo.update(&new_user).await?;
+            // 新しいマスタートークンの作成
+            let token = Token::create_master(
+                auth_user.id.clone(),
+                device.clone(),
+                &context.ports.clock,
+                &context.ports.object_id_generator,
+                &context.ports.safe_id_generator,
+            );
 
-        // マスタートークンの削除
-        context.ports.token_repo.del_master_token(&auth_user).await?;
+            // ユーザーの保存とマスタートークンの入れ替えをまとめて行う
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                repos.user_repo.update(&new_user).await?;
+                repos.token_repo.del_master_token(auth_user).await?;
+                repos.token_repo.insert(&token).await?;
+                Ok((new_user, token))
+            })).await
+        }).await?;
+
+        // 新しいマスタートークンの本人として、本人にだけ見せるフィールドも返す
+        let auth = token.auth(&token.access_token, &context.ports.clock)?;
+        Ok(new_user.to_schema_type(&context.with_auth_token(Some(auth)).ports.auth_container))
+    }
 
-        // 新しいマスタートークンの作成
-        let token = TokenMaster::create(
-            &context.ports.object_id_generator,
-            auth_user,
-            context.ports.clock.now(),
+    // 回復コードを返す。この時しか表示できず、前に発行した回復コードは使えなくなる
+    async fn create_recovery_codes(&self, context: &Context) -> AtResult<Vec<String>> {
+        let codes = usecases::create_recovery_codes(
+            &context.ports.recovery_code_repo,
+            context.ports.auth_container.get_token_master()?,
+            &context.ports.clock,
             &context.ports.safe_id_generator,
-        );
+        ).await?;
 
-        // トークンの保存
-        context.ports.token_repo.insert(&token).await?;
+        Ok(codes)
+    }
+
+    // パスワードを忘れた時のためのもので、ログインは不要。成功したら全ての端末がログアウトされる
+    async fn recover_account(&self, context: &Context, input: RecoverAccountInput) -> AtResult<bool> {
+        // 入力のバリデーション
+        input.validate()?;
 
-        Ok(UserType::from(new_user))
+        // reCAPTCHAの検証
+        verify_recaptcha(context, &input.recaptcha).await?;
+
+        usecases::recover_account(
+            &context.ports.unit_of_work,
+            &input.sn,
+            &input.code,
+            &input.pass,
+            &context.config.password,
+        ).await?;
+
+        Ok(true)
     }
 
-    async fn create_client(&self, context: &Context, input: CreateClientInput) -> FieldResult<ClientType> {
+    async fn create_client(&self, context: &Context, input: CreateClientInput) -> AtResult<ClientType> {
         // クライアントの作成
         let client = Client::create(
             &context.ports.object_id_generator,
-            context.ports.auth_container.get_token_master(),
+            context.ports.auth_container.get_token_master()?,
             &input.name,
             &input.url,
             context.ports.clock.now(),
@@ -106,13 +209,13 @@ impl Mutation {
         Ok(ClientType::from(client))
     }
 
-    async fn update_client(&self, context: &Context, input: UpdateClientInput) -> FieldResult<ClientType> {
+    async fn update_client(&self, context: &Context, input: UpdateClientInput) -> AtResult<ClientType> {
         // クライアントの取得
         let client = context.ports.client_repo.find_one(&input.id).await?;
 
         // クライアントの更新
         let new_client = client.change_data(
-            context.ports.auth_container.get_token_master(),
+            context.ports.auth_container.get_token_master()?,
             input.name.as_deref(),
             input.url.as_deref(),
             context.ports.clock.now(),
@@ -132,38 +235,31 @@ impl Mutation {
         Ok(ClientType::from(new_client))
     }
 
-    async fn create_token_general(&self, context: &Context, client: ID) -> FieldResult<CreateTokenGeneralResponse> {
+    async fn create_token_general(&self, context: &Context, client: ID, scopes: Vec<TokenScopeEnum>) -> AtResult<TokenType> {
+        // 一般トークンはマスタートークンでしか発行できない
+        let auth = context.ports.auth_container.get_token_master()?;
+
         // クライアントの取得
         let client = context.ports.client_repo.find_one(&client).await?;
 
         // トークンの作成
-        let token = TokenGeneral::create(
+        let token = Token::create_general(
+            auth.base.user.clone(),
+            client.id.clone(),
+            scopes.into_iter().map(TokenScope::from).collect(),
+            context.token_device().await,
+            &context.ports.clock,
             &context.ports.object_id_generator,
-            context.ports.auth_container.get_token_master(),
-            &client,
-            context.ports.clock.now(),
-            &context.ports.safe_id_generator,
-        );
-
-        // トークンリクエストの作成
-        let (req, new_token) = token.create_req(
-            context.ports.clock.now(),
             &context.ports.safe_id_generator,
         );
 
         // トークンの保存
-        context.ports.token_repo.insert(&new_token).await?;
-
-        Ok(CreateTokenGeneralResponse {
-            token: TokenType::from(token),
-            req: TokenReq {
-                token: req.token,
-                key: req.key,
-            },
-        })
+        context.ports.token_repo.insert(&token).await?;
+
+        Ok(TokenType::from(token))
     }
 
-    async fn create_token_req(&self, context: &Context) -> FieldResult<TokenReq> {
+    async fn create_token_req(&self, context: &Context) -> AtResult<TokenReq> {
         // トークンリクエストの作成
         let req = TokenReq::create(
             context.ports.clock.now(),
@@ -173,18 +269,28 @@ impl Mutation {
         Ok(req)
     }
 
-    async fn create_token_master(&self, context: &Context, auth: AuthUser) -> FieldResult<TokenType> {
+    async fn create_token_master(&self, context: &Context, auth: AuthUser) -> AtResult<TokenType> {
         // 認証ユーザーの取得
-        let auth_user = context.ports.auth_from_api_param.auth_user_request_to_user(
-            &context.ports.user_repo,
-            &auth,
+        let auth_user = usecases::authenticate_user(
+            &mut context.ports.user_repo.clone(),
+            &auth.id,
+            &auth.pass,
+            &context.config.password,
+            &context.ports.clock,
+        ).await?;
+        usecases::verify_second_factor(
+            &context.ports.user_totp_repo,
+            &auth_user.id,
+            auth.totp.as_deref(),
+            &context.ports.clock,
         ).await?;
 
         // マスタートークンの作成
-        let token = TokenMaster::create(
+        let token = Token::create_master(
+            auth_user.id.clone(),
+            context.token_device().await,
+            &context.ports.clock,
             &context.ports.object_id_generator,
-            auth_user,
-            context.ports.clock.now(),
             &context.ports.safe_id_generator,
         );
 
@@ -194,50 +300,182 @@ impl Mutation {
         Ok(TokenType::from(token))
     }
 
-    async fn auth_token_req(&self, context: &Context, id: ID, key: String) -> FieldResult<TokenType> {
+    async fn auth_token_req(&self, context: &Context, id: ID, key: String) -> AtResult<TokenType> {
         // トークンの認証
         let token = context.ports.token_repo.auth_token_req(&id, &key).await?;
 
         Ok(TokenType::from(token))
     }
 
-    async fn del_token_client(&self, context: &Context, client: ID) -> FieldResult<bool> {
+    async fn del_token_client(&self, context: &Context, client: ID) -> AtResult<bool> {
         // クライアントの取得
         let client = context.ports.client_repo.find_one(&client).await?;
 
         // クライアントトークンの削除
         context.ports.token_repo.del_client_token(
-            context.ports.auth_container.get_token_master(),
+            context.ports.auth_container.get_token_master()?,
             &client.id,
         ).await?;
 
         Ok(true)
     }
 
-    async fn create_topic_normal(&self, context: &Context, title: String, tags: Vec<String>, text: String) -> FieldResult<TopicType> {
+    async fn revoke_session(&self, context: &Context, id: ID) -> AtResult<bool> {
+        usecases::revoke_session(
+            &context.ports.token_repo,
+            context.ports.auth_container.get_token_master()?,
+            &id,
+        ).await?;
+
+        Ok(true)
+    }
+
+    // 他の端末を全てログアウトさせる。今使っているセッションは残る
+    async fn revoke_other_sessions(&self, context: &Context) -> AtResult<bool> {
+        usecases::revoke_other_sessions(
+            &context.ports.token_repo,
+            context.ports.auth_container.get_token_master()?,
+        ).await?;
+
+        Ok(true)
+    }
+
+    async fn create_pairing_code(&self, context: &Context) -> AtResult<PairingCodeType> {
+        let req = usecases::create_pairing_code(
+            &context.ports.token_req_repo,
+            context.ports.auth_container.get_token_master()?,
+            &context.ports.clock,
+            &context.ports.safe_id_generator,
+        ).await?;
+
+        Ok(PairingCodeType {
+            code: req.pairing_code(),
+            qr_payload: req.pairing_qr_payload(),
+            expires_at: req.expires,
+        })
+    }
+
+    async fn enroll_totp(&self, context: &Context) -> AtResult<TotpEnrollmentType> {
+        let auth = context.ports.auth_container.get_token_master()?;
+        let user = context.ports.user_repo.find_one(&auth.base.user).await?;
+        let totp = usecases::enroll_totp(
+            &context.ports.user_totp_repo,
+            auth,
+            &context.ports.clock,
+            &context.ports.safe_id_generator,
+        ).await?;
+
+        Ok(TotpEnrollmentType {
+            secret: totp.secret_base32(),
+            provisioning_uri: totp.provisioning_uri(&user.sn),
+        })
+    }
+
+    // 二要素認証のバックアップコードを返す。この時しか表示できない
+    async fn confirm_totp(&self, context: &Context, code: String) -> AtResult<Vec<String>> {
+        let backup_codes = usecases::confirm_totp(
+            &context.ports.user_totp_repo,
+            context.ports.auth_container.get_token_master()?,
+            &code,
+            &context.ports.clock,
+            &context.ports.safe_id_generator,
+        ).await?;
+
+        Ok(backup_codes)
+    }
+
+    async fn disable_totp(&self, context: &Context, code: String) -> AtResult<bool> {
+        usecases::disable_totp(
+            &context.ports.user_totp_repo,
+            context.ports.auth_container.get_token_master()?,
+            &code,
+            &context.ports.clock,
+        ).await?;
+
+        Ok(true)
+    }
+
+    // 新しい端末はまだログインしていないので認証は不要
+    async fn redeem_pairing_code(&self, context: &Context, code: String) -> AtResult<TokenType> {
+        let token = usecases::redeem_pairing_code(
+            &context.ports.token_repo,
+            &context.ports.token_req_repo,
+            &context.ports.rate_limiter,
+            &code,
+            context.token_device().await,
+            &context.ports.clock,
+            &context.ports.object_id_generator,
+            &context.ports.safe_id_generator,
+        ).await?;
+
+        Ok(TokenType::from(token))
+    }
+
+    // 使用済みのリフレッシュトークンが使われた場合は同じファミリーのトークンを全て無効にする
+    async fn refresh_token(&self, context: &Context, refresh_token: String) -> AtResult<TokenRefreshResponse> {
+        let token = usecases::rotate_token(
+            &context.ports.token_repo,
+            &refresh_token,
+            None,
+            &context.ports.clock,
+            &context.ports.object_id_generator,
+            &context.ports.safe_id_generator,
+        ).await?;
+
+        Ok(TokenRefreshResponse::from(token))
+    }
+
+    async fn create_topic_normal(&self, context: &Context, title: String, tags: Vec<String>, text: String) -> AtResult<TopicType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::CreateTopic)?;
+
+        // 入力のバリデーション
+        TopicBase::check_fields(Validator::new(), Some(&title), Some(&tags), Some(&text)).finish()?;
+
         // ユーザーの取得
         let user = context.ports.user_repo.find_one(
             context.ports.auth_container.get_token().user,
         ).await?;
 
-        // トピックの作成
-        let create = TopicNormal::create(
-            &context.ports.object_id_generator,
-            &title,
-            &tags,
-            &text,
+        // 連投制限
+        // 書き込めないと分かっている時に枠を使わないように、確認を済ませてから記録する
+        let slot = usecases::acquire_rate_limit(
             &user,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        );
+            RateLimitAction::Topic,
+            &context.ports.rate_limiter,
+            &context.ports.clock,
+        ).await?;
 
-        // トピックの保存
-        context.ports.topic_repo.insert(&create.topic).await?;
+        let (title, tags, text, slot) = (&title, &tags, &text, &slot);
+        // 同時にユーザーが更新されて競合したら取得からやり直す
+        let result = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let mut user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+            slot.apply(&mut user);
+
+            // トピックの作成
+            let create = TopicNormal::create(
+                &context.ports.object_id_generator,
+                &title,
+                &tags,
+                &text,
+                &user,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            );
 
-        // ユーザー、レス、履歴の保存
-        context.ports.user_repo.update(&create.user).await?;
-        context.ports.res_repo.insert(&create.res).await?;
-        context.ports.history_repo.insert(&create.history).await?;
+            // トピック、ユーザー、レス、履歴をまとめて保存
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                repos.topic_repo.insert(&create.topic).await?;
+                repos.user_repo.update(&create.user).await?;
+                repos.res_repo.insert(&create.res).await?;
+                repos.history_repo.insert(&create.history).await?;
+                Ok(create)
+            })).await
+        }).await;
+        let create = release_rate_limit_on_error(context, slot, result).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -262,29 +500,56 @@ impl Mutation {
         Ok(TopicType::from(create.topic))
     }
 
-    async fn create_topic_one(&self, context: &Context, title: String, text: String) -> FieldResult<TopicType> {
+    async fn create_topic_one(&self, context: &Context, title: String, text: String) -> AtResult<TopicType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::CreateTopic)?;
+
+        // 入力のバリデーション
+        TopicBase::check_fields(Validator::new(), Some(&title), None, Some(&text)).finish()?;
+
         // ユーザーの取得
         let user = context.ports.user_repo.find_one(
             context.ports.auth_container.get_token().user,
         ).await?;
 
-        // トピックの作成
-        let create = TopicOne::create(
-            &context.ports.object_id_generator,
-            &title,
-            &text,
+        // 連投制限
+        // 書き込めないと分かっている時に枠を使わないように、確認を済ませてから記録する
+        let slot = usecases::acquire_rate_limit(
             &user,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        );
+            RateLimitAction::Topic,
+            &context.ports.rate_limiter,
+            &context.ports.clock,
+        ).await?;
 
-        // トピックの保存
-        context.ports.topic_repo.insert(&create.topic).await?;
+        let (title, text, slot) = (&title, &text, &slot);
+        // 同時にユーザーが更新されて競合したら取得からやり直す
+        let result = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let mut user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+            slot.apply(&mut user);
+
+            // トピックの作成
+            let create = TopicOne::create(
+                &context.ports.object_id_generator,
+                &title,
+                &text,
+                &user,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            );
 
-        // ユーザー、レス、履歴の保存
-        context.ports.user_repo.update(&create.user).await?;
-        context.ports.res_repo.insert(&create.res).await?;
-        context.ports.history_repo.insert(&create.history).await?;
+            // トピック、ユーザー、レス、履歴をまとめて保存
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                repos.topic_repo.insert(&create.topic).await?;
+                repos.user_repo.update(&create.user).await?;
+                repos.res_repo.insert(&create.res).await?;
+                repos.history_repo.insert(&create.history).await?;
+                Ok(create)
+            })).await
+        }).await;
+        let create = release_rate_limit_on_error(context, slot, result).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -309,33 +574,61 @@ impl Mutation {
         Ok(TopicType::from(create.topic))
     }
 
-    async fn create_topic_fork(&self, context: &Context, title: String, text: String, parent: ID) -> FieldResult<TopicType> {
+    async fn create_topic_fork(&self, context: &Context, title: String, text: String, parent: ID) -> AtResult<TopicType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::CreateTopic)?;
+
+        // 入力のバリデーション
+        let input = CreateTopicForkInput { title, text, parent: parent.to_string() };
+        input.validate()?;
+
+        // 親トピックの取得
+        let parent = context.ports.topic_repo.find_one(&input.parent).await?;
+
         // ユーザーの取得
         let user = context.ports.user_repo.find_one(
             context.ports.auth_container.get_token().user,
         ).await?;
 
-        // 親トピックの取得
-        let parent = context.ports.topic_repo.find_one(&parent).await?;
-
-        // トピックの作成
-        let create = TopicFork::create(
-            &context.ports.object_id_generator,
-            &title,
-            &text,
+        // 連投制限
+        // 書き込めないと分かっている時に枠を使わないように、確認を済ませてから記録する
+        let slot = usecases::acquire_rate_limit(
             &user,
-            &parent,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        );
+            RateLimitAction::Topic,
+            &context.ports.rate_limiter,
+            &context.ports.clock,
+        ).await?;
 
-        // トピックの保存
-        context.ports.topic_repo.insert(&create.topic).await?;
+        let (title, text, parent, slot) = (&input.title, &input.text, &parent, &slot);
+        // 同時にユーザーが更新されて競合したら取得からやり直す
+        let result = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let mut user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+            slot.apply(&mut user);
+
+            // トピックの作成
+            let create = TopicFork::create(
+                &context.ports.object_id_generator,
+                &title,
+                &text,
+                &user,
+                &parent,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            );
 
-        // ユーザー、レス、履歴の保存
-        context.ports.user_repo.update(&create.user).await?;
-        context.ports.res_repo.insert(&create.res).await?;
-        context.ports.history_repo.insert(&create.history).await?;
+            // トピック、ユーザー、レス、履歴をまとめて保存
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                repos.topic_repo.insert(&create.topic).await?;
+                repos.user_repo.update(&create.user).await?;
+                repos.res_repo.insert(&create.res).await?;
+                repos.history_repo.insert(&create.history).await?;
+                Ok(create)
+            })).await
+        }).await;
+        let create = release_rate_limit_on_error(context, slot, result).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -360,33 +653,46 @@ impl Mutation {
         Ok(TopicType::from(create.topic))
     }
 
-    async fn create_topic_edit(&self, context: &Context, title: String, text: String, parent: ID) -> FieldResult<TopicType> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
+    async fn create_topic_edit(&self, context: &Context, title: String, text: String, parent: ID) -> AtResult<TopicType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::CreateTopic)?;
+
+        // 入力のバリデーション
+        TopicBase::check_fields(Validator::new(), Some(&title), None, Some(&text))
+            .str("parent", "親トピック", &parent, &[StrRule::Required])
+            .finish()?;
 
         // 親トピックの取得
         let parent = context.ports.topic_repo.find_one(&parent).await?;
 
-        // トピックの作成
-        let create = TopicEdit::create(
-            &context.ports.object_id_generator,
-            &title,
-            &text,
-            &user,
-            &parent,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        );
-
-        // トピックの保存
-        context.ports.topic_repo.insert(&create.topic).await?;
+        let (title, text, parent) = (&title, &text, &parent);
+        // 同時にユーザーが更新されて競合したら取得からやり直す
+        let create = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // トピックの作成
+            let create = TopicEdit::create(
+                &context.ports.object_id_generator,
+                &title,
+                &text,
+                &user,
+                &parent,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            );
 
-        // ユーザー、レス、履歴の保存
-        context.ports.user_repo.update(&create.user).await?;
-        context.ports.res_repo.insert(&create.res).await?;
-        context.ports.history_repo.insert(&create.history).await?;
+            // トピック、ユーザー、レス、履歴をまとめて保存
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                repos.topic_repo.insert(&create.topic).await?;
+                repos.user_repo.update(&create.user).await?;
+                repos.res_repo.insert(&create.res).await?;
+                repos.history_repo.insert(&create.history).await?;
+                Ok(create)
+            })).await
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -411,27 +717,38 @@ impl Mutation {
         Ok(TopicType::from(create.topic))
     }
 
-    async fn update_topic(&self, context: &Context, id: ID, title: Option<String>, tags: Option<Vec<String>>, text: Option<String>) -> FieldResult<TopicType> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // トピックの取得
-        let topic = context.ports.topic_repo.find_one(&id).await?;
-
-        // トピックの更新
-        let update = topic.update(
-            &user,
-            title.as_deref(),
-            tags.as_deref(),
-            text.as_deref(),
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        )?;
-
-        // トピックの保存
-        context.ports.topic_repo.update(&update.topic).await?;
+    async fn update_topic(&self, context: &Context, id: ID, title: Option<String>, tags: Option<Vec<String>>, text: Option<String>) -> AtResult<TopicType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::CreateTopic)?;
+
+        // 入力のバリデーション
+        TopicBase::check_fields(Validator::new(), title.as_deref(), tags.as_deref(), text.as_deref()).finish()?;
+
+        let (id, title, tags, text) = (&id, &title, &tags, &text);
+        // 同時に編集されて競合したら取得からやり直す
+        let update = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // トピックの取得
+            let topic = context.ports.topic_repo.find_one(id).await?;
+
+            // トピックの更新
+            let update = topic.update(
+                &user,
+                title.as_deref(),
+                tags.as_deref(),
+                text.as_deref(),
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            )?;
+
+            // トピックの保存
+            context.ports.topic_repo.update(&update.topic).await?;
+            Ok(update)
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -444,31 +761,98 @@ impl Mutation {
         Ok(TopicType::from(update.topic))
     }
 
-    async fn create_res(&self, context: &Context, text: String, topic: ID) -> FieldResult<ResType> {
+    // nameが`name#key`形式ならトリップ付きの名前になる
+    async fn create_res(&self, context: &Context, name: Option<String>, text: String, topic: ID) -> AtResult<ResType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::PostRes)?;
+
+        // 入力のバリデーション
+        Validator::new()
+            .opt_str("name", "名前", name.as_deref().map(trip_name), res::NAME_RULES)
+            .str("text", "本文", &text, res::TEXT_RULES)
+            .str("topic", "トピック", &topic, &[StrRule::Required])
+            .finish()?;
+
+        // トピックの取得
+        // 上限は保存する時に採番と同じ文で確かめるので、ここでは明らかに書き込めない場合だけ弾く
+        let topic = context.ports.topic_repo.find_one(&topic).await?;
+        if !topic.base().can_create_res(&context.config.topic_lifecycle) {
+            return Err(AtError::Prerequisite(
+                "トピックが落ちているかレス数が上限に達しています".to_string(),
+            ));
+        }
+
         // ユーザーの取得
         let user = context.ports.user_repo.find_one(
             context.ports.auth_container.get_token().user,
         ).await?;
 
-        // トピックの取得
-        let topic = context.ports.topic_repo.find_one(&topic).await?;
-
-        // レスの作成
-        let create = Res::create(
-            &context.ports.object_id_generator,
-            &text,
+        // 連投制限
+        // 書き込めないと分かっている時に枠を使わないように、確認を済ませてから記録する
+        let slot = usecases::acquire_rate_limit(
             &user,
-            &topic,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        );
+            RateLimitAction::Res,
+            &context.ports.rate_limiter,
+            &context.ports.clock,
+        ).await?;
 
-        // レスの保存
-        context.ports.res_repo.insert(&create.res).await?;
+        let (name, text, topic, slot) = (&name, &text, &topic, &slot);
+        // 同時にユーザーが更新されて競合したら取得からやり直す
+        let result = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let mut user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+            slot.apply(&mut user);
+
+            // レスの作成
+            let create = Res::create(
+                &context.ports.object_id_generator,
+                name.as_deref(),
+                text,
+                &user,
+                topic,
+                &context.config.hash,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            );
 
-        // ユーザー、履歴の保存
-        context.ports.user_repo.update(&create.user).await?;
-        context.ports.history_repo.insert(&create.history).await?;
+            // レス、ユーザー、履歴、返信、次スレをまとめて保存
+            let topic_id = &topic.base().id;
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                let created = repos.res_repo.create_within_limit(
+                    &create.res,
+                    context.config.topic_lifecycle.res_limit,
+                ).await?;
+                repos.user_repo.update(&create.user).await?;
+                repos.history_repo.insert(&create.history).await?;
+
+                // アンカーの返信先を記録
+                let linked = usecases::link_res_replies(&created, &repos.res_repo).await?;
+
+                // レス数が上限に達したら次スレを立てる
+                let continued = usecases::continue_topic(
+                    topic_id,
+                    &context.config.topic_lifecycle,
+                    &mut repos.topic_repo,
+                    &repos.res_repo,
+                    &context.ports.object_id_generator,
+                    &context.ports.clock,
+                ).await?;
+                Ok((create, created, linked, continued))
+            })).await
+        }).await;
+        let (create, created, linked, continued) = release_rate_limit_on_error(context, slot, result).await?;
+
+        // 通知と配信はコミットされた書き込みに対してだけ行う
+        for notification in linked.notifications {
+            context.ports.notification_queue.push(notification).await?;
+        }
+        if let Some(continued) = continued {
+            context.ports.topic_event_bus.publish(&TopicEvent::TopicClosed {
+                topic: continued.closed.base().id.clone(),
+            }).await?;
+        }
 
         // ログの出力
         context.ports.logger.info(
@@ -484,30 +868,44 @@ impl Mutation {
             )
         );
 
-        Ok(ResType::from(create.res))
+        Ok(ResType::from(created))
     }
 
-    async fn vote_res(&self, context: &Context, res: ID) -> FieldResult<ResType> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // レスの取得
-        let res = context.ports.res_repo.find_one(&res).await?;
-
-        // レスの投票
-        let vote = res.vote(
-            &user,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        );
+    async fn vote_res(&self, context: &Context, res: ID) -> AtResult<ResType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Vote)?;
+
+        let res = &res;
+        // 同時に投票されて競合したら取得からやり直す
+        let vote = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // レスの取得
+            let res = context.ports.res_repo.find_one(res).await?;
+
+            // レスの投票
+            let vote = res.vote(
+                &user,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            );
 
-        // レスの保存
-        context.ports.res_repo.update(&vote.res).await?;
+            // レス、ユーザーをまとめて保存
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                repos.res_repo.update(&vote.res).await?;
+                repos.user_repo.update(&vote.user).await?;
+                Ok(vote)
+            })).await
+        }).await?;
 
-        // ユーザーの保存
-        context.ports.user_repo.update(&vote.user).await?;
+        // 購読者に投票数が変わったことを知らせる
+        context.ports.topic_event_bus.publish(&TopicEvent::ResVoted {
+            res: vote.res.id.clone(),
+            topic: vote.res.topic_id.clone(),
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -520,24 +918,32 @@ impl Mutation {
         Ok(ResType::from(vote.res))
     }
 
-    async fn del_res(&self, context: &Context, res: ID) -> FieldResult<bool> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // レスの取得
-        let res = context.ports.res_repo.find_one(&res).await?;
-
-        // レスの削除
-        res.del(
-            &user,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        )?;
-
-        // レスの保存
-        context.ports.res_repo.update(&res).await?;
+    async fn del_res(&self, context: &Context, res: ID) -> AtResult<bool> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::PostRes)?;
+
+        let res = &res;
+        // 同時に更新されて競合したら取得からやり直す
+        let res = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // レスの取得
+            let res = context.ports.res_repo.find_one(res).await?;
+
+            // レスの削除
+            res.del(
+                &user,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            )?;
+
+            // レスの保存
+            context.ports.res_repo.update(&res).await?;
+            Ok(res)
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -550,27 +956,41 @@ impl Mutation {
         Ok(true)
     }
 
-    async fn create_profile(&self, context: &Context, name: String, text: String) -> FieldResult<ProfileType> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // プロフィールの作成
-        let create = Profile::create(
-            &context.ports.object_id_generator,
-            &name,
-            &text,
-            &user,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        );
+    async fn create_profile(&self, context: &Context, name: String, text: String) -> AtResult<ProfileType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Profile)?;
+
+        // 入力のバリデーション
+        Validator::new()
+            .str("name", "名前", &name, profile::NAME_RULES)
+            .str("text", "本文", &text, profile::TEXT_RULES)
+            .finish()?;
+
+        let (name, text) = (&name, &text);
+        // 同時にユーザーが更新されて競合したら取得からやり直す
+        let create = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // プロフィールの作成
+            let create = Profile::create(
+                &context.ports.object_id_generator,
+                &name,
+                &text,
+                &user,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            );
 
-        // プロフィールの保存
-        context.ports.profile_repo.insert(&create.profile).await?;
-
-        // ユーザーの保存
-        context.ports.user_repo.update(&create.user).await?;
+            // プロフィールとユーザーをまとめて保存
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                repos.profile_repo.insert(&create.profile).await?;
+                repos.user_repo.update(&create.user).await?;
+                Ok(create)
+            })).await
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -583,26 +1003,40 @@ impl Mutation {
         Ok(ProfileType::from(create.profile))
     }
 
-    async fn update_profile(&self, context: &Context, id: ID, name: String, text: String) -> FieldResult<ProfileType> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // プロフィールの取得
-        let profile = context.ports.profile_repo.find_one(&id).await?;
-
-        // プロフィールの更新
-        let update = profile.update(
-            &user,
-            &name,
-            &text,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        )?;
-
-        // プロフィールの保存
-        context.ports.profile_repo.update(&update.profile).await?;
+    async fn update_profile(&self, context: &Context, id: ID, name: String, text: String) -> AtResult<ProfileType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Profile)?;
+
+        // 入力のバリデーション
+        Validator::new()
+            .str("name", "名前", &name, profile::NAME_RULES)
+            .str("text", "本文", &text, profile::TEXT_RULES)
+            .finish()?;
+
+        let (id, name, text) = (&id, &name, &text);
+        // 同時に編集されて競合したら取得からやり直す
+        let update = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // プロフィールの取得
+            let profile = context.ports.profile_repo.find_one(id).await?;
+
+            // プロフィールの更新
+            let update = profile.update(
+                &user,
+                name,
+                text,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            )?;
+
+            // プロフィールの保存
+            context.ports.profile_repo.update(&update.profile).await?;
+            Ok(update)
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -615,24 +1049,32 @@ impl Mutation {
         Ok(ProfileType::from(update.profile))
     }
 
-    async fn del_profile(&self, context: &Context, id: ID) -> FieldResult<bool> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // プロフィールの取得
-        let profile = context.ports.profile_repo.find_one(&id).await?;
-
-        // プロフィールの削除
-        profile.del(
-            &user,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        )?;
-
-        // プロフィールの保存
-        context.ports.profile_repo.update(&profile).await?;
+    async fn del_profile(&self, context: &Context, id: ID) -> AtResult<bool> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Profile)?;
+
+        let id = &id;
+        // 同時に編集されて競合したら取得からやり直す
+        let profile = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // プロフィールの取得
+            let profile = context.ports.profile_repo.find_one(id).await?;
+
+            // プロフィールの削除
+            profile.del(
+                &user,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            )?;
+
+            // プロフィールの保存
+            context.ports.profile_repo.update(&profile).await?;
+            Ok(profile)
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -645,27 +1087,40 @@ impl Mutation {
         Ok(true)
     }
 
-    async fn create_storage(&self, context: &Context, key: String, value: String) -> FieldResult<StorageType> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // ストレージの作成
-        let create = Storage::create(
-            &context.ports.object_id_generator,
-            &key,
-            &value,
-            &user,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        );
-
-        // ストレージの保存
-        context.ports.storage_repo.insert(&create.storage).await?;
+    async fn create_storage(&self, context: &Context, key: String, value: String) -> AtResult<StorageType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Storage)?;
+
+        // 入力のバリデーション
+        Validator::new()
+            .str("key", "キー", &key, storage::KEY_RULES)
+            .finish()?;
+
+        let (key, value) = (&key, &value);
+        // 同時にユーザーが更新されて競合したら取得からやり直す
+        let create = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // ストレージの作成
+            let create = Storage::create(
+                &context.ports.object_id_generator,
+                &key,
+                &value,
+                &user,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            );
 
-        // ユーザーの保存
-        context.ports.user_repo.update(&create.user).await?;
+            // ストレージとユーザーをまとめて保存
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                repos.storage_repo.insert(&create.storage).await?;
+                repos.user_repo.update(&create.user).await?;
+                Ok(create)
+            })).await
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -678,25 +1133,33 @@ impl Mutation {
         Ok(StorageType::from(create.storage))
     }
 
-    async fn update_storage(&self, context: &Context, id: ID, value: String) -> FieldResult<StorageType> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // ストレージの取得
-        let storage = context.ports.storage_repo.find_one(&id).await?;
-
-        // ストレージの更新
-        let update = storage.update(
-            &user,
-            &value,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        )?;
-
-        // ストレージの保存
-        context.ports.storage_repo.update(&update.storage).await?;
+    async fn update_storage(&self, context: &Context, id: ID, value: String) -> AtResult<StorageType> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Storage)?;
+
+        let (id, value) = (&id, &value);
+        // 同時に編集されて競合したら取得からやり直す
+        let update = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // ストレージの取得
+            let storage = context.ports.storage_repo.find_one(id).await?;
+
+            // ストレージの更新
+            let update = storage.update(
+                &user,
+                value,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            )?;
+
+            // ストレージの保存
+            context.ports.storage_repo.update(&update.storage).await?;
+            Ok(update)
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -709,24 +1172,32 @@ impl Mutation {
         Ok(StorageType::from(update.storage))
     }
 
-    async fn del_storage(&self, context: &Context, id: ID) -> FieldResult<bool> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // ストレージの取得
-        let storage = context.ports.storage_repo.find_one(&id).await?;
-
-        // ストレージの削除
-        storage.del(
-            &user,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        )?;
-
-        // ストレージの保存
-        context.ports.storage_repo.update(&storage).await?;
+    async fn del_storage(&self, context: &Context, id: ID) -> AtResult<bool> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Storage)?;
+
+        let id = &id;
+        // 同時に編集されて競合したら取得からやり直す
+        let storage = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // ストレージの取得
+            let storage = context.ports.storage_repo.find_one(id).await?;
+
+            // ストレージの削除
+            storage.del(
+                &user,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            )?;
+
+            // ストレージの保存
+            context.ports.storage_repo.update(&storage).await?;
+            Ok(storage)
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -739,7 +1210,25 @@ impl Mutation {
         Ok(true)
     }
 
-    async fn set_storages(&self, context: &Context, input: SetStoragesInput) -> FieldResult<SetStoragesPayload> {
+    async fn set_storages(&self, context: &Context, input: SetStoragesInput) -> AtResult<SetStoragesPayload> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Storage)?;
+
+        // 入力のバリデーション
+        input
+            .storages
+            .iter()
+            .enumerate()
+            .fold(Validator::new(), |v, (i, s)| {
+                v.str(&format!("storages[{}].key", i), "キー", &s.key, storage::KEY_RULES)
+            })
+            .finish()?;
+        let storages = input
+            .storages
+            .into_iter()
+            .map(|s| StorageInput { key: normalize(&s.key), value: s.value })
+            .collect::<Vec<_>>();
+
         // ユーザーの取得
         let user = context.ports.user_repo.find_one(
             context.ports.auth_container.get_token().user,
@@ -748,7 +1237,7 @@ impl Mutation {
         // ストレージの設定
         let storages = context.ports.storage_repo.set_storages(
             &user,
-            &input.storages,
+            &storages,
             context.ports.auth_container.get_token(),
             context.ports.clock.now(),
         ).await?;
@@ -768,27 +1257,35 @@ impl Mutation {
         })
     }
 
-    async fn subscribe_topic(&self, context: &Context, topic: ID) -> FieldResult<bool> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // トピックの取得
-        let topic = context.ports.topic_repo.find_one(&topic).await?;
-
-        // トピックの購読
-        let subscribe = topic.subscribe(
-            &user,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        )?;
-
-        // トピックの保存
-        context.ports.topic_repo.update(&subscribe.topic).await?;
-
-        // ユーザーの保存
-        context.ports.user_repo.update(&subscribe.user).await?;
+    async fn subscribe_topic(&self, context: &Context, topic: ID) -> AtResult<bool> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Subscribe)?;
+
+        let topic = &topic;
+        // 同時に更新されて競合したら取得からやり直す
+        let subscribe = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // トピックの取得
+            let topic = context.ports.topic_repo.find_one(topic).await?;
+
+            // トピックの購読
+            let subscribe = topic.subscribe(
+                &user,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            )?;
+
+            // トピック、ユーザーをまとめて保存
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                repos.topic_repo.update(&subscribe.topic).await?;
+                repos.user_repo.update(&subscribe.user).await?;
+                Ok(subscribe)
+            })).await
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -801,27 +1298,35 @@ impl Mutation {
         Ok(true)
     }
 
-    async fn unsubscribe_topic(&self, context: &Context, topic: ID) -> FieldResult<bool> {
-        // ユーザーの取得
-        let user = context.ports.user_repo.find_one(
-            context.ports.auth_container.get_token().user,
-        ).await?;
-
-        // トピックの取得
-        let topic = context.ports.topic_repo.find_one(&topic).await?;
-
-        // トピックの購読解除
-        let unsubscribe = topic.unsubscribe(
-            &user,
-            context.ports.auth_container.get_token(),
-            context.ports.clock.now(),
-        )?;
-
-        // トピックの保存
-        context.ports.topic_repo.update(&unsubscribe.topic).await?;
-
-        // ユーザーの保存
-        context.ports.user_repo.update(&unsubscribe.user).await?;
+    async fn unsubscribe_topic(&self, context: &Context, topic: ID) -> AtResult<bool> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Subscribe)?;
+
+        let topic = &topic;
+        // 同時に更新されて競合したら取得からやり直す
+        let unsubscribe = usecases::retry_on_conflict(|| async move {
+            // ユーザーの取得
+            let user = context.ports.user_repo.find_one(
+                context.ports.auth_container.get_token().user,
+            ).await?;
+
+            // トピックの取得
+            let topic = context.ports.topic_repo.find_one(topic).await?;
+
+            // トピックの購読解除
+            let unsubscribe = topic.unsubscribe(
+                &user,
+                context.ports.auth_container.get_token(),
+                context.ports.clock.now(),
+            )?;
+
+            // トピック、ユーザーをまとめて保存
+            context.ports.unit_of_work.run(|repos| Box::pin(async move {
+                repos.topic_repo.update(&unsubscribe.topic).await?;
+                repos.user_repo.update(&unsubscribe.user).await?;
+                Ok(unsubscribe)
+            })).await
+        }).await?;
 
         // ログの出力
         context.ports.logger.info(
@@ -834,7 +1339,10 @@ impl Mutation {
         Ok(true)
     }
 
-    async fn resister_push_subscription(&self, context: &Context, endpoint: String, p256dh: String, auth: String) -> FieldResult<bool> {
+    async fn resister_push_subscription(&self, context: &Context, endpoint: String, p256dh: String, auth: String) -> AtResult<bool> {
+        // トークンの権限の確認
+        context.ports.auth_container.require_scope(TokenScope::Subscribe)?;
+
         // プッシュ通知の購読登録
         context.ports.push_subscriptions_repo.upsert(
             context.ports.auth_container.get_token().user,
@@ -851,7 +1359,8 @@ impl Mutation {
         input: CreateResInput,
         res_port: &dyn ResPort,
         user_port: &dyn UserPort,
-    ) -> FieldResult<ResType> {
+    ) -> AtResult<ResType> {
+        input.validate()?;
         let res = res_port.create(input).await?;
         Ok(ResType::from(res))
     }
@@ -862,7 +1371,7 @@ impl Mutation {
         vote_type: VoteType,
         res_port: &dyn ResPort,
         user_port: &dyn UserPort,
-    ) -> FieldResult<ResType> {
+    ) -> AtResult<ResType> {
         let res = res_port.vote(res_id, vote_type).await?;
         Ok(ResType::from(res))
     }
@@ -872,7 +1381,7 @@ impl Mutation {
         res_id: String,
         res_port: &dyn ResPort,
         user_port: &dyn UserPort,
-    ) -> FieldResult<ResType> {
+    ) -> AtResult<ResType> {
         let res = res_port.delete(res_id).await?;
         Ok(ResType::from(res))
     }
@@ -882,7 +1391,8 @@ impl Mutation {
         input: CreateTopicNormalInput,
         topic_port: &dyn TopicPort,
         user_port: &dyn UserPort,
-    ) -> FieldResult<TopicType> {
+    ) -> AtResult<TopicType> {
+        input.validate()?;
         let topic = topic_port.create_normal(input).await?;
         Ok(TopicType::from(topic))
     }
@@ -892,7 +1402,8 @@ impl Mutation {
         input: CreateTopicOneInput,
         topic_port: &dyn TopicPort,
         user_port: &dyn UserPort,
-    ) -> FieldResult<TopicType> {
+    ) -> AtResult<TopicType> {
+        input.validate()?;
         let topic = topic_port.create_one(input).await?;
         Ok(TopicType::from(topic))
     }
@@ -902,7 +1413,8 @@ impl Mutation {
         input: CreateTopicForkInput,
         topic_port: &dyn TopicPort,
         user_port: &dyn UserPort,
-    ) -> FieldResult<TopicType> {
+    ) -> AtResult<TopicType> {
+        input.validate()?;
         let topic = topic_port.create_fork(input).await?;
         Ok(TopicType::from(topic))
     }
@@ -912,7 +1424,8 @@ impl Mutation {
         input: UpdateTopicInput,
         topic_port: &dyn TopicPort,
         user_port: &dyn UserPort,
-    ) -> FieldResult<TopicType> {
+    ) -> AtResult<TopicType> {
+        input.validate()?;
         let topic = topic_port.update(input).await?;
         Ok(TopicType::from(topic))
     }
@@ -922,7 +1435,7 @@ impl Mutation {
         topic_id: String,
         topic_port: &dyn TopicPort,
         user_port: &dyn UserPort,
-    ) -> FieldResult<bool> {
+    ) -> AtResult<bool> {
         topic_port.subscribe(topic_id).await?;
         Ok(true)
     }
@@ -932,7 +1445,7 @@ impl Mutation {
         topic_id: String,
         topic_port: &dyn TopicPort,
         user_port: &dyn UserPort,
-    ) -> FieldResult<bool> {
+    ) -> AtResult<bool> {
         topic_port.unsubscribe(topic_id).await?;
         Ok(true)
     }
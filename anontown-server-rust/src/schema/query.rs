@@ -1,16 +1,22 @@
-use juniper::{graphql_object, FieldResult, ID};
+use juniper::{graphql_object, ID};
 
+use crate::at_error::AtResult;
 use crate::schema::types::{
     ClientType, HistoryType, ProfileType, ResType, StorageType, TopicType, UserType, ToSchemaType,
+    ResSearchHitType, SearchSnippetType, TopicSearchHitType, ReplyTreeNodeType, SessionType,
 };
 use crate::schema::input::{ResQuery, TopicQuery};
+use crate::ports::res::ResSearchQuery;
 use crate::schema::context::Context;
+use crate::usecases::get_reply_tree::{self, get_reply_tree};
+use crate::usecases::list_sessions;
+use crate::validation::{normalize, Validate};
 
 pub struct Query;
 
 #[graphql_object]
 impl Query {
-    async fn user(&self, context: &Context, id: Option<ID>) -> FieldResult<UserType> {
+    async fn user(&self, context: &Context, id: Option<ID>) -> AtResult<UserType> {
         let user_id = match id {
             Some(id) => id,
             None => context.ports.auth_container.get_token().user,
@@ -19,44 +25,52 @@ impl Query {
         Ok(user.to_schema_type(&context.ports.auth_container))
     }
 
-    async fn user_id(&self, sn: String, context: &Context) -> FieldResult<ID> {
+    async fn user_id(&self, sn: String, context: &Context) -> AtResult<ID> {
         let id = context.ports.user_repo.find_id(&sn).await?;
         Ok(id)
     }
 
-    async fn user_sn(&self, id: ID, context: &Context) -> FieldResult<String> {
+    async fn user_sn(&self, id: ID, context: &Context) -> AtResult<String> {
         let user = context.ports.user_repo.find_one(&id).await?;
         Ok(user.sn)
     }
 
-    async fn users(&self, context: &Context) -> FieldResult<Vec<UserType>> {
+    // 全てのユーザーを列挙できるのは管理者だけ
+    async fn users(&self, context: &Context) -> AtResult<Vec<UserType>> {
+        context.ports.auth_container.require_admin()?;
         let users = context.ports.user_repo.find_all().await?;
         Ok(users.into_iter().map(|u| u.to_schema_type(&context.ports.auth_container)).collect())
     }
 
-    async fn client(&self, id: ID, context: &Context) -> FieldResult<ClientType> {
+    async fn client(&self, id: ID, context: &Context) -> AtResult<ClientType> {
         let client = context.ports.client_repo.find_one(&id).await?;
         Ok(client.to_schema_type(&context.ports.auth_container))
     }
 
-    async fn clients(&self, context: &Context) -> FieldResult<Vec<ClientType>> {
+    async fn clients(&self, context: &Context) -> AtResult<Vec<ClientType>> {
         let clients = context.ports.client_repo.find_all().await?;
         Ok(clients.into_iter().map(|c| c.to_schema_type(&context.ports.auth_container)).collect())
     }
 
-    async fn token(&self, context: &Context) -> FieldResult<TokenType> {
+    async fn token(&self, context: &Context) -> AtResult<TokenType> {
         let token = context.ports.token_repo.find_one(
             context.ports.auth_container.get_token().id,
         ).await?;
         Ok(token.to_schema_type(&context.ports.auth_container))
     }
 
-    async fn tokens(&self, context: &Context) -> FieldResult<Vec<TokenType>> {
+    async fn tokens(&self, context: &Context) -> AtResult<Vec<TokenType>> {
         let tokens = context.ports.token_repo.find_all().await?;
         Ok(tokens.into_iter().map(|t| t.to_schema_type(&context.ports.auth_container)).collect())
     }
 
-    async fn topic(&self, id: ID, context: &Context) -> FieldResult<TopicType> {
+    async fn sessions(&self, context: &Context) -> AtResult<Vec<SessionType>> {
+        let auth = context.ports.auth_container.get_token_master()?;
+        let sessions = list_sessions(&context.ports.token_repo, auth, &context.ports.clock).await?;
+        Ok(sessions.into_iter().map(|t| SessionType::new(t, &auth.base.id)).collect())
+    }
+
+    async fn topic(&self, id: ID, context: &Context) -> AtResult<TopicType> {
         let topic = context.ports.topic_repo.find_one(&id).await?;
         Ok(topic.to_schema_type(&context.ports.auth_container))
     }
@@ -67,21 +81,41 @@ impl Query {
         skip: i32,
         limit: i32,
         context: &Context,
-    ) -> FieldResult<Vec<TopicType>> {
+    ) -> AtResult<Vec<TopicType>> {
+        query.validate()?;
         let topics = context.ports.topic_repo.find(query, skip, limit).await?;
         Ok(topics.into_iter().map(|t| t.to_schema_type(&context.ports.auth_container)).collect())
     }
 
+    async fn search_topics(
+        &self,
+        query: TopicQuery,
+        skip: i32,
+        limit: i32,
+        context: &Context,
+    ) -> AtResult<Vec<TopicSearchHitType>> {
+        query.validate()?;
+        let hits = context.ports.topic_repo.search(query, skip, limit).await?;
+        Ok(hits
+            .into_iter()
+            .map(|hit| TopicSearchHitType {
+                topic: hit.item.to_schema_type(&context.ports.auth_container),
+                score: hit.score,
+                snippet: SearchSnippetType::from(hit.snippet),
+            })
+            .collect())
+    }
+
     async fn topic_tags(
         &self,
         limit: i32,
         context: &Context,
-    ) -> FieldResult<Vec<TagType>> {
+    ) -> AtResult<Vec<TagType>> {
         let tags = context.ports.topic_repo.find_tags(limit).await?;
         Ok(tags.into_iter().map(|(name, count)| TagType { name, count }).collect())
     }
 
-    async fn res(&self, id: ID, context: &Context) -> FieldResult<ResType> {
+    async fn res(&self, id: ID, context: &Context) -> AtResult<ResType> {
         let res = context.ports.res_repo.find_one(&id).await?;
         Ok(res.to_schema_type(&context.ports.auth_container))
     }
@@ -91,12 +125,78 @@ impl Query {
         query: ResQuery,
         limit: i32,
         context: &Context,
-    ) -> FieldResult<Vec<ResType>> {
+    ) -> AtResult<Vec<ResType>> {
+        query.validate()?;
         let reses = context.ports.res_repo.find(query, limit).await?;
         Ok(reses.into_iter().map(|r| r.to_schema_type(&context.ports.auth_container)).collect())
     }
 
-    async fn history(&self, id: ID, context: &Context) -> FieldResult<HistoryType> {
+    async fn reses_by_hash(
+        &self,
+        topic: ID,
+        hash: String,
+        context: &Context,
+    ) -> AtResult<Vec<ResType>> {
+        let reses = context.ports.res_repo.find_by_topic_hash(&topic, &hash).await?;
+        Ok(reses.into_iter().map(|r| r.to_schema_type(&context.ports.auth_container)).collect())
+    }
+
+    async fn reses_by_number(
+        &self,
+        topic: ID,
+        from: i32,
+        to: i32,
+        context: &Context,
+    ) -> AtResult<Vec<ResType>> {
+        let reses = context.ports.res_repo.find_by_number_range(&topic, from, to).await?;
+        Ok(reses.into_iter().map(|r| r.to_schema_type(&context.ports.auth_container)).collect())
+    }
+
+    async fn reply_tree(
+        &self,
+        res: ID,
+        depth: Option<i32>,
+        context: &Context,
+    ) -> AtResult<Vec<ReplyTreeNodeType>> {
+        let nodes = get_reply_tree(
+            &res,
+            depth.unwrap_or(get_reply_tree::MAX_REPLY_TREE_DEPTH),
+            &context.ports.res_repo,
+        )
+        .await?;
+        Ok(nodes
+            .into_iter()
+            .map(|node| ReplyTreeNodeType {
+                res: node.res.to_schema_type(&context.ports.auth_container),
+                parent: node.parent.map(ID::new),
+                depth: node.depth,
+            })
+            .collect())
+    }
+
+    async fn search_reses(
+        &self,
+        text: String,
+        topic: Option<ID>,
+        limit: i32,
+        context: &Context,
+    ) -> AtResult<Vec<ResSearchHitType>> {
+        let query = ResSearchQuery {
+            text,
+            topic: topic.map(|topic| topic.to_string()),
+        };
+        let hits = context.ports.res_repo.search(&query, limit).await?;
+        Ok(hits
+            .into_iter()
+            .map(|hit| ResSearchHitType {
+                res: hit.item.to_schema_type(&context.ports.auth_container),
+                score: hit.score,
+                snippet: SearchSnippetType::from(hit.snippet),
+            })
+            .collect())
+    }
+
+    async fn history(&self, id: ID, context: &Context) -> AtResult<HistoryType> {
         let history = context.ports.history_repo.find_one(&id).await?;
         Ok(history.to_schema_type(&context.ports.auth_container))
     }
@@ -106,27 +206,28 @@ impl Query {
         query: HistoryQuery,
         limit: i32,
         context: &Context,
-    ) -> FieldResult<Vec<HistoryType>> {
+    ) -> AtResult<Vec<HistoryType>> {
         let histories = context.ports.history_repo.find(query, limit).await?;
         Ok(histories.into_iter().map(|h| h.to_schema_type(&context.ports.auth_container)).collect())
     }
 
-    async fn profile(&self, id: ID, context: &Context) -> FieldResult<ProfileType> {
+    async fn profile(&self, id: ID, context: &Context) -> AtResult<ProfileType> {
         let profile = context.ports.profile_repo.find_one(&id).await?;
         Ok(profile.to_schema_type(&context.ports.auth_container))
     }
 
-    async fn profiles(&self, context: &Context) -> FieldResult<Vec<ProfileType>> {
+    async fn profiles(&self, context: &Context) -> AtResult<Vec<ProfileType>> {
         let profiles = context.ports.profile_repo.find_all().await?;
         Ok(profiles.into_iter().map(|p| p.to_schema_type(&context.ports.auth_container)).collect())
     }
 
-    async fn storage(&self, key: String, context: &Context) -> FieldResult<StorageType> {
-        let storage = context.ports.storage_repo.find_one(&key).await?;
+    async fn storage(&self, key: String, context: &Context) -> AtResult<StorageType> {
+        // キーは正規化して保存している
+        let storage = context.ports.storage_repo.find_one(&normalize(&key)).await?;
         Ok(storage.to_schema_type(&context.ports.auth_container))
     }
 
-    async fn storages(&self, context: &Context) -> FieldResult<Vec<StorageType>> {
+    async fn storages(&self, context: &Context) -> AtResult<Vec<StorageType>> {
         let storages = context.ports.storage_repo.find_all().await?;
         Ok(storages.into_iter().map(|s| s.to_schema_type(&context.ports.auth_container)).collect())
     }
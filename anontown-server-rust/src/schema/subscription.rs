@@ -1,20 +1,29 @@
-use juniper::{FieldResult, GraphQLObject};
-use crate::schema::types::{ResType, ResSubscript};
+use futures::{Stream, StreamExt};
+use juniper::{graphql_subscription, FieldResult, IntoFieldError, ID};
+use std::pin::Pin;
+
+use crate::at_error::{AtError, AtResult};
+use crate::schema::context::Context;
+use crate::schema::types::{ResSubscript, ResType};
 use crate::ports::ResPort;
 
+type ResSubscriptStream = Pin<Box<dyn Stream<Item = FieldResult<ResSubscript>> + Send>>;
+
 pub struct Subscription;
 
-#[juniper::graphql_subscription]
+#[graphql_subscription(context = Context)]
 impl Subscription {
-    pub async fn res_added(
-        &self,
-        topic_id: String,
-        res_port: &dyn ResPort,
-    ) -> FieldResult<ResSubscript> {
-        let (res, count) = res_port.subscribe(topic_id).await?;
-        Ok(ResSubscript {
-            res: ResType::from(res),
-            count,
-        })
+    /// トピックに書き込まれたレスを、書き込み後のレス数と一緒に流す
+    async fn res_added(context: &Context, topic: ID) -> AtResult<ResSubscriptStream> {
+        let stream = context.ports.res_repo.subscribe_insert_event(&topic);
+        Ok(Box::pin(stream.map(|event| {
+            let (res, count) = event.map_err(|e| {
+                AtError::Internal(anyhow::anyhow!(e.to_string())).into_field_error()
+            })?;
+            Ok(ResSubscript {
+                res: ResType::from(res),
+                count: count as i32,
+            })
+        })))
     }
-} 
\ No newline at end of file
+}
@@ -2,16 +2,34 @@ use juniper::{GraphQLInputObject, GraphQLObject, ID, GraphQLEnum, GraphQLUnion};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::entities::{client::Client, token::Token, user::User, topic::Topic, res::Res};
+use crate::entities::{client::Client, token::Token, user::User, topic::Topic, res::{Res, TopicLink}};
+use crate::entities::search_query::SearchSnippet;
 use crate::schema::scalar::DateTimeScalar;
 use crate::ports::AuthContainer;
+use crate::auth::TokenScope;
+use crate::schema::visibility::{FieldGuard, Visibility};
 
+// 公開範囲はToSchemaTypeで決める。見られないフィールドはnullになる
+// パスワードのハッシュは誰にも返さない
 #[derive(GraphQLObject)]
 pub struct UserType {
     pub id: String,
     pub sn: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub lv: Option<i32>,
+    pub point: Option<i32>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub res_last_created_at: Option<DateTime<Utc>>,
+    pub topic_last_created_at: Option<DateTime<Utc>>,
+    pub one_topic_last_created_at: Option<DateTime<Utc>>,
+    pub count_created_res_m10: Option<i32>,
+    pub count_created_res_m30: Option<i32>,
+    pub count_created_res_h1: Option<i32>,
+    pub count_created_res_h6: Option<i32>,
+    pub count_created_res_h12: Option<i32>,
+    pub count_created_res_d1: Option<i32>,
 }
 
 #[derive(GraphQLInputObject)]
@@ -20,6 +38,15 @@ pub struct CreateUserInput {
     pub pass: String,
 }
 
+// 回復コードでパスワードを再設定する。passは新しいパスワード
+#[derive(GraphQLInputObject)]
+pub struct RecoverAccountInput {
+    pub sn: String,
+    pub code: String,
+    pub pass: String,
+    pub recaptcha: String,
+}
+
 #[derive(GraphQLInputObject)]
 pub struct UpdateUserInput {
     pub id: String,
@@ -55,18 +82,116 @@ pub struct TokenType {
     pub id: String,
     pub key: String,
     pub date: DateTime<Utc>,
+    // マスタートークンは全ての操作ができるので空
+    pub scopes: Vec<TokenScopeEnum>,
 }
 
+// ログイン中のセッション。同じファミリーのトークンは一つのセッションになる
 #[derive(GraphQLObject)]
-pub struct TokenReq {
-    pub token: String,
+pub struct SessionType {
+    pub id: String,
+    // マスタートークンではnull
+    pub client: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    // このリクエストで使っているセッションか
+    pub current: bool,
+}
+
+impl SessionType {
+    pub fn new(token: Token, current_token_id: &str) -> Self {
+        Self {
+            current: token.id == current_token_id,
+            client: (!token.is_master()).then_some(token.client_id),
+            id: token.id,
+            ip: token.device.ip,
+            user_agent: token.device.user_agent,
+            created_at: token.created_at,
+            last_used_at: token.last_used_at,
+        }
+    }
+}
+
+// 端末の引き継ぎコード。codeを画面に表示し、qrPayloadをQRコードにする
+#[derive(GraphQLObject)]
+pub struct PairingCodeType {
+    pub code: String,
+    pub qr_payload: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+// 登録した二要素認証の秘密鍵。provisioningUriをQRコードにし、読み取れない場合はsecretを手で入力する
+#[derive(GraphQLObject)]
+pub struct TotpEnrollmentType {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+// 更新後のトークン。keyとrefresh_tokenはどちらも`{id},{key}`の形式で使う
+#[derive(GraphQLObject)]
+pub struct TokenRefreshResponse {
+    pub id: String,
     pub key: String,
+    pub refresh_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl From<Token> for TokenRefreshResponse {
+    fn from(token: Token) -> Self {
+        Self {
+            id: token.id,
+            key: token.access_token,
+            refresh_token: token.refresh_token,
+            expires_at: token.expires_at,
+        }
+    }
+}
+
+#[derive(GraphQLEnum)]
+pub enum TokenScopeEnum {
+    Read,
+    PostRes,
+    CreateTopic,
+    Vote,
+    Storage,
+    Profile,
+    Subscribe,
+}
+
+impl From<TokenScope> for TokenScopeEnum {
+    fn from(scope: TokenScope) -> Self {
+        match scope {
+            TokenScope::Read => TokenScopeEnum::Read,
+            TokenScope::PostRes => TokenScopeEnum::PostRes,
+            TokenScope::CreateTopic => TokenScopeEnum::CreateTopic,
+            TokenScope::Vote => TokenScopeEnum::Vote,
+            TokenScope::Storage => TokenScopeEnum::Storage,
+            TokenScope::Profile => TokenScopeEnum::Profile,
+            TokenScope::Subscribe => TokenScopeEnum::Subscribe,
+        }
+    }
+}
+
+impl From<TokenScopeEnum> for TokenScope {
+    fn from(scope: TokenScopeEnum) -> Self {
+        match scope {
+            TokenScopeEnum::Read => TokenScope::Read,
+            TokenScopeEnum::PostRes => TokenScope::PostRes,
+            TokenScopeEnum::CreateTopic => TokenScope::CreateTopic,
+            TokenScopeEnum::Vote => TokenScope::Vote,
+            TokenScopeEnum::Storage => TokenScope::Storage,
+            TokenScopeEnum::Profile => TokenScope::Profile,
+            TokenScopeEnum::Subscribe => TokenScope::Subscribe,
+        }
+    }
 }
 
 #[derive(GraphQLObject)]
-pub struct CreateTokenGeneralResponse {
-    pub token: TokenType,
-    pub req: TokenReq,
+pub struct TokenReq {
+    pub token: String,
+    pub key: String,
 }
 
 #[derive(GraphQLObject)]
@@ -116,6 +241,7 @@ pub struct TopicForkType {
 pub struct ResBaseType {
     pub id: ID,
     pub topic: TopicType,
+    pub number: i32,
     pub date: DateTimeScalar,
     pub self_: Option<bool>,
     pub uv: i32,
@@ -147,6 +273,8 @@ pub struct ResHistoryType {
 pub struct ResTopicType {
     #[graphql(flatten)]
     pub base: ResBaseType,
+    pub next_topic_id: Option<ID>,
+    pub prev_topic_id: Option<ID>,
 }
 
 #[derive(GraphQLObject)]
@@ -217,6 +345,39 @@ pub struct ResSubscript {
     pub count: i32,
 }
 
+#[derive(GraphQLObject)]
+pub struct SearchHighlightType {
+    pub start: i32,
+    pub end: i32,
+}
+
+#[derive(GraphQLObject)]
+pub struct SearchSnippetType {
+    pub text: String,
+    pub highlights: Vec<SearchHighlightType>,
+}
+
+#[derive(GraphQLObject)]
+pub struct TopicSearchHitType {
+    pub topic: TopicType,
+    pub score: f64,
+    pub snippet: SearchSnippetType,
+}
+
+#[derive(GraphQLObject)]
+pub struct ResSearchHitType {
+    pub res: ResType,
+    pub score: f64,
+    pub snippet: SearchSnippetType,
+}
+
+#[derive(GraphQLObject)]
+pub struct ReplyTreeNodeType {
+    pub res: ResType,
+    pub parent: Option<ID>,
+    pub depth: i32,
+}
+
 #[derive(GraphQLObject)]
 pub struct ProfileType {
     pub id: String,
@@ -288,15 +449,23 @@ pub enum ResDeleteFlag {
 pub struct AuthUser {
     pub id: String,
     pub pass: String,
+    // 二要素認証が有効なユーザーだけ必要。認証アプリのコードかバックアップコード
+    pub totp: Option<String>,
 }
 
-impl From<User> for UserType {
-    fn from(user: User) -> Self {
+// 認証情報が無いので公開するフィールドだけを返す
+impl From<SearchSnippet> for SearchSnippetType {
+    fn from(snippet: SearchSnippet) -> Self {
         Self {
-            id: user.id,
-            sn: user.sn,
-            created_at: user.created_at,
-            updated_at: user.updated_at,
+            text: snippet.text,
+            highlights: snippet
+                .highlights
+                .into_iter()
+                .map(|h| SearchHighlightType {
+                    start: h.start as i32,
+                    end: h.end as i32,
+                })
+                .collect(),
         }
     }
 }
@@ -318,8 +487,9 @@ impl From<Token> for TokenType {
     fn from(token: Token) -> Self {
         Self {
             id: token.id,
-            key: token.key,
-            date: token.date,
+            key: token.access_token,
+            date: token.created_at,
+            scopes: token.scopes.into_iter().map(TokenScopeEnum::from).collect(),
         }
     }
 }
@@ -390,6 +560,7 @@ impl From<Res> for ResType {
                 base: ResBaseType {
                     id: ID::new(&base.id),
                     topic: TopicType::from(normal.topic),
+                    number: base.number,
                     date: DateTimeScalar::new(base.created_at),
                     self_: None,
                     uv: base.uv,
@@ -408,6 +579,7 @@ impl From<Res> for ResType {
                 base: ResBaseType {
                     id: ID::new(&base.id),
                     topic: TopicType::from(history.topic),
+                    number: base.number,
                     date: DateTimeScalar::new(base.created_at),
                     self_: None,
                     uv: base.uv,
@@ -429,6 +601,7 @@ impl From<Res> for ResType {
                 base: ResBaseType {
                     id: ID::new(&base.id),
                     topic: TopicType::from(topic.topic),
+                    number: base.number,
                     date: DateTimeScalar::new(base.created_at),
                     self_: None,
                     uv: base.uv,
@@ -437,11 +610,20 @@ impl From<Res> for ResType {
                     reply_count: base.reply_count,
                     vote_flag: base.vote_flag,
                 },
+                next_topic_id: match &topic.link {
+                    Some(TopicLink::Next(id)) => Some(ID::new(id)),
+                    _ => None,
+                },
+                prev_topic_id: match &topic.link {
+                    Some(TopicLink::Prev(id)) => Some(ID::new(id)),
+                    _ => None,
+                },
             }),
             Res::Fork(fork) => ResType::Fork(ResForkType {
                 base: ResBaseType {
                     id: ID::new(&base.id),
                     topic: TopicType::from(fork.topic),
+                    number: base.number,
                     date: DateTimeScalar::new(base.created_at),
                     self_: None,
                     uv: base.uv,
@@ -479,6 +661,7 @@ impl From<Res> for ResType {
                 base: ResBaseType {
                     id: ID::new(&base.id),
                     topic: TopicType::from(delete.topic),
+                    number: base.number,
                     date: DateTimeScalar::new(base.created_at),
                     self_: None,
                     uv: base.uv,
@@ -517,12 +700,29 @@ impl ToSchemaType for Client {
 impl ToSchemaType for User {
     type SchemaType = UserType;
 
-    fn to_schema_type(&self, _auth_container: &AuthContainer) -> Self::SchemaType {
+    fn to_schema_type(&self, auth_container: &AuthContainer) -> Self::SchemaType {
+        use Visibility::{AdminOnly, SelfOnly};
+
+        let guard = FieldGuard::new(&self.id, auth_container);
         UserType {
             id: self.id.clone(),
-            sn: self.sn.clone(),
+            sn: self.screen_name.clone(),
             created_at: self.created_at,
             updated_at: self.updated_at,
+            lv: guard.field(SelfOnly, self.lv),
+            point: guard.field(SelfOnly, self.point),
+            name: guard.field(SelfOnly, self.name.clone()),
+            email: guard.field(SelfOnly, self.email.clone()),
+            res_last_created_at: guard.field(SelfOnly, self.res_last_created_at),
+            topic_last_created_at: guard.field(SelfOnly, self.topic_last_created_at),
+            one_topic_last_created_at: guard.field(SelfOnly, self.one_topic_last_created_at),
+            // 連投制限のための件数なので、本人にも見せない
+            count_created_res_m10: guard.field(AdminOnly, self.count_created_res_m10),
+            count_created_res_m30: guard.field(AdminOnly, self.count_created_res_m30),
+            count_created_res_h1: guard.field(AdminOnly, self.count_created_res_h1),
+            count_created_res_h6: guard.field(AdminOnly, self.count_created_res_h6),
+            count_created_res_h12: guard.field(AdminOnly, self.count_created_res_h12),
+            count_created_res_d1: guard.field(AdminOnly, self.count_created_res_d1),
         }
     }
 }
@@ -535,6 +735,7 @@ impl ToSchemaType for Token {
             id: self.id.clone(),
             key: self.key.clone(),
             date: self.date,
+            scopes: self.scopes.iter().copied().map(TokenScopeEnum::from).collect(),
         }
     }
 }
@@ -616,6 +817,7 @@ impl ToSchemaType for Res {
                 base: ResBaseType {
                     id: ID::new(&base.id),
                     topic: TopicType::from(normal.topic.clone()),
+                    number: base.number,
                     date: DateTimeScalar::new(base.created_at),
                     self_,
                     uv: base.uv,
@@ -634,6 +836,7 @@ impl ToSchemaType for Res {
                 base: ResBaseType {
                     id: ID::new(&base.id),
                     topic: TopicType::from(history.topic.clone()),
+                    number: base.number,
                     date: DateTimeScalar::new(base.created_at),
                     self_,
                     uv: base.uv,
@@ -655,6 +858,7 @@ impl ToSchemaType for Res {
                 base: ResBaseType {
                     id: ID::new(&base.id),
                     topic: TopicType::from(topic.topic.clone()),
+                    number: base.number,
                     date: DateTimeScalar::new(base.created_at),
                     self_,
                     uv: base.uv,
@@ -663,11 +867,20 @@ impl ToSchemaType for Res {
                     reply_count: base.reply_count,
                     vote_flag: base.vote_flag,
                 },
+                next_topic_id: match &topic.link {
+                    Some(TopicLink::Next(id)) => Some(ID::new(id)),
+                    _ => None,
+                },
+                prev_topic_id: match &topic.link {
+                    Some(TopicLink::Prev(id)) => Some(ID::new(id)),
+                    _ => None,
+                },
             }),
             Res::Fork(fork) => ResType::Fork(ResForkType {
                 base: ResBaseType {
                     id: ID::new(&base.id),
                     topic: TopicType::from(fork.topic.clone()),
+                    number: base.number,
                     date: DateTimeScalar::new(base.created_at),
                     self_,
                     uv: base.uv,
@@ -705,6 +918,7 @@ impl ToSchemaType for Res {
                 base: ResBaseType {
                     id: ID::new(&base.id),
                     topic: TopicType::from(delete.topic.clone()),
+                    number: base.number,
                     date: DateTimeScalar::new(base.created_at),
                     self_,
                     uv: base.uv,
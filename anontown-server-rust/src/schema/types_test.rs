@@ -1,6 +1,9 @@
 use chrono::{DateTime, Utc};
 use crate::schema::types::{ToSchemaType, ClientType, UserType, TokenType, TopicType, ResType, HistoryType, ProfileType, StorageType};
 use crate::ports::AuthContainer;
+use crate::adapters::AuthContainerImpl;
+use crate::adapters::seq_id_generator::SeqIdGenerator;
+use crate::auth::{AuthToken, AuthTokenBase, AuthTokenMaster};
 use crate::entities::{client::Client, user::User, token::Token, topic::Topic, res::Res, history::History, profile::Profile, storage::Storage};
 
 #[test]
@@ -36,22 +39,57 @@ fn test_client_to_schema_type() {
     assert_eq!(schema_type.self_, None);
 }
 
+fn master_token(user: &str) -> Option<AuthToken> {
+    Some(AuthToken::Master(AuthTokenMaster {
+        base: AuthTokenBase {
+            id: "token".to_string(),
+            key: "key".to_string(),
+            user: user.to_string(),
+        },
+    }))
+}
+
 #[test]
 fn test_user_to_schema_type() {
-    let now = Utc::now();
-    let user = User {
-        id: "test_user".to_string(),
-        sn: "test_sn".to_string(),
-        created_at: now,
-        updated_at: now,
-    };
+    let user = User::create(
+        &SeqIdGenerator::new(),
+        "test_sn".to_string(),
+        "name".to_string(),
+        "email".to_string(),
+        "hash".to_string(),
+    );
 
-    let auth_container = AuthContainer::new();
-    let schema_type = user.to_schema_type(&auth_container);
-    assert_eq!(schema_type.id, "test_user");
+    // 他のユーザーには公開されたフィールドだけ
+    let schema_type = user.to_schema_type(&AuthContainerImpl::with_token(master_token("other")));
+    assert_eq!(schema_type.id, user.id);
     assert_eq!(schema_type.sn, "test_sn");
-    assert_eq!(schema_type.created_at, now);
-    assert_eq!(schema_type.updated_at, now);
+    assert_eq!(schema_type.created_at, user.created_at);
+    assert_eq!(schema_type.updated_at, user.updated_at);
+    assert_eq!(schema_type.lv, None);
+    assert_eq!(schema_type.email, None);
+    assert_eq!(schema_type.count_created_res_m10, None);
+
+    // 本人には連投制限の件数以外
+    let schema_type = user.to_schema_type(&AuthContainerImpl::with_token(master_token(&user.id)));
+    assert_eq!(schema_type.lv, Some(1));
+    assert_eq!(schema_type.point, Some(0));
+    assert_eq!(schema_type.email, Some("email".to_string()));
+    assert_eq!(schema_type.res_last_created_at, Some(user.res_last_created_at));
+    assert_eq!(schema_type.count_created_res_m10, None);
+
+    // 管理者には全て
+    let admins = vec!["admin".to_string()];
+    let schema_type = user.to_schema_type(
+        &AuthContainerImpl::with_token(master_token("admin")).with_admins(&admins),
+    );
+    assert_eq!(schema_type.email, Some("email".to_string()));
+    assert_eq!(schema_type.count_created_res_m10, Some(0));
+    assert_eq!(schema_type.count_created_res_d1, Some(0));
+
+    // 未認証では公開されたフィールドだけ
+    let schema_type = user.to_schema_type(&AuthContainerImpl::new());
+    assert_eq!(schema_type.sn, "test_sn");
+    assert_eq!(schema_type.email, None);
 }
 
 #[test]
@@ -84,6 +122,7 @@ fn test_topic_to_schema_type() {
             res_count: 0,
             last_res_at: now,
             is_closed: false,
+            version: 0,
             tags: vec!["test".to_string()],
         },
     });
@@ -123,6 +162,7 @@ fn test_res_to_schema_type() {
             res_count: 0,
             last_res_at: now,
             is_closed: false,
+            version: 0,
             tags: vec!["test".to_string()],
         },
     });
@@ -138,6 +178,7 @@ fn test_res_to_schema_type() {
             dv: 0,
             hash: "test_hash".to_string(),
             reply_count: 0,
+            version: 0,
             vote_flag: None,
         },
         name: Some("Test Name".to_string()),
@@ -177,6 +218,7 @@ fn test_history_to_schema_type() {
             res_count: 0,
             last_res_at: now,
             is_closed: false,
+            version: 0,
             tags: vec!["test".to_string()],
         },
     });
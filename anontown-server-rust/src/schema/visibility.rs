@@ -0,0 +1,120 @@
+//! GraphQLで返すフィールドごとの公開範囲
+//!
+//! `ToSchemaType`で見せられないフィールドは`None`にする。エラーにしないのは、
+//! 他のユーザーを含む一覧でも見せられるフィールドだけは返せるようにするため
+
+use crate::ports::AuthContainer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    // 誰でも見られる
+    Public,
+    // 持ち主のマスタートークンか管理者だけが見られる
+    SelfOnly,
+    // 管理者だけが見られる
+    AdminOnly,
+}
+
+/// 1つのエンティティのフィールドの公開範囲を判定する
+pub struct FieldGuard<'a> {
+    owner_id: &'a str,
+    auth_container: &'a dyn AuthContainer,
+}
+
+impl<'a> FieldGuard<'a> {
+    pub fn new(owner_id: &'a str, auth_container: &'a dyn AuthContainer) -> Self {
+        Self {
+            owner_id,
+            auth_container,
+        }
+    }
+
+    pub fn can_view(&self, visibility: Visibility) -> bool {
+        match visibility {
+            Visibility::Public => true,
+            // 一般トークンは連携しているクライアントのものなので、本人とはみなさない
+            Visibility::SelfOnly => {
+                self.auth_container.is_admin()
+                    || self
+                        .auth_container
+                        .get_token_master_or_null()
+                        .is_some_and(|token| token.base.user == self.owner_id)
+            }
+            Visibility::AdminOnly => self.auth_container.is_admin(),
+        }
+    }
+
+    /// 見られるフィールドなら`value`を返す
+    pub fn field<T>(&self, visibility: Visibility, value: T) -> Option<T> {
+        self.can_view(visibility).then_some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::AuthContainerImpl;
+    use crate::auth::{AuthToken, AuthTokenBase, AuthTokenGeneral, AuthTokenMaster};
+
+    fn base(user: &str) -> AuthTokenBase {
+        AuthTokenBase {
+            id: "token".to_string(),
+            key: "key".to_string(),
+            user: user.to_string(),
+        }
+    }
+
+    fn master(user: &str) -> Option<AuthToken> {
+        Some(AuthToken::Master(AuthTokenMaster { base: base(user) }))
+    }
+
+    fn can_view(auth_container: &AuthContainerImpl) -> [bool; 3] {
+        let guard = FieldGuard::new("user", auth_container);
+        [
+            guard.can_view(Visibility::Public),
+            guard.can_view(Visibility::SelfOnly),
+            guard.can_view(Visibility::AdminOnly),
+        ]
+    }
+
+    #[test]
+    fn test_can_view() {
+        let admins = vec!["admin".to_string()];
+
+        assert_eq!(can_view(&AuthContainerImpl::new()), [true, false, false]);
+        assert_eq!(
+            can_view(&AuthContainerImpl::with_token(master("other"))),
+            [true, false, false]
+        );
+        assert_eq!(
+            can_view(&AuthContainerImpl::with_token(master("user"))),
+            [true, true, false]
+        );
+        assert_eq!(
+            can_view(&AuthContainerImpl::with_token(Some(AuthToken::General(
+                AuthTokenGeneral {
+                    base: base("user"),
+                    client: "client".to_string(),
+                    scopes: vec![],
+                }
+            )))),
+            [true, false, false]
+        );
+        assert_eq!(
+            can_view(&AuthContainerImpl::with_token(master("admin")).with_admins(&admins)),
+            [true, true, true]
+        );
+        // 管理者でも一般トークンでは管理者として扱わない
+        assert_eq!(
+            can_view(
+                &AuthContainerImpl::with_token(Some(AuthToken::General(AuthTokenGeneral {
+                    base: base("admin"),
+                    client: "client".to_string(),
+                    scopes: vec![],
+                })))
+                .with_admins(&admins)
+            ),
+            [true, false, false]
+        );
+    }
+}
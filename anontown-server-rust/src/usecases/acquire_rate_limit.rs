@@ -0,0 +1,148 @@
+use chrono::{DateTime, Utc};
+
+use crate::at_error::AtResult;
+use crate::entities::rate_limit_rule::RateLimitAction;
+use crate::entities::user::{TimeRange, User};
+use crate::ports::clock::ClockPort;
+use crate::ports::rate_limiter::RateLimiter;
+
+/// `acquire_rate_limit`で記録した今回の書き込み
+#[derive(Debug, Clone)]
+pub struct RateLimitSlot {
+    pub user_id: String,
+    pub action: RateLimitAction,
+    // ルールごとの件数(今回の分を含む)
+    pub counts: Vec<(TimeRange, i32)>,
+    pub acquired_at: DateTime<Utc>,
+}
+
+impl RateLimitSlot {
+    /// 今回の書き込みをユーザーに反映する(保存は呼び出し側で行う)
+    ///
+    /// レスの場合はユーザーの`count_created_res_*`を数えた件数で更新する。
+    /// 競合して取得し直したユーザーにも同じ内容を反映できる
+    pub fn apply(&self, user: &mut User) {
+        match self.action {
+            RateLimitAction::Res => user.update_res_count_snapshot(&self.counts, self.acquired_at),
+            RateLimitAction::Topic => {
+                user.topic_last_created_at = self.acquired_at;
+                user.updated_at = self.acquired_at;
+            }
+            // ユーザーの書き込みではないので記録するものは無い
+            RateLimitAction::Pairing => {}
+        }
+    }
+}
+
+/// 連投制限を確認し、書き込めるなら今回の書き込みを記録する
+///
+/// 記録した分は書き込みが保存できなくても数えられるので、入力やトピックの確認を済ませてから呼び、
+/// 保存に失敗した時は`release_rate_limit`で取り消す
+///
+/// # 引数
+/// * `user` - 書き込むユーザー
+/// * `action` - 書き込みの種類
+///
+/// # 返り値
+/// * 記録した書き込み。保存するユーザーに`RateLimitSlot::apply`で反映する
+///
+/// # エラー
+/// * 制限に達している場合は`AtError::Prerequisite`
+pub async fn acquire_rate_limit(
+    user: &User,
+    action: RateLimitAction,
+    rate_limiter: &impl RateLimiter,
+    clock: &impl ClockPort,
+) -> AtResult<RateLimitSlot> {
+    let now = clock.now();
+    let rules = action.rules(user.lv);
+    let counts = rate_limiter.acquire(&user.id, action, &rules, now).await?;
+
+    Ok(RateLimitSlot {
+        user_id: user.id.clone(),
+        action,
+        counts: rules
+            .iter()
+            .zip(counts)
+            .map(|(rule, count)| (rule.window, count))
+            .collect(),
+        acquired_at: now,
+    })
+}
+
+/// `acquire_rate_limit`で記録した書き込みを取り消す
+///
+/// # 引数
+/// * `slot` - 保存できなかった書き込み
+pub async fn release_rate_limit(slot: &RateLimitSlot, rate_limiter: &impl RateLimiter) -> AtResult<()> {
+    rate_limiter
+        .release(&slot.user_id, slot.action, slot.acquired_at)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::adapters::RateLimiterMockImpl;
+    use crate::at_error::AtError;
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[tokio::test]
+    async fn test_acquire_rate_limit() {
+        let rate_limiter = RateLimiterMockImpl::new();
+        let start = Utc.timestamp_opt(0, 0).unwrap();
+        let mut user = User::create(
+            &SeqIdGenerator::new(),
+            "sn".to_string(),
+            "name".to_string(),
+            "".to_string(),
+            "pass".to_string(),
+        );
+
+        // Lv1は10分に10件まで
+        for i in 0..10 {
+            let clock = FixClock::new(start + Duration::seconds(i));
+            acquire_rate_limit(&user, RateLimitAction::Res, &rate_limiter, &clock)
+                .await
+                .unwrap()
+                .apply(&mut user);
+        }
+        assert_eq!(user.count_created_res_m10, 10);
+        assert_eq!(user.count_created_res_d1, 10);
+        assert_eq!(user.res_last_created_at, start + Duration::seconds(9));
+
+        let clock = FixClock::new(start + Duration::seconds(10));
+        let result = acquire_rate_limit(&user, RateLimitAction::Res, &rate_limiter, &clock).await;
+        assert!(matches!(result, Err(AtError::Prerequisite(_))));
+
+        // 10分経てば書き込める
+        let clock = FixClock::new(start + Duration::minutes(10));
+        acquire_rate_limit(&user, RateLimitAction::Res, &rate_limiter, &clock)
+            .await
+            .unwrap()
+            .apply(&mut user);
+        assert_eq!(user.count_created_res_m10, 10);
+        assert_eq!(user.count_created_res_m30, 11);
+
+        // トピックはレスと別に数える
+        acquire_rate_limit(&user, RateLimitAction::Topic, &rate_limiter, &clock)
+            .await
+            .unwrap()
+            .apply(&mut user);
+        assert_eq!(user.topic_last_created_at, clock.now());
+        let result = acquire_rate_limit(&user, RateLimitAction::Topic, &rate_limiter, &clock).await;
+        assert!(matches!(result, Err(AtError::Prerequisite(_))));
+
+        // 取り消した分は数えない
+        let clock = FixClock::new(start + Duration::minutes(40));
+        let slot = acquire_rate_limit(&user, RateLimitAction::Topic, &rate_limiter, &clock)
+            .await
+            .unwrap();
+        release_rate_limit(&slot, &rate_limiter).await.unwrap();
+        acquire_rate_limit(&user, RateLimitAction::Topic, &rate_limiter, &clock)
+            .await
+            .unwrap();
+    }
+}
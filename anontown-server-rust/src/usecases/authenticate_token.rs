@@ -0,0 +1,33 @@
+use crate::at_error::{AtError, AtResult};
+use crate::auth::AuthToken;
+use crate::ports::{clock::ClockPort, TokenRepo};
+
+/// `X-Token`ヘッダーやWebSocketの`connection_init`で渡される`{id},{key}`形式のトークンで認証する
+///
+/// 認証に成功したらセッション一覧に出すためにトークンの最終使用日時を更新する
+///
+/// # 引数
+/// * `raw` - `{id},{key}`形式の文字列
+///
+/// # 返り値
+/// 認証されたトークン
+///
+/// # エラー
+/// * 形式が不正、トークンが存在しない、キーが一致しない、期限切れ、更新済みの場合は`AtError::TokenAuth`
+/// * DBの読み書きに失敗した場合はそのエラー
+pub async fn authenticate_token(
+    token_repo: &impl TokenRepo,
+    clock: &impl ClockPort,
+    raw: &str,
+) -> AtResult<AuthToken> {
+    let (id, key) = raw.split_once(',').ok_or(AtError::TokenAuth)?;
+    let mut token = token_repo.find_one(id.trim()).await.map_err(|e| match e {
+        AtError::NotFound(_) => AtError::TokenAuth,
+        e => e,
+    })?;
+    let auth = token.auth(key.trim(), clock)?;
+    if token.touch(clock) {
+        token_repo.update_last_used(&token).await?;
+    }
+    Ok(auth)
+}
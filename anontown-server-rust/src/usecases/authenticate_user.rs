@@ -0,0 +1,113 @@
+use crate::at_error::{AtError, AtResult};
+use crate::auth::AuthUser;
+use crate::entities::password::{self, PasswordConfig};
+use crate::ports::clock::ClockPort;
+use crate::ports::user::UserPort;
+
+/// ユーザーIDとパスワードで認証する
+///
+/// 古い形式のハッシュで保存されているユーザーは、認証に成功した時に今の設定でハッシュし直して保存する。
+/// ユーザーが存在しない場合もパスワードを検証するので、応答時間からはユーザーの有無が分からない
+///
+/// # 返り値
+/// 認証されたユーザー
+///
+/// # エラー
+/// * ユーザーが存在しない、またはパスワードが一致しない場合は`AtError::UserAuth`
+pub async fn authenticate_user(
+    user_repo: &mut impl UserPort,
+    id: &str,
+    password: &str,
+    config: &PasswordConfig,
+    clock: &impl ClockPort,
+) -> AtResult<AuthUser> {
+    let user = user_repo
+        .find_by_id(id)
+        .await
+        .map_err(|e| AtError::Internal(anyhow::anyhow!(e.to_string())))?;
+    let Some(mut user) = user else {
+        password::verify_dummy_password(password, config);
+        return Err(AtError::UserAuth);
+    };
+    let auth = user.auth(password, config)?;
+
+    if user.upgrade_password_hash(password, config, clock)? {
+        // パスワードは正しいので、保存に失敗しても次のログインでハッシュし直せばよい
+        if let Err(e) = user_repo.update(&user).await {
+            log::warn!("failed to upgrade password hash of {}: {}", user.id, e);
+        }
+    }
+
+    Ok(AuthUser {
+        id: auth.id,
+        pass: user.password_hash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::adapters::UserRepoMock;
+    use crate::entities::User;
+    use chrono::Utc;
+
+    fn config() -> PasswordConfig {
+        PasswordConfig {
+            memory_kib: 8,
+            iterations: 1,
+            ..PasswordConfig::new("salt".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_user() {
+        let config = config();
+        let mut user_repo = UserRepoMock::new();
+        let now = Utc::now();
+        let clock = FixClock::new(now);
+        // TypeScript版で作られたユーザー
+        let legacy = "7a37b85c8918eac19a9089c0fa5a2ab4dce3f90528dcdeec108b23ddf3607b99";
+        let user = User::create(
+            &SeqIdGenerator::new(),
+            "sn".to_string(),
+            "name".to_string(),
+            "email".to_string(),
+            legacy.to_string(),
+        );
+        user_repo.create(&user).await.unwrap();
+        let id = user.id.as_str();
+
+        assert!(matches!(
+            authenticate_user(&mut user_repo, id, "wrong", &config, &clock).await,
+            Err(AtError::UserAuth)
+        ));
+        assert!(matches!(
+            authenticate_user(&mut user_repo, "other", "password", &config, &clock).await,
+            Err(AtError::UserAuth)
+        ));
+        // 失敗した時はハッシュし直さない
+        let stored = user_repo.find_by_id(id).await.unwrap().unwrap();
+        assert_eq!(stored.password_hash, legacy);
+
+        let auth = authenticate_user(&mut user_repo, id, "password", &config, &clock)
+            .await
+            .unwrap();
+        assert_eq!(auth.id, id);
+        let stored = user_repo.find_by_id(id).await.unwrap().unwrap();
+        assert_ne!(stored.password_hash, legacy);
+        assert_eq!(auth.pass, stored.password_hash);
+        assert!(!password::needs_rehash(&stored.password_hash, &config));
+        assert_eq!(stored.updated_at, now);
+
+        // ハッシュし直した後も同じパスワードで認証できる
+        assert!(authenticate_user(&mut user_repo, id, "password", &config, &clock)
+            .await
+            .is_ok());
+        assert!(matches!(
+            authenticate_user(&mut user_repo, id, "wrong", &config, &clock).await,
+            Err(AtError::UserAuth)
+        ));
+    }
+}
@@ -0,0 +1,49 @@
+use crate::at_error::AtResult;
+use crate::auth::{AuthTokenMaster, TokenScope};
+use crate::entities::client::Client;
+use crate::entities::token_req::{check_code_challenge, TokenReq};
+use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort, TokenReqRepo};
+
+/// OAuthの認可コードフローで、ユーザーがクライアントを認可する
+///
+/// 認可コードをトークンリクエストとして発行する。クライアント用のトークンはコードを交換する時に作るので、
+/// 交換されなかった認可コードが使えるトークンを残すことはない。
+/// PKCEはS256のみ受け付け、省略は認めない
+///
+/// # 引数
+/// * `auth` - 認可したユーザーのマスタートークン。ログアウトすると交換前の認可コードも使えなくなる
+/// * `redirect_uri` - `Client::redirect_uri`で検証済みのリダイレクトURI
+/// * `scopes` - ユーザーが許可した、クライアントに与える権限
+///
+/// # 返り値
+/// 認可コードを持つトークンリクエスト
+///
+/// # エラー
+/// * code_challengeが無い、S256でない、形式が不正な場合は`AtError::Params`
+#[allow(clippy::too_many_arguments)]
+pub async fn authorize_oauth_client(
+    token_req_repo: &impl TokenReqRepo,
+    auth: &AuthTokenMaster,
+    client: &Client,
+    redirect_uri: String,
+    scopes: Vec<TokenScope>,
+    code_challenge: Option<&str>,
+    code_challenge_method: Option<&str>,
+    clock: &impl ClockPort,
+    safe_id_generator: &impl SafeIdGeneratorPort,
+) -> AtResult<TokenReq> {
+    let code_challenge = check_code_challenge(code_challenge, code_challenge_method)?;
+
+    let req = TokenReq::create_authorization_code(
+        auth.base.id.clone(),
+        client.id.clone(),
+        scopes,
+        redirect_uri,
+        code_challenge.to_string(),
+        clock,
+        safe_id_generator,
+    );
+    token_req_repo.insert(&req).await?;
+
+    Ok(req)
+}
@@ -0,0 +1,96 @@
+use crate::at_error::{AtError, AtResult};
+use crate::auth::AuthTokenMaster;
+use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort, UserTotpRepo};
+
+/// 認証アプリで生成したコードを確認して二要素認証を有効にする
+///
+/// # 返り値
+/// 表示用のバックアップコード。この時しか表示できない
+///
+/// # エラー
+/// * 秘密鍵が発行されていない、または既に有効な場合は`AtError::Prerequisite`
+/// * コードが一致しない場合は`AtError::Auth`
+pub async fn confirm_totp(
+    user_totp_repo: &impl UserTotpRepo,
+    auth: &AuthTokenMaster,
+    code: &str,
+    clock: &impl ClockPort,
+    safe_id_generator: &impl SafeIdGeneratorPort,
+) -> AtResult<Vec<String>> {
+    let mut totp = user_totp_repo
+        .find_one(&auth.base.user)
+        .await?
+        .ok_or_else(|| AtError::Prerequisite("二要素認証が登録されていません".to_string()))?;
+    let backup_codes = totp.confirm(code, clock, safe_id_generator)?;
+    user_totp_repo.update(&totp).await?;
+
+    Ok(backup_codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::UserTotpRepoMockImpl;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::auth::AuthTokenBase;
+    use crate::usecases::{enroll_totp, verify_second_factor};
+    use chrono::{TimeZone, Utc};
+
+    fn auth() -> AuthTokenMaster {
+        AuthTokenMaster {
+            base: AuthTokenBase {
+                id: "token".to_string(),
+                key: "key".to_string(),
+                user: "user".to_string(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_confirm_totp() {
+        let user_totp_repo = UserTotpRepoMockImpl::new();
+        let clock = FixClock::new(Utc.timestamp_opt(1234567890, 0).unwrap());
+        let id_generator = SeqIdGenerator::new();
+
+        assert!(matches!(
+            confirm_totp(&user_totp_repo, &auth(), "000000", &clock, &id_generator).await,
+            Err(AtError::Prerequisite(_))
+        ));
+
+        // 確認が済むまではコード無しでログインできる
+        let totp = enroll_totp(&user_totp_repo, &auth(), &clock, &id_generator)
+            .await
+            .unwrap();
+        assert!(!totp.enabled);
+        assert!(verify_second_factor(&user_totp_repo, "user", None, &clock).await.is_ok());
+
+        assert!(matches!(
+            confirm_totp(&user_totp_repo, &auth(), "000000", &clock, &id_generator).await,
+            Err(AtError::Auth(_))
+        ));
+        assert!(!user_totp_repo.find_one("user").await.unwrap().unwrap().enabled);
+
+        let backup_codes = confirm_totp(
+            &user_totp_repo,
+            &auth(),
+            &totp.current_code(&clock),
+            &clock,
+            &id_generator,
+        )
+        .await
+        .unwrap();
+        assert_eq!(backup_codes.len(), 10);
+        assert!(user_totp_repo.find_one("user").await.unwrap().unwrap().enabled);
+
+        // 有効になったらコードが必要になり、発行し直すこともできない
+        assert!(matches!(
+            verify_second_factor(&user_totp_repo, "user", None, &clock).await,
+            Err(AtError::Auth(_))
+        ));
+        assert!(matches!(
+            enroll_totp(&user_totp_repo, &auth(), &clock, &id_generator).await,
+            Err(AtError::Prerequisite(_))
+        ));
+    }
+}
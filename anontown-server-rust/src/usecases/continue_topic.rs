@@ -0,0 +1,212 @@
+use crate::entities::res::{Res, ResTopic, TopicLink};
+use crate::entities::topic::{Topic, TopicLifecycle};
+use crate::ports::clock::ClockPort;
+use crate::ports::object_id_generator::ObjectIdGeneratorPort;
+use crate::ports::res::ResPort;
+use crate::ports::topic::TopicPort;
+
+/// `continue_topic`で落としたトピック
+#[derive(Debug)]
+pub struct ContinuedTopic {
+    pub closed: Topic,
+    // 通常トピックなら次スレ
+    pub next: Option<Topic>,
+}
+
+/// レス数が上限に達したトピックを落とし、通常トピックなら次スレを立てる
+///
+/// 上限に達したトピックには次スレへのリンク、次スレには前スレへのリンクをシステムのレスとして書き込む。
+/// 落とす、次スレを立てる、リンクを書き込むのが全て保存されるように、レスを書き込んだのと同じ
+/// `UnitOfWork`の中で呼び、`TopicEvent::TopicClosed`はコミットしてから配信する。
+/// レスの採番でトピックの行ロックを取っているので、数えてから落とすまでに他のレスは書き込まれない
+///
+/// # 引数
+/// * `topic_id` - レスが書き込まれたトピックのid
+/// * `lifecycle` - レス数の上限
+///
+/// # 返り値
+/// * トピックを落とした場合はそのトピックと次スレ
+///
+/// # エラー
+/// * トピックの取得やリポジトリへの書き込みに失敗した場合はそのエラー。呼び出し側でロールバックする
+pub async fn continue_topic(
+    topic_id: &str,
+    lifecycle: &TopicLifecycle,
+    topic_repo: &mut impl TopicPort,
+    res_repo: &impl ResPort,
+    object_id_generator: &impl ObjectIdGeneratorPort,
+    clock: &impl ClockPort,
+) -> Result<Option<ContinuedTopic>, Box<dyn std::error::Error>> {
+    let mut topic = topic_repo.find_one(topic_id).await?;
+    topic.base_mut().res_count = res_repo.count_by_topic_id(topic_id).await? as i32;
+    if !topic.base().is_full(lifecycle) {
+        return Ok(None);
+    }
+
+    // 同時に上限に達しても次スレを立てるのは落とせた1回だけ
+    if !topic_repo.close(topic_id).await? {
+        return Ok(None);
+    }
+    topic.base_mut().close();
+
+    let next = match &topic {
+        Topic::Normal(normal) => Topic::Normal(normal.create_next(object_id_generator, clock)),
+        _ => {
+            return Ok(Some(ContinuedTopic {
+                closed: topic,
+                next: None,
+            }))
+        }
+    };
+    topic_repo.insert(&next).await?;
+
+    let prev_link = ResTopic::create_link(
+        object_id_generator,
+        clock,
+        &next,
+        TopicLink::Prev(topic.base().id.clone()),
+    );
+    res_repo.create(&Res::Topic(prev_link)).await?;
+
+    let next_link = ResTopic::create_link(
+        object_id_generator,
+        clock,
+        &topic,
+        TopicLink::Next(next.base().id.clone()),
+    );
+    res_repo.create(&Res::Topic(next_link)).await?;
+
+    Ok(Some(ContinuedTopic {
+        closed: topic,
+        next: Some(next),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::{ResRepoMock, TopicRepoMock};
+    use crate::entities::res::ResNormal;
+    use crate::entities::topic::{HashConfig, TopicNormal, TopicOne};
+    use crate::entities::user::User;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use chrono::Utc;
+
+    async fn write_reses(topic: &Topic, count: usize, res_repo: &ResRepoMock, id_gen: &SeqIdGenerator) {
+        let user = User::create(
+            id_gen,
+            "sn".to_string(),
+            "name".to_string(),
+            "".to_string(),
+            "pass".to_string(),
+        );
+        for _ in 0..count {
+            let res = ResNormal::create(
+                id_gen,
+                topic,
+                &user,
+                &HashConfig::jst("salt".to_string()),
+                None,
+                "text".to_string(),
+                None,
+                None,
+                true,
+            ).unwrap();
+            res_repo.create(&Res::Normal(res)).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_continue_topic() {
+        let id_gen = SeqIdGenerator::new();
+        let clock = FixClock::new(Utc::now());
+        let mut topic_repo = TopicRepoMock::new();
+        let res_repo = ResRepoMock::new();
+        let lifecycle = TopicLifecycle {
+            res_limit: 3,
+            ..TopicLifecycle::default()
+        };
+
+        let topic = Topic::Normal(TopicNormal::create(
+            &id_gen,
+            &clock,
+            "雑談スレ".to_string(),
+            "text".to_string(),
+            "user".to_string(),
+            vec!["雑談".to_string()],
+        ));
+        let topic_id = topic.base().id.clone();
+        topic_repo.insert(&topic).await.unwrap();
+
+        // 上限未満
+        write_reses(&topic, 2, &res_repo, &id_gen).await;
+        let next = continue_topic(&topic_id, &lifecycle, &mut topic_repo, &res_repo, &id_gen, &clock)
+            .await
+            .unwrap();
+        assert!(next.is_none());
+
+        // 上限に達すると落ちて次スレが立つ
+        write_reses(&topic, 1, &res_repo, &id_gen).await;
+        let continued = continue_topic(&topic_id, &lifecycle, &mut topic_repo, &res_repo, &id_gen, &clock)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(continued.closed.base().id, topic_id);
+        assert!(continued.closed.base().is_closed);
+        let next = continued.next.unwrap();
+        let next_id = next.base().id.clone();
+        assert_eq!(next.base().title, "雑談スレ Part2");
+        assert_eq!(next.base().tags, vec!["雑談".to_string()]);
+        assert!(topic_repo.find_one(&topic_id).await.unwrap().base().is_closed);
+
+        let reses = res_repo.find_by_number_range(&topic_id, 4, 4).await.unwrap();
+        match &reses[..] {
+            [Res::Topic(res)] => assert_eq!(res.link, Some(TopicLink::Next(next_id.clone()))),
+            _ => panic!("次スレへのリンクがありません"),
+        }
+        let reses = res_repo.find_by_number_range(&next_id, 1, 1).await.unwrap();
+        match &reses[..] {
+            [Res::Topic(res)] => assert_eq!(res.link, Some(TopicLink::Prev(topic_id.clone()))),
+            _ => panic!("前スレへのリンクがありません"),
+        }
+
+        // 既に落ちているので2回目は何もしない
+        let again = continue_topic(&topic_id, &lifecycle, &mut topic_repo, &res_repo, &id_gen, &clock)
+            .await
+            .unwrap();
+        assert!(again.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_continue_topic_one() {
+        let id_gen = SeqIdGenerator::new();
+        let clock = FixClock::new(Utc::now());
+        let mut topic_repo = TopicRepoMock::new();
+        let res_repo = ResRepoMock::new();
+        let lifecycle = TopicLifecycle {
+            res_limit: 1,
+            ..TopicLifecycle::default()
+        };
+
+        let topic = Topic::One(TopicOne::create(
+            &id_gen,
+            &clock,
+            "title".to_string(),
+            "text".to_string(),
+            "user".to_string(),
+            vec![],
+        ));
+        let topic_id = topic.base().id.clone();
+        topic_repo.insert(&topic).await.unwrap();
+        write_reses(&topic, 1, &res_repo, &id_gen).await;
+
+        // 単発トピックは落ちるだけで次スレは立たない
+        let continued = continue_topic(&topic_id, &lifecycle, &mut topic_repo, &res_repo, &id_gen, &clock)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(continued.next.is_none());
+        assert!(topic_repo.find_one(&topic_id).await.unwrap().base().is_closed);
+    }
+}
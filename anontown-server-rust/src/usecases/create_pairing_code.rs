@@ -0,0 +1,20 @@
+use crate::at_error::AtResult;
+use crate::auth::AuthTokenMaster;
+use crate::entities::token_req::TokenReq;
+use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort, TokenReqRepo};
+
+/// ログイン中の端末から別の端末にログインを引き継ぐための引き継ぎコードを発行する
+///
+/// # 返り値
+/// 引き継ぎコードを持つトークンリクエスト。`pairing_code`を画面に表示し、`pairing_qr_payload`をQRコードにする
+pub async fn create_pairing_code(
+    token_req_repo: &impl TokenReqRepo,
+    auth: &AuthTokenMaster,
+    clock: &impl ClockPort,
+    safe_id_generator: &impl SafeIdGeneratorPort,
+) -> AtResult<TokenReq> {
+    let req = TokenReq::create_pairing(auth.base.id.clone(), clock, safe_id_generator);
+    token_req_repo.insert(&req).await?;
+
+    Ok(req)
+}
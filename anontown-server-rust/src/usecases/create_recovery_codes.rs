@@ -0,0 +1,24 @@
+use crate::at_error::AtResult;
+use crate::auth::AuthTokenMaster;
+use crate::entities::recovery_code::RecoveryCode;
+use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort, RecoveryCodeRepo};
+
+/// パスワードを忘れた時にアカウントを回復するための回復コードを発行する
+///
+/// 既に発行されている回復コードは使えなくなる
+///
+/// # 返り値
+/// 表示用の回復コード。この時しか表示できない
+pub async fn create_recovery_codes(
+    recovery_code_repo: &impl RecoveryCodeRepo,
+    auth: &AuthTokenMaster,
+    clock: &impl ClockPort,
+    safe_id_generator: &impl SafeIdGeneratorPort,
+) -> AtResult<Vec<String>> {
+    let (recovery_codes, codes) = RecoveryCode::create_set(&auth.base.user, clock, safe_id_generator);
+    recovery_code_repo
+        .replace_all(&auth.base.user, &recovery_codes)
+        .await?;
+
+    Ok(codes)
+}
@@ -0,0 +1,160 @@
+use crate::entities::res::{Res, ResTopic};
+use crate::entities::topic::TopicLifecycle;
+use crate::entities::topic_event::TopicEvent;
+use crate::ports::clock::ClockPort;
+use crate::ports::object_id_generator::ObjectIdGeneratorPort;
+use crate::ports::res::ResPort;
+use crate::ports::topic::TopicPort;
+use crate::ports::topic_event_bus::TopicEventBus;
+use crate::ports::unit_of_work::UnitOfWork;
+
+/// 落ちる条件を満たしたトピックを落とし、落ちたことを示すレスを書き込んで購読者に知らせる
+///
+/// トピックごとに、落とすのと落ちたことを示すレスを1つのトランザクションで保存し、コミットしてから配信する。
+/// 1つのトピックで失敗してもログに残して残りのトピックを続け、失敗したトピックは次の実行でやり直す
+///
+/// # 引数
+/// * `lifecycle` - トピックの種類ごとの落ちるまでの期間
+///
+/// # 返り値
+/// * 落としたトピックの数
+///
+/// # エラー
+/// * 落とすトピックの取得に失敗した場合はそのエラー
+pub async fn cron_topic_check(
+    lifecycle: &TopicLifecycle,
+    topic_repo: &mut impl TopicPort,
+    unit_of_work: &impl UnitOfWork,
+    topic_event_bus: &impl TopicEventBus,
+    object_id_generator: &(impl ObjectIdGeneratorPort + Sync),
+    clock: &(impl ClockPort + Sync),
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let expired = topic_repo.find_expired(lifecycle, clock.now()).await?;
+
+    let mut count = 0;
+    for mut topic in expired {
+        let id = topic.base().id.clone();
+        let closed = unit_of_work
+            .run(|repos| {
+                Box::pin(async move {
+                    // 取得してから他で落とされていればレスは書き込まない
+                    if !repos.topic_repo.close(&topic.base().id).await? {
+                        return Ok(false);
+                    }
+                    topic.base_mut().close();
+                    let res = ResTopic::create_system(object_id_generator, clock, &topic);
+                    repos.res_repo.create(&Res::Topic(res)).await?;
+                    Ok(true)
+                })
+            })
+            .await;
+
+        match closed {
+            Ok(true) => count += 1,
+            Ok(false) => continue,
+            Err(e) => {
+                log::error!("cron_topic_check: failed to close topic {}: {}", id, e);
+                continue;
+            }
+        }
+
+        if let Err(e) = topic_event_bus
+            .publish(&TopicEvent::TopicClosed { topic: id.clone() })
+            .await
+        {
+            log::error!("cron_topic_check: failed to publish close of topic {}: {}", id, e);
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::adapters::{
+        HistoryRepoMock, ProfileRepoMock, RecoveryCodeRepoMockImpl, ResRepoMock, StorageRepoMock,
+        TokenRepoMockImpl, TopicEventBusMockImpl, TopicRepoMock, UnitOfWorkMockImpl, UserRepoMock,
+    };
+    use crate::entities::res::ResType;
+    use crate::entities::topic::{Topic, TopicNormal, TopicOne};
+    use crate::ports::unit_of_work::Repos;
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[tokio::test]
+    async fn test_cron_topic_check() {
+        let created = Utc.timestamp_opt(0, 0).unwrap();
+        let id_gen = SeqIdGenerator::new();
+        let mut topic_repo = TopicRepoMock::new();
+        let res_repo = ResRepoMock::new();
+        let unit_of_work = UnitOfWorkMockImpl::new(Repos {
+            topic_repo: topic_repo.clone(),
+            user_repo: UserRepoMock::new(),
+            res_repo: res_repo.clone(),
+            history_repo: HistoryRepoMock::new(),
+            profile_repo: ProfileRepoMock::new(),
+            storage_repo: StorageRepoMock::new(),
+            token_repo: TokenRepoMockImpl::new(),
+            recovery_code_repo: RecoveryCodeRepoMockImpl::new(),
+        });
+        let topic_event_bus = TopicEventBusMockImpl::new();
+        let lifecycle = TopicLifecycle::default();
+
+        let normal = TopicNormal::create(
+            &id_gen,
+            &FixClock::new(created),
+            "normal".to_string(),
+            "text".to_string(),
+            "user".to_string(),
+            vec![],
+        );
+        let one = TopicOne::create(
+            &id_gen,
+            &FixClock::new(created),
+            "one".to_string(),
+            "text".to_string(),
+            "user".to_string(),
+            vec![],
+        );
+        topic_repo.insert(&Topic::Normal(normal.clone())).await.unwrap();
+        topic_repo.insert(&Topic::One(one.clone())).await.unwrap();
+
+        // まだ落ちない
+        let clock = FixClock::new(created + Duration::days(6));
+        let count = cron_topic_check(&lifecycle, &mut topic_repo, &unit_of_work, &topic_event_bus, &id_gen, &clock)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+
+        // 単発トピックだけが落ち、システムレスが書き込まれる
+        let clock = FixClock::new(created + Duration::days(7));
+        let count = cron_topic_check(&lifecycle, &mut topic_repo, &unit_of_work, &topic_event_bus, &id_gen, &clock)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let topic = topic_repo.find_one(&one.base().id).await.unwrap();
+        assert!(topic.base().is_closed);
+        let topic = topic_repo.find_one(&normal.base().id).await.unwrap();
+        assert!(!topic.base().is_closed);
+
+        let reses = res_repo.find_by_topic_id(&one.base().id, 10, 0).await.unwrap();
+        assert_eq!(reses.len(), 1);
+        assert_eq!(reses[0].base().res_type(), ResType::Topic);
+        assert_eq!(reses[0].base().date(), clock.now());
+        assert_eq!(
+            topic_event_bus.events(),
+            vec![TopicEvent::TopicClosed {
+                topic: one.base().id.clone(),
+            }]
+        );
+
+        // 2回目は何もしない
+        let count = cron_topic_check(&lifecycle, &mut topic_repo, &unit_of_work, &topic_event_bus, &id_gen, &clock)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+}
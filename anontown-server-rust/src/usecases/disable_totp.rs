@@ -0,0 +1,32 @@
+use crate::at_error::{AtError, AtResult};
+use crate::auth::AuthTokenMaster;
+use crate::ports::{clock::ClockPort, UserTotpRepo};
+use crate::usecases::verify_second_factor;
+
+/// 二要素認証を無効にする
+///
+/// マスタートークンが漏れても二要素認証を外されないように、コードの確認が必要
+///
+/// # 引数
+/// * `code` - 認証アプリのコードかバックアップコード
+///
+/// # エラー
+/// * 二要素認証が有効でない場合は`AtError::Prerequisite`
+/// * コードが一致しない、または使用済みの場合は`AtError::Auth`
+pub async fn disable_totp(
+    user_totp_repo: &impl UserTotpRepo,
+    auth: &AuthTokenMaster,
+    code: &str,
+    clock: &impl ClockPort,
+) -> AtResult<()> {
+    match user_totp_repo.find_one(&auth.base.user).await? {
+        Some(totp) if totp.enabled => {}
+        _ => {
+            return Err(AtError::Prerequisite(
+                "二要素認証は有効ではありません".to_string(),
+            ))
+        }
+    }
+    verify_second_factor(user_totp_repo, &auth.base.user, Some(code), clock).await?;
+    user_totp_repo.delete(&auth.base.user).await
+}
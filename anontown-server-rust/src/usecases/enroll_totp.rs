@@ -0,0 +1,33 @@
+use crate::at_error::{AtError, AtResult};
+use crate::auth::AuthTokenMaster;
+use crate::entities::user_totp::UserTotp;
+use crate::ports::{clock::ClockPort, safe_id_generator::SafeIdGeneratorPort, UserTotpRepo};
+
+/// 二要素認証に使う秘密鍵を発行する
+///
+/// 確認が済むまでは有効にならないので、ログインには影響しない。確認前なら何度でも発行し直せる
+///
+/// # 返り値
+/// 有効になっていない二要素認証の設定。`provisioning_uri`をQRコードにする
+///
+/// # エラー
+/// * 既に有効な場合は`AtError::Prerequisite`
+pub async fn enroll_totp(
+    user_totp_repo: &impl UserTotpRepo,
+    auth: &AuthTokenMaster,
+    clock: &impl ClockPort,
+    safe_id_generator: &impl SafeIdGeneratorPort,
+) -> AtResult<UserTotp> {
+    if let Some(totp) = user_totp_repo.find_one(&auth.base.user).await? {
+        if totp.enabled {
+            return Err(AtError::Prerequisite(
+                "二要素認証は既に有効です".to_string(),
+            ));
+        }
+    }
+
+    let totp = UserTotp::create(auth.base.user.clone(), clock, safe_id_generator);
+    user_totp_repo.save(&totp).await?;
+
+    Ok(totp)
+}
@@ -0,0 +1,230 @@
+use crate::at_error::{AtError, AtResult};
+use crate::entities::token_req::TokenReq;
+use crate::entities::Token;
+use crate::ports::{
+    clock::ClockPort, object_id_generator::ObjectIdGeneratorPort,
+    safe_id_generator::SafeIdGeneratorPort, TokenRepo, TokenReqRepo,
+};
+
+/// OAuthの認可コードをトークンと交換する
+///
+/// 認可したユーザーのマスタートークンがまだ有効な場合だけ、クライアント用のトークンを作る
+///
+/// # 引数
+/// * `client_id` - 認可コードを発行したクライアント
+/// * `code` - `/oauth/authorize`で発行した認可コード
+/// * `redirect_uri` - 認可リクエストで使ったリダイレクトURI
+/// * `code_verifier` - 認可リクエストのcode_challengeの元になったPKCEのcode_verifier
+///
+/// # 返り値
+/// クライアントに紐付いたトークン
+///
+/// # エラー
+/// * 認可コードが存在しない、使用済み、期限切れ、他のクライアントのもの、
+///   リダイレクトURIかcode_verifierが一致しない、または認可したユーザーがログアウトしている場合は`AtError::Auth`
+#[allow(clippy::too_many_arguments)]
+pub async fn exchange_oauth_code(
+    token_repo: &impl TokenRepo,
+    token_req_repo: &impl TokenReqRepo,
+    client_id: &str,
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: &str,
+    clock: &impl ClockPort,
+    object_id_generator: &impl ObjectIdGeneratorPort,
+    safe_id_generator: &impl SafeIdGeneratorPort,
+) -> AtResult<Token> {
+    // どの理由で失敗したかはクライアントに教えない
+    let invalid = || AtError::Auth("認可コードが無効です".to_string());
+
+    let (token_id, key) = TokenReq::parse_code(code).ok_or_else(invalid)?;
+    let mut req = token_req_repo
+        .find_one(token_id, key)
+        .await
+        .map_err(|_| invalid())?;
+    req.exchange(client_id, redirect_uri, code_verifier, clock)?;
+
+    let source = token_repo.find_one(&req.token_id).await.map_err(|_| invalid())?;
+    if !source.is_master() || !source.is_active(clock) {
+        return Err(invalid());
+    }
+
+    // 並行して同じ認可コードが交換された場合は片方だけが成功する
+    token_req_repo.update(&req).await.map_err(|e| match e {
+        AtError::Conflict(_) => invalid(),
+        e => e,
+    })?;
+
+    // 認可した端末の情報を引き継ぎ、セッション一覧でどこから認可したか分かるようにする
+    let token = Token::create_general(
+        source.user_id,
+        client_id.to_string(),
+        req.scopes,
+        source.device,
+        clock,
+        object_id_generator,
+        safe_id_generator,
+    );
+    token_repo.insert(&token).await?;
+
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::{TokenRepoMockImpl, TokenReqRepoMockImpl};
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::auth::{AuthTokenBase, AuthTokenMaster, TokenScope};
+    use crate::entities::client::Client;
+    use crate::entities::token::TokenDevice;
+    use crate::usecases::authorize_oauth_client;
+    use chrono::Utc;
+
+    // RFC 7636 Appendix Bの例
+    const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+    const WRONG_CODE_VERIFIER: &str = "xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx";
+    const REDIRECT_URI: &str = "https://example.com/callback";
+
+    fn client() -> Client {
+        Client {
+            id: "client".to_string(),
+            name: "name".to_string(),
+            url: REDIRECT_URI.to_string(),
+            date: Utc::now(),
+            update: Utc::now(),
+        }
+    }
+
+    fn auth(master: &Token) -> AuthTokenMaster {
+        AuthTokenMaster {
+            base: AuthTokenBase {
+                id: master.id.clone(),
+                key: master.access_token.clone(),
+                user: master.user_id.clone(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exchange_oauth_code() {
+        let token_repo = TokenRepoMockImpl::new();
+        let token_req_repo = TokenReqRepoMockImpl::new();
+        let clock = FixClock::new(Utc::now());
+        let id_generator = SeqIdGenerator::new();
+        let device = TokenDevice {
+            ip: Some("192.0.2.1".to_string()),
+            user_agent: Some("Mozilla/5.0".to_string()),
+        };
+        let master = Token::create_master(
+            "user".to_string(),
+            device.clone(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        token_repo.insert(&master).await.unwrap();
+        let auth = auth(&master);
+
+        // PKCEは必須
+        let result = authorize_oauth_client(
+            &token_req_repo,
+            &auth,
+            &client(),
+            REDIRECT_URI.to_string(),
+            vec![TokenScope::Read, TokenScope::PostRes],
+            Some(CODE_VERIFIER),
+            Some("plain"),
+            &clock,
+            &id_generator,
+        )
+        .await;
+        assert!(matches!(result, Err(AtError::Params(_))));
+
+        let req = authorize_oauth_client(
+            &token_req_repo,
+            &auth,
+            &client(),
+            REDIRECT_URI.to_string(),
+            vec![TokenScope::Read, TokenScope::PostRes],
+            Some(CODE_CHALLENGE),
+            Some("S256"),
+            &clock,
+            &id_generator,
+        )
+        .await
+        .unwrap();
+        let code = req.code();
+        // 交換するまでクライアント用のトークンは作らない
+        assert_eq!(token_repo.find_all(&auth).await.unwrap().len(), 1);
+
+        let exchange = |client_id: &'static str, code: String, verifier: &'static str| {
+            let token_repo = &token_repo;
+            let token_req_repo = &token_req_repo;
+            let clock = &clock;
+            let id_generator = &id_generator;
+            async move {
+                exchange_oauth_code(
+                    token_repo,
+                    token_req_repo,
+                    client_id,
+                    &code,
+                    REDIRECT_URI,
+                    verifier,
+                    clock,
+                    id_generator,
+                    id_generator,
+                )
+                .await
+            }
+        };
+
+        assert!(matches!(
+            exchange("other", code.clone(), CODE_VERIFIER).await,
+            Err(AtError::Auth(_))
+        ));
+        assert!(matches!(
+            exchange("client", code.clone(), WRONG_CODE_VERIFIER).await,
+            Err(AtError::Auth(_))
+        ));
+        assert!(matches!(
+            exchange("client", "invalid".to_string(), CODE_VERIFIER).await,
+            Err(AtError::Auth(_))
+        ));
+
+        let token = exchange("client", code.clone(), CODE_VERIFIER).await.unwrap();
+        assert_eq!(token_repo.find_one(&token.id).await.unwrap().id, token.id);
+        assert_eq!(token.user_id, "user");
+        assert!(token.is_client("client"));
+        assert_eq!(token.scopes, vec![TokenScope::Read, TokenScope::PostRes]);
+        assert_eq!(token.device, device);
+
+        // 認可コードは1回しか使えない
+        assert!(matches!(
+            exchange("client", code, CODE_VERIFIER).await,
+            Err(AtError::Auth(_))
+        ));
+
+        // 認可したユーザーがログアウトしたら交換できない
+        let req = authorize_oauth_client(
+            &token_req_repo,
+            &auth,
+            &client(),
+            REDIRECT_URI.to_string(),
+            vec![TokenScope::Read],
+            Some(CODE_CHALLENGE),
+            Some("S256"),
+            &clock,
+            &id_generator,
+        )
+        .await
+        .unwrap();
+        token_repo.del_family(&master.family_id).await.unwrap();
+        assert!(matches!(
+            exchange("client", req.code(), CODE_VERIFIER).await,
+            Err(AtError::Auth(_))
+        ));
+    }
+}
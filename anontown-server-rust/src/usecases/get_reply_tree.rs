@@ -0,0 +1,146 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::entities::res::Res;
+use crate::ports::res::ResPort;
+
+// 返信ツリーを辿る最大の深さ。巨大なスレッドで際限なく取得しないようにする
+pub const MAX_REPLY_TREE_DEPTH: i32 = 10;
+
+#[derive(Debug, Clone)]
+pub struct ReplyTreeNode {
+    pub res: Res,
+    // 返信先のレスのid。根はNone
+    pub parent: Option<String>,
+    pub depth: i32,
+}
+
+/// 指定されたレスと、そのレスへの返信を再帰的に辿ったツリーを幅優先順で返す
+///
+/// 1つのレスが複数のレスにアンカーしている場合は最初に辿り着いた位置にだけ含める
+///
+/// # 引数
+/// * `id` - 根になるレスのid
+/// * `max_depth` - 辿る深さ。`MAX_REPLY_TREE_DEPTH`より大きい値は切り詰める
+///
+/// # 返り値
+/// * レスが存在しなければ空
+///
+/// # エラー
+/// なし
+pub async fn get_reply_tree(
+    id: &str,
+    max_depth: i32,
+    res_repo: &impl ResPort,
+) -> Result<Vec<ReplyTreeNode>, Box<dyn std::error::Error>> {
+    let max_depth = max_depth.clamp(0, MAX_REPLY_TREE_DEPTH);
+
+    let root = match res_repo.find_by_id(id).await? {
+        Some(root) => root,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut visited = HashSet::new();
+    visited.insert(root.base().id().to_string());
+
+    let mut nodes = Vec::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(ReplyTreeNode {
+        res: root,
+        parent: None,
+        depth: 0,
+    });
+
+    while let Some(node) = queue.pop_front() {
+        if node.depth < max_depth {
+            for reply in res_repo.find_by_reply_id(node.res.base().id()).await? {
+                if visited.insert(reply.base().id().to_string()) {
+                    queue.push_back(ReplyTreeNode {
+                        res: reply,
+                        parent: Some(node.res.base().id().to_string()),
+                        depth: node.depth + 1,
+                    });
+                }
+            }
+        }
+        nodes.push(node);
+    }
+
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::adapters::ResRepoMock;
+    use crate::entities::res::ResNormal;
+    use crate::entities::topic::{HashConfig, Topic, TopicNormal};
+    use crate::entities::user::User;
+    use chrono::Utc;
+
+    #[tokio::test]
+    async fn test_get_reply_tree() {
+        let id_gen = SeqIdGenerator::new();
+        let res_repo = ResRepoMock::new();
+        let hash_config = HashConfig::jst("salt".to_string());
+        let user = User::create(
+            &id_gen,
+            "sn1".to_string(),
+            "name".to_string(),
+            "".to_string(),
+            "pass".to_string(),
+        );
+        let topic = Topic::Normal(TopicNormal::create(
+            &id_gen,
+            &FixClock::new(Utc::now()),
+            "title".to_string(),
+            "text".to_string(),
+            user.id.clone(),
+            vec![],
+        ));
+
+        // 0 <- 1 <- 3
+        //   <- 2 <- 3
+        let mut ids: Vec<String> = Vec::new();
+        for replies in [vec![], vec![0], vec![0], vec![1, 2]] {
+            let res = Res::Normal(ResNormal::create(
+                &id_gen,
+                &topic,
+                &user,
+                &hash_config,
+                None,
+                "text".to_string(),
+                None,
+                None,
+                true,
+            ).unwrap());
+            let id = res.base().id().to_string();
+            res_repo.create(&res).await.unwrap();
+            let replies = replies.into_iter().map(|i: usize| ids[i].clone()).collect::<Vec<_>>();
+            res_repo.add_replies(&id, &replies).await.unwrap();
+            ids.push(id);
+        }
+
+        let tree = get_reply_tree(&ids[0], MAX_REPLY_TREE_DEPTH, &res_repo).await.unwrap();
+        let nodes = tree
+            .iter()
+            .map(|node| (node.res.base().id(), node.parent.as_deref(), node.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            vec![
+                (ids[0].as_str(), None, 0),
+                (ids[1].as_str(), Some(ids[0].as_str()), 1),
+                (ids[2].as_str(), Some(ids[0].as_str()), 1),
+                (ids[3].as_str(), Some(ids[1].as_str()), 2),
+            ]
+        );
+
+        let tree = get_reply_tree(&ids[0], 1, &res_repo).await.unwrap();
+        assert_eq!(tree.len(), 3);
+
+        let tree = get_reply_tree("none", MAX_REPLY_TREE_DEPTH, &res_repo).await.unwrap();
+        assert!(tree.is_empty());
+    }
+}
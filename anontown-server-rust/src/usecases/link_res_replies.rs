@@ -0,0 +1,183 @@
+use std::collections::HashSet;
+
+use crate::entities::notification::Notification;
+use crate::entities::res::Res;
+use crate::ports::res::ResPort;
+
+/// `link_res_replies`で記録した返信
+#[derive(Debug, Default)]
+pub struct LinkedReplies {
+    // 返信先のレス(番号順)
+    pub replies: Vec<Res>,
+    // 返信先の作者への通知
+    pub notifications: Vec<Notification>,
+}
+
+/// 保存済みのレスの返信先(replyとアンカー)を返信グラフに記録し、返信先の作者への通知を作る
+///
+/// レスと同じ`UnitOfWork`の中で呼び、通知はコミットしてから送る
+///
+/// # 事前条件
+/// * `res`は番号が振られた保存済みのレス
+///
+/// # 引数
+/// * `res` - 返信元のレス
+///
+/// # 返り値
+/// * 返信先のレスと、新しく記録した返信の通知
+///
+/// # エラー
+/// * リポジトリへの書き込みに失敗した場合はそのエラー
+pub async fn link_res_replies(
+    res: &Res,
+    res_repo: &impl ResPort,
+) -> Result<LinkedReplies, Box<dyn std::error::Error>> {
+    let normal = match res {
+        Res::Normal(normal) => normal,
+        _ => return Ok(LinkedReplies::default()),
+    };
+    let base = normal.base();
+
+    let mut replies = res_repo
+        .find_by_numbers(base.topic_id(), &normal.anchors())
+        .await?;
+    if let Some(reply) = &normal.reply {
+        if !replies.iter().any(|r| r.base().id() == reply.res) {
+            if let Some(reply) = res_repo.find_by_id(&reply.res).await? {
+                replies.push(reply);
+                replies.sort_by_key(|r| r.base().number());
+            }
+        }
+    }
+    replies.retain(|r| r.base().id() != base.id() && r.base().topic_id() == base.topic_id());
+
+    if replies.is_empty() {
+        return Ok(LinkedReplies::default());
+    }
+
+    let reply_ids = replies
+        .iter()
+        .map(|r| r.base().id().to_string())
+        .collect::<Vec<_>>();
+    let added = res_repo.add_replies(base.id(), &reply_ids).await?;
+
+    // 同じ人の複数のレスにアンカーしても通知は1回。記録済みの返信は通知済みなので送らない
+    let mut notified = HashSet::new();
+    let mut notifications = Vec::new();
+    for reply in replies.iter().filter(|r| added.iter().any(|id| id == r.base().id())) {
+        let user_id = reply.base().user_id();
+        if user_id == base.user_id() || !notified.insert(user_id.to_string()) {
+            continue;
+        }
+        notifications.push(Notification::reply(
+            user_id.to_string(),
+            base.topic_id().to_string(),
+            base.id().to_string(),
+            reply.base().id().to_string(),
+        ));
+    }
+
+    Ok(LinkedReplies {
+        replies,
+        notifications,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::adapters::ResRepoMock;
+    use crate::entities::notification::NotificationPayload;
+    use crate::entities::res::{Reply, ResNormal};
+    use crate::entities::topic::{HashConfig, Topic, TopicNormal};
+    use crate::entities::user::User;
+    use chrono::Utc;
+
+    fn user(id_gen: &SeqIdGenerator, sn: &str) -> User {
+        User::create(
+            id_gen,
+            sn.to_string(),
+            "name".to_string(),
+            "".to_string(),
+            "pass".to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_link_res_replies() {
+        let id_gen = SeqIdGenerator::new();
+        let res_repo = ResRepoMock::new();
+        let hash_config = HashConfig::jst("salt".to_string());
+        let user1 = user(&id_gen, "user1");
+        let user2 = user(&id_gen, "user2");
+        let user3 = user(&id_gen, "user3");
+        let topic = Topic::Normal(TopicNormal::create(
+            &id_gen,
+            &FixClock::new(Utc::now()),
+            "title".to_string(),
+            "text".to_string(),
+            user1.id.clone(),
+            vec![],
+        ));
+
+        let mut created: Vec<Res> = Vec::new();
+        for (user, text, reply_to) in [
+            (&user1, "1", None),
+            (&user2, "2", None),
+            (&user3, "3", None),
+            (&user2, ">>1-2 >>4 >>9", Some(2)),
+        ] {
+            let reply = reply_to.map(|i: usize| Reply {
+                res: created[i].base().id().to_string(),
+                user: user3.id.clone(),
+            });
+            let res = ResNormal::create(
+                &id_gen,
+                &topic,
+                user,
+                &hash_config,
+                None,
+                text.to_string(),
+                reply,
+                None,
+                true,
+            ).unwrap();
+            created.push(res_repo.create(&Res::Normal(res)).await.unwrap());
+        }
+        let res_ids = created.iter().map(|res| res.base().id().to_string()).collect::<Vec<_>>();
+
+        // 自分自身と存在しないレスへのアンカーは無視する
+        let linked = link_res_replies(&created[3], &res_repo).await.unwrap();
+        let ids = linked.replies.iter().map(|res| res.base().id()).collect::<Vec<_>>();
+        assert_eq!(ids, res_ids[..3].to_vec());
+
+        for id in &res_ids[..3] {
+            let res = res_repo.find_by_id(id).await.unwrap().unwrap();
+            assert_eq!(res.base().reply_count(), 1);
+        }
+        let reses = res_repo.find_by_reply_id(&res_ids[0]).await.unwrap();
+        assert_eq!(reses.len(), 1);
+        assert_eq!(reses[0].base().id(), res_ids[3]);
+
+        // 自分のレスへの返信は通知しない
+        let notifications = linked.notifications;
+        let users = notifications.iter().map(|n| n.user_id.as_str()).collect::<Vec<_>>();
+        assert_eq!(users, vec![user1.id.as_str(), user3.id.as_str()]);
+        assert_eq!(
+            notifications[0].payload,
+            NotificationPayload::Reply {
+                topic_id: topic.base().id.clone(),
+                res_id: res_ids[3].clone(),
+                reply_id: res_ids[0].clone(),
+            }
+        );
+
+        // 2回目は返信数を増やさず、通知もしない
+        let linked = link_res_replies(&created[3], &res_repo).await.unwrap();
+        let res = res_repo.find_by_id(&res_ids[0]).await.unwrap().unwrap();
+        assert_eq!(res.base().reply_count(), 1);
+        assert!(linked.notifications.is_empty());
+    }
+}
@@ -0,0 +1,90 @@
+use crate::at_error::AtResult;
+use crate::auth::AuthTokenMaster;
+use crate::entities::Token;
+use crate::ports::{clock::ClockPort, TokenRepo};
+
+/// ログイン中のセッションの一覧を取得する
+///
+/// トークンを更新しても同じファミリーは一つのセッションとして扱うので、各ファミリーの最新のトークンだけを返す
+///
+/// # 返り値
+/// 有効なトークン。最後に使われたものから順に並ぶ
+pub async fn list_sessions(
+    token_repo: &impl TokenRepo,
+    auth: &AuthTokenMaster,
+    clock: &impl ClockPort,
+) -> AtResult<Vec<Token>> {
+    let mut tokens: Vec<Token> = token_repo
+        .find_all(auth)
+        .await?
+        .into_iter()
+        .filter(|token| token.is_active(clock))
+        .collect();
+    tokens.sort_by(|a, b| b.last_used_at.cmp(&a.last_used_at));
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::TokenRepoMockImpl;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::auth::{AuthTokenBase, TokenScope};
+    use crate::entities::token::{TokenDevice, MASTER_TOKEN_LIFETIME_DAYS};
+    use chrono::{Duration, Utc};
+
+    #[tokio::test]
+    async fn test_list_sessions() {
+        let token_repo = TokenRepoMockImpl::new();
+        let now = Utc::now();
+        let clock = FixClock::new(now);
+        let id_generator = SeqIdGenerator::new();
+
+        let master = Token::create_master(
+            "user".to_string(),
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        let later = FixClock::new(now + Duration::days(1));
+        let general = Token::create_general(
+            "user".to_string(),
+            "client".to_string(),
+            vec![TokenScope::Read],
+            TokenDevice::default(),
+            &later,
+            &id_generator,
+            &id_generator,
+        );
+        let other_user = Token::create_master(
+            "other".to_string(),
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        for token in [&master, &general, &other_user] {
+            token_repo.insert(token).await.unwrap();
+        }
+        let auth = AuthTokenMaster {
+            base: AuthTokenBase {
+                id: master.id.clone(),
+                key: master.access_token.clone(),
+                user: "user".to_string(),
+            },
+        };
+
+        let sessions = list_sessions(&token_repo, &auth, &later).await.unwrap();
+        let ids: Vec<&str> = sessions.iter().map(|token| token.id.as_str()).collect();
+        assert_eq!(ids, vec![general.id.as_str(), master.id.as_str()]);
+
+        // 期限が切れたセッションは出さない
+        let expired = FixClock::new(now + Duration::days(MASTER_TOKEN_LIFETIME_DAYS));
+        let sessions = list_sessions(&token_repo, &auth, &expired).await.unwrap();
+        let ids: Vec<&str> = sessions.iter().map(|token| token.id.as_str()).collect();
+        assert_eq!(ids, vec![general.id.as_str()]);
+    }
+}
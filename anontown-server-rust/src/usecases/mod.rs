@@ -1,7 +1,53 @@
 pub mod get_history;
 pub mod get_profile;
 pub mod get_client;
+pub mod cron_topic_check;
+pub mod link_res_replies;
+pub mod get_reply_tree;
+pub mod continue_topic;
+pub mod acquire_rate_limit;
+pub mod retry_on_conflict;
+pub mod authenticate_token;
+pub mod subscribe_topic_events;
+pub mod authorize_oauth_client;
+pub mod exchange_oauth_code;
+pub mod rotate_token;
+pub mod list_sessions;
+pub mod revoke_session;
+pub mod revoke_other_sessions;
+pub mod create_pairing_code;
+pub mod redeem_pairing_code;
+pub mod authenticate_user;
+pub mod enroll_totp;
+pub mod confirm_totp;
+pub mod disable_totp;
+pub mod verify_second_factor;
+pub mod create_recovery_codes;
+pub mod recover_account;
 
 pub use get_history::get_history;
 pub use get_profile::get_profile;
-pub use get_client::get_client; 
\ No newline at end of file
+pub use get_client::get_client;
+pub use cron_topic_check::cron_topic_check;
+pub use link_res_replies::link_res_replies;
+pub use get_reply_tree::get_reply_tree;
+pub use continue_topic::continue_topic;
+pub use acquire_rate_limit::{acquire_rate_limit, release_rate_limit};
+pub use retry_on_conflict::retry_on_conflict;
+pub use authenticate_token::authenticate_token;
+pub use subscribe_topic_events::subscribe_topic_events;
+pub use authorize_oauth_client::authorize_oauth_client;
+pub use exchange_oauth_code::exchange_oauth_code;
+pub use rotate_token::rotate_token;
+pub use list_sessions::list_sessions;
+pub use revoke_session::revoke_session;
+pub use revoke_other_sessions::revoke_other_sessions;
+pub use create_pairing_code::create_pairing_code;
+pub use redeem_pairing_code::redeem_pairing_code;
+pub use authenticate_user::authenticate_user;
+pub use enroll_totp::enroll_totp;
+pub use confirm_totp::confirm_totp;
+pub use disable_totp::disable_totp;
+pub use verify_second_factor::verify_second_factor;
+pub use create_recovery_codes::create_recovery_codes;
+pub use recover_account::recover_account;
@@ -0,0 +1,157 @@
+use crate::at_error::{AtError, AtResult};
+use crate::entities::password::{self, PasswordConfig};
+use crate::entities::recovery_code::RecoveryCode;
+use crate::ports::unit_of_work::UnitOfWork;
+use crate::ports::user::UserPort;
+use crate::ports::{RecoveryCodeRepo, TokenRepo};
+use crate::usecases::retry_on_conflict;
+
+/// 回復コードでパスワードを再設定し、全ての端末をログアウトさせる
+///
+/// 回復コードの消費、パスワードの更新、ログアウトは1つのトランザクションで行う。
+/// 途中で失敗しても回復コードだけが使用済みになることはなく、
+/// 並行して同じコードが使われた場合は片方だけが成功する。
+/// 二要素認証が有効なユーザーは、再設定後のログインでも二要素認証が必要
+///
+/// # エラー
+/// * ユーザーが存在しない、または回復コードが一致しないか使用済みの場合は`AtError::UserAuth`
+pub async fn recover_account(
+    unit_of_work: &impl UnitOfWork,
+    screen_name: &str,
+    code: &str,
+    password: &str,
+    config: &PasswordConfig,
+) -> AtResult<()> {
+    let code_hash = RecoveryCode::hash_input(code).ok_or(AtError::UserAuth)?;
+    // 再試行のたびにハッシュを計算し直さないように先に計算する
+    let password_hash = password::hash_password(password, config)?;
+
+    let (code_hash, password_hash) = (&code_hash, &password_hash);
+    // 同時にユーザーが更新されて競合したら取得からやり直す
+    retry_on_conflict(|| async move {
+        unit_of_work
+            .run(|repos| {
+                Box::pin(async move {
+                    let mut user = repos
+                        .user_repo
+                        .find_by_screen_name(screen_name)
+                        .await?
+                        .ok_or(AtError::UserAuth)?;
+                    match repos.recovery_code_repo.consume(&user.id, code_hash).await {
+                        Ok(()) => {}
+                        Err(AtError::NotFound(_)) => return Err(AtError::UserAuth.into()),
+                        Err(e) => return Err(e.into()),
+                    }
+
+                    user.reset_password(password_hash.clone());
+                    repos.user_repo.update(&user).await?;
+                    repos.token_repo.del_all(&user.id).await?;
+                    Ok(())
+                })
+            })
+            .await
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::{
+        HistoryRepoMock, ProfileRepoMock, RecoveryCodeRepoMockImpl, ResRepoMock, StorageRepoMock,
+        TokenRepoMockImpl, TopicRepoMock, UnitOfWorkMockImpl, UserRepoMock,
+    };
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::auth::{AuthTokenBase, AuthTokenMaster};
+    use crate::entities::token::TokenDevice;
+    use crate::entities::{Token, User};
+    use crate::ports::unit_of_work::Repos;
+    use crate::usecases::{authenticate_user, create_recovery_codes};
+    use chrono::Utc;
+
+    fn config() -> PasswordConfig {
+        PasswordConfig {
+            memory_kib: 8,
+            iterations: 1,
+            ..PasswordConfig::new("salt".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recover_account() {
+        let config = config();
+        let mut user_repo = UserRepoMock::new();
+        let token_repo = TokenRepoMockImpl::new();
+        let recovery_code_repo = RecoveryCodeRepoMockImpl::new();
+        let unit_of_work = UnitOfWorkMockImpl::new(Repos {
+            topic_repo: TopicRepoMock::new(),
+            user_repo: user_repo.clone(),
+            res_repo: ResRepoMock::new(),
+            history_repo: HistoryRepoMock::new(),
+            profile_repo: ProfileRepoMock::new(),
+            storage_repo: StorageRepoMock::new(),
+            token_repo: token_repo.clone(),
+            recovery_code_repo: recovery_code_repo.clone(),
+        });
+        let clock = FixClock::new(Utc::now());
+        let id_generator = SeqIdGenerator::new();
+
+        let user = User::create(
+            &id_generator,
+            "sn".to_string(),
+            "name".to_string(),
+            "email".to_string(),
+            password::hash_password("old", &config).unwrap(),
+        );
+        user_repo.create(&user).await.unwrap();
+        let token = Token::create_master(
+            user.id.clone(),
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        token_repo.insert(&token).await.unwrap();
+
+        let auth = AuthTokenMaster {
+            base: AuthTokenBase {
+                id: token.id.clone(),
+                key: token.access_token.clone(),
+                user: user.id.clone(),
+            },
+        };
+        let codes = create_recovery_codes(&recovery_code_repo, &auth, &clock, &id_generator)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            recover_account(&unit_of_work, "other", &codes[0], "new", &config).await,
+            Err(AtError::UserAuth)
+        ));
+        assert!(matches!(
+            recover_account(&unit_of_work, "sn", "wrong", "new", &config).await,
+            Err(AtError::UserAuth)
+        ));
+
+        recover_account(&unit_of_work, "sn", &codes[0], "new", &config)
+            .await
+            .unwrap();
+        assert!(authenticate_user(&mut user_repo, &user.id, "new", &config, &clock).await.is_ok());
+        assert!(matches!(
+            authenticate_user(&mut user_repo, &user.id, "old", &config, &clock).await,
+            Err(AtError::UserAuth)
+        ));
+        // 全ての端末がログアウトされる
+        assert!(token_repo.find_all(&auth).await.unwrap().is_empty());
+
+        // 回復コードは1回しか使えない
+        assert!(matches!(
+            recover_account(&unit_of_work, "sn", &codes[0], "other", &config).await,
+            Err(AtError::UserAuth)
+        ));
+        assert_eq!(recovery_code_repo.count(&user.id).await.unwrap(), codes.len() as i64 - 1);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::at_error::{AtError, AtResult};
+use crate::entities::rate_limit_rule::RateLimitAction;
+use crate::entities::token::{Token, TokenDevice};
+use crate::entities::token_req::{TokenReq, PAIRING_MAX_FAILED_ATTEMPTS};
+use crate::ports::rate_limiter::RateLimiter;
+use crate::ports::{
+    clock::ClockPort, object_id_generator::ObjectIdGeneratorPort,
+    safe_id_generator::SafeIdGeneratorPort, TokenRepo, TokenReqRepo,
+};
+
+/// 引き継ぎコードを使って、新しい端末用のマスタートークンを発行する
+///
+/// 引き継ぎコードは短いので、総当たりされないように成否に関係なく入力をIPごとに制限する。
+/// さらに前半が一致して後半が違う入力を引き継ぎコードごとに数え、一定回数で無効にする
+///
+/// # 引数
+/// * `code` - 入力された引き継ぎコード、またはQRコードのURI
+/// * `device` - 引き継ぐ新しい端末
+///
+/// # 返り値
+/// 新しい端末のマスタートークン
+///
+/// # エラー
+/// * 入力回数の制限に達している場合は`AtError::Prerequisite`
+/// * 引き継ぎコードが存在しない、使用済み、期限切れ、または引き継ぎ元の端末がログアウトしている場合は`AtError::Auth`
+#[allow(clippy::too_many_arguments)]
+pub async fn redeem_pairing_code(
+    token_repo: &impl TokenRepo,
+    token_req_repo: &impl TokenReqRepo,
+    rate_limiter: &impl RateLimiter,
+    code: &str,
+    device: TokenDevice,
+    clock: &impl ClockPort,
+    object_id_generator: &impl ObjectIdGeneratorPort,
+    safe_id_generator: &impl SafeIdGeneratorPort,
+) -> AtResult<Token> {
+    // IPが分からない場合はまとめて数える
+    let ip = device.ip.as_deref().unwrap_or("unknown");
+    let action = RateLimitAction::Pairing;
+    rate_limiter
+        .acquire(ip, action, &action.rules(0), clock.now())
+        .await
+        .map_err(|e| match e {
+            AtError::Prerequisite(_) => AtError::Prerequisite(
+                "引き継ぎコードの入力回数が多すぎます。しばらく待ってから入力してください".to_string(),
+            ),
+            e => e,
+        })?;
+
+    // どの理由で失敗したかは教えない
+    let invalid = || AtError::Auth("引き継ぎコードが無効です".to_string());
+
+    let key = TokenReq::normalize_pairing_code(code).ok_or_else(invalid)?;
+    let candidates = token_req_repo
+        .find_pairing(TokenReq::pairing_selector(&key))
+        .await?;
+    let Some(mut req) = candidates
+        .iter()
+        .find(|req| req.matches_pairing_code(&key))
+        .cloned()
+    else {
+        // IPを変えながら1つのコードを狙われても、試行回数がコードごとに制限されるようにする
+        for candidate in &candidates {
+            token_req_repo
+                .add_failed_attempt(candidate, PAIRING_MAX_FAILED_ATTEMPTS)
+                .await?;
+        }
+        return Err(invalid());
+    };
+    req.redeem_pairing(clock)?;
+
+    let source = token_repo.find_one(&req.token_id).await.map_err(|_| invalid())?;
+    if !source.is_master() || !source.is_active(clock) {
+        return Err(invalid());
+    }
+
+    // 並行して同じ引き継ぎコードが使われた場合は片方だけが成功する
+    token_req_repo.update(&req).await.map_err(|e| match e {
+        AtError::Conflict(_) => invalid(),
+        e => e,
+    })?;
+
+    let token = Token::create_master(
+        source.user_id,
+        device,
+        clock,
+        object_id_generator,
+        safe_id_generator,
+    );
+    token_repo.insert(&token).await?;
+
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::{RateLimiterMockImpl, TokenRepoMockImpl, TokenReqRepoMockImpl};
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::auth::{AuthToken, AuthTokenBase, AuthTokenMaster};
+    use crate::entities::token_req::PAIRING_CODE_LIFETIME_MINUTES;
+    use crate::usecases::create_pairing_code;
+    use chrono::{Duration, Utc};
+
+    fn device(ip: &str) -> TokenDevice {
+        TokenDevice {
+            ip: Some(ip.to_string()),
+            user_agent: Some("Mozilla/5.0".to_string()),
+        }
+    }
+
+    async fn setup(
+        token_repo: &TokenRepoMockImpl,
+        clock: &FixClock,
+        id_generator: &SeqIdGenerator,
+    ) -> (Token, AuthTokenMaster) {
+        let master = Token::create_master(
+            "user".to_string(),
+            device("192.0.2.1"),
+            clock,
+            id_generator,
+            id_generator,
+        );
+        token_repo.insert(&master).await.unwrap();
+        let auth = AuthTokenMaster {
+            base: AuthTokenBase {
+                id: master.id.clone(),
+                key: master.access_token.clone(),
+                user: master.user_id.clone(),
+            },
+        };
+        (master, auth)
+    }
+
+    #[tokio::test]
+    async fn test_redeem_pairing_code() {
+        let token_repo = TokenRepoMockImpl::new();
+        let token_req_repo = TokenReqRepoMockImpl::new();
+        let rate_limiter = RateLimiterMockImpl::new();
+        let now = Utc::now();
+        let clock = FixClock::new(now);
+        let id_generator = SeqIdGenerator::new();
+        let (master, auth) = setup(&token_repo, &clock, &id_generator).await;
+
+        let req = create_pairing_code(&token_req_repo, &auth, &clock, &id_generator)
+            .await
+            .unwrap();
+
+        // 期限切れ
+        let expired = FixClock::new(now + Duration::minutes(PAIRING_CODE_LIFETIME_MINUTES));
+        assert!(matches!(
+            redeem_pairing_code(
+                &token_repo,
+                &token_req_repo,
+                &rate_limiter,
+                &req.pairing_code(),
+                device("198.51.100.1"),
+                &expired,
+                &id_generator,
+                &id_generator,
+            )
+            .await,
+            Err(AtError::Auth(_))
+        ));
+
+        let token = redeem_pairing_code(
+            &token_repo,
+            &token_req_repo,
+            &rate_limiter,
+            &req.pairing_code().to_lowercase(),
+            device("198.51.100.1"),
+            &clock,
+            &id_generator,
+            &id_generator,
+        )
+        .await
+        .unwrap();
+        assert_ne!(token.id, master.id);
+        assert_ne!(token.family_id, master.family_id);
+        assert_eq!(token.device, device("198.51.100.1"));
+        assert!(matches!(
+            token.auth(&token.access_token, &clock),
+            Ok(AuthToken::Master(auth)) if auth.base.user == "user"
+        ));
+
+        // 2回は使えない
+        assert!(matches!(
+            redeem_pairing_code(
+                &token_repo,
+                &token_req_repo,
+                &rate_limiter,
+                &req.pairing_qr_payload(),
+                device("198.51.100.2"),
+                &clock,
+                &id_generator,
+                &id_generator,
+            )
+            .await,
+            Err(AtError::Auth(_))
+        ));
+
+        // 引き継ぎ元がログアウトしたら使えない
+        let req = create_pairing_code(&token_req_repo, &auth, &clock, &id_generator)
+            .await
+            .unwrap();
+        token_repo.del_family(&master.family_id).await.unwrap();
+        assert!(matches!(
+            redeem_pairing_code(
+                &token_repo,
+                &token_req_repo,
+                &rate_limiter,
+                &req.pairing_code(),
+                device("198.51.100.3"),
+                &clock,
+                &id_generator,
+                &id_generator,
+            )
+            .await,
+            Err(AtError::Auth(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_redeem_pairing_code_rate_limit() {
+        let token_repo = TokenRepoMockImpl::new();
+        let token_req_repo = TokenReqRepoMockImpl::new();
+        let rate_limiter = RateLimiterMockImpl::new();
+        let clock = FixClock::new(Utc::now());
+        let id_generator = SeqIdGenerator::new();
+        let (_, auth) = setup(&token_repo, &clock, &id_generator).await;
+        let req = create_pairing_code(&token_req_repo, &auth, &clock, &id_generator)
+            .await
+            .unwrap();
+
+        let max = RateLimitAction::Pairing.rules(0)[0].max;
+        for _ in 0..max {
+            assert!(matches!(
+                redeem_pairing_code(
+                    &token_repo,
+                    &token_req_repo,
+                    &rate_limiter,
+                    "ZZZZ-ZZZZ",
+                    device("203.0.113.1"),
+                    &clock,
+                    &id_generator,
+                    &id_generator,
+                )
+                .await,
+                Err(AtError::Auth(_))
+            ));
+        }
+
+        // 制限に達したら正しいコードでも使えない
+        assert!(matches!(
+            redeem_pairing_code(
+                &token_repo,
+                &token_req_repo,
+                &rate_limiter,
+                &req.pairing_code(),
+                device("203.0.113.1"),
+                &clock,
+                &id_generator,
+                &id_generator,
+            )
+            .await,
+            Err(AtError::Prerequisite(_))
+        ));
+
+        // 他のIPからは使える
+        assert!(redeem_pairing_code(
+            &token_repo,
+            &token_req_repo,
+            &rate_limiter,
+            &req.pairing_code(),
+            device("203.0.113.2"),
+            &clock,
+            &id_generator,
+            &id_generator,
+        )
+        .await
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_redeem_pairing_code_failed_attempts() {
+        let token_repo = TokenRepoMockImpl::new();
+        let token_req_repo = TokenReqRepoMockImpl::new();
+        let rate_limiter = RateLimiterMockImpl::new();
+        let clock = FixClock::new(Utc::now());
+        let id_generator = SeqIdGenerator::new();
+        let (_, auth) = setup(&token_repo, &clock, &id_generator).await;
+        let req = create_pairing_code(&token_req_repo, &auth, &clock, &id_generator)
+            .await
+            .unwrap();
+
+        // 前半だけ一致するコードをIPを変えながら入力する
+        let selector = TokenReq::pairing_selector(&req.key);
+        let tail = if req.key.ends_with("ZZZZ") {
+            "YYYY"
+        } else {
+            "ZZZZ"
+        };
+        let wrong = format!("{}{}", selector, tail);
+        for i in 0..PAIRING_MAX_FAILED_ATTEMPTS {
+            assert!(matches!(
+                redeem_pairing_code(
+                    &token_repo,
+                    &token_req_repo,
+                    &rate_limiter,
+                    &wrong,
+                    device(&format!("203.0.113.{}", i)),
+                    &clock,
+                    &id_generator,
+                    &id_generator,
+                )
+                .await,
+                Err(AtError::Auth(_))
+            ));
+        }
+
+        // 上限に達したら正しいコードでも使えない
+        assert!(matches!(
+            redeem_pairing_code(
+                &token_repo,
+                &token_req_repo,
+                &rate_limiter,
+                &req.pairing_code(),
+                device("198.51.100.1"),
+                &clock,
+                &id_generator,
+                &id_generator,
+            )
+            .await,
+            Err(AtError::Auth(_))
+        ));
+    }
+}
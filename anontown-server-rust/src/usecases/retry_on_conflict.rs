@@ -0,0 +1,81 @@
+use std::future::Future;
+
+use crate::at_error::AtError;
+
+/// 競合した時に再試行する最大回数
+pub const MAX_CONFLICT_RETRIES: usize = 3;
+
+/// `AtError::Conflict`で失敗した場合に`f`を最大`MAX_CONFLICT_RETRIES`回まで再試行する
+///
+/// `f`は毎回エンティティを取得し直すところから実行する必要がある
+///
+/// # 返り値
+/// 最後に実行した`f`の結果
+///
+/// # エラー
+/// * 再試行しても競合した場合は`AtError::Conflict`
+/// * それ以外のエラーは再試行せずにそのまま返す
+pub async fn retry_on_conflict<T, F, Fut>(mut f: F) -> Result<T, Box<dyn std::error::Error>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Box<dyn std::error::Error>>>,
+{
+    let mut retries = 0;
+    loop {
+        match f().await {
+            Err(e) if is_conflict(e.as_ref()) && retries < MAX_CONFLICT_RETRIES => {
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_conflict(e: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(e.downcast_ref::<AtError>(), Some(AtError::Conflict(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[tokio::test]
+    async fn test_retry_on_conflict() {
+        // 競合が解消すれば成功する
+        let calls = Cell::new(0);
+        let result = retry_on_conflict(|| async {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err(Box::new(AtError::Conflict("conflict".to_string())) as Box<dyn std::error::Error>)
+            } else {
+                Ok(calls.get())
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        // 上限まで再試行したら諦める
+        let calls = Cell::new(0);
+        let result: Result<(), _> = retry_on_conflict(|| async {
+            calls.set(calls.get() + 1);
+            Err(Box::new(AtError::Conflict("conflict".to_string())) as Box<dyn std::error::Error>)
+        })
+        .await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<AtError>(),
+            Some(AtError::Conflict(_))
+        ));
+        assert_eq!(calls.get(), MAX_CONFLICT_RETRIES + 1);
+
+        // 競合以外のエラーは再試行しない
+        let calls = Cell::new(0);
+        let result: Result<(), _> = retry_on_conflict(|| async {
+            calls.set(calls.get() + 1);
+            Err(Box::new(AtError::NotFound("not found".to_string())) as Box<dyn std::error::Error>)
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+}
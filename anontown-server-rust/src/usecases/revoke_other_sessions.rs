@@ -0,0 +1,82 @@
+use crate::at_error::AtResult;
+use crate::auth::AuthTokenMaster;
+use crate::ports::TokenRepo;
+
+/// 今使っているセッション以外のセッションを全て無効にする
+///
+/// マスタートークンだけでなくクライアントに発行したトークンも無効になる
+pub async fn revoke_other_sessions(
+    token_repo: &impl TokenRepo,
+    auth: &AuthTokenMaster,
+) -> AtResult<()> {
+    let current = token_repo.find_one(&auth.base.id).await?;
+    token_repo
+        .del_other_families(&auth.base.user, &current.family_id)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::TokenRepoMockImpl;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::auth::{AuthTokenBase, TokenScope};
+    use crate::entities::token::TokenDevice;
+    use crate::entities::Token;
+    use chrono::Utc;
+
+    #[tokio::test]
+    async fn test_revoke_other_sessions() {
+        let token_repo = TokenRepoMockImpl::new();
+        let clock = FixClock::new(Utc::now());
+        let id_generator = SeqIdGenerator::new();
+
+        let current = Token::create_master(
+            "user".to_string(),
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        let master = Token::create_master(
+            "user".to_string(),
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        let general = Token::create_general(
+            "user".to_string(),
+            "client".to_string(),
+            vec![TokenScope::Read],
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        let other_user = Token::create_master(
+            "other".to_string(),
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        for token in [&current, &master, &general, &other_user] {
+            token_repo.insert(token).await.unwrap();
+        }
+        let auth = AuthTokenMaster {
+            base: AuthTokenBase {
+                id: current.id.clone(),
+                key: current.access_token.clone(),
+                user: "user".to_string(),
+            },
+        };
+
+        revoke_other_sessions(&token_repo, &auth).await.unwrap();
+        assert!(token_repo.find_one(&current.id).await.is_ok());
+        assert!(token_repo.find_one(&master.id).await.is_err());
+        assert!(token_repo.find_one(&general.id).await.is_err());
+        assert!(token_repo.find_one(&other_user.id).await.is_ok());
+    }
+}
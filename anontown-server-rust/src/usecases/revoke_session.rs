@@ -0,0 +1,102 @@
+use crate::at_error::{AtError, AtResult};
+use crate::auth::AuthTokenMaster;
+use crate::ports::TokenRepo;
+
+/// セッションを一つ無効にする
+///
+/// 更新されたトークンも含めて、同じファミリーのトークンを全て削除する。今使っているセッションも指定できる
+///
+/// # 引数
+/// * `id` - `list_sessions`で取得したトークンのID
+///
+/// # エラー
+/// * トークンが存在しない、または他のユーザーのものの場合は`AtError::NotFound`
+pub async fn revoke_session(
+    token_repo: &impl TokenRepo,
+    auth: &AuthTokenMaster,
+    id: &str,
+) -> AtResult<()> {
+    let token = token_repo.find_one(id).await?;
+    // 他のユーザーのトークンが存在するかどうかは教えない
+    if !token.is_self(&auth.base.user) {
+        return Err(AtError::NotFound("トークンが存在しません".to_string()));
+    }
+
+    token_repo.del_family(&token.family_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::TokenRepoMockImpl;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::auth::{AuthTokenBase, TokenScope};
+    use crate::entities::token::TokenDevice;
+    use crate::entities::Token;
+    use crate::usecases::rotate_token;
+    use chrono::Utc;
+
+    #[tokio::test]
+    async fn test_revoke_session() {
+        let token_repo = TokenRepoMockImpl::new();
+        let clock = FixClock::new(Utc::now());
+        let id_generator = SeqIdGenerator::new();
+
+        let master = Token::create_master(
+            "user".to_string(),
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        let general = Token::create_general(
+            "user".to_string(),
+            "client".to_string(),
+            vec![TokenScope::Read],
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        let other_user = Token::create_master(
+            "other".to_string(),
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        for token in [&master, &general, &other_user] {
+            token_repo.insert(token).await.unwrap();
+        }
+        let rotated = rotate_token(
+            &token_repo,
+            &format!("{},{}", general.id, general.refresh_token),
+            None,
+            &clock,
+            &id_generator,
+            &id_generator,
+        )
+        .await
+        .unwrap();
+        let auth = AuthTokenMaster {
+            base: AuthTokenBase {
+                id: master.id.clone(),
+                key: master.access_token.clone(),
+                user: "user".to_string(),
+            },
+        };
+
+        assert!(matches!(
+            revoke_session(&token_repo, &auth, &other_user.id).await,
+            Err(AtError::NotFound(_))
+        ));
+        assert!(token_repo.find_one(&other_user.id).await.is_ok());
+
+        // 更新前のトークンも同じセッションとして消える
+        revoke_session(&token_repo, &auth, &rotated.id).await.unwrap();
+        assert!(token_repo.find_one(&rotated.id).await.is_err());
+        assert!(token_repo.find_one(&general.id).await.is_err());
+        assert!(token_repo.find_one(&master.id).await.is_ok());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::at_error::{AtError, AtResult};
+use crate::entities::Token;
+use crate::ports::{
+    clock::ClockPort, object_id_generator::ObjectIdGeneratorPort,
+    safe_id_generator::SafeIdGeneratorPort, TokenRepo,
+};
+
+/// リフレッシュトークンでアクセストークンとリフレッシュトークンを両方更新する
+///
+/// 使用済みのリフレッシュトークンが再び使われた場合は漏洩したとみなし、
+/// 同じファミリーのトークンを全て無効にする
+///
+/// # 引数
+/// * `raw` - `{id},{refresh_token}`形式の文字列
+/// * `client_id` - トークンエンドポイントでリクエストしたクライアント。指定した場合はトークンのクライアントと一致しなければならない
+///
+/// # 返り値
+/// 新しいトークン
+///
+/// # エラー
+/// * 形式が不正、トークンが存在しない、リフレッシュトークンが一致しない、他のクライアントのもの、
+///   期限切れ、または使用済みの場合は`AtError::TokenAuth`
+pub async fn rotate_token(
+    token_repo: &impl TokenRepo,
+    raw: &str,
+    client_id: Option<&str>,
+    clock: &impl ClockPort,
+    id_generator: &impl ObjectIdGeneratorPort,
+    safe_id_generator: &impl SafeIdGeneratorPort,
+) -> AtResult<Token> {
+    let (id, refresh_token) = raw.split_once(',').ok_or(AtError::TokenAuth)?;
+    let mut token = token_repo
+        .find_one(id.trim())
+        .await
+        .map_err(|_| AtError::TokenAuth)?;
+    if !token.verify_refresh_token(refresh_token.trim()) {
+        return Err(AtError::TokenAuth);
+    }
+    // 他のクライアントに渡ったリフレッシュトークンでは更新しない
+    if client_id.is_some_and(|client_id| !token.is_client(client_id)) {
+        return Err(AtError::TokenAuth);
+    }
+    if token.is_rotated() {
+        token_repo.del_family(&token.family_id).await?;
+        return Err(AtError::TokenAuth);
+    }
+
+    let new_token = token.rotate(clock, id_generator, safe_id_generator)?;
+    match token_repo.rotate(&token, &new_token).await {
+        Ok(()) => Ok(new_token),
+        // 取得してから保存するまでに他で使われた
+        Err(AtError::Conflict(_)) => {
+            token_repo.del_family(&token.family_id).await?;
+            Err(AtError::TokenAuth)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::TokenRepoMockImpl;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::auth::TokenScope;
+    use crate::entities::token::TokenDevice;
+    use crate::usecases::authenticate_token;
+    use chrono::{Duration, Utc};
+
+    fn refresh_raw(token: &Token) -> String {
+        format!("{},{}", token.id, token.refresh_token)
+    }
+
+    fn access_raw(token: &Token) -> String {
+        format!("{},{}", token.id, token.access_token)
+    }
+
+    #[tokio::test]
+    async fn test_rotate_token() {
+        let token_repo = TokenRepoMockImpl::new();
+        let now = Utc::now();
+        let clock = FixClock::new(now);
+        let id_generator = SeqIdGenerator::new();
+
+        let token = Token::create_general(
+            "user".to_string(),
+            "client".to_string(),
+            vec![TokenScope::Read],
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        token_repo.insert(&token).await.unwrap();
+
+        // アクセストークンの期限が切れたら認証できない
+        let later = FixClock::new(token.expires_at);
+        assert!(matches!(
+            authenticate_token(&token_repo, &later, &access_raw(&token)).await,
+            Err(AtError::TokenAuth)
+        ));
+
+        assert!(matches!(
+            rotate_token(&token_repo, &format!("{},wrong", token.id), None, &later, &id_generator, &id_generator).await,
+            Err(AtError::TokenAuth)
+        ));
+
+        // 他のクライアントからは更新できない
+        assert!(matches!(
+            rotate_token(&token_repo, &refresh_raw(&token), Some("other"), &later, &id_generator, &id_generator).await,
+            Err(AtError::TokenAuth)
+        ));
+
+        let rotated = rotate_token(&token_repo, &refresh_raw(&token), Some("client"), &later, &id_generator, &id_generator)
+            .await
+            .unwrap();
+        assert_eq!(rotated.family_id, token.family_id);
+        assert!(authenticate_token(&token_repo, &later, &access_raw(&rotated)).await.is_ok());
+
+        let rotated2 = rotate_token(&token_repo, &refresh_raw(&rotated), None, &later, &id_generator, &id_generator)
+            .await
+            .unwrap();
+
+        // 使用済みのリフレッシュトークンが使われたらファミリーごと無効にする
+        assert!(matches!(
+            rotate_token(&token_repo, &refresh_raw(&token), None, &later, &id_generator, &id_generator).await,
+            Err(AtError::TokenAuth)
+        ));
+        assert!(matches!(
+            authenticate_token(&token_repo, &later, &access_raw(&rotated2)).await,
+            Err(AtError::TokenAuth)
+        ));
+        assert!(matches!(
+            rotate_token(&token_repo, &refresh_raw(&rotated2), None, &later, &id_generator, &id_generator).await,
+            Err(AtError::TokenAuth)
+        ));
+
+        // 他のファミリーには影響しない
+        let other = Token::create_general(
+            "user".to_string(),
+            "client".to_string(),
+            vec![TokenScope::Read],
+            TokenDevice::default(),
+            &clock,
+            &id_generator,
+            &id_generator,
+        );
+        token_repo.insert(&other).await.unwrap();
+        let expired = FixClock::new(now + Duration::days(1));
+        assert!(authenticate_token(&token_repo, &clock, &access_raw(&other)).await.is_ok());
+        assert!(rotate_token(&token_repo, &refresh_raw(&other), None, &expired, &id_generator, &id_generator)
+            .await
+            .is_ok());
+    }
+}
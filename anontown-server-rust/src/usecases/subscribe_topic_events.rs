@@ -0,0 +1,172 @@
+use futures::stream;
+use futures::StreamExt;
+use std::collections::HashMap;
+
+use crate::at_error::{AtError, AtResult};
+use crate::entities::topic_event::TopicEventFilter;
+use crate::ports::topic::TopicPort;
+use crate::ports::topic_event_bus::{TopicEventBus, TopicEventSubscription};
+
+/// トピックまたはタグのイベントを購読する
+///
+/// タグで購読する場合はイベントが起きたトピックのタグを取得して絞り込む。
+/// タグは接続中はキャッシュするので、途中で変更されたタグは再接続するまで反映されない
+///
+/// # 引数
+/// * `filter` - 購読するトピックまたはタグ
+/// * `last_event_id` - 最後に受け取ったイベントの連番。指定するとそれより後のイベントから流す
+///
+/// # 返り値
+/// 条件に合うイベントのストリーム。`last_event_id`より後のイベントを取りこぼしていれば`missed`になる
+///
+/// # エラー
+/// * 購読できない場合は`AtError::Internal`
+pub async fn subscribe_topic_events<T>(
+    filter: TopicEventFilter,
+    last_event_id: Option<i64>,
+    topic_event_bus: &impl TopicEventBus,
+    topic_repo: T,
+) -> AtResult<TopicEventSubscription>
+where
+    T: TopicPort + Send + 'static,
+{
+    let TopicEventSubscription { missed, events } =
+        topic_event_bus.subscribe(last_event_id).await?;
+    let state = (events, topic_repo, HashMap::<String, Vec<String>>::new());
+
+    let events = stream::unfold(state, move |(mut events, mut topic_repo, mut tags)| {
+        let filter = filter.clone();
+        async move {
+            loop {
+                let record = match events.next().await? {
+                    Ok(record) => record,
+                    Err(e) => return Some((Err(e), (events, topic_repo, tags))),
+                };
+
+                let topic_id = record.event.topic_id().to_string();
+                if filter.needs_tags() && !tags.contains_key(&topic_id) {
+                    let topic_tags = match topic_repo.find_one(&topic_id).await {
+                        Ok(topic) => topic.base().tags.clone(),
+                        Err(e) => {
+                            let e = AtError::Internal(anyhow::anyhow!(e.to_string()));
+                            return Some((Err(e), (events, topic_repo, tags)));
+                        }
+                    };
+                    tags.insert(topic_id.clone(), topic_tags);
+                }
+
+                let topic_tags = tags.get(&topic_id).map(Vec::as_slice).unwrap_or(&[]);
+                if filter.matches(&record.event, topic_tags) {
+                    return Some((Ok(record), (events, topic_repo, tags)));
+                }
+            }
+        }
+    })
+    .boxed();
+
+    Ok(TopicEventSubscription { missed, events })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::adapters::{TopicEventBusMockImpl, TopicRepoMock};
+    use crate::entities::topic::{Topic, TopicNormal};
+    use crate::entities::topic_event::TopicEvent;
+    use chrono::Utc;
+
+    async fn insert_topic(topic_repo: &mut TopicRepoMock, id_gen: &SeqIdGenerator, tag: &str) -> String {
+        let topic = Topic::Normal(TopicNormal::create(
+            id_gen,
+            &FixClock::new(Utc::now()),
+            "title".to_string(),
+            "text".to_string(),
+            "user".to_string(),
+            vec![tag.to_string()],
+        ));
+        let id = topic.base().id.clone();
+        topic_repo.insert(&topic).await.unwrap();
+        id
+    }
+
+    fn res_added(res: &str, topic: &str) -> TopicEvent {
+        TopicEvent::ResAdded {
+            res: res.to_string(),
+            topic: topic.to_string(),
+            count: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_topic_events() {
+        let id_gen = SeqIdGenerator::new();
+        let bus = TopicEventBusMockImpl::new();
+        let mut topic_repo = TopicRepoMock::new();
+        let topic1 = insert_topic(&mut topic_repo, &id_gen, "雑談").await;
+        let topic2 = insert_topic(&mut topic_repo, &id_gen, "質問").await;
+
+        bus.publish(&res_added("res1", &topic1)).await.unwrap();
+        bus.publish(&res_added("res2", &topic2)).await.unwrap();
+        bus.publish(&TopicEvent::TopicClosed { topic: topic1.clone() }).await.unwrap();
+
+        // タグで絞り込む
+        let events = subscribe_topic_events(
+            TopicEventFilter::Tag("雑談".to_string()),
+            Some(0),
+            &bus,
+            topic_repo.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(!events.missed);
+        let records = events.events.take(2).map(Result::unwrap).collect::<Vec<_>>().await;
+        assert_eq!(records.iter().map(|r| r.seq).collect::<Vec<_>>(), vec![1, 3]);
+
+        // Last-Event-IDより後だけが流れ、新しいイベントも続けて流れる
+        let events = subscribe_topic_events(
+            TopicEventFilter::Topic(topic1.clone()),
+            Some(1),
+            &bus,
+            topic_repo.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(!events.missed);
+        bus.publish(&res_added("res3", &topic2)).await.unwrap();
+        bus.publish(&res_added("res4", &topic1)).await.unwrap();
+        let records = events.events.take(2).map(Result::unwrap).collect::<Vec<_>>().await;
+        assert_eq!(
+            records.into_iter().map(|r| r.event).collect::<Vec<_>>(),
+            vec![
+                TopicEvent::TopicClosed { topic: topic1.clone() },
+                res_added("res4", &topic1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_topic_events_missed() {
+        let id_gen = SeqIdGenerator::new();
+        let bus = TopicEventBusMockImpl::with_backlog(1);
+        let mut topic_repo = TopicRepoMock::new();
+        let topic = insert_topic(&mut topic_repo, &id_gen, "雑談").await;
+
+        bus.publish(&res_added("res1", &topic)).await.unwrap();
+        bus.publish(&res_added("res2", &topic)).await.unwrap();
+
+        // 連番1のイベントはもう残っていない
+        let events = subscribe_topic_events(
+            TopicEventFilter::Topic(topic.clone()),
+            Some(0),
+            &bus,
+            topic_repo.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(events.missed);
+        let records = events.events.take(1).map(Result::unwrap).collect::<Vec<_>>().await;
+        assert_eq!(records.iter().map(|r| r.seq).collect::<Vec<_>>(), vec![2]);
+    }
+}
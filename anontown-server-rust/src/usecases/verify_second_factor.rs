@@ -0,0 +1,95 @@
+use crate::at_error::{AtError, AtResult};
+use crate::ports::{clock::ClockPort, UserTotpRepo};
+
+/// 二要素認証が有効なユーザーについて、パスワードの次にコードを確認する
+///
+/// 有効でないユーザーは`code`に関わらず成功する。使ったコードは記録され、二度と使えない
+///
+/// # 引数
+/// * `code` - 認証アプリのコードかバックアップコード
+///
+/// # エラー
+/// * 二要素認証が有効で、コードが無い、一致しない、または使用済みの場合は`AtError::Auth`
+pub async fn verify_second_factor(
+    user_totp_repo: &impl UserTotpRepo,
+    user_id: &str,
+    code: Option<&str>,
+    clock: &impl ClockPort,
+) -> AtResult<()> {
+    let mut totp = match user_totp_repo.find_one(user_id).await? {
+        Some(totp) if totp.enabled => totp,
+        _ => return Ok(()),
+    };
+    let code = code.ok_or_else(|| AtError::Auth("二要素認証のコードが必要です".to_string()))?;
+    totp.verify(code, clock)?;
+
+    match user_totp_repo.update(&totp).await {
+        Ok(()) => Ok(()),
+        // 取得してから保存するまでに他でコードが使われた
+        Err(AtError::Conflict(_)) => Err(AtError::Auth(
+            "二要素認証のコードが正しくありません".to_string(),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::clock::fix_clock::FixClock;
+    use crate::adapters::UserTotpRepoMockImpl;
+    use crate::adapters::seq_id_generator::SeqIdGenerator;
+    use crate::entities::user_totp::UserTotp;
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[tokio::test]
+    async fn test_verify_second_factor() {
+        let user_totp_repo = UserTotpRepoMockImpl::new();
+        let now = Utc.timestamp_opt(1234567890, 0).unwrap();
+        let clock = FixClock::new(now);
+        let id_generator = SeqIdGenerator::new();
+
+        // 登録していなければコードは不要
+        assert!(verify_second_factor(&user_totp_repo, "user", None, &clock).await.is_ok());
+
+        let mut totp = UserTotp::create("user".to_string(), &clock, &id_generator);
+        let backup_codes = totp
+            .confirm(&totp.current_code(&clock), &clock, &id_generator)
+            .unwrap();
+        user_totp_repo.save(&totp).await.unwrap();
+
+        assert!(matches!(
+            verify_second_factor(&user_totp_repo, "user", None, &clock).await,
+            Err(AtError::Auth(_))
+        ));
+
+        let later = FixClock::new(now + Duration::minutes(1));
+        let code = totp.current_code(&later);
+        verify_second_factor(&user_totp_repo, "user", Some(&code), &later)
+            .await
+            .unwrap();
+        // 同じコードは二度使えない
+        assert!(matches!(
+            verify_second_factor(&user_totp_repo, "user", Some(&code), &later).await,
+            Err(AtError::Auth(_))
+        ));
+
+        verify_second_factor(&user_totp_repo, "user", Some(&backup_codes[0]), &later)
+            .await
+            .unwrap();
+        assert!(matches!(
+            verify_second_factor(&user_totp_repo, "user", Some(&backup_codes[0]), &later).await,
+            Err(AtError::Auth(_))
+        ));
+        assert_eq!(
+            user_totp_repo
+                .find_one("user")
+                .await
+                .unwrap()
+                .unwrap()
+                .backup_code_hashes
+                .len(),
+            backup_codes.len() - 1
+        );
+    }
+}
@@ -0,0 +1,427 @@
+//! 入力値のバリデーション
+//!
+//! 項目ごとにルールを並べて検査し、違反はまとめて`AtError::Params`で返す。
+//! 文字数は見た目の1文字(書記素クラスタ)で数え、検査は`normalize`した値に対して行う
+
+use crate::at_error::{AtError, AtResult, ParamErrorData};
+use std::collections::HashSet;
+use unicode_general_category::{get_general_category, GeneralCategory};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+// 取り除くゼロ幅文字。絵文字の結合や一部の文字体系で使うZWJ・ZWNJは残す
+const ZERO_WIDTH: &[char] = &['\u{200B}', '\u{2060}', '\u{FEFF}', '\u{180E}'];
+
+// 書式文字のうち、絵文字の結合や一部の文字体系で使うので文字の間にあれば許すZWNJ・ZWJ
+const JOINERS: &[char] = &['\u{200C}', '\u{200D}'];
+
+// 幅を持たない、または何も表示されない文字
+const INVISIBLE: &[char] = &[
+    '\u{200C}', '\u{200D}', '\u{200E}', '\u{200F}', '\u{115F}', '\u{1160}', '\u{3164}',
+    '\u{FFA0}', '\u{2800}',
+];
+
+/// 保存する前の入力の正規化
+///
+/// 改行を`\n`に揃え、ゼロ幅文字を取り除いてNFCにする
+pub fn normalize(value: &str) -> String {
+    value
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .chars()
+        .filter(|c| !ZERO_WIDTH.contains(c))
+        .nfc()
+        .collect()
+}
+
+/// 見た目の文字数
+pub fn grapheme_len(value: &str) -> usize {
+    value.graphemes(true).count()
+}
+
+/// 使えない書式文字(General_Category=Cf)を含むか
+///
+/// 双方向テキストの埋め込みや上書き(U+202A〜202E, U+2066〜2069)は表示を偽装できるので許さない。
+/// ZWNJ・ZWJは表示される文字の間にある場合だけ許す
+pub fn has_format_char(value: &str) -> bool {
+    let chars = value.chars().collect::<Vec<_>>();
+    let is_joinable = |c: Option<&char>| {
+        c.is_some_and(|&c| !c.is_whitespace() && !c.is_control() && !is_format(c))
+    };
+    chars.iter().enumerate().any(|(i, &c)| {
+        is_format(c)
+            && !(JOINERS.contains(&c)
+                && i > 0
+                && is_joinable(chars.get(i - 1))
+                && is_joinable(chars.get(i + 1)))
+    })
+}
+
+fn is_format(c: char) -> bool {
+    get_general_category(c) == GeneralCategory::Format
+}
+
+/// 空白・結合文字・不可視文字だけで、見た目が空か
+pub fn is_blank(value: &str) -> bool {
+    value
+        .chars()
+        .all(|c| c.is_whitespace() || is_combining_mark(c) || INVISIBLE.contains(&c))
+}
+
+/// 文字列のルール
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StrRule {
+    /// 空文字列を許さない
+    Required,
+    /// 空白や結合文字だけの見た目が空の文字列を許さない
+    Visible,
+    /// 改行を含む制御文字と、文字の間のZWNJ・ZWJ以外の書式文字を許さない
+    SingleLine,
+    /// 改行とタブ以外の制御文字と、文字の間のZWNJ・ZWJ以外の書式文字を許さない
+    MultiLine,
+    /// 文字数の上限
+    MaxLen(usize),
+    /// 文字数の下限
+    MinLen(usize),
+    /// 半角英数字と`_`以外を許さない
+    Word,
+}
+
+/// リストのルール
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListRule {
+    /// 要素数の上限
+    MaxItems(usize),
+    /// 同じ要素を許さない
+    Unique,
+}
+
+/// 違反を集めるバリデーター
+///
+/// `field`はクライアントが入力欄に対応付けられるよう、GraphQLの項目名で`tags[2]`や`date.to`のように指定する
+#[derive(Debug, Default)]
+#[must_use]
+pub struct Validator {
+    errors: Vec<ParamErrorData>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn str(self, field: &str, label: &str, value: &str, rules: &[StrRule]) -> Self {
+        self.check_str(field, label, &normalize(value), rules)
+    }
+
+    fn check_str(mut self, field: &str, label: &str, value: &str, rules: &[StrRule]) -> Self {
+        for rule in rules {
+            let error = match *rule {
+                StrRule::Required if value.is_empty() => Some(("required", format!("{}が空です", label))),
+                StrRule::Visible if is_blank(value) => Some(("blank", format!("{}が空白だけです", label))),
+                StrRule::SingleLine if value.chars().any(char::is_control) || has_format_char(value) => {
+                    Some(("invalid_char", format!("{}に使えない文字が含まれています", label)))
+                }
+                StrRule::MultiLine
+                    if value.chars().any(|c| c.is_control() && c != '\n' && c != '\t')
+                        || has_format_char(value) =>
+                {
+                    Some(("invalid_char", format!("{}に使えない文字が含まれています", label)))
+                }
+                StrRule::MaxLen(max) if grapheme_len(value) > max => {
+                    Some(("too_long", format!("{}が長すぎます({}文字以内)", label, max)))
+                }
+                StrRule::MinLen(min) if grapheme_len(value) < min => {
+                    Some(("too_short", format!("{}が短すぎます({}文字以上)", label, min)))
+                }
+                StrRule::Word if !value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => {
+                    Some(("invalid_char", format!("{}は半角英数字と_だけで入力してください", label)))
+                }
+                _ => None,
+            };
+
+            if let Some((code, message)) = error {
+                self.errors.push(ParamErrorData::new(field, code, message));
+                // 空なら他のルールは見ない
+                if matches!(rule, StrRule::Required | StrRule::Visible) {
+                    break;
+                }
+            }
+        }
+        self
+    }
+
+    /// 省略された場合は検査しない
+    pub fn opt_str(self, field: &str, label: &str, value: Option<&str>, rules: &[StrRule]) -> Self {
+        match value {
+            Some(value) => self.str(field, label, value, rules),
+            None => self,
+        }
+    }
+
+    /// リスト全体を`rules`で、各要素を`each`で検査する
+    pub fn list<S: AsRef<str>>(
+        mut self,
+        field: &str,
+        label: &str,
+        values: &[S],
+        rules: &[ListRule],
+        each: &[StrRule],
+    ) -> Self {
+        let values = values.iter().map(|v| normalize(v.as_ref())).collect::<Vec<_>>();
+        for rule in rules {
+            match *rule {
+                ListRule::MaxItems(max) if values.len() > max => {
+                    self.errors.push(ParamErrorData::new(
+                        field,
+                        "too_many",
+                        format!("{}が多すぎます({}個以内)", label, max),
+                    ));
+                }
+                ListRule::Unique => {
+                    let mut seen = HashSet::new();
+                    if let Some(i) = values.iter().position(|v| !seen.insert(v)) {
+                        self.errors.push(ParamErrorData::new(
+                            format!("{}[{}]", field, i),
+                            "duplicate",
+                            format!("{}に重複があります", label),
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (i, value) in values.iter().enumerate() {
+            self = self.check_str(&format!("{}[{}]", field, i), label, value, each);
+        }
+        self
+    }
+
+    /// 省略された場合は検査しない
+    pub fn opt_list<S: AsRef<str>>(
+        self,
+        field: &str,
+        label: &str,
+        values: Option<&[S]>,
+        rules: &[ListRule],
+        each: &[StrRule],
+    ) -> Self {
+        match values {
+            Some(values) => self.list(field, label, values, rules, each),
+            None => self,
+        }
+    }
+
+    /// ネストした入力を検査し、項目パスの前に`field.`を付ける
+    pub fn nested(mut self, field: &str, f: impl FnOnce(Validator) -> Validator) -> Self {
+        for mut e in f(Validator::new()).errors {
+            e.field = format!("{}.{}", field, e.field);
+            self.errors.push(e);
+        }
+        self
+    }
+
+    /// ルールで表せない違反を追加する
+    pub fn error(mut self, field: &str, code: &str, message: &str) -> Self {
+        self.errors.push(ParamErrorData::new(field, code, message));
+        self
+    }
+
+    /// 違反があれば`AtError::Params`
+    pub fn finish(self) -> AtResult<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AtError::Params(self.errors))
+        }
+    }
+}
+
+/// GraphQLの入力オブジェクトのバリデーション
+pub trait Validate {
+    fn check(&self, v: Validator) -> Validator;
+
+    fn validate(&self) -> AtResult<()> {
+        self.check(Validator::new()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(v: Validator) -> Vec<(String, String)> {
+        match v.finish() {
+            Ok(()) => vec![],
+            Err(AtError::Params(errors)) => errors.into_iter().map(|e| (e.field, e.code)).collect(),
+            Err(e) => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    fn pair(field: &str, code: &str) -> (String, String) {
+        (field.to_string(), code.to_string())
+    }
+
+    #[test]
+    fn test_str() {
+        let rules = &[StrRule::Required, StrRule::MaxLen(3)];
+        assert_eq!(errors(Validator::new().str("title", "タイトル", "abc", rules)), vec![]);
+        assert_eq!(errors(Validator::new().str("title", "タイトル", "あいう", rules)), vec![]);
+        assert_eq!(
+            errors(Validator::new().str("title", "タイトル", "", rules)),
+            vec![pair("title", "required")]
+        );
+        assert_eq!(
+            errors(Validator::new().str("title", "タイトル", "abcd", rules)),
+            vec![pair("title", "too_long")]
+        );
+        assert_eq!(errors(Validator::new().opt_str("title", "タイトル", None, rules)), vec![]);
+
+        let rules = &[StrRule::Required, StrRule::Word, StrRule::MinLen(3)];
+        assert_eq!(errors(Validator::new().str("sn", "スクリーンネーム", "ab_1", rules)), vec![]);
+        assert_eq!(
+            errors(Validator::new().str("sn", "スクリーンネーム", "ab", rules)),
+            vec![pair("sn", "too_short")]
+        );
+        assert_eq!(
+            errors(Validator::new().str("sn", "スクリーンネーム", "あいう", rules)),
+            vec![pair("sn", "invalid_char")]
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        let each = &[StrRule::Required, StrRule::MaxLen(2)];
+        let rules = &[ListRule::MaxItems(3), ListRule::Unique];
+        assert_eq!(errors(Validator::new().list("tags", "タグ", &["a", "b"], rules, each)), vec![]);
+        assert_eq!(
+            errors(Validator::new().list("tags", "タグ", &["a", "b", "c", "d"], rules, each)),
+            vec![pair("tags", "too_many")]
+        );
+        assert_eq!(
+            errors(Validator::new().list("tags", "タグ", &["a", "", "abc", "a"], rules, each)),
+            vec![
+                pair("tags", "too_many"),
+                pair("tags[3]", "duplicate"),
+                pair("tags[1]", "required"),
+                pair("tags[2]", "too_long"),
+            ]
+        );
+        assert_eq!(
+            errors(Validator::new().opt_list::<String>("tags", "タグ", None, rules, each)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_collects_all_errors() {
+        let v = Validator::new()
+            .str("title", "タイトル", "", &[StrRule::Required])
+            .str("text", "本文", "abcd", &[StrRule::MaxLen(3)])
+            .nested("date", |v| v.error("to", "invalid", "期間が不正です"));
+        assert_eq!(
+            errors(v),
+            vec![pair("title", "required"), pair("text", "too_long"), pair("date.to", "invalid")]
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        // 結合文字はNFCで1文字にまとめる
+        assert_eq!(normalize("か\u{3099}"), "が");
+        assert_eq!(normalize("e\u{0301}"), "\u{00E9}");
+        // ゼロ幅スペースとBOMは取り除く
+        assert_eq!(normalize("\u{FEFF}a\u{200B}b"), "ab");
+        // 改行は\nに揃える
+        assert_eq!(normalize("a\r\nb\rc"), "a\nb\nc");
+        // 絵文字のZWJ結合は残す
+        assert_eq!(normalize("👨\u{200D}👩\u{200D}👧"), "👨\u{200D}👩\u{200D}👧");
+    }
+
+    #[test]
+    fn test_grapheme_len() {
+        assert_eq!(grapheme_len("あいう"), 3);
+        assert_eq!(grapheme_len("e\u{0301}"), 1);
+        assert_eq!(grapheme_len("👨\u{200D}👩\u{200D}👧"), 1);
+        assert_eq!(grapheme_len("🇯🇵"), 1);
+    }
+
+    #[test]
+    fn test_unicode_rules() {
+        let rules = &[StrRule::Required, StrRule::Visible, StrRule::SingleLine, StrRule::MaxLen(3)];
+        // 日本語や絵文字は見た目の文字数で数える
+        assert_eq!(errors(Validator::new().str("title", "タイトル", "あい👨\u{200D}👩\u{200D}👧", rules)), vec![]);
+        assert_eq!(
+            errors(Validator::new().str("title", "タイトル", "あいうえ", rules)),
+            vec![pair("title", "too_long")]
+        );
+        // ゼロ幅文字は数えない
+        assert_eq!(errors(Validator::new().str("title", "タイトル", "a\u{200B}\u{200B}bc", rules)), vec![]);
+        // 見た目が空
+        for blank in [" ", "\u{3000}", "\u{0301}\u{0301}", "\u{200D}", "\u{3164}"] {
+            assert_eq!(
+                errors(Validator::new().str("title", "タイトル", blank, rules)),
+                vec![pair("title", "blank")]
+            );
+        }
+        // ゼロ幅文字だけなら取り除いた結果が空
+        assert_eq!(
+            errors(Validator::new().str("title", "タイトル", "\u{200B}", rules)),
+            vec![pair("title", "required")]
+        );
+        // 制御文字
+        assert_eq!(
+            errors(Validator::new().str("title", "タイトル", "a\nb", rules)),
+            vec![pair("title", "invalid_char")]
+        );
+        let rules = &[StrRule::Required, StrRule::MultiLine];
+        assert_eq!(errors(Validator::new().str("text", "本文", "a\r\n\tb", rules)), vec![]);
+        assert_eq!(
+            errors(Validator::new().str("text", "本文", "a\u{0007}b", rules)),
+            vec![pair("text", "invalid_char")]
+        );
+        // 表示を偽装できる双方向テキストの書式文字
+        for spoof in ["abc\u{202E}fed", "a\u{2066}b\u{2069}", "a\u{200F}b", "\u{00AD}ab"] {
+            assert_eq!(
+                errors(Validator::new().str("text", "本文", spoof, rules)),
+                vec![pair("text", "invalid_char")]
+            );
+        }
+        let rules = &[StrRule::Required, StrRule::SingleLine];
+        assert_eq!(
+            errors(Validator::new().str("title", "タイトル", "\u{202E}abc", rules)),
+            vec![pair("title", "invalid_char")]
+        );
+        assert_eq!(
+            errors(Validator::new().str("title", "タイトル", "\u{2066}abc", rules)),
+            vec![pair("title", "invalid_char")]
+        );
+        // 文字の間のZWJ・ZWNJは使える
+        assert_eq!(errors(Validator::new().str("title", "タイトル", "👨\u{200D}👩", rules)), vec![]);
+        assert_eq!(errors(Validator::new().str("title", "タイトル", "\u{0928}\u{094D}\u{200C}\u{0915}", rules)), vec![]);
+        assert_eq!(
+            errors(Validator::new().str("title", "タイトル", "ab\u{200D}", rules)),
+            vec![pair("title", "invalid_char")]
+        );
+    }
+
+    #[test]
+    fn test_has_format_char() {
+        assert!(!has_format_char("abc"));
+        assert!(has_format_char("\u{202E}"));
+        assert!(has_format_char("a\u{2066}b"));
+        assert!(!has_format_char("a\u{200D}b"));
+        assert!(has_format_char("\u{200D}b"));
+        assert!(has_format_char("a \u{200C}b"));
+    }
+
+    #[test]
+    fn test_list_unique_after_normalize() {
+        assert_eq!(
+            errors(Validator::new().list("tags", "タグ", &["が", "か\u{3099}"], &[ListRule::Unique], &[])),
+            vec![pair("tags[1]", "duplicate")]
+        );
+    }
+}